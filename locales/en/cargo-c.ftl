@@ -0,0 +1,99 @@
+# English messages, the fallback for every other locale.
+#
+# Translations go in {locale}/cargo-c.ftl, e.g. it/cargo-c.ftl or pt_BR/cargo-c.ftl,
+# they may contain only a subset of the messages.
+
+build-header-cbindgen = header file using cbindgen
+build-umbrella-header = umbrella header
+build-c-array = library as a C array
+build-pc = pkg-config files
+build-def = .def file
+build-implib = implib
+build-csources = C sources for { $name }
+populate-uninstalled-header = uninstalled header directory
+multiple-targets = Multiple targets not supported yet
+
+install-pkg-config = pkg-config file
+install-header = header file
+install-data = data file
+install-bin = executable
+install-libexec = helper executable
+install-locale = message catalog
+install-man = manual page
+install-doc = documentation
+install-static-lib = static library
+install-shared-lib = shared library
+install-debug-info = debugging information
+install-manifest = install manifest { $path }
+
+ide-no-capi = No package in the workspace has a `capi` feature
+ide-exists = { $path } already exists, pass --force to overwrite it or --stdout to print the configuration
+
+vendored = { $package } bundles vendored system libraries through { $list }
+
+crate-type-not-built = { $package } declares the `{ $kind }` crate-type, it is not built since it is not a requested library type, `default_library_types` of `package.metadata.capi.library` sets the ones built by default
+no-library-target = { $package } has no library target to generate the header from, set `generation = false` in `package.metadata.capi.header`
+include-guard-style = The headers do not use the `include_guard_style` { $style }:
+umbrella-overwrite = The umbrella header { $path } would overwrite another header
+header-from-library = the headers of { $package } are generated from the built library, they are not available
+invalid-source-date-epoch = Invalid SOURCE_DATE_EPOCH value { $value }
+set-timestamp-failed = Cannot set the timestamp of { $path }
+stale-artifacts = the artifacts of { $package } in { $path } are missing or built with different options, build them with the same options first
+pretend-target-unsupported = `--pretend-target` is supported only to build, install and explain
+emit-c-array-library = --emit-c-array requires the library type it embeds to be built
+msvc-not-found = cannot find the MSVC compiler, the C tests may need to be run from a developer prompt
+
+windows-arch-unsupported = Windows support for { $arch } is not implemented yet.
+dlltool-msvc = binutils dlltool does not produce import libraries for msvc, use `--implib-tool llvm-dlltool`
+implib-tool-failed = failed to build the import library with { $tool }
+delay-load-msvc = link with `/DELAYLOAD:{ $dll } delayimp.lib` to delay-load { $dll }
+delay-load-dlltool = the delay-load import library requires dlltool, pass it with `--dlltool` or `DLLTOOL`
+def-exclude-gnu = { $package }: `library.def.exclude` only applies to the import libraries, the gnu linkers keep the exports of rustc in the dll
+sign-failed = Cannot sign { $path }
+write-failed = Cannot write { $path }
+strip-failed = Cannot strip { $path }
+dsym-program-missing = no dSYM bundle, cannot run { $program }: { $error }
+dsym-failed = Cannot write the dSYM bundle of { $path }
+
+unversioned-soname = The shared libraries have no versioned soname:
+sibling-requires = The pkg-config requirements do not match the workspace:
+requirement-not-found = pkg-config cannot find `{ $requirement }`, required by { $dependency }, its libraries are not in Libs.private
+
+unmatched-install-targets = The install targets match no file:
+install-target-unmatched = `package.metadata.capi.{ $key }` `{ $pattern }` matches no file
+man-page-section = Cannot detect the section of the man page { $path }
+man-page-skipped = { $error }, it is not installed
+set-mode-failed = failed to set the mode of { $path }
+read-failed = Cannot read { $path }
+user-prefix = --user requires HOME, or LOCALAPPDATA on Windows, to be set
+pkg-config-path-hint = add { $path } to PKG_CONFIG_PATH to use the installed .pc files
+library-path-hint = add { $path } to { $var } to load the installed libraries
+
+check-header-symbols = header declarations
+check-library-symbols = static and shared library symbols
+symbols-not-declared = symbols exported by { $name } but not declared in the headers: { $symbols }
+symbols-not-exported = symbols declared in the headers but not exported by { $name }: { $symbols }
+symbols-static-only = symbols available in the static { $name } but not in the shared { $name }: { $symbols }
+symbols-shared-only = symbols available in the shared { $name } but not in the static { $name }: { $symbols }
+symbols-removed = symbols removed from { $name } since the previous build: { $symbols }
+symbols-removed-hint = the ABI is not compatible anymore, bump the version if it is intended
+symbols-unreadable = cannot read the symbols of { $name }
+symbols-unchecked = cannot read the symbols of { $name }, they are not checked: { $error }
+removed-symbols-unreadable = cannot read the symbols of { $name }, the removed ones are not reported: { $error }
+
+unused-key = `package.metadata.capi.{ $key }` has no effect, { $reason }
+unused-key-overridden = `package.metadata.capi.{ $key }` has no effect, `{ $last }` overrides it
+unused-header-disabled = the header is disabled
+unused-no-shared-library = no shared library is built
+unused-no-rpath = the shared libraries of the target have no rpath
+unused-not-apple = only the Apple dylibs use it
+unused-no-import-library = only the dlls have import libraries
+unused-no-dll = only the dlls use it
+unused-not-mingw = only the MinGW dlls use it
+unused-not-msvc = only the msvc targets use it
+unused-not-elf = only the ELF linkers resolve `-l:`
+
+verify-header-generator = { $package }: only the headers generated with cbindgen can be verified
+verify-header-missing = missing
+verify-header-differs = differs from line { $line }
+verify-header-diverging = The committed headers do not match the generated ones:
@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use cargo::core::Workspace;
+use cargo_util::paths::{read, write};
+use clap::ArgMatches;
+
+use crate::build::CPackage;
+use crate::symbols::{exported_symbols, static_library_symbols, HeaderApi};
+
+/// The ABI of a library, as recorded in the baseline
+///
+/// The symbols map to their declaration in the headers, empty if not declared.
+/// The types are recorded only with `--layouts`.
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct LibraryAbi {
+    symbols: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    types: BTreeMap<String, String>,
+}
+
+/// The baseline, by library name
+type Baseline = BTreeMap<String, LibraryAbi>;
+
+fn library_abi(cpkg: &CPackage, layouts: bool) -> anyhow::Result<Option<LibraryAbi>> {
+    let build_targets = &cpkg.build_targets;
+    let target = &build_targets.target;
+    let symbols = if let Some(shared_lib) = &build_targets.shared_lib {
+        exported_symbols(shared_lib, target)?
+    } else if let Some(static_lib) = &build_targets.static_lib {
+        static_library_symbols(static_lib, target)?
+    } else {
+        return Ok(None);
+    };
+
+    let mut api = HeaderApi::default();
+    for (from, _) in &build_targets.extra.include {
+        if from.extension().is_some_and(|ext| ext == "h") {
+            let header = HeaderApi::parse(&read(from)?);
+            api.declarations.extend(header.declarations);
+            api.types.extend(header.types);
+        }
+    }
+
+    let symbols = symbols
+        .into_iter()
+        .map(|name| {
+            let declaration = api.declarations.remove(&name).unwrap_or_default();
+            (name, declaration)
+        })
+        .collect();
+    let types = if layouts { api.types } else { BTreeMap::new() };
+
+    Ok(Some(LibraryAbi { symbols, types }))
+}
+
+/// The changes breaking the ABI, the additions are compatible
+fn breaking_changes(name: &str, baseline: &LibraryAbi, current: &LibraryAbi) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for (symbol, declaration) in &baseline.symbols {
+        match current.symbols.get(symbol) {
+            None => problems.push(format!("{name}: symbol `{symbol}` removed")),
+            Some(current) if !declaration.is_empty() && current != declaration => problems.push(
+                format!("{name}: symbol `{symbol}` changed from `{declaration}` to `{current}`"),
+            ),
+            _ => {}
+        }
+    }
+
+    // Compare the layouts only if both record them
+    if !current.types.is_empty() {
+        for (ty, definition) in &baseline.types {
+            match current.types.get(ty) {
+                None => problems.push(format!("{name}: type `{ty}` removed")),
+                Some(current) if current != definition => problems.push(format!(
+                    "{name}: type `{ty}` changed from `{definition}` to `{current}`"
+                )),
+                _ => {}
+            }
+        }
+    }
+
+    problems
+}
+
+/// Compare the ABI of the libraries with the baseline, or record it
+///
+/// The baseline is written if it does not exist or with `--update`.
+pub fn abi_check(ws: &Workspace, packages: &[CPackage], args: &ArgMatches) -> anyhow::Result<()> {
+    let path = ws
+        .gctx()
+        .cwd()
+        .join(args.get_one::<PathBuf>("baseline").unwrap());
+    let layouts = args.get_flag("layouts");
+
+    let mut current = Baseline::new();
+    for cpkg in packages {
+        if let Some(abi) = library_abi(cpkg, layouts)? {
+            current.insert(cpkg.capi_config.library.name.clone(), abi);
+        }
+    }
+
+    if args.get_flag("update") || !path.exists() {
+        return write_baseline(ws, &path, &current);
+    }
+
+    let baseline: Baseline = serde_json::from_str(&read(&path)?)?;
+    let mut problems = Vec::new();
+    for (name, abi) in &current {
+        let Some(old) = baseline.get(name) else {
+            continue;
+        };
+        problems.extend(breaking_changes(name, old, abi));
+
+        let added: Vec<_> = abi
+            .symbols
+            .keys()
+            .filter(|s| !old.symbols.contains_key(*s))
+            .map(String::as_str)
+            .collect();
+        if !added.is_empty() {
+            ws.gctx()
+                .shell()
+                .note(format!("{name}: symbols added: {}", added.join(", ")))?;
+        }
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "The ABI is not compatible with {}:\n  {}",
+            path.display(),
+            problems.join("\n  ")
+        );
+    }
+
+    ws.gctx()
+        .shell()
+        .status("Checked", format!("the ABI against {}", path.display()))?;
+
+    Ok(())
+}
+
+fn write_baseline(ws: &Workspace, path: &Path, baseline: &Baseline) -> anyhow::Result<()> {
+    ws.gctx()
+        .shell()
+        .status("Writing", format!("ABI baseline {}", path.display()))?;
+    write(path, serde_json::to_string_pretty(baseline)? + "\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn abi(symbols: &[(&str, &str)], types: &[(&str, &str)]) -> LibraryAbi {
+        let map = |items: &[(&str, &str)]| {
+            items
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        LibraryAbi {
+            symbols: map(symbols),
+            types: map(types),
+        }
+    }
+
+    #[test]
+    fn breaking() {
+        let baseline = abi(
+            &[
+                ("ex_new", "Ex *ex_new(void)"),
+                ("ex_free", "void ex_free(Ex *ex)"),
+            ],
+            &[("ExConfig", "typedef struct { int32_t a; } ExConfig")],
+        );
+
+        let added = abi(
+            &[
+                ("ex_new", "Ex *ex_new(void)"),
+                ("ex_free", "void ex_free(Ex *ex)"),
+                ("ex_get", "int ex_get(Ex *ex)"),
+            ],
+            &[("ExConfig", "typedef struct { int32_t a; } ExConfig")],
+        );
+        assert!(breaking_changes("ex", &baseline, &added).is_empty());
+
+        let changed = abi(
+            &[("ex_new", "Ex *ex_new(int flags)")],
+            &[("ExConfig", "typedef struct { int64_t a; } ExConfig")],
+        );
+        assert_eq!(
+            breaking_changes("ex", &baseline, &changed),
+            [
+                "ex: symbol `ex_free` removed",
+                "ex: symbol `ex_new` changed from `Ex *ex_new(void)` to `Ex *ex_new(int flags)`",
+                "ex: type `ExConfig` changed from `typedef struct { int32_t a; } ExConfig` to `typedef struct { int64_t a; } ExConfig`",
+            ]
+        );
+
+        // Without the layouts only the symbols are compared
+        let no_layouts = LibraryAbi {
+            symbols: baseline.symbols.clone(),
+            types: BTreeMap::new(),
+        };
+        assert!(breaking_changes("ex", &baseline, &no_layouts).is_empty());
+    }
+}
@@ -1,10 +1,8 @@
-use cargo_c::build::{cbuild, ctest};
 use cargo_c::cli::*;
 use cargo_c::config::*;
-use cargo_c::install::cinstall;
+use cargo_c::dispatch::{legacy_name, run};
 
 use cargo::util::command_prelude::flag;
-use cargo::util::command_prelude::ArgMatchesExt;
 use cargo::{CliResult, GlobalContext};
 
 use clap::*;
@@ -15,6 +13,25 @@ fn main() -> CliResult {
     let cli_build = subcommand_build("build", "Build the crate C-API");
     let cli_install = subcommand_install("install", "Install the crate C-API");
     let cli_test = subcommand_test("test");
+    let cli_doc = subcommand_doc("doc");
+    let cli_abi_check = subcommand_abi_check("abi-check");
+    let cli_ide_setup = subcommand_ide_setup("ide-setup");
+    let cli_explain = subcommand_explain("explain");
+    let cli_verify_header = subcommand_verify_header("verify-header");
+    let cli_size_report = subcommand_size_report("size-report");
+    let cli_msi = subcommand_msi("msi");
+    let cli_dirs = subcommand_dirs("dirs");
+    let cli_shell = subcommand_shell("shell");
+    let cli_upgrade_metadata = subcommand_upgrade_metadata("upgrade-metadata");
+
+    // The names of the standalone binaries, deprecated
+    let cli_legacy = [
+        subcommand_build("cbuild", "Build the crate C-API"),
+        subcommand_install("cinstall", "Install the crate C-API"),
+        subcommand_test("ctest"),
+        subcommand_doc("cdoc"),
+    ]
+    .map(|cmd| cmd.hide(true));
 
     let mut app = main_cli().subcommand(
         Command::new("capi")
@@ -23,16 +40,32 @@ fn main() -> CliResult {
             .arg(flag("version", "Print version info and exit").short('V'))
             .subcommand(cli_build)
             .subcommand(cli_install)
-            .subcommand(cli_test),
+            .subcommand(cli_test)
+            .subcommand(cli_doc)
+            .subcommand(cli_abi_check)
+            .subcommand(cli_ide_setup)
+            .subcommand(cli_explain)
+            .subcommand(cli_verify_header)
+            .subcommand(cli_size_report)
+            .subcommand(cli_msi)
+            .subcommand(cli_dirs)
+            .subcommand(cli_shell)
+            .subcommand(cli_upgrade_metadata)
+            .subcommands(cli_legacy),
     );
 
-    let args = app.clone().get_matches();
+    let args = get_matches(&app, &config)?;
 
-    let (cmd, subcommand_args, default_profile) = match args.subcommand() {
+    let (cmd, subcommand_args) = match args.subcommand() {
         Some(("capi", args)) => match args.subcommand() {
-            Some(("build", args)) => ("build", args, "dev"),
-            Some(("test", args)) => ("test", args, "dev"),
-            Some(("install", args)) => ("install", args, "release"),
+            Some((
+                cmd @ ("build" | "install" | "test" | "doc" | "abi-check" | "ide-setup" | "explain"
+                | "verify-header" | "size-report" | "msi" | "dirs" | "shell"
+                | "upgrade-metadata"),
+                args,
+            )) => (cmd, args),
+            // Deprecated, `run` warns about them
+            Some((legacy, args)) if legacy_name(legacy).is_some() => (legacy, args),
             Some((cmd, args)) => {
                 return run_cargo_fallback(cmd, args);
             }
@@ -51,22 +84,5 @@ fn main() -> CliResult {
         }
     };
 
-    if subcommand_args.flag("version") {
-        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-        return Ok(());
-    }
-
-    global_context_configure(&mut config, subcommand_args)?;
-
-    let mut ws = subcommand_args.workspace(&config)?;
-
-    let (packages, compile_opts) = cbuild(&mut ws, &config, subcommand_args, default_profile)?;
-
-    if cmd == "install" {
-        cinstall(&ws, &packages)?;
-    } else if cmd == "test" {
-        ctest(&ws, subcommand_args, &packages, compile_opts)?;
-    }
-
-    Ok(())
+    run(cmd, subcommand_args, &mut config)
 }
@@ -0,0 +1,29 @@
+use cargo::CliResult;
+use cargo::GlobalContext;
+
+use cargo_c::cli::{main_cli, run_cargo_fallback, subcommand_doc};
+use cargo_c::config::get_matches;
+use cargo_c::dispatch::run;
+
+fn main() -> CliResult {
+    let mut config = GlobalContext::default()?;
+
+    let subcommand = subcommand_doc("cdoc");
+    let mut app = main_cli().subcommand(subcommand);
+
+    let args = get_matches(&app, &config)?;
+
+    let subcommand_args = match args.subcommand() {
+        Some(("cdoc", args)) => args,
+        Some((cmd, args)) => {
+            return run_cargo_fallback(cmd, args);
+        }
+        _ => {
+            // No subcommand provided.
+            app.print_help()?;
+            return Ok(());
+        }
+    };
+
+    run("doc", subcommand_args, &mut config)
+}
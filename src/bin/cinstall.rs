@@ -1,11 +1,9 @@
-use cargo::util::command_prelude::ArgMatchesExt;
 use cargo::CliResult;
 use cargo::GlobalContext;
 
-use cargo_c::build::cbuild;
 use cargo_c::cli::{main_cli, run_cargo_fallback, subcommand_install};
-use cargo_c::config::global_context_configure;
-use cargo_c::install::cinstall;
+use cargo_c::config::get_matches;
+use cargo_c::dispatch::run;
 
 fn main() -> CliResult {
     let mut config = GlobalContext::default()?;
@@ -13,7 +11,7 @@ fn main() -> CliResult {
     let subcommand = subcommand_install("cinstall", "Install the crate C-API");
     let mut app = main_cli().subcommand(subcommand);
 
-    let args = app.clone().get_matches();
+    let args = get_matches(&app, &config)?;
 
     let subcommand_args = match args.subcommand() {
         Some(("cinstall", args)) => args,
@@ -27,18 +25,5 @@ fn main() -> CliResult {
         }
     };
 
-    if subcommand_args.flag("version") {
-        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
-        return Ok(());
-    }
-
-    global_context_configure(&mut config, subcommand_args)?;
-
-    let mut ws = subcommand_args.workspace(&config)?;
-
-    let (packages, _) = cbuild(&mut ws, &config, subcommand_args, "release")?;
-
-    cinstall(&ws, &packages)?;
-
-    Ok(())
+    run("install", subcommand_args, &mut config)
 }
@@ -1,15 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use cargo::core::compiler::{unit_graph::UnitDep, unit_graph::UnitGraph, Executor, Unit};
+use cargo::core::compiler::{
+    unit_graph::UnitDep, unit_graph::UnitGraph, CompileKind, Executor, Unit,
+};
+use cargo::core::dependency::DepKind;
 use cargo::core::profiles::Profiles;
 use cargo::core::{FeatureValue, Package, PackageId, Target, TargetKind, Workspace};
 use cargo::ops::{self, CompileFilter, CompileOptions, FilterRule, LibRule};
 use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt, CompileMode, ProfileChecking};
 use cargo::util::interning::InternedString;
+use cargo::util::StableHasher;
 use cargo::{CliResult, GlobalContext};
 
 use anyhow::Context as _;
@@ -19,29 +24,156 @@ use implib::{Flavor, ImportLibrary, MachineType};
 use itertools::Itertools;
 use semver::Version;
 
-use crate::build_targets::BuildTargets;
-use crate::install::InstallPaths;
-use crate::pkg_config_gen::PkgConfig;
+use crate::build_targets::{BuildTargets, ExtraTargets};
+use crate::csources;
+use crate::def::{self, DefLink};
+use crate::generator::HeaderGenerator;
+use crate::i18n::tr;
+use crate::install::{InstallPaths, LibType};
+use crate::overrides::effective_capi_config;
+use crate::pkg_config_gen::{probe_cflags, probe_libs, satisfies, PkgConfig};
+use crate::probe;
+use crate::progress::{self, Phase};
+use crate::reuse::build_key;
+use crate::symbols::{
+    check_header_symbols, check_library_symbols, check_removed_symbols, library_symbols,
+};
 use crate::target;
+use crate::vendored::check_vendored;
+
+/// The headers cbindgen writes, in `root_output`
+fn header_files(header: &HeaderCApiConfig, root_output: &Path) -> Vec<PathBuf> {
+    let mut files = vec![root_output.join(format!("{}.h", header.name))];
+    files.extend(
+        header
+            .headers
+            .iter()
+            .map(|h| root_output.join(h.file_name())),
+    );
+    if header.cpp.is_some() {
+        files.push(root_output.join(header.cpp_file_name()));
+    }
+    files
+}
+
+/// The hash of what the cbindgen headers depend on
+///
+/// The header configuration, the cbindgen configuration file, the manifest and the Rust
+/// sources cargo lists in the dep-info file of the library, `None` if one cannot be read.
+/// The dep-info file does not list the sources of the dependencies, `None` as well if
+/// cbindgen parses them.
+fn header_inputs_hash(
+    header: &HeaderCApiConfig,
+    version: &Version,
+    root_path: &Path,
+    cargo_dep_info: &Path,
+) -> Option<String> {
+    use std::hash::{Hash, Hasher};
+
+    if !header.dependencies.is_empty()
+        || load_cbindgen_config(header, root_path)
+            .ok()?
+            .parse
+            .parse_deps
+    {
+        return None;
+    }
+
+    let mut hasher = StableHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    format!("{header:?}").hash(&mut hasher);
+    version.hash(&mut hasher);
 
-/// Build the C header
+    let cbindgen_config = root_path.join(
+        header
+            .cbindgen_config
+            .as_deref()
+            .unwrap_or(Path::new("cbindgen.toml")),
+    );
+    let mut files = vec![root_path.join("Cargo.toml")];
+    files.extend(cbindgen_config.exists().then_some(cbindgen_config));
+    files.extend(parse_dep_info(&read(cargo_dep_info).ok()?));
+
+    for file in files {
+        file.hash(&mut hasher);
+        hash_file(&mut hasher, &file).ok()?;
+    }
+
+    Some(hasher.finish().to_string())
+}
+
+/// The cbindgen configuration of the header, the default one if there is none
+fn load_cbindgen_config(
+    header: &HeaderCApiConfig,
+    root_path: &Path,
+) -> anyhow::Result<cbindgen::Config> {
+    // TODO: map the errors
+    Ok(if let Some(path) = &header.cbindgen_config {
+        cbindgen::Config::from_file(root_path.join(path)).map_err(|e| anyhow::anyhow!(e))?
+    } else {
+        cbindgen::Config::from_root_or_default(root_path)
+    })
+}
+
+/// Build the C header and the additional headers
 fn build_include_file(
     ws: &Workspace,
-    name: &str,
+    header: &HeaderCApiConfig,
+    version: &Version,
+    root_output: &Path,
+    root_path: &Path,
+) -> anyhow::Result<()> {
+    progress::status(
+        ws,
+        Phase::Header,
+        "Building",
+        tr("build-header-cbindgen", &[]),
+    )?;
+
+    write_headers(header, version, root_output, root_path)
+}
+
+/// Write the cbindgen headers of the crate in `root_path` to `root_output`
+///
+/// The functions of the additional headers are left out of the main one, they include it
+/// for the types.
+fn write_headers(
+    header: &HeaderCApiConfig,
     version: &Version,
     root_output: &Path,
     root_path: &Path,
 ) -> anyhow::Result<()> {
-    ws.gctx()
-        .shell()
-        .status("Building", "header file using cbindgen")?;
-    let mut header_name = PathBuf::from(name);
-    header_name.set_extension("h");
-    let include_path = root_output.join(header_name);
     let crate_path = root_path;
 
-    // TODO: map the errors
-    let mut config = cbindgen::Config::from_root_or_default(crate_path);
+    let base_config = load_cbindgen_config(header, root_path)?;
+    let generate = |config: cbindgen::Config| {
+        cbindgen::Builder::new()
+            .with_crate(crate_path)
+            .with_config(config)
+            .generate()
+            .map_err(|e| anyhow::anyhow!(e))
+    };
+
+    let name = &header.name;
+    let mut base_config = base_config;
+    match header.include_guard_style {
+        Some(IncludeGuardStyle::PragmaOnce) => {
+            base_config.pragma_once = true;
+            base_config.include_guard = None;
+        }
+        Some(IncludeGuardStyle::Guard) => {
+            base_config.pragma_once = false;
+            base_config
+                .include_guard
+                .get_or_insert_with(|| include_guard(name));
+        }
+        None => {}
+    }
+    let dependency_items =
+        build_dependency_headers(header, &mut base_config, crate_path, root_output)?;
+    let mut config = base_config.clone();
+    header.filter.apply(&mut config);
+    use_dependency_headers(&mut config, header, &dependency_items, 0);
     let warning = config.autogen_warning.unwrap_or_default();
     let version_info = format!(
         "\n#define {0}_MAJOR {1}\n#define {0}_MINOR {2}\n#define {0}_PATCH {3}\n",
@@ -51,12 +183,402 @@ fn build_include_file(
         version.patch
     );
     config.autogen_warning = Some(warning + &version_info);
-    cbindgen::Builder::new()
-        .with_crate(crate_path)
-        .with_config(config)
-        .generate()
-        .unwrap()
-        .write_to_file(include_path);
+    let mut header_name = PathBuf::from(name);
+    header_name.set_extension("h");
+    let mut bindings = generate(config)?;
+    bindings
+        .functions
+        .retain(|f| header.extra_header_of(f.path.name()).is_none());
+    bindings.write_to_file(root_output.join(&header_name));
+
+    for (i, extra) in header.headers.iter().enumerate() {
+        let mut config = base_config.clone();
+        let depth = extra.file_name().components().count() - 1;
+        let main_header = format!("{}{}", "../".repeat(depth), header_name.display());
+        config.includes.push(main_header.replace('\\', "/"));
+        config.includes.extend(extra.includes.iter().cloned());
+        // Declared once, in the main header
+        config.after_includes = None;
+        // Every header needs its own include guard
+        if let Some(guard) = &config.include_guard {
+            config.include_guard = Some(format!("{guard}_{}", macro_name(&extra.name)));
+        }
+        let include_path = root_output.join(extra.file_name());
+        create_dir_all(include_path.parent().unwrap())?;
+        let mut bindings = generate(config)?;
+        bindings.items.clear();
+        bindings.constants.clear();
+        bindings.globals.clear();
+        bindings
+            .functions
+            .retain(|f| header.extra_header_of(f.path.name()) == Some(i));
+        bindings.write_to_file(include_path);
+    }
+
+    if let Some(namespace) = &header.cpp {
+        let mut config = base_config;
+        header.filter.apply(&mut config);
+        config.language = cbindgen::Language::Cxx;
+        config.enumeration.enum_class = true;
+        if !namespace.is_empty() {
+            config.namespace = Some(namespace.clone());
+        }
+        if let Some(guard) = &config.include_guard {
+            config.include_guard = Some(format!("{guard}PP"));
+        }
+        generate(config)?.write_to_file(root_output.join(header.cpp_file_name()));
+    }
+
+    Ok(())
+}
+
+/// Parse the crates of `header.dependencies` and write the `deps/{crate}.h` headers
+///
+/// Return the items declared in the dependency headers, to leave out of the others.
+fn build_dependency_headers(
+    header: &HeaderCApiConfig,
+    base_config: &mut cbindgen::Config,
+    crate_path: &Path,
+    root_output: &Path,
+) -> anyhow::Result<BTreeSet<String>> {
+    if header.dependencies.is_empty() {
+        return Ok(BTreeSet::new());
+    }
+
+    let parse = &mut base_config.parse;
+    // Every dependency may be parsed already
+    if !parse.parse_deps || parse.include.is_some() {
+        parse
+            .include
+            .get_or_insert_with(Vec::new)
+            .extend(header.dependencies.keys().cloned());
+    }
+    parse.parse_deps = true;
+
+    let generate = |config: cbindgen::Config| {
+        cbindgen::Builder::new()
+            .with_crate(crate_path)
+            .with_config(config)
+            .generate()
+            .map_err(|e| anyhow::anyhow!(e))
+    };
+    let item_names = |bindings: cbindgen::Bindings| -> BTreeSet<String> {
+        let items = bindings
+            .items
+            .iter()
+            .map(|item| item.deref().path().name().to_string());
+        let constants = bindings.constants.iter().map(|c| c.path.name().to_string());
+        items.chain(constants).collect()
+    };
+
+    let all = item_names(generate(base_config.clone())?);
+    let mut dependency_items = BTreeSet::new();
+    for (krate, _) in header
+        .dependencies
+        .iter()
+        .filter(|(_, d)| **d == DependencyHeader::Header)
+    {
+        // The items of the crate are the ones missing if it is not parsed
+        let mut config = base_config.clone();
+        config.parse.exclude.push(krate.clone());
+        let without = item_names(generate(config)?);
+        let items: BTreeSet<_> = all.difference(&without).cloned().collect();
+
+        let mut config = base_config.clone();
+        // Without the functions nothing refers to them
+        config.export.include.extend(items.iter().cloned());
+        config
+            .export
+            .exclude
+            .extend(all.difference(&items).cloned());
+        config.export.item_types = {
+            use cbindgen::ItemType::*;
+            vec![Constants, Enums, Structs, Unions, Typedefs, OpaqueItems]
+        };
+        if let Some(guard) = &config.include_guard {
+            config.include_guard = Some(format!("{guard}_DEPS_{}", macro_name(krate)));
+        }
+        let include_path = root_output.join(DependencyHeader::file_name(krate));
+        create_dir_all(include_path.parent().unwrap())?;
+        generate(config)?.write_to_file(include_path);
+
+        dependency_items.extend(items);
+    }
+
+    Ok(dependency_items)
+}
+
+/// Include the dependency headers in place of the items they declare
+///
+/// `depth` is the number of directories between the header and the header subdirectory.
+fn use_dependency_headers(
+    config: &mut cbindgen::Config,
+    header: &HeaderCApiConfig,
+    dependency_items: &BTreeSet<String>,
+    depth: usize,
+) {
+    config
+        .export
+        .exclude
+        .extend(dependency_items.iter().cloned());
+    for (krate, _) in header
+        .dependencies
+        .iter()
+        .filter(|(_, d)| **d == DependencyHeader::Header)
+    {
+        let path = DependencyHeader::file_name(krate);
+        let include = format!("{}{}", "../".repeat(depth), path.display());
+        config.includes.push(include.replace('\\', "/"));
+    }
+}
+
+/// `name` as a C macro name, uppercase with `_` in place of the other characters
+fn macro_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_uppercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn include_guard(name: &str) -> String {
+    format!("{}_H", macro_name(name))
+}
+
+/// The include guard style of a header, from its first two preprocessor directives
+fn detect_include_guard_style(content: &str) -> Option<IncludeGuardStyle> {
+    // Drop the comments, the guard may come after a license notice
+    let mut code = String::with_capacity(content.len());
+    let mut rest = content;
+    while !rest.is_empty() {
+        let block = rest.find("/*");
+        let line = rest.find("//");
+        match (block, line) {
+            (Some(b), l) if l.map_or(true, |l| b < l) => {
+                code.push_str(&rest[..b]);
+                rest = rest[b..].split_once("*/").map_or("", |(_, r)| r);
+            }
+            (_, Some(l)) => {
+                code.push_str(&rest[..l]);
+                rest = rest[l..].find('\n').map_or("", |n| &rest[l + n..]);
+            }
+            _ => {
+                code.push_str(rest);
+                rest = "";
+            }
+        }
+    }
+
+    let mut directives = code
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .map(|l| l.split_whitespace().collect::<Vec<_>>());
+    match directives.next()?.as_slice() {
+        ["#pragma", "once"] => Some(IncludeGuardStyle::PragmaOnce),
+        ["#ifndef", guard] => match directives.next()?.as_slice() {
+            ["#define", define] if define == guard => Some(IncludeGuardStyle::Guard),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Check that every header to install uses the include guard style required
+fn check_include_guards(
+    build_targets: &BuildTargets,
+    style: IncludeGuardStyle,
+) -> anyhow::Result<()> {
+    let offending: Vec<_> = build_targets
+        .extra
+        .include
+        .iter()
+        .filter(|(from, _)| from.extension().is_some_and(|e| e == "h" || e == "hpp"))
+        .filter(|(from, _)| {
+            let content = std::fs::read_to_string(from).unwrap_or_default();
+            detect_include_guard_style(&content) != Some(style)
+        })
+        .map(|(from, _)| from.display().to_string())
+        .collect();
+
+    if !offending.is_empty() {
+        anyhow::bail!(
+            "{}\n  {}",
+            tr("include-guard-style", &[("style", &style.as_str())]),
+            offending.join("\n  ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The headers in inclusion order, every header comes after the ones it includes
+///
+/// `headers` are the install paths and the contents of the headers.
+fn umbrella_order(headers: &[(&Path, String)]) -> Vec<usize> {
+    let includes: Vec<Vec<usize>> = headers
+        .iter()
+        .map(|(_, content)| {
+            content
+                .lines()
+                .filter_map(|line| {
+                    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+                    let path = directive.strip_prefix("include")?.trim();
+                    path.strip_prefix('"')
+                        .and_then(|p| p.split('"').next())
+                        .or_else(|| path.strip_prefix('<')?.split('>').next())
+                })
+                .filter_map(|include| headers.iter().position(|(to, _)| to.ends_with(include)))
+                .collect()
+        })
+        .collect();
+
+    fn visit(i: usize, includes: &[Vec<usize>], visited: &mut [bool], order: &mut Vec<usize>) {
+        if std::mem::replace(&mut visited[i], true) {
+            return;
+        }
+        for &dep in &includes[i] {
+            visit(dep, includes, visited, order);
+        }
+        order.push(i);
+    }
+
+    let mut visited = vec![false; headers.len()];
+    let mut order = Vec::with_capacity(headers.len());
+    for i in 0..headers.len() {
+        visit(i, &includes, &mut visited, &mut order);
+    }
+
+    order
+}
+
+/// `to` relative to the directory `dir`
+fn relative_path(dir: &Path, to: &Path) -> PathBuf {
+    let common = dir
+        .components()
+        .zip(to.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut path: PathBuf = dir.components().skip(common).map(|_| "..").collect();
+    path.extend(to.components().skip(common));
+    path
+}
+
+fn build_umbrella_header(
+    ws: &Workspace,
+    name: &str,
+    header: &HeaderCApiConfig,
+    build_targets: &BuildTargets,
+    root_output: &Path,
+    umbrella: &Path,
+) -> anyhow::Result<()> {
+    progress::status(
+        ws,
+        Phase::Header,
+        "Building",
+        tr("build-umbrella-header", &[]),
+    )?;
+
+    let from = root_output.join("umbrella").join(umbrella);
+    let (_, to) = build_targets
+        .extra
+        .include
+        .iter()
+        .find(|(f, _)| *f == from)
+        .unwrap();
+    let includes: Vec<_> = build_targets
+        .extra
+        .include
+        .iter()
+        .filter(|(f, _)| *f != from)
+        .cloned()
+        .collect();
+    let out = umbrella_header(name, header, &includes, to);
+
+    create_dir_all(from.parent().unwrap())?;
+    write(&from, out)?;
+
+    Ok(())
+}
+
+/// The umbrella header installed as `to`, including the headers among `includes`
+fn umbrella_header(
+    name: &str,
+    header: &HeaderCApiConfig,
+    includes: &[(PathBuf, PathBuf)],
+    to: &Path,
+) -> String {
+    let umbrella = header.umbrella.as_deref().unwrap_or(Path::new(""));
+    let dir = to.parent().unwrap_or(Path::new(""));
+
+    let headers: Vec<_> = includes
+        .iter()
+        .filter(|(_, t)| t.extension().is_some_and(|e| e == "h"))
+        .map(|(f, t)| (t.as_path(), std::fs::read_to_string(f).unwrap_or_default()))
+        .collect();
+
+    let guard = macro_name(&format!("{name}_{}", umbrella.display()));
+    let pragma_once = header.include_guard_style == Some(IncludeGuardStyle::PragmaOnce);
+
+    let mut out = format!("/* Generated by cargo-c, it includes every header of {name} */\n\n");
+    if pragma_once {
+        out.push_str("#pragma once\n\n");
+    } else {
+        out.push_str(&format!("#ifndef {guard}\n#define {guard}\n\n"));
+    }
+    for i in umbrella_order(&headers) {
+        let include = relative_path(dir, headers[i].0);
+        out.push_str(&format!(
+            "#include \"{}\"\n",
+            include.display().to_string().replace('\\', "/")
+        ));
+    }
+    if !pragma_once {
+        out.push_str(&format!("\n#endif /* {guard} */\n"));
+    }
+
+    out
+}
+
+/// Write `library` as a C array in `c_file` and declare it in the matching header
+fn build_c_array(ws: &Workspace, name: &str, library: &Path, c_file: &Path) -> anyhow::Result<()> {
+    use std::fmt::Write as _;
+
+    progress::status(ws, Phase::CArray, "Building", tr("build-c-array", &[]))?;
+
+    let bytes = read_bytes(library)?;
+    let ident = macro_name(name).to_lowercase();
+    let h_file = c_file.with_extension("h");
+    let h_name = h_file.file_name().unwrap().to_string_lossy();
+    let source = library.file_name().unwrap().to_string_lossy();
+    let guard = macro_name(&h_name);
+
+    let header = format!(
+        "/* Generated by cargo-c from {source} */\n\n\
+         #ifndef {guard}\n#define {guard}\n\n#include <stddef.h>\n\n\
+         extern const unsigned char {ident}_blob[];\n\
+         extern const size_t {ident}_blob_len;\n\n\
+         #endif /* {guard} */\n"
+    );
+
+    let mut c = String::with_capacity(bytes.len() * 6 + 256);
+    // writing to a String only fails on OOM, which we disregard
+    writeln!(c, "/* Generated by cargo-c from {source} */\n").unwrap();
+    writeln!(c, "#include \"{h_name}\"\n").unwrap();
+    writeln!(c, "const unsigned char {ident}_blob[] = {{").unwrap();
+    for line in bytes.chunks(12) {
+        let line = line.iter().map(|b| format!("0x{b:02x},")).join(" ");
+        writeln!(c, "    {line}").unwrap();
+    }
+    writeln!(c, "}};\n").unwrap();
+    writeln!(c, "const size_t {ident}_blob_len = sizeof({ident}_blob);").unwrap();
+
+    write(&h_file, header)?;
+    write(c_file, c)?;
 
     Ok(())
 }
@@ -67,8 +589,12 @@ fn copy_prebuilt_include_file(
     build_targets: &BuildTargets,
     root_output: &Path,
 ) -> anyhow::Result<()> {
-    let mut shell = ws.gctx().shell();
-    shell.status("Populating", "uninstalled header directory")?;
+    progress::status(
+        ws,
+        Phase::Header,
+        "Populating",
+        tr("populate-uninstalled-header", &[]),
+    )?;
     for (from, to) in build_targets.extra.include.iter() {
         let to = root_output.join("include").join(to);
         create_dir_all(to.parent().unwrap())?;
@@ -91,7 +617,7 @@ fn build_pc_files(
     root_output: &Path,
     pc: &PkgConfig,
 ) -> anyhow::Result<()> {
-    ws.gctx().shell().status("Building", "pkg-config files")?;
+    progress::status(ws, Phase::PkgConfig, "Building", tr("build-pc", &[]))?;
     build_pc_file(filename, root_output, pc)?;
     let pc_uninstalled = pc.uninstalled(root_output);
     build_pc_file(
@@ -101,17 +627,185 @@ fn build_pc_files(
     )
 }
 
+fn build_msbuild_props(
+    path: &Path,
+    capi_config: &CApiConfig,
+    install_paths: &InstallPaths,
+    build_targets: &BuildTargets,
+    system_libs: &str,
+) -> anyhow::Result<()> {
+    let props =
+        crate::msbuild::render_props(capi_config, install_paths, build_targets, system_libs);
+    write(path, props)?;
+
+    Ok(())
+}
+
+fn build_system_deps(path: &Path, capi_config: &CApiConfig) -> anyhow::Result<()> {
+    write(path, crate::pkg_config_gen::render_system_deps(capi_config))?;
+
+    Ok(())
+}
+
+fn build_config_script(path: &Path, pc: &PkgConfig) -> anyhow::Result<()> {
+    let name = path.file_name().unwrap().to_string_lossy();
+    write(path, pc.render_config_script(&name))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(())
+}
+
+/// The dependencies listed by a Makefile-style dep-info file
+///
+/// As cargo reads them, only `\ `, `\#` and `$$` are escapes, the other backslashes are the
+/// separators of the Windows paths.
+pub(crate) fn parse_dep_info(content: &str) -> Vec<PathBuf> {
+    let mut deps = Vec::new();
+
+    for line in content.lines() {
+        let Some((_, line_deps)) = line.split_once(": ") else {
+            continue;
+        };
+        let mut dep = String::new();
+        let mut chars = line_deps.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if matches!(chars.peek(), Some(' ' | '#')) => dep.extend(chars.next()),
+                '$' if chars.peek() == Some(&'$') => dep.extend(chars.next()),
+                ' ' => {
+                    if !dep.is_empty() {
+                        deps.push(PathBuf::from(std::mem::take(&mut dep)));
+                    }
+                }
+                _ => dep.push(c),
+            }
+        }
+        if !dep.is_empty() {
+            deps.push(PathBuf::from(dep));
+        }
+    }
+
+    deps
+}
+
+pub(crate) fn escape_dep_path(path: &Path) -> String {
+    path.display()
+        .to_string()
+        .replace('$', "$$")
+        .replace(' ', "\\ ")
+        .replace('#', "\\#")
+}
+
+/// Write a Makefile-style dep-info file listing the sources the cargo-c artifacts depend on
+///
+/// The Rust sources are taken from the dep-info file cargo writes for the library.
+fn build_dep_file(
+    ws: &Workspace,
+    name: &str,
+    root_output: &Path,
+    cargo_dep_info: &Path,
+    targets: &[&Path],
+    extra_deps: &[PathBuf],
+) -> anyhow::Result<()> {
+    let mut deps = match read(cargo_dep_info) {
+        Ok(content) => parse_dep_info(&content),
+        Err(_) => Vec::new(),
+    };
+    deps.extend(extra_deps.iter().filter(|p| p.exists()).cloned());
+    let deps: Vec<_> = deps.into_iter().unique().collect();
+
+    let path = root_output.join(format!("cargo-c-{name}.d"));
+    ws.gctx()
+        .shell()
+        .verbose(|shell| shell.status("Building", format!("{}", path.display())))?;
+
+    let buf = format!(
+        "{}: {}\n",
+        targets.iter().map(|p| escape_dep_path(p)).join(" "),
+        deps.iter().map(|p| escape_dep_path(p)).join(" ")
+    );
+
+    write(path, buf)
+}
+
+/// Timestamp used for the generated files when building reproducibly
+///
+/// `SOURCE_DATE_EPOCH` takes precedence, `--reproducible` alone uses the unix epoch.
+fn reproducible_timestamp(args: &ArgMatches) -> anyhow::Result<Option<SystemTime>> {
+    if let Ok(epoch) = std::env::var("SOURCE_DATE_EPOCH") {
+        let secs = epoch.trim().parse::<u64>().with_context(|| {
+            tr(
+                "invalid-source-date-epoch",
+                &[("value", &format!("{epoch:?}"))],
+            )
+        })?;
+        return Ok(Some(UNIX_EPOCH + Duration::from_secs(secs)));
+    }
+
+    Ok(args.flag("reproducible").then_some(UNIX_EPOCH))
+}
+
+/// Strip the build-specific paths from the debug information and panic messages
+fn remap_path_prefix_args(ws: &Workspace) -> Vec<String> {
+    // rustc uses the last matching remapping, keep the most specific last.
+    vec![
+        format!(
+            "--remap-path-prefix={}=/cargo",
+            ws.gctx().home().as_path_unlocked().display()
+        ),
+        format!("--remap-path-prefix={}=.", ws.root().display()),
+    ]
+}
+
+/// Set the modification time of the files cargo-c generates to a fixed value
+fn normalize_timestamps<'a>(
+    ws: &Workspace,
+    paths: impl IntoIterator<Item = &'a Path>,
+    timestamp: SystemTime,
+) -> anyhow::Result<()> {
+    for path in paths.into_iter().filter(|p| p.exists()) {
+        ws.gctx().shell().verbose(|shell| {
+            shell.status("Normalizing", format!("timestamp of {}", path.display()))
+        })?;
+        std::fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .and_then(|f| f.set_modified(timestamp))
+            .with_context(|| tr("set-timestamp-failed", &[("path", &path.display())]))?;
+    }
+
+    Ok(())
+}
+
+/// Build the requested library types, the other crate types declared in the manifest,
+/// e.g. the `rlib` the other workspace members link, are kept
+///
+/// The linkable ones are built by a unit of their own, see `split_rlib_units`.
 fn patch_target(
+    gctx: &GlobalContext,
     pkg: &mut Package,
     library_types: LibraryTypes,
     capi_config: &CApiConfig,
 ) -> anyhow::Result<()> {
     use cargo::core::compiler::CrateType;
 
-    let manifest = pkg.manifest_mut();
-    let targets = manifest.targets_mut();
+    let declared: Vec<CrateType> = pkg
+        .manifest()
+        .original_toml()
+        .lib
+        .as_ref()
+        .and_then(|lib| lib.crate_types())
+        .into_iter()
+        .flatten()
+        .map(CrateType::from)
+        .collect();
 
-    let mut kinds = Vec::with_capacity(2);
+    let mut kinds = Vec::with_capacity(declared.len() + 2);
 
     if library_types.staticlib {
         kinds.push(CrateType::Staticlib);
@@ -121,6 +815,28 @@ fn patch_target(
         kinds.push(CrateType::Cdylib);
     }
 
+    // The dependents in the workspace link the rlib
+    if library_types.rlib && !declared.iter().any(|k| k.is_linkable()) {
+        kinds.push(CrateType::Lib);
+    }
+
+    for kind in declared {
+        if kinds.contains(&kind) {
+            continue;
+        }
+        if matches!(kind, CrateType::Staticlib | CrateType::Cdylib) {
+            gctx.shell().warn(tr(
+                "crate-type-not-built",
+                &[("package", &pkg.name()), ("kind", &kind)],
+            ))?;
+        } else {
+            kinds.push(kind);
+        }
+    }
+
+    let manifest = pkg.manifest_mut();
+    let targets = manifest.targets_mut();
+
     for target in targets.iter_mut().filter(|t| t.is_lib()) {
         target.set_kind(TargetKind::Lib(kinds.to_vec()));
         target.set_name(&capi_config.library.name);
@@ -129,86 +845,234 @@ fn patch_target(
     Ok(())
 }
 
-/// Build def file for windows-msvc
-fn build_def_file(
-    ws: &Workspace,
-    name: &str,
+/// The dlltool command for the target machine
+///
+/// `--dlltool` and `DLLTOOL` override the default program, the binutils one
+/// is prefixed with the mingw triple when cross compiling.
+fn dlltool_command(
     target: &target::Target,
-    targetdir: &Path,
-) -> anyhow::Result<()> {
-    if target.os == "windows" && target.env == "msvc" {
-        ws.gctx().shell().status("Building", ".def file")?;
+    tool: &str,
+    dlltool: Option<&Path>,
+) -> anyhow::Result<ProcessBuilder> {
+    let (machine, triple_arch) = match target.arch.as_str() {
+        "x86_64" => ("i386:x86-64", "x86_64"),
+        "x86" => ("i386", "i686"),
+        "aarch64" => ("arm64", "aarch64"),
+        arch => anyhow::bail!(tr("windows-arch-unsupported", &[("arch", &arch)])),
+    };
+    let program = dlltool
+        .map(|p| p.as_os_str().to_owned())
+        .or_else(|| std::env::var_os("DLLTOOL"))
+        .unwrap_or_else(|| {
+            if tool == "llvm-dlltool" {
+                "llvm-dlltool".into()
+            } else if cfg!(windows) {
+                "dlltool".into()
+            } else if target.is_cygwin() {
+                format!("{triple_arch}-pc-cygwin-dlltool").into()
+            } else {
+                format!("{triple_arch}-w64-mingw32-dlltool").into()
+            }
+        });
 
-        // Parse the .dll as an object file
-        let dll_path = targetdir.join(format!("{}.dll", name.replace('-', "_")));
-        let dll_content = std::fs::read(&dll_path)?;
-        let dll_file = object::File::parse(&*dll_content)?;
+    let mut cmd = ProcessBuilder::new(program);
+    cmd.arg("-m").arg(machine);
 
-        // Create the .def output file
-        let def_file = cargo_util::paths::create(targetdir.join(format!("{name}.def")))?;
+    Ok(cmd)
+}
 
-        write_def_file(name, dll_file, def_file)?;
-    }
+/// Sign the dylib at `path` with `identity`, `-` for an ad-hoc signature
+pub(crate) fn codesign(ws: &Workspace, identity: &str, path: &Path) -> anyhow::Result<()> {
+    ws.gctx()
+        .shell()
+        .status("Signing", format!("{}", path.display()))?;
+    let program = std::env::var_os("CODESIGN").unwrap_or_else(|| "codesign".into());
+    ProcessBuilder::new(program)
+        .args(&["--force", "--sign", identity])
+        .arg(path)
+        .exec_with_output()
+        .with_context(|| tr("sign-failed", &[("path", &path.display())]))?;
 
     Ok(())
 }
 
-fn write_def_file<W: std::io::Write>(
-    name: &str,
-    dll_file: object::File,
-    mut def_file: W,
-) -> anyhow::Result<W> {
-    use object::read::Object;
+/// Write the debug info of the ELF library `shared_lib` to `debug` and a copy stripped of it
+/// to `stripped`, linked with a `.gnu_debuglink` section, `OBJCOPY` overrides the program
+///
+/// The library cargo built is left as is.
+fn split_debug_info(
+    ws: &Workspace,
+    shared_lib: &Path,
+    stripped: &Path,
+    debug: &Path,
+) -> anyhow::Result<()> {
+    ws.gctx()
+        .shell()
+        .verbose(|s| s.status("Splitting", format!("{}", debug.display())))?;
+    let program = std::env::var_os("OBJCOPY").unwrap_or_else(|| "objcopy".into());
+    ProcessBuilder::new(&program)
+        .arg("--only-keep-debug")
+        .arg(shared_lib)
+        .arg(debug)
+        .exec_with_output()
+        .with_context(|| tr("write-failed", &[("path", &debug.display())]))?;
+    // The debug link has the name of the file, looked up next to the library or in the
+    // debug directories
+    let mut debuglink = std::ffi::OsString::from("--add-gnu-debuglink=");
+    debuglink.push(debug);
+    create_dir_all(stripped.parent().unwrap())?;
+    ProcessBuilder::new(&program)
+        .arg("--strip-debug")
+        .arg(debuglink)
+        .arg(shared_lib)
+        .arg(stripped)
+        .exec_with_output()
+        .with_context(|| tr("strip-failed", &[("path", &shared_lib.display())]))?;
+
+    Ok(())
+}
 
-    writeln!(def_file, "LIBRARY \"{name}.dll\"")?;
-    writeln!(def_file, "EXPORTS")?;
+/// Write the dSYM bundle of the dylib with `dsymutil`, `DSYMUTIL` overrides the program
+///
+/// The bundle is removed if the dylib has no debug info, a missing program is not an error,
+/// e.g. when cross compiling.
+fn build_dsym(ws: &Workspace, shared_lib: &Path, dsym: &Path) -> anyhow::Result<()> {
+    let program = std::env::var_os("DSYMUTIL").unwrap_or_else(|| "dsymutil".into());
+    let mut shell = ws.gctx().shell();
+    let out = match std::process::Command::new(&program)
+        .arg(shared_lib)
+        .arg("-o")
+        .arg(dsym)
+        .output()
+    {
+        Ok(out) => out,
+        Err(e) => {
+            return shell.verbose(|s| {
+                s.note(tr(
+                    "dsym-program-missing",
+                    &[("program", &program.to_string_lossy()), ("error", &e)],
+                ))
+            });
+        }
+    };
+    if !out.status.success() {
+        return shell.warn(format!(
+            "{}\n{}",
+            tr("dsym-failed", &[("path", &shared_lib.display())]),
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
 
-    for export in dll_file.exports()? {
-        def_file.write_all(export.name())?;
-        def_file.write_all(b"\n")?;
+    let dwarf = dsym.join("Contents/Resources/DWARF");
+    if std::fs::read_dir(&dwarf).map_or(true, |mut dir| dir.next().is_none()) && dsym.exists() {
+        cargo_util::paths::remove_dir_all(dsym)?;
     }
 
-    Ok(def_file)
+    Ok(())
 }
 
-/// Build import library for windows-gnu
+/// Build the import library of the dll
+///
+/// `tool` is `builtin`, using the implib crate, `dlltool` or `llvm-dlltool`.
 fn build_implib_file(
     ws: &Workspace,
     name: &str,
+    dll_name: &str,
     target: &target::Target,
     targetdir: &Path,
+    tool: &str,
+    dlltool: Option<&Path>,
 ) -> anyhow::Result<()> {
-    if target.os == "windows" {
-        ws.gctx().shell().status("Building", "implib")?;
+    if target.os == "windows" || target.os == "cygwin" {
+        progress::status(
+            ws,
+            Phase::ImportLibrary,
+            "Building",
+            tr("build-implib", &[]),
+        )?;
 
         let def_path = targetdir.join(format!("{name}.def"));
-        let def_contents = cargo_util::paths::read(&def_path)?;
 
         let flavor = match target.env.as_str() {
             "msvc" => Flavor::Msvc,
             _ => Flavor::Gnu,
         };
 
-        let machine_type = match target.arch.as_str() {
-            "x86_64" => MachineType::AMD64,
-            "x86" => MachineType::I386,
-            "aarch64" => MachineType::ARM64,
-            _ => {
-                return Err(anyhow::anyhow!(
-                    "Windows support for {} is not implemented yet.",
-                    target.arch
-                ))
-            }
-        };
-
         let lib_path = match flavor {
             Flavor::Msvc => targetdir.join(format!("{name}.dll.lib")),
             Flavor::Gnu => targetdir.join(format!("{name}.dll.a")),
         };
 
+        if tool != "builtin" {
+            if tool == "dlltool" && matches!(flavor, Flavor::Msvc) {
+                anyhow::bail!(tr("dlltool-msvc", &[]));
+            }
+            let mut cmd = dlltool_command(target, tool, dlltool)?;
+            cmd.arg("-d")
+                .arg(&def_path)
+                .arg("-D")
+                .arg(dll_name)
+                .arg("-l")
+                .arg(&lib_path);
+            cmd.exec_with_output()
+                .with_context(|| tr("implib-tool-failed", &[("tool", &tool)]))?;
+            return Ok(());
+        }
+
+        let def_contents = cargo_util::paths::read(&def_path)?;
+
         let lib_file = cargo_util::paths::create(lib_path)?;
-        write_implib(lib_file, machine_type, flavor, &def_contents)?;
+        write_implib(
+            lib_file,
+            machine_type(target)?,
+            flavor,
+            &def_contents,
+            dll_name,
+        )?;
+    }
+
+    Ok(())
+}
+
+fn machine_type(target: &target::Target) -> anyhow::Result<MachineType> {
+    match target.arch.as_str() {
+        "x86_64" => Ok(MachineType::AMD64),
+        "x86" => Ok(MachineType::I386),
+        "aarch64" => Ok(MachineType::ARM64),
+        _ => Err(anyhow::anyhow!(tr(
+            "windows-arch-unsupported",
+            &[("arch", &target.arch)]
+        ))),
     }
+}
+
+/// Build the MSVC import library of a dll built with the gnu toolchain, so the msvc
+/// consumers can link it as well
+fn build_msvc_implib_file(
+    ws: &Workspace,
+    build_targets: &BuildTargets,
+    dll_name: &str,
+    def_path: &Path,
+) -> anyhow::Result<()> {
+    let Some(msvc_impl_lib) = &build_targets.msvc_impl_lib else {
+        return Ok(());
+    };
+
+    progress::status(
+        ws,
+        Phase::ImportLibrary,
+        "Building",
+        format!("MSVC import library for {dll_name}"),
+    )?;
+    let def_contents = cargo_util::paths::read(def_path)?;
+    let lib_file = cargo_util::paths::create(msvc_impl_lib)?;
+    write_implib(
+        lib_file,
+        machine_type(&build_targets.target)?,
+        Flavor::Msvc,
+        &def_contents,
+        dll_name,
+    )?;
 
     Ok(())
 }
@@ -218,8 +1082,11 @@ fn write_implib<W: std::io::Write + std::io::Seek>(
     machine_type: MachineType,
     flavor: Flavor,
     def_contents: &str,
+    dll_name: &str,
 ) -> anyhow::Result<W> {
-    let module_def = ModuleDef::parse(def_contents, machine_type)?;
+    let mut module_def = ModuleDef::parse(def_contents, machine_type)?;
+    // The dll may be installed with a different name, e.g. with the Meson naming convention
+    module_def.import_name = dll_name.to_string();
     let import_library = ImportLibrary::from_def(module_def, machine_type, flavor);
 
     import_library.write_to(&mut w)?;
@@ -227,19 +1094,112 @@ fn write_implib<W: std::io::Write + std::io::Seek>(
     Ok(w)
 }
 
+/// Build the import library delay-loading the dll with dlltool, `-y` is a binutils extension
+///
+/// With msvc the regular import library is used with `/DELAYLOAD`.
+fn build_delay_implib_file(
+    ws: &Workspace,
+    build_targets: &BuildTargets,
+    dll_name: &str,
+    def_path: &Path,
+    dlltool: Option<&Path>,
+) -> anyhow::Result<()> {
+    let target = &build_targets.target;
+    if target.env == "msvc" {
+        return ws
+            .gctx()
+            .shell()
+            .note(tr("delay-load-msvc", &[("dll", &dll_name)]));
+    }
+    let Some(delay_impl_lib) = &build_targets.delay_impl_lib else {
+        return Ok(());
+    };
+
+    progress::status(
+        ws,
+        Phase::ImportLibrary,
+        "Building",
+        format!("delay-load import library for {dll_name}"),
+    )?;
+
+    let mut cmd = dlltool_command(target, "dlltool", dlltool)?;
+    cmd.arg("-d")
+        .arg(def_path)
+        .arg("-D")
+        .arg(dll_name)
+        .arg("-y")
+        .arg(delay_impl_lib);
+    cmd.exec_with_output()
+        .with_context(|| tr("delay-load-dlltool", &[]))?;
+
+    Ok(())
+}
+
+/// Feed the content of a file to the hasher in chunks, the libraries may be large
+fn hash_file(hasher: &mut impl std::hash::Hasher, path: &Path) -> std::io::Result<()> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut buf)? {
+            0 => return Ok(()),
+            n => hasher.write(&buf[..n]),
+        }
+    }
+}
+
+/// The hash of a file, the same across the releases and the platforms
+fn file_hash(path: &Path) -> Option<String> {
+    use std::hash::Hasher;
+
+    let mut hasher = StableHasher::new();
+    hash_file(&mut hasher, path).ok()?;
+    Some(hasher.finish().to_string())
+}
+
+/// The hash of a fingerprint from the hashes of its inputs, `None` if one is missing
+fn inputs_hash(inputs: &[(String, Option<String>)]) -> Option<String> {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = StableHasher::new();
+    for (name, hash) in inputs {
+        name.hash(&mut hasher);
+        hash.as_ref()?.hash(&mut hasher);
+    }
+    // the hash is stored in a toml file which does not support u64 so store
+    // it as a string to prevent overflows.
+    Some(hasher.finish().to_string())
+}
+
 #[derive(Debug)]
-struct FingerPrint {
+pub(crate) struct FingerPrint {
     id: PackageId,
-    root_output: PathBuf,
+    pub(crate) root_output: PathBuf,
     build_targets: BuildTargets,
     install_paths: InstallPaths,
     static_libs: String,
+    symbols: BTreeSet<String>,
+    /// The options the libraries are compiled with, see `build_key`
+    build_key: String,
+    /// The inputs of the cbindgen headers, see `header_inputs_hash`
+    header_hash: String,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
-struct Cache {
+pub(crate) struct Cache {
     hash: String,
     static_libs: String,
+    #[serde(default)]
+    pub(crate) build_key: String,
+    #[serde(default)]
+    header_hash: String,
+    #[serde(default)]
+    symbols: BTreeSet<String>,
+    /// The hash of each input, for `--explain-fingerprint`
+    #[serde(default)]
+    inputs: BTreeMap<String, String>,
+    /// The tools run to probe the build environment, reused with `--frozen`
+    #[serde(default)]
+    probes: probe::ProbeResults,
 }
 
 impl FingerPrint {
@@ -255,35 +1215,109 @@ impl FingerPrint {
             build_targets: build_targets.clone(),
             install_paths: install_paths.clone(),
             static_libs: String::new(),
+            symbols: BTreeSet::new(),
+            build_key: String::new(),
+            header_hash: String::new(),
+        }
+    }
+
+    /// The install paths the generated files depend on, the destdir is not one of them
+    fn install_paths(&self) -> InstallPaths {
+        InstallPaths {
+            destdir: None,
+            ..self.install_paths.clone()
         }
     }
 
-    fn hash(&self) -> anyhow::Result<Option<String>> {
-        use std::collections::hash_map::DefaultHasher;
+    /// The hash of the fingerprint, `None` if one of the files cannot be read
+    fn hash(&self) -> Option<String> {
+        inputs_hash(&self.inputs())
+    }
+
+    /// The hashed inputs and their own hash, `None` if the file cannot be read
+    fn inputs(&self) -> Vec<(String, Option<String>)> {
         use std::hash::{Hash, Hasher};
 
-        let mut hasher = DefaultHasher::new();
-        self.install_paths.hash(&mut hasher);
+        fn hash_of(value: impl Hash) -> String {
+            let mut hasher = StableHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish().to_string()
+        }
 
-        let mut paths: Vec<&PathBuf> = Vec::new();
-        if let Some(include) = &self.build_targets.include {
-            paths.push(include);
+        let mut inputs = vec![
+            (
+                "install-paths".to_string(),
+                Some(hash_of(self.install_paths())),
+            ),
+            // The names change with `--install-name-map`
+            (
+                format!("pc-path:{}", self.build_targets.pc.display()),
+                Some(hash_of(&self.build_targets.pc)),
+            ),
+        ];
+
+        let files = self
+            .build_targets
+            .include
+            .iter()
+            .chain(&self.build_targets.static_lib)
+            .chain(&self.build_targets.shared_lib);
+        for path in files {
+            inputs.push((format!("file:{}", path.display()), file_hash(path)));
         }
-        paths.extend(&self.build_targets.static_lib);
-        paths.extend(&self.build_targets.shared_lib);
 
-        for path in paths.iter() {
-            if let Ok(buf) = read_bytes(path) {
-                hasher.write(&buf);
-            } else {
-                return Ok(None);
-            };
+        inputs
+    }
+
+    /// The inputs compared with the previous build, as JSON
+    fn explain(&self, new_build: bool) -> anyhow::Result<serde_json::Value> {
+        let previous = self.load_previous().ok();
+        let previous_inputs = previous.as_ref().map(|p| &p.inputs);
+        // The caches of the previous versions do not record the inputs
+        let recorded = previous_inputs.is_some_and(|p| !p.is_empty());
+        let current = self.inputs();
+        let current_hash = inputs_hash(&current);
+
+        let mut inputs: Vec<_> = current
+            .iter()
+            .map(|(name, hash)| {
+                let previous = previous_inputs.and_then(|p| p.get(name));
+                let status = match (hash, previous) {
+                    (None, _) => "unreadable",
+                    (Some(_), None) if previous_inputs.is_some() && !recorded => "unrecorded",
+                    (Some(_), None) => "new",
+                    (Some(hash), Some(previous)) if hash == previous => "matched",
+                    (Some(_), Some(_)) => "changed",
+                };
+                serde_json::json!({
+                    "input": name,
+                    "hash": hash,
+                    "previous": previous,
+                    "status": status,
+                })
+            })
+            .collect();
+        for (name, hash) in previous_inputs.into_iter().flatten() {
+            if !current.iter().any(|(n, _)| n == name) {
+                inputs.push(serde_json::json!({
+                    "input": name,
+                    "hash": null,
+                    "previous": hash,
+                    "status": "removed",
+                }));
+            }
         }
 
-        let hash = hasher.finish();
-        // the hash is stored in a toml file which does not support u64 so store
-        // it as a string to prevent overflows.
-        Ok(Some(hash.to_string()))
+        Ok(serde_json::json!({
+            "package": self.id.to_string(),
+            "cache": self.path(),
+            "cache-found": previous.is_some(),
+            "hash": current_hash,
+            "previous-hash": previous.as_ref().map(|p| &p.hash),
+            "compiled": new_build,
+            "regenerate": new_build && !self.is_valid(),
+            "inputs": inputs,
+        }))
     }
 
     fn path(&self) -> PathBuf {
@@ -293,7 +1327,7 @@ impl FingerPrint {
             .join(format!("cargo-c-{}.cache", self.id.name()))
     }
 
-    fn load_previous(&self) -> anyhow::Result<Cache> {
+    pub(crate) fn load_previous(&self) -> anyhow::Result<Cache> {
         let mut f = open(self.path())?;
         let mut cache_str = String::new();
         f.read_to_string(&mut cache_str)?;
@@ -302,18 +1336,27 @@ impl FingerPrint {
         Ok(cache)
     }
 
-    fn is_valid(&self) -> bool {
+    pub(crate) fn is_valid(&self) -> bool {
         match (self.load_previous(), self.hash()) {
-            (Ok(prev), Ok(Some(current))) => prev.hash == current,
+            (Ok(prev), Some(current)) => prev.hash == current,
             _ => false,
         }
     }
 
     fn store(&self) -> anyhow::Result<()> {
-        if let Some(hash) = self.hash()? {
+        let inputs = self.inputs();
+        if let Some(hash) = inputs_hash(&inputs) {
             let cache = Cache {
                 hash,
                 static_libs: self.static_libs.to_owned(),
+                symbols: self.symbols.clone(),
+                build_key: self.build_key.clone(),
+                header_hash: self.header_hash.clone(),
+                inputs: inputs
+                    .into_iter()
+                    .filter_map(|(name, hash)| Some((name, hash?)))
+                    .collect(),
+                probes: probe::results(),
             };
             let buf = toml::ser::to_string(&cache)?;
             write(self.path(), buf)?;
@@ -321,6 +1364,29 @@ impl FingerPrint {
 
         Ok(())
     }
+
+    /// Record the probes in the previous cache, the artifacts are unchanged
+    fn store_probes(&self, probes: &probe::ProbeResults) -> anyhow::Result<()> {
+        if let Ok(mut cache) = self.load_previous() {
+            cache.probes.clone_from(probes);
+            write(self.path(), toml::ser::to_string(&cache)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The probes recorded in the fingerprints of the workspace members built in `root_output`
+fn recorded_probes(ws: &Workspace, root_output: &Path) -> probe::ProbeResults {
+    let mut probes = probe::ProbeResults::new();
+    for pkg in ws.members() {
+        let path = root_output.join(format!("cargo-c-{}.cache", pkg.name()));
+        let cache = read(&path)
+            .ok()
+            .and_then(|cache| toml::from_str::<Cache>(&cache).ok());
+        probes.extend(cache.into_iter().flat_map(|cache| cache.probes));
+    }
+    probes
 }
 
 #[derive(Debug)]
@@ -329,6 +1395,67 @@ pub struct CApiConfig {
     pub pkg_config: PkgConfigCApiConfig,
     pub library: LibraryCApiConfig,
     pub install: InstallCApiConfig,
+    pub csources: CSourcesCApiConfig,
+    pub test: TestCApiConfig,
+}
+
+/// How the C tests are compiled, to pin the compiler behavior the C-API targets
+#[derive(Debug, Default, Clone)]
+pub struct TestCApiConfig {
+    pub cflags: Vec<String>,
+    /// The C standard, e.g. `c11`
+    pub std: Option<String>,
+    /// The C compiler, e.g. `clang`
+    pub cc: Option<String>,
+}
+
+impl TestCApiConfig {
+    /// The configuration overridden by the `--test-*` options
+    pub fn with_args(&self, args: &ArgMatches) -> Self {
+        let mut test = self.clone();
+        if let Some(cc) = args.get_one::<String>("test-cc") {
+            test.cc = Some(cc.clone());
+        }
+        if let Some(std) = args.get_one::<String>("test-std") {
+            test.std = Some(std.clone());
+        }
+        test.cflags.extend(
+            args.get_many::<String>("test-cflags")
+                .into_iter()
+                .flatten()
+                .flat_map(|flags| flags.split_whitespace())
+                .map(String::from),
+        );
+        test
+    }
+
+    /// The flags for the compiler, including the one selecting the standard in the option
+    /// syntax of its flavor, `msvc` for cl and clang-cl
+    pub fn flags(&self, msvc: bool) -> Vec<String> {
+        let mut flags = self.cflags.clone();
+        flags.extend(self.std_flag(msvc));
+        flags
+    }
+
+    /// The option selecting the standard, if any
+    pub fn std_flag(&self, msvc: bool) -> Option<String> {
+        let std = self.std.as_ref()?;
+        Some(if msvc {
+            format!("/std:{std}")
+        } else {
+            format!("-std={std}")
+        })
+    }
+}
+
+/// C sources compiled and linked into the library
+#[derive(Debug, Default)]
+pub struct CSourcesCApiConfig {
+    pub files: Vec<String>,
+    pub include_dirs: Vec<String>,
+    /// Installed along with the generated header
+    pub headers: Vec<String>,
+    pub flags: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -337,6 +1464,160 @@ pub struct HeaderCApiConfig {
     pub subdirectory: String,
     pub generation: bool,
     pub enabled: bool,
+    pub generator: HeaderGenerator,
+    /// The cbindgen configuration, relative to the package root
+    ///
+    /// If unset `cbindgen.toml` is used if present.
+    pub cbindgen_config: Option<PathBuf>,
+    pub filter: HeaderFilter,
+    /// The additional headers generated from the same crate
+    pub headers: Vec<ExtraHeader>,
+    /// Also generate a C++ header, in the given namespace if not empty
+    pub cpp: Option<String>,
+    /// Generate a header including all the others, relative to the header subdirectory
+    pub umbrella: Option<PathBuf>,
+    /// The include guard every installed header must use
+    pub include_guard_style: Option<IncludeGuardStyle>,
+    /// How the types coming from the dependency crates are declared, by crate name
+    pub dependencies: BTreeMap<String, DependencyHeader>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncludeGuardStyle {
+    PragmaOnce,
+    Guard,
+}
+
+impl IncludeGuardStyle {
+    fn from_value(value: &toml::Value) -> anyhow::Result<Self> {
+        match value.as_str() {
+            Some("pragma_once") => Ok(IncludeGuardStyle::PragmaOnce),
+            Some("guard") => Ok(IncludeGuardStyle::Guard),
+            _ => anyhow::bail!(
+                "Value for `include_guard_style` is not \"pragma_once\" or \"guard\": {value:?}"
+            ),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            IncludeGuardStyle::PragmaOnce => "pragma_once",
+            IncludeGuardStyle::Guard => "guard",
+        }
+    }
+}
+
+impl HeaderCApiConfig {
+    /// The additional header declaring the function `name`, the first one matching it
+    fn extra_header_of(&self, name: &str) -> Option<usize> {
+        self.headers
+            .iter()
+            .position(|h| h.functions.iter().any(|pattern| pattern.matches(name)))
+    }
+
+    pub fn cpp_file_name(&self) -> PathBuf {
+        let mut name = PathBuf::from(&self.name);
+        name.set_extension("hpp");
+        name
+    }
+
+    /// The headers cbindgen generates: the main one, the additional ones, the C++ one and the
+    /// ones of the dependencies
+    pub(crate) fn generated_names(&self) -> Vec<PathBuf> {
+        let mut main = PathBuf::from(&self.name);
+        main.set_extension("h");
+        let dependency_names = self
+            .dependencies
+            .iter()
+            .filter(|(_, d)| **d == DependencyHeader::Header)
+            .map(|(krate, _)| DependencyHeader::file_name(krate));
+        std::iter::once(main)
+            .chain(self.headers.iter().map(|h| h.file_name()))
+            .chain(self.cpp.as_ref().map(|_| self.cpp_file_name()))
+            .chain(dependency_names)
+            .collect()
+    }
+}
+
+/// The cbindgen settings selecting what goes in a header
+#[derive(Debug, Default, Clone)]
+pub struct HeaderFilter {
+    /// The items to always export
+    pub include: Vec<String>,
+    /// The items to leave out
+    pub exclude: Vec<String>,
+    /// The headers to `#include`, e.g. the other headers of the crate
+    pub includes: Vec<String>,
+}
+
+/// The list of strings of `key` in the `value` table, empty if unset
+fn string_list(value: &toml::Value, key: &str) -> anyhow::Result<Vec<String>> {
+    value
+        .get(key)
+        .map(|v| v.clone().try_into::<Vec<String>>())
+        .transpose()
+        .with_context(|| format!("Value for `{key}` is not a list of strings"))
+        .map(Option::unwrap_or_default)
+}
+
+impl HeaderFilter {
+    fn from_value(value: &toml::Value) -> anyhow::Result<Self> {
+        Ok(HeaderFilter {
+            include: string_list(value, "include")?,
+            exclude: string_list(value, "exclude")?,
+            includes: string_list(value, "includes")?,
+        })
+    }
+
+    fn apply(&self, config: &mut cbindgen::Config) {
+        config.export.include.extend(self.include.iter().cloned());
+        config.export.exclude.extend(self.exclude.iter().cloned());
+        config.includes.extend(self.includes.iter().cloned());
+    }
+}
+
+#[derive(Debug)]
+pub struct ExtraHeader {
+    /// The header path relative to the header subdirectory, without extension
+    pub name: String,
+    /// The functions declared in this header instead of the main one
+    pub functions: Vec<glob::Pattern>,
+    /// The headers to `#include` besides the main one
+    pub includes: Vec<String>,
+}
+
+impl ExtraHeader {
+    pub(crate) fn file_name(&self) -> PathBuf {
+        let mut name = PathBuf::from(&self.name);
+        name.set_extension("h");
+        name
+    }
+}
+
+/// Where the types of a dependency crate used by the C-API are declared
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyHeader {
+    /// In the headers using them
+    Inline,
+    /// In `deps/{crate}.h`, included by the C headers using them
+    Header,
+}
+
+impl DependencyHeader {
+    fn from_value(value: &toml::Value) -> anyhow::Result<Self> {
+        match value.as_str() {
+            Some("inline") => Ok(DependencyHeader::Inline),
+            Some("header") => Ok(DependencyHeader::Header),
+            _ => anyhow::bail!(
+                "Value for `header.dependencies` is not \"inline\" or \"header\": {value:?}"
+            ),
+        }
+    }
+
+    /// The header of the dependency, relative to the header subdirectory
+    pub(crate) fn file_name(krate: &str) -> PathBuf {
+        Path::new("deps").join(krate).with_extension("h")
+    }
 }
 
 #[derive(Debug)]
@@ -347,7 +1628,42 @@ pub struct PkgConfigCApiConfig {
     pub version: String,
     pub requires: Option<String>,
     pub requires_private: Option<String>,
+    pub cflags_private: Vec<String>,
     pub strip_include_path_components: usize,
+    /// Additional `Libs` items, e.g. `-framework CoreFoundation`
+    pub link: Vec<String>,
+    /// Generate a `{filename}-config` script for the consumers without pkg-config
+    pub config_script: bool,
+    /// Generate the `{filename}-system-deps.toml` snippet for the Rust consumers
+    pub system_deps: bool,
+    /// Install the `.pc` in `{datarootdir}/pkgconfig`, e.g. for header-only or data-only packages
+    pub noarch: bool,
+    /// Whether the requirements of a workspace dependency are merged with
+    /// `--include-dependencies-pc`, by package name, they are by default
+    pub include_dependencies: BTreeMap<String, bool>,
+    /// Name the library file in `Libs`, e.g. `-l:libfoo.so.1`
+    pub link_filename: Option<LinkFilename>,
+}
+
+/// The library `Libs` names with the `-l:` exact filename syntax of the ELF linkers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkFilename {
+    /// The soname of the shared library, `libfoo.so.1`
+    Shared,
+    /// The static library, `libfoo.a`
+    Static,
+}
+
+impl LinkFilename {
+    fn from_value(value: &toml::Value) -> anyhow::Result<Self> {
+        match value.as_str() {
+            Some("shared") => Ok(LinkFilename::Shared),
+            Some("static") => Ok(LinkFilename::Static),
+            _ => anyhow::bail!(
+                "Value for `link_filename` is not \"shared\" or \"static\": {value:?}"
+            ),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -357,15 +1673,233 @@ pub enum VersionSuffix {
     MajorMinorPatch,
 }
 
+/// What becomes of the debug info of the shared libraries, `--debuginfo`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DebugInfoPolicy {
+    /// Not generated, the libraries are built without it
+    None,
+    /// The PDB and the dSYM bundle are installed next to the library
+    #[default]
+    Keep,
+    /// Split from the ELF libraries as well, installed in the `--debugdir`
+    Separate,
+}
+
+impl DebugInfoPolicy {
+    pub fn from_args(args: &ArgMatches) -> Self {
+        match args.try_get_one::<String>("debuginfo").ok().flatten() {
+            Some(policy) if policy == "none" => DebugInfoPolicy::None,
+            Some(policy) if policy == "separate" => DebugInfoPolicy::Separate,
+            _ => DebugInfoPolicy::Keep,
+        }
+    }
+
+    /// The rustc arguments of the libraries, `none` drops the debug info the profile sets
+    ///
+    /// As cargo does for `debug = false`, the one of the standard library is stripped too.
+    /// `-Csplit-debuginfo=off` is stable only outside of Windows.
+    fn rustc_args(self, target: &target::Target) -> Vec<String> {
+        let mut args = Vec::new();
+        if self == DebugInfoPolicy::None {
+            args.extend(["-C", "debuginfo=0", "-C", "strip=debuginfo"].map(String::from));
+            if target.os != "windows" {
+                args.extend(["-C".into(), "split-debuginfo=off".into()]);
+            }
+        }
+        args
+    }
+}
+
+/// The directory recorded in the Apple dylib install_name
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum InstallName {
+    /// The absolute `libdir`
+    #[default]
+    Absolute,
+    /// `@rpath`, for relocatable bundles and frameworks
+    Rpath,
+    /// A custom directory, e.g. `@loader_path/../lib`
+    Custom(String),
+}
+
+impl InstallName {
+    fn from_str(value: &str) -> Self {
+        match value {
+            "absolute" => InstallName::Absolute,
+            "rpath" => InstallName::Rpath,
+            custom => InstallName::Custom(custom.trim_end_matches('/').to_string()),
+        }
+    }
+
+    /// The directory the dynamic linker looks up the library from
+    pub fn dir(&self, libdir: &Path) -> String {
+        match self {
+            InstallName::Absolute => libdir.display().to_string(),
+            InstallName::Rpath => "@rpath".to_string(),
+            InstallName::Custom(dir) => dir.clone(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct LibraryCApiConfig {
     pub name: String,
     pub version: Version,
     pub install_subdir: Option<String>,
+    /// Add an rpath from the `install_subdir` to the `libdir`, for the plugins
+    pub origin_rpath: bool,
+    /// The other rpaths of the shared library, e.g. `$ORIGIN/../lib`
+    pub rpath: Vec<String>,
     pub versioning: bool,
     pub version_suffix_components: Option<VersionSuffix>,
     pub import_library: bool,
+    /// Also build an import library delay-loading the dll, with the gnu toolchain
+    pub delay_load_import_library: bool,
+    /// Also build an MSVC import library of the dll, with the gnu toolchain
+    pub msvc_import_library: bool,
     pub rustflags: Vec<String>,
+    /// Apple `-current_version`, defaults to the library version
+    pub current_version: Option<String>,
+    /// Apple `-compatibility_version`, defaults to the sover
+    pub compatibility_version: Option<String>,
+    pub install_name: InstallName,
+    pub def: DefCApiConfig,
+    /// Generate a `{name}.props` MSBuild property sheet for the msvc targets
+    pub msbuild_props: bool,
+    /// Link the C runtime statically, as `--crt-static` does
+    pub crt_static: bool,
+    /// The library types built without `--library-type`, e.g. `cdylib` on musl
+    pub default_library_types: Option<Vec<String>>,
+    /// The LTO of the libraries, overriding the one of the profile
+    pub lto: Option<Lto>,
+    /// How to report the symbols only one of the libraries provides, e.g. `off`
+    pub symbols_check: Option<String>,
+}
+
+/// The `lto` of the libraries, the dependencies provide the bitcode it requires
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lto {
+    Thin,
+    Fat,
+    Off,
+}
+
+impl Lto {
+    fn from_value(value: &toml::Value) -> anyhow::Result<Self> {
+        match value.as_str() {
+            Some("thin") => Ok(Lto::Thin),
+            Some("fat") => Ok(Lto::Fat),
+            Some("off") => Ok(Lto::Off),
+            _ => anyhow::bail!(
+                "Value for `lto` is not one of \"thin\", \"fat\" or \"off\": {value:?}"
+            ),
+        }
+    }
+
+    /// The rustc arguments of the library, after the ones cargo derives from the profile
+    fn library_args(self) -> Vec<String> {
+        let lto = match self {
+            Lto::Thin => "lto=thin",
+            Lto::Fat => "lto=fat",
+            Lto::Off => "lto=off",
+        };
+        let mut args = vec!["-C".to_string(), lto.to_string()];
+        if self != Lto::Off {
+            args.extend(["-C".to_string(), "embed-bitcode=yes".to_string()]);
+        }
+        args
+    }
+
+    /// The rustc arguments of the dependencies of the library
+    ///
+    /// Without the profile LTO cargo does not embed the bitcode, with it cargo emits only the
+    /// bitcode.
+    fn dependency_args(self) -> Vec<String> {
+        let arg = match self {
+            Lto::Thin | Lto::Fat => "embed-bitcode=yes",
+            Lto::Off => "linker-plugin-lto=no",
+        };
+        vec!["-C".to_string(), arg.to_string()]
+    }
+}
+
+/// How the `.def` file lists the dll exports, see [`crate::def`]
+#[derive(Debug, Default, Clone)]
+pub struct DefCApiConfig {
+    /// The exports left out
+    pub exclude: Vec<glob::Pattern>,
+    /// The fixed ordinals, by symbol or alias
+    pub ordinals: BTreeMap<String, u16>,
+    /// The additional exports, `alias=symbol`
+    pub aliases: BTreeMap<String, String>,
+}
+
+impl DefCApiConfig {
+    fn from_value(value: &toml::Value) -> anyhow::Result<Self> {
+        let exclude = value
+            .get("exclude")
+            .map(|v| v.clone().try_into::<Vec<String>>())
+            .transpose()
+            .context("Value for `def.exclude` is not a list of strings")?
+            .unwrap_or_default()
+            .iter()
+            .map(|p| {
+                glob::Pattern::new(p).with_context(|| format!("Invalid `def.exclude` pattern {p}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let ordinals = value
+            .get("ordinals")
+            .map(|v| v.clone().try_into())
+            .transpose()
+            .context("Value for `def.ordinals` is not a table of ordinals")?
+            .unwrap_or_default();
+        let aliases = value
+            .get("aliases")
+            .map(|v| v.clone().try_into())
+            .transpose()
+            .context("Value for `def.aliases` is not a table of strings")?
+            .unwrap_or_default();
+
+        Ok(DefCApiConfig {
+            exclude,
+            ordinals,
+            aliases,
+        })
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.exclude.is_empty() && self.ordinals.is_empty() && self.aliases.is_empty()
+    }
+
+    /// The ` @{ordinal}` suffix of the export, if any
+    pub(crate) fn ordinal(&self, symbol: &str) -> String {
+        self.ordinals
+            .get(symbol)
+            .map(|o| format!(" @{o}"))
+            .unwrap_or_default()
+    }
+}
+
+/// Validate a Mach-O dylib version, `X[.Y[.Z]]` with X up to 65535 and Y, Z up to 255
+fn parse_apple_version(key: &str, value: &toml::Value) -> anyhow::Result<String> {
+    let version = value
+        .as_str()
+        .with_context(|| format!("Value for `{key}` is not a string: {value:?}"))?;
+
+    let components: Vec<_> = version.split('.').collect();
+    let valid = components.len() <= 3
+        && components.iter().enumerate().all(|(i, c)| {
+            let max = if i == 0 { 65535 } else { 255 };
+            c.parse::<u32>().is_ok_and(|v| v <= max)
+        });
+
+    if !valid {
+        anyhow::bail!(
+            "Invalid `{key}` {version:?}, expected X[.Y[.Z]] with X <= 65535 and Y, Z <= 255"
+        );
+    }
+
+    Ok(version.to_string())
 }
 
 impl LibraryCApiConfig {
@@ -385,12 +1919,78 @@ impl LibraryCApiConfig {
             Some(VersionSuffix::MajorMinorPatch) => format!("{major}.{minor}.{patch}"),
         }
     }
+
+    /// Why the soname does not identify the ABI, if it does not
+    pub fn unversioned_soname(&self) -> Option<String> {
+        if !self.versioning {
+            return Some(
+                "`versioning` is false, set it to true or remove it from `package.metadata.capi.library`"
+                    .to_string(),
+            );
+        }
+        // The components up to the first non-zero one are breaking, as for cargo
+        let version = &self.version;
+        let breaking = match (version.major, version.minor, version.patch) {
+            (0, 0, patch) if patch != 0 => 3,
+            (0, minor, _) if minor != 0 => 2,
+            _ => 1,
+        };
+        let sover = self.sover();
+        if sover.split('.').count() < breaking {
+            return Some(format!(
+                "the soname version `{sover}` is shared by releases incompatible with {version}, set `version_suffix_components` to {breaking} or remove it from `package.metadata.capi.library`"
+            ));
+        }
+        None
+    }
+
+    /// The rpath from the `install_subdir` to the `libdir`, with `origin_rpath`
+    ///
+    /// `$ORIGIN` on ELF targets, `@loader_path` on Apple ones.
+    pub fn origin_rpath(&self, apple: bool) -> Option<String> {
+        if !self.origin_rpath {
+            return None;
+        }
+        let subdir = Path::new(self.install_subdir.as_ref()?);
+        let origin = if apple { "@loader_path" } else { "$ORIGIN" };
+        let depth = subdir
+            .components()
+            .filter(|c| matches!(c, std::path::Component::Normal(_)))
+            .count();
+
+        Some(format!("{origin}{}", "/..".repeat(depth)))
+    }
+
+    /// The rpaths of the shared library, the `origin_rpath` one first
+    pub fn rpaths(&self, apple: bool) -> Vec<String> {
+        self.origin_rpath(apple)
+            .into_iter()
+            .chain(self.rpath.iter().cloned())
+            .collect()
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct InstallCApiConfig {
     pub include: Vec<InstallTarget>,
     pub data: Vec<InstallTarget>,
+    pub data_subdirectory: String,
+    /// The `{lang}.mo` message catalogs
+    pub locale: Vec<InstallTarget>,
+    /// The gettext domain, the name of the installed catalogs
+    pub locale_domain: String,
+    /// The binaries to build and install in the bindir, empty for all of them
+    pub bins: Option<Vec<String>>,
+    /// The man pages, installed in `{mandir}/man{section}`
+    pub man: Vec<InstallTarget>,
+    /// Compress the man pages with gzip
+    pub man_gzip: bool,
+    /// The documentation, e.g. the README and the license, installed in the docdir
+    pub doc: Vec<InstallTarget>,
+    /// The executables, e.g. helper scripts, installed in the bindir
+    pub bin: Vec<InstallTarget>,
+    /// The executables run by the library, not by the user, installed in the libexecdir
+    pub libexec: Vec<InstallTarget>,
 }
 
 #[derive(Debug)]
@@ -409,6 +2009,11 @@ pub struct InstallTargetPaths {
     /// The path to be joined to the canonical directory to install the files discovered by the
     /// glob, e.g. `{includedir}/{to}` for includes.
     pub to: String,
+    /// The dependency whose build script OUT_DIR is the root of a Generated target
+    pub dependency: Option<String>,
+    /// The metadata key declaring the target, e.g. `install.include.asset`, `None` for the
+    /// default ones
+    pub declared_in: Option<String>,
 }
 
 impl InstallTargetPaths {
@@ -422,9 +2027,16 @@ impl InstallTargetPaths {
             .and_then(|v| v.as_str())
             .unwrap_or(default_to);
 
+        let dependency = value
+            .get("dependency")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+
         Ok(InstallTargetPaths {
             from: from.to_string(),
             to: to.to_string(),
+            dependency,
+            declared_in: None,
         })
     }
 
@@ -457,21 +2069,202 @@ impl InstallTargetPaths {
             }
         });
 
-        Ok(g)
+        Ok(g)
+    }
+}
+
+/// The `asset` and `generated` targets of the `install.{table}` metadata
+fn custom_install_target_paths(
+    root: &toml::Value,
+    table: &str,
+    subdirectory: &str,
+    targets: &mut Vec<InstallTarget>,
+) -> anyhow::Result<()> {
+    if let Some(assets) = root.get("asset").and_then(|v| v.as_array()) {
+        for asset in assets {
+            let mut target_paths = InstallTargetPaths::from_value(asset, subdirectory)?;
+            target_paths.declared_in = Some(format!("install.{table}.asset"));
+            targets.push(InstallTarget::Asset(target_paths));
+        }
+    }
+
+    if let Some(generated) = root.get("generated").and_then(|v| v.as_array()) {
+        for gen in generated {
+            let mut target_paths = InstallTargetPaths::from_value(gen, subdirectory)?;
+            target_paths.declared_in = Some(format!("install.{table}.generated"));
+            targets.push(InstallTarget::Generated(target_paths));
+        }
+    }
+
+    Ok(())
+}
+
+/// The targets of the `install.{table}` metadata, with `defaults = true` the `asset` and
+/// `generated` default ones as well
+fn install_category_targets(
+    install: Option<&toml::Value>,
+    table: &str,
+    defaults: [&str; 2],
+) -> anyhow::Result<Vec<InstallTarget>> {
+    let mut targets = Vec::new();
+    let Some(value) = install.and_then(|v| v.get(table)) else {
+        return Ok(targets);
+    };
+    custom_install_target_paths(value, table, "", &mut targets)?;
+
+    if value
+        .get("defaults")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+    {
+        let [asset, generated] = defaults.map(|from| InstallTargetPaths {
+            from: from.to_string(),
+            to: String::new(),
+            dependency: None,
+            declared_in: None,
+        });
+        targets.extend([
+            InstallTarget::Asset(asset),
+            InstallTarget::Generated(generated),
+        ]);
+    }
+
+    Ok(targets)
+}
+
+/// The `cbindgen_config` of the header section
+fn cbindgen_config(header: Option<&toml::Value>) -> anyhow::Result<Option<PathBuf>> {
+    let Some(header) = header else {
+        return Ok(None);
+    };
+
+    header
+        .get("cbindgen_config")
+        .map(|v| {
+            v.as_str()
+                .map(PathBuf::from)
+                .with_context(|| format!("Value for `cbindgen_config` is not a string: {v:?}"))
+        })
+        .transpose()
+}
+
+/// Split the linker arguments in items, keeping `-framework X` and `-F dir` together
+fn link_items<'a>(args: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut tokens = args.flat_map(str::split_whitespace);
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "-framework" | "-F" => {
+                if let Some(value) = tokens.next() {
+                    let sep = if token == "-F" { "" } else { " " };
+                    items.push(format!("{token}{sep}{value}"));
+                }
+            }
+            _ => items.push(token.to_string()),
+        }
+    }
+
+    items
+}
+
+/// The `link` items of the pkg_config section, added to the `Libs`
+///
+/// The frameworks and the framework search paths are kept only for the Apple targets.
+fn pkg_config_link(pc: &toml::Value, rustc_target: &target::Target) -> anyhow::Result<Vec<String>> {
+    let Some(link) = pc.get("link") else {
+        return Ok(Vec::new());
+    };
+
+    let args = link
+        .as_array()
+        .and_then(|a| a.iter().map(|v| v.as_str()).collect::<Option<Vec<_>>>())
+        .with_context(|| format!("Value for `link` is not a list of strings: {link:?}"))?;
+
+    Ok(link_items(args.into_iter())
+        .into_iter()
+        .filter(|item| {
+            rustc_target.is_apple() || !(item.starts_with("-framework") || item.starts_with("-F"))
+        })
+        .collect())
+}
+
+/// Parse the `--install-name-map` values, `FROM=TO` pairs or files with a pair per line
+fn install_name_map(args: &ArgMatches) -> anyhow::Result<HashMap<String, String>> {
+    let mut map = HashMap::new();
+
+    for value in args
+        .get_many::<String>("install_name_map")
+        .into_iter()
+        .flatten()
+    {
+        let lines = if value.contains('=') {
+            value.clone()
+        } else {
+            std::fs::read_to_string(value)
+                .with_context(|| format!("Cannot read the install name map {value}"))?
+        };
+        for line in lines.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((from, to)) = line.split_once('=') else {
+                anyhow::bail!("Invalid install name map entry `{line}`, expected `FROM=TO`");
+            };
+            map.insert(from.trim().to_string(), to.trim().to_string());
+        }
+    }
+
+    Ok(map)
+}
+
+/// Rename the library and the `.pc` as requested by `--install-name-map`
+///
+/// The soname and the `Libs` follow the library name.
+fn apply_install_name_map(capi_config: &mut CApiConfig, args: &ArgMatches) -> anyhow::Result<()> {
+    let map = install_name_map(args)?;
+
+    if let Some(name) = map.get(&capi_config.library.name) {
+        capi_config.library.name.clone_from(name);
+    }
+    if let Some(filename) = map.get(&capi_config.pkg_config.filename) {
+        capi_config.pkg_config.filename.clone_from(filename);
+    }
+
+    Ok(())
+}
+
+/// `--rpath` adds to the rpaths of the metadata, `--no-rpath` drops all of them
+fn apply_rpath_args(capi_config: &mut CApiConfig, args: &ArgMatches) {
+    let library = &mut capi_config.library;
+    if args.flag("no_rpath") {
+        library.origin_rpath = false;
+        library.rpath.clear();
+    }
+    library.rpath.extend(
+        args.get_many::<String>("rpath")
+            .into_iter()
+            .flatten()
+            .cloned(),
+    );
+}
+
+/// `--install-name` replaces the `install_name` of the metadata
+fn apply_install_name_args(capi_config: &mut CApiConfig, args: &ArgMatches) {
+    if let Some(install_name) = args.get_one::<String>("install_name") {
+        capi_config.library.install_name = InstallName::from_str(install_name);
     }
 }
 
 fn load_manifest_capi_config(
     pkg: &Package,
     rustc_target: &target::Target,
+    profile: &str,
 ) -> anyhow::Result<CApiConfig> {
+    // The packages without a library are named after the package, as cargo names the crates
     let name = &pkg
-        .manifest()
-        .targets()
-        .iter()
-        .find(|t| t.is_lib())
-        .unwrap()
-        .crate_name();
+        .library()
+        .map_or_else(|| pkg.name().replace('-', "_"), |lib| lib.crate_name());
     let root_path = pkg.root().to_path_buf();
     let manifest_str = read(&root_path.join("Cargo.toml"))?;
     let toml = manifest_str.parse::<toml::Value>()?;
@@ -479,7 +2272,10 @@ fn load_manifest_capi_config(
     let capi = toml
         .get("package")
         .and_then(|v| v.get("metadata"))
-        .and_then(|v| v.get("capi"));
+        .and_then(|v| v.get("capi"))
+        .map(|capi| effective_capi_config(capi, rustc_target, profile))
+        .transpose()?;
+    let capi = capi.as_ref();
 
     if let Some(min_version) = capi
         .as_ref()
@@ -534,6 +2330,69 @@ fn load_manifest_capi_config(
                 .and_then(|h| h.get("enabled"))
                 .map(|v| v.clone().try_into())
                 .unwrap_or(Ok(true))?,
+            generator: HeaderGenerator::from_value(header)?,
+            cbindgen_config: cbindgen_config(header)?,
+            filter: header
+                .map(HeaderFilter::from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            headers: header
+                .and_then(|h| h.get("headers"))
+                .and_then(|v| v.as_array())
+                .into_iter()
+                .flatten()
+                .map(|v| {
+                    let name = v
+                        .get("name")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow::anyhow!("a name field is required"))?;
+                    let functions = string_list(v, "functions")?
+                        .iter()
+                        .map(|pattern| glob::Pattern::new(pattern))
+                        .collect::<Result<_, _>>()
+                        .context("Value for `functions` is not a list of patterns")?;
+                    Ok(ExtraHeader {
+                        name: name.trim_end_matches(".h").to_string(),
+                        functions,
+                        includes: string_list(v, "includes")?,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+            cpp: header
+                .and_then(|h| h.get("cpp"))
+                .map(|v| match v {
+                    toml::Value::Boolean(true) => Ok(Some(String::new())),
+                    toml::Value::Boolean(false) => Ok(None),
+                    toml::Value::String(namespace) => Ok(Some(namespace.clone())),
+                    _ => Err(anyhow::anyhow!(
+                        "Value for `cpp` is not a boolean or a namespace: {v:?}"
+                    )),
+                })
+                .transpose()?
+                .flatten(),
+            umbrella: header
+                .and_then(|h| h.get("umbrella"))
+                .map(|v| match v {
+                    toml::Value::Boolean(true) => Ok(Some(PathBuf::from("umbrella.h"))),
+                    toml::Value::Boolean(false) => Ok(None),
+                    toml::Value::String(name) => Ok(Some(PathBuf::from(name))),
+                    _ => Err(anyhow::anyhow!(
+                        "Value for `umbrella` is not a boolean or a file name: {v:?}"
+                    )),
+                })
+                .transpose()?
+                .flatten(),
+            include_guard_style: header
+                .and_then(|h| h.get("include_guard_style"))
+                .map(IncludeGuardStyle::from_value)
+                .transpose()?,
+            dependencies: header
+                .and_then(|h| h.get("dependencies"))
+                .and_then(|v| v.as_table())
+                .into_iter()
+                .flatten()
+                .map(|(krate, v)| Ok((krate.clone(), DependencyHeader::from_value(v)?)))
+                .collect::<anyhow::Result<_>>()?,
         }
     } else {
         HeaderCApiConfig {
@@ -541,6 +2400,14 @@ fn load_manifest_capi_config(
             subdirectory: String::from(name),
             generation: true,
             enabled: true,
+            generator: HeaderGenerator::Cbindgen,
+            cbindgen_config: None,
+            filter: HeaderFilter::default(),
+            headers: Vec::new(),
+            cpp: None,
+            umbrella: None,
+            include_guard_style: None,
+            dependencies: BTreeMap::new(),
         }
     };
 
@@ -557,7 +2424,14 @@ fn load_manifest_capi_config(
     let mut version = pkg.version().to_string();
     let mut requires = None;
     let mut requires_private = None;
+    let mut cflags_private = Vec::new();
     let mut strip_include_path_components = 0;
+    let mut link = Vec::new();
+    let mut config_script = false;
+    let mut system_deps = false;
+    let mut noarch = false;
+    let mut include_dependencies = BTreeMap::new();
+    let mut link_filename = None;
 
     if let Some(pc) = pc {
         if let Some(override_name) = pc.get("name").and_then(|v| v.as_str()) {
@@ -578,10 +2452,36 @@ fn load_manifest_capi_config(
         if let Some(req) = pc.get("requires_private").and_then(|v| v.as_str()) {
             requires_private = Some(String::from(req));
         }
+        if let Some(flags) = pc.get("cflags_private").and_then(|v| v.as_str()) {
+            cflags_private.extend(flags.split_whitespace().map(str::to_string));
+        }
         strip_include_path_components = pc
             .get("strip_include_path_components")
             .map(|v| v.clone().try_into())
-            .unwrap_or_else(|| Ok(0))?
+            .unwrap_or_else(|| Ok(0))?;
+        link = pkg_config_link(pc, rustc_target)?;
+        config_script = pc
+            .get("config_script")
+            .map(|v| v.clone().try_into())
+            .unwrap_or(Ok(false))?;
+        system_deps = pc
+            .get("system_deps")
+            .map(|v| v.clone().try_into())
+            .unwrap_or(Ok(false))?;
+        noarch = pc
+            .get("noarch")
+            .map(|v| v.clone().try_into())
+            .unwrap_or(Ok(false))?;
+        include_dependencies = pc
+            .get("include_dependencies")
+            .map(|v| v.clone().try_into())
+            .transpose()
+            .context("Value for `include_dependencies` is not a table of booleans")?
+            .unwrap_or_default();
+        link_filename = pc
+            .get("link_filename")
+            .map(LinkFilename::from_value)
+            .transpose()?;
     }
 
     let pkg_config = PkgConfigCApiConfig {
@@ -591,17 +2491,37 @@ fn load_manifest_capi_config(
         version,
         requires,
         requires_private,
+        cflags_private,
         strip_include_path_components,
+        link,
+        config_script,
+        system_deps,
+        noarch,
+        include_dependencies,
+        link_filename,
     };
 
     let library = capi.and_then(|v| v.get("library"));
     let mut lib_name = String::from(name);
     let mut version = pkg.version().clone();
     let mut install_subdir = None;
+    let mut origin_rpath = false;
+    let mut rpath = Vec::new();
     let mut versioning = true;
     let mut version_suffix_components = None;
     let mut import_library = true;
+    let mut delay_load_import_library = false;
+    let mut msvc_import_library = false;
     let mut rustflags = Vec::new();
+    let mut current_version = None;
+    let mut compatibility_version = None;
+    let mut install_name = InstallName::default();
+    let mut def = DefCApiConfig::default();
+    let mut msbuild_props = false;
+    let mut crt_static = false;
+    let mut default_library_types = None;
+    let mut lto = None;
+    let mut symbols_check = None;
 
     if let Some(library) = library {
         if let Some(override_name) = library.get("name").and_then(|v| v.as_str()) {
@@ -613,6 +2533,22 @@ fn load_manifest_capi_config(
         if let Some(subdir) = library.get("install_subdir").and_then(|v| v.as_str()) {
             install_subdir = Some(String::from(subdir));
         }
+        origin_rpath = library
+            .get("origin_rpath")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if let Some(value) = library.get("rpath") {
+            rpath = value
+                .as_array()
+                .and_then(|a| {
+                    a.iter()
+                        .map(|v| v.as_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                })
+                .with_context(|| {
+                    format!("Value for `rpath` is not a list of strings: {value:?}")
+                })?;
+        }
         versioning = library
             .get("versioning")
             .and_then(|v| v.as_bool())
@@ -634,6 +2570,14 @@ fn load_manifest_capi_config(
             .get("import_library")
             .and_then(|v| v.as_bool())
             .unwrap_or(true);
+        delay_load_import_library = library
+            .get("delay_load_import_library")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        msvc_import_library = library
+            .get("msvc_import_library")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
         if let Some(args) = library.get("rustflags").and_then(|v| v.as_str()) {
             let args = args
                 .split(' ')
@@ -642,6 +2586,60 @@ fn load_manifest_capi_config(
                 .map(str::to_string);
             rustflags.extend(args);
         }
+        if let Some(value) = library.get("current_version") {
+            current_version = Some(parse_apple_version("current_version", value)?);
+        }
+        if let Some(value) = library.get("compatibility_version") {
+            compatibility_version = Some(parse_apple_version("compatibility_version", value)?);
+        }
+        if let Some(value) = library.get("install_name") {
+            let value = value
+                .as_str()
+                .with_context(|| format!("Value for `install_name` is not a string: {value:?}"))?;
+            install_name = InstallName::from_str(value);
+        }
+        msbuild_props = library
+            .get("msbuild_props")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        if let Some(value) = library.get("crt_static") {
+            crt_static = value
+                .as_bool()
+                .with_context(|| format!("Value for `crt_static` is not a boolean: {value:?}"))?;
+        }
+        if let Some(value) = library.get("default_library_types") {
+            let types: Vec<String> = value
+                .clone()
+                .try_into()
+                .ok()
+                .filter(|types: &Vec<String>| {
+                    !types.is_empty()
+                        && types
+                            .iter()
+                            .all(|t| matches!(t.as_str(), "staticlib" | "cdylib"))
+                })
+                .with_context(|| {
+                    format!("Value for `default_library_types` is not a non-empty list of \"staticlib\" and \"cdylib\": {value:?}")
+                })?;
+            default_library_types = Some(types);
+        }
+        if let Some(value) = library.get("def") {
+            def = DefCApiConfig::from_value(value)?;
+        }
+        if let Some(value) = library.get("lto") {
+            lto = Some(Lto::from_value(value)?);
+        }
+        if let Some(value) = library.get("symbols_check") {
+            let policy = value
+                .as_str()
+                .filter(|v| matches!(*v, "warn" | "deny" | "off"))
+                .with_context(|| {
+                    format!(
+                        "Value for `symbols_check` is not \"warn\", \"deny\" or \"off\": {value:?}"
+                    )
+                })?;
+            symbols_check = Some(policy.to_string());
+        }
     }
 
     if rustc_target.os == "android" {
@@ -652,15 +2650,30 @@ fn load_manifest_capi_config(
         name: lib_name,
         version,
         install_subdir,
+        origin_rpath,
+        rpath,
         versioning,
         version_suffix_components,
         import_library,
+        delay_load_import_library,
+        msvc_import_library,
         rustflags,
+        current_version,
+        compatibility_version,
+        install_name,
+        def,
+        msbuild_props,
+        crt_static,
+        default_library_types,
+        lto,
+        symbols_check,
     };
 
     let default_assets_include = InstallTargetPaths {
         from: "assets/capi/include/**/*".to_string(),
         to: header.subdirectory.clone(),
+        dependency: None,
+        declared_in: None,
     };
 
     let header_name = if header.name.ends_with(".h") {
@@ -672,11 +2685,15 @@ fn load_manifest_capi_config(
     let default_legacy_asset_include = InstallTargetPaths {
         from: header_name,
         to: header.subdirectory.clone(),
+        dependency: None,
+        declared_in: None,
     };
 
     let default_generated_include = InstallTargetPaths {
         from: "capi/include/**/*".to_string(),
         to: header.subdirectory.clone(),
+        dependency: None,
+        declared_in: None,
     };
 
     let mut include_targets = vec![
@@ -686,51 +2703,98 @@ fn load_manifest_capi_config(
     ];
     let mut data_targets = Vec::new();
 
-    let mut data_subdirectory = name.clone();
-
-    fn custom_install_target_paths(
-        root: &toml::Value,
-        subdirectory: &str,
-        targets: &mut Vec<InstallTarget>,
-    ) -> anyhow::Result<()> {
-        if let Some(assets) = root.get("asset").and_then(|v| v.as_array()) {
-            for asset in assets {
-                let target_paths = InstallTargetPaths::from_value(asset, subdirectory)?;
-                targets.push(InstallTarget::Asset(target_paths));
-            }
-        }
+    let mut csources = CSourcesCApiConfig::default();
+    if let Some(value) = capi.and_then(|v| v.get("csources")) {
+        let list = |key: &str| -> anyhow::Result<Vec<String>> {
+            value
+                .get(key)
+                .map(|v| v.clone().try_into::<Vec<String>>())
+                .transpose()
+                .with_context(|| format!("Value for `csources.{key}` is not a list of strings"))
+                .map(Option::unwrap_or_default)
+        };
+        csources = CSourcesCApiConfig {
+            files: list("files")?,
+            include_dirs: list("include_dirs")?,
+            headers: list("headers")?,
+            flags: list("flags")?,
+        };
+    }
 
-        if let Some(generated) = root.get("generated").and_then(|v| v.as_array()) {
-            for gen in generated {
-                let target_paths = InstallTargetPaths::from_value(gen, subdirectory)?;
-                targets.push(InstallTarget::Generated(target_paths));
-            }
+    let mut test = TestCApiConfig::default();
+    if let Some(value) = capi.and_then(|v| v.get("test")) {
+        if let Some(flags) = value.get("cflags").and_then(|v| v.as_str()) {
+            test.cflags
+                .extend(flags.split_whitespace().map(str::to_string));
         }
+        test.std = value.get("std").and_then(|v| v.as_str()).map(String::from);
+        test.cc = value.get("cc").and_then(|v| v.as_str()).map(String::from);
+    }
 
-        Ok(())
+    for path in &csources.headers {
+        include_targets.push(InstallTarget::Asset(InstallTargetPaths {
+            from: path.clone(),
+            to: header.subdirectory.clone(),
+            dependency: None,
+            declared_in: Some("csources.headers".to_string()),
+        }));
     }
 
+    let mut data_subdirectory = name.clone();
+    let mut locale_domain = library.name.clone();
+
+    let mut bins = None;
+    let mut man_gzip = false;
+
     let install = capi.and_then(|v| v.get("install"));
     if let Some(install) = install {
+        if let Some(value) = install.get("bins") {
+            bins = match value {
+                toml::Value::Boolean(true) => Some(Vec::new()),
+                toml::Value::Boolean(false) => None,
+                v => Some(v.clone().try_into().with_context(|| {
+                    format!(
+                        "Value for `install.bins` is not a boolean or a list of binaries: {v:?}"
+                    )
+                })?),
+            };
+        }
         if let Some(includes) = install.get("include") {
-            custom_install_target_paths(includes, &header.subdirectory, &mut include_targets)?;
+            custom_install_target_paths(
+                includes,
+                "include",
+                &header.subdirectory,
+                &mut include_targets,
+            )?;
         }
         if let Some(data) = install.get("data") {
             if let Some(subdir) = data.get("subdirectory").and_then(|v| v.as_str()) {
                 data_subdirectory = String::from(subdir);
             }
-            custom_install_target_paths(data, &data_subdirectory, &mut data_targets)?;
+            custom_install_target_paths(data, "data", &data_subdirectory, &mut data_targets)?;
+        }
+        if let Some(locale) = install.get("locale") {
+            if let Some(domain) = locale.get("domain").and_then(|v| v.as_str()) {
+                locale_domain = String::from(domain);
+            }
+        }
+        if let Some(man) = install.get("man") {
+            man_gzip = man.get("gzip").and_then(|v| v.as_bool()).unwrap_or(false);
         }
     }
 
     let default_assets_data = InstallTargetPaths {
         from: "assets/capi/share/**/*".to_string(),
         to: data_subdirectory.clone(),
+        dependency: None,
+        declared_in: None,
     };
 
     let default_generated_data = InstallTargetPaths {
         from: "capi/share/**/*".to_string(),
-        to: data_subdirectory,
+        to: data_subdirectory.clone(),
+        dependency: None,
+        declared_in: None,
     };
 
     data_targets.extend([
@@ -738,9 +2802,61 @@ fn load_manifest_capi_config(
         InstallTarget::Generated(default_generated_data),
     ]);
 
+    let locale_targets = install_category_targets(install, "locale", ["po/*.mo", "po/*.mo"])?;
+
+    let man_targets =
+        install_category_targets(install, "man", ["assets/capi/man/**/*", "capi/man/**/*"])?;
+
+    let doc_targets =
+        install_category_targets(install, "doc", ["assets/capi/doc/**/*", "capi/doc/**/*"])?;
+
+    let bin_targets =
+        install_category_targets(install, "bin", ["assets/capi/bin/**/*", "capi/bin/**/*"])?;
+
+    let libexec_targets = install_category_targets(
+        install,
+        "libexec",
+        ["assets/capi/libexec/**/*", "capi/libexec/**/*"],
+    )?;
+
+    for target in include_targets
+        .iter()
+        .chain(&data_targets)
+        .chain(&locale_targets)
+        .chain(&man_targets)
+        .chain(&doc_targets)
+        .chain(&bin_targets)
+        .chain(&libexec_targets)
+    {
+        if let InstallTarget::Generated(InstallTargetPaths {
+            dependency: Some(dep),
+            ..
+        }) = target
+        {
+            if !pkg
+                .dependencies()
+                .iter()
+                .any(|d| d.package_name() == dep.as_str())
+            {
+                anyhow::bail!(
+                    "`{dep}` used in `package.metadata.capi.install` is not a dependency"
+                );
+            }
+        }
+    }
+
     let install = InstallCApiConfig {
         include: include_targets,
         data: data_targets,
+        data_subdirectory,
+        locale: locale_targets,
+        locale_domain,
+        bins,
+        man: man_targets,
+        man_gzip,
+        doc: doc_targets,
+        bin: bin_targets,
+        libexec: libexec_targets,
     };
 
     Ok(CApiConfig {
@@ -748,17 +2864,18 @@ fn load_manifest_capi_config(
         pkg_config,
         library,
         install,
+        csources,
+        test,
     })
 }
 
-fn compile_options(
+pub(crate) fn compile_options(
     ws: &Workspace,
     gctx: &GlobalContext,
     args: &ArgMatches,
     profile: InternedString,
     compile_mode: CompileMode,
 ) -> anyhow::Result<CompileOptions> {
-    use cargo::core::compiler::CompileKind;
     let mut compile_opts =
         args.compile_options(gctx, compile_mode, Some(ws), ProfileChecking::Custom)?;
 
@@ -793,6 +2910,12 @@ fn compile_options(
 struct Exec {
     ran: AtomicBool,
     link_line: Mutex<HashMap<PackageId, String>>,
+    out_dirs: Mutex<HashMap<PackageId, PathBuf>>,
+    /// The inputs of the libraries rustc does not know about, the C sources and headers,
+    /// see `csources::build`, and the `.def` customization
+    link_inputs: Mutex<HashMap<PackageId, Vec<PathBuf>>>,
+    /// The libraries linked with a customized `.def`, see `def::relink`
+    def_links: Mutex<HashMap<PackageId, DefLink>>,
 }
 
 use cargo::CargoResult;
@@ -803,12 +2926,18 @@ impl Executor for Exec {
         &self,
         cmd: &ProcessBuilder,
         id: PackageId,
-        _target: &Target,
-        _mode: CompileMode,
+        target: &Target,
+        mode: CompileMode,
         on_stdout_line: &mut dyn FnMut(&str) -> CargoResult<()>,
         on_stderr_line: &mut dyn FnMut(&str) -> CargoResult<()>,
     ) -> CargoResult<()> {
         self.ran.store(true, Ordering::Relaxed);
+        if let Some(out_dir) = cmd.get_env("OUT_DIR") {
+            self.out_dirs
+                .lock()
+                .unwrap()
+                .insert(id, PathBuf::from(out_dir));
+        }
         cmd.exec_with_streaming(
             on_stdout_line,
             &mut |s| {
@@ -837,12 +2966,30 @@ impl Executor for Exec {
                 }
             },
             false,
-        )
-        .map(drop)
+        )?;
+
+        if target.is_lib() && mode == CompileMode::Build {
+            if let Some(link) = self.def_links.lock().unwrap().get(&id) {
+                def::relink(cmd, link, on_stderr_line)?;
+            }
+        }
+
+        // cargo relinks the library if a file of its dep-info changes, rustc does not know
+        // about the C sources.
+        if target.is_lib() && mode == CompileMode::Build {
+            if let Some(inputs) = self.link_inputs.lock().unwrap().get(&id) {
+                if let Some(dep_info) = csources::rustc_dep_info(cmd) {
+                    let content = read(&dep_info)?;
+                    write(&dep_info, csources::add_dep_info_inputs(&content, inputs))?;
+                }
+            }
+        }
+
+        Ok(())
     }
 }
 
-use cargo::core::compiler::{unit_graph, UnitInterner};
+use cargo::core::compiler::{unit_graph, BuildContext, UnitInterner};
 use cargo::ops::create_bcx;
 
 fn set_deps_args(
@@ -861,25 +3008,103 @@ fn set_deps_args(
     }
 }
 
+/// Build the linkable crate types of the roots, e.g. the rlib the other workspace members
+/// link, by units of their own
+///
+/// The staticlib and cdylib units get the C-API arguments alone, rustc refuses the LTO ones
+/// if the rlib is produced as well. The rlib units are returned by the library unit and kept
+/// only if a unit of the build depends on them.
+fn split_rlib_units(bcx: &mut BuildContext, interner: &UnitInterner) -> HashMap<Unit, Unit> {
+    use cargo::core::compiler::CrateType;
+
+    let intern = |unit: &Unit, kinds: Vec<CrateType>| {
+        let mut target = unit.target.clone();
+        target.set_kind(TargetKind::Lib(kinds));
+        interner.intern(
+            &unit.pkg,
+            &target,
+            unit.profile.clone(),
+            unit.kind,
+            unit.mode,
+            unit.features.clone(),
+            unit.rustflags.clone(),
+            unit.rustdocflags.clone(),
+            unit.links_overrides.clone(),
+            unit.is_std,
+            unit.dep_hash,
+            unit.artifact,
+            unit.artifact_target_for_features,
+        )
+    };
+
+    let mut rlibs = HashMap::new();
+    for root in bcx.roots.iter_mut() {
+        let (linkable, capi): (Vec<_>, Vec<_>) = root
+            .target
+            .rustc_crate_types()
+            .into_iter()
+            .partition(|k| k.is_linkable());
+        if linkable.is_empty() || capi.is_empty() {
+            continue;
+        }
+
+        let rlib = intern(root, linkable);
+        let lib = intern(root, capi);
+        let deps = bcx.unit_graph.remove(root).unwrap_or_default();
+
+        let mut linked = false;
+        for dep in bcx.unit_graph.values_mut().flatten() {
+            if dep.unit == *root {
+                dep.unit = rlib.clone();
+                linked = true;
+            }
+        }
+        if linked {
+            bcx.unit_graph.insert(rlib.clone(), deps.clone());
+            rlibs.insert(lib.clone(), rlib);
+        }
+
+        bcx.unit_graph.insert(lib.clone(), deps);
+        *root = lib;
+    }
+
+    rlibs
+}
+
 fn compile_with_exec(
     ws: &Workspace<'_>,
     options: &CompileOptions,
-    exec: &Arc<dyn Executor>,
+    exec: &Arc<Exec>,
     rustc_target: &target::Target,
     root_output: &Path,
     args: &ArgMatches,
-) -> CargoResult<HashMap<PackageId, PathBuf>> {
+) -> CargoResult<HashSet<PackageId>> {
     ws.emit_warnings()?;
     let interner = UnitInterner::new();
     let mut bcx = create_bcx(ws, options, &interner)?;
+    let rlibs = split_rlib_units(&mut bcx, &interner);
     let unit_graph = &bcx.unit_graph;
     let extra_compiler_args = &mut bcx.extra_compiler_args;
 
+    let remap_args = if reproducible_timestamp(args)?.is_some() {
+        remap_path_prefix_args(ws)
+    } else {
+        Vec::new()
+    };
+
+    check_vendored(ws, &bcx.roots, unit_graph, args.flag("forbid_vendored"))?;
+
     for unit in bcx.roots.iter() {
         let pkg = &unit.pkg;
-        let capi_config = load_manifest_capi_config(pkg, rustc_target)?;
+        let profile = options.build_config.requested_profile;
+        let mut capi_config = load_manifest_capi_config(pkg, rustc_target, &profile)?;
+        apply_install_name_map(&mut capi_config, args)?;
+        apply_rpath_args(&mut capi_config, args);
+        apply_install_name_args(&mut capi_config, args);
         let name = &capi_config.library.name;
-        let install_paths = InstallPaths::new(name, rustc_target, args, &capi_config);
+        let library_types = LibraryTypes::from_args(rustc_target, args, &capi_config.library);
+        let install_paths =
+            InstallPaths::new(name, rustc_target, args, &capi_config, library_types)?;
         let pkg_rustflags = &capi_config.library.rustflags;
 
         let mut leaf_args: Vec<String> = rustc_target
@@ -889,6 +3114,7 @@ fn compile_with_exec(
             .collect();
 
         leaf_args.extend(pkg_rustflags.clone());
+        leaf_args.extend(remap_args.iter().cloned());
 
         leaf_args.push("--cfg".into());
         leaf_args.push("cargo_c".into());
@@ -896,173 +3122,574 @@ fn compile_with_exec(
         leaf_args.push("--print".into());
         leaf_args.push("native-static-libs".into());
 
-        if args.flag("crt-static") {
+        leaf_args.extend(DebugInfoPolicy::from_args(args).rustc_args(rustc_target));
+
+        if args.flag("crt_static") || capi_config.library.crt_static {
             leaf_args.push("-C".into());
             leaf_args.push("target-feature=+crt-static".into());
         }
 
+        if !capi_config.csources.files.is_empty() {
+            let (csources_args, inputs) = csources::build(
+                ws,
+                &capi_config.csources,
+                name,
+                pkg.root(),
+                root_output,
+                bcx.target_data.short_name(&unit.kind),
+                &bcx.target_data.rustc.host,
+                &unit.profile,
+            )?;
+            leaf_args.extend(csources_args);
+            exec.link_inputs
+                .lock()
+                .unwrap()
+                .entry(pkg.package_id())
+                .or_default()
+                .extend(inputs);
+        }
+
+        let def = &capi_config.library.def;
+        if let Some((exports, def_args)) = def::link_args(def, name, rustc_target, root_output)? {
+            if rustc_target.is_msvc() {
+                let link = DefLink {
+                    def: def.clone(),
+                    path: root_output.join(format!("{name}-link.def")),
+                };
+                exec.def_links
+                    .lock()
+                    .unwrap()
+                    .insert(pkg.package_id(), link);
+            } else if !def.exclude.is_empty() {
+                ws.gctx()
+                    .shell()
+                    .note(tr("def-exclude-gnu", &[("package", &pkg.name())]))?;
+            }
+            leaf_args.extend(def_args);
+            exec.link_inputs
+                .lock()
+                .unwrap()
+                .entry(pkg.package_id())
+                .or_default()
+                .push(exports);
+        }
+
+        let mut deps_args: Vec<String> = pkg_rustflags.iter().chain(&remap_args).cloned().collect();
+
+        if let Some(lto) = capi_config.library.lto {
+            leaf_args.extend(lto.library_args());
+            deps_args.extend(lto.dependency_args());
+        }
+
         extra_compiler_args.insert(unit.clone(), leaf_args.to_owned());
+        if let Some(rlib) = rlibs.get(unit) {
+            extra_compiler_args.insert(rlib.clone(), deps_args.clone());
+        }
 
         for dep in unit_graph[unit].iter() {
-            set_deps_args(dep, unit_graph, extra_compiler_args, pkg_rustflags);
+            set_deps_args(dep, unit_graph, extra_compiler_args, &deps_args);
         }
     }
 
     if options.build_config.unit_graph {
         unit_graph::emit_serialized_unit_graph(&bcx.roots, &bcx.unit_graph, ws.gctx())?;
-        return Ok(HashMap::new());
+        return Ok(HashSet::new());
     }
+    let build_scripts = build_script_packages(&bcx);
     let cx = cargo::core::compiler::BuildRunner::new(&bcx)?;
 
-    let r = cx.compile(exec)?;
+    cx.compile(&(exec.clone() as Arc<dyn Executor>))?;
 
-    let out_dirs = r
-        .cdylibs
-        .iter()
-        .filter_map(|l| {
-            let id = l.unit.pkg.package_id();
-            if let Some(ref m) = l.script_meta {
-                if let Some(env) = r.extra_env.get(m) {
-                    env.iter().find_map(|e| {
-                        if e.0 == "OUT_DIR" {
-                            Some((id, PathBuf::from(&e.1)))
-                        } else {
-                            None
-                        }
-                    })
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        })
-        .collect();
+    Ok(build_scripts)
+}
+
+/// The packages with a build script in the build
+fn build_script_packages(bcx: &BuildContext) -> HashSet<PackageId> {
+    bcx.unit_graph
+        .keys()
+        .filter(|unit| unit.mode.is_run_custom_build())
+        .map(|unit| unit.pkg.package_id())
+        .collect()
+}
+
+/// The OUT_DIR of the build scripts recorded by the previous build of `packages`, without
+/// compiling
+pub(crate) fn previous_out_dirs(
+    ws: &Workspace,
+    compile_opts: &CompileOptions,
+    packages: &[CPackage],
+) -> anyhow::Result<HashMap<PackageId, PathBuf>> {
+    // The packages share the target directory
+    let Some(root_output) = packages.first().map(|p| &p.finger_print.root_output) else {
+        return Ok(HashMap::new());
+    };
+    let interner = UnitInterner::new();
+    let bcx = create_bcx(ws, compile_opts, &interner)?;
 
-    Ok(out_dirs)
+    record_out_dirs(root_output, &build_script_packages(&bcx), &HashMap::new())
 }
 
 #[derive(Debug)]
 pub struct CPackage {
     pub version: Version,
+    pub name: InternedString,
     pub root_path: PathBuf,
     pub capi_config: CApiConfig,
     pub build_targets: BuildTargets,
     pub install_paths: InstallPaths,
-    finger_print: FingerPrint,
+    pub library_types: LibraryTypes,
+    pub(crate) finger_print: FingerPrint,
+    dependencies: Vec<InternedString>,
 }
 
 impl CPackage {
     fn from_package(
+        gctx: &GlobalContext,
         pkg: &mut Package,
         args: &ArgMatches,
-        library_types: LibraryTypes,
+        rlib: bool,
         rustc_target: &target::Target,
         root_output: &Path,
+        profile: &str,
     ) -> anyhow::Result<CPackage> {
         let id = pkg.package_id();
+        let pkg_name = pkg.name();
         let version = pkg.version().clone();
         let root_path = pkg.root().to_path_buf();
-        let capi_config = load_manifest_capi_config(pkg, rustc_target)?;
-
-        patch_target(pkg, library_types, &capi_config)?;
+        let mut capi_config = load_manifest_capi_config(pkg, rustc_target, profile)?;
+        apply_install_name_map(&mut capi_config, args)?;
+        apply_rpath_args(&mut capi_config, args);
+        apply_install_name_args(&mut capi_config, args);
+
+        // The header-only and data-only packages have no library to build
+        let library_types = if pkg.library().is_some() {
+            LibraryTypes {
+                rlib,
+                ..LibraryTypes::from_args(rustc_target, args, &capi_config.library)
+            }
+        } else {
+            let header = &capi_config.header;
+            if header.enabled && header.generation {
+                anyhow::bail!(tr("no-library-target", &[("package", &pkg_name)]));
+            }
+            LibraryTypes::default()
+        };
+        patch_target(gctx, pkg, library_types, &capi_config)?;
 
         let name = &capi_config.library.name;
 
-        let install_paths = InstallPaths::new(name, rustc_target, args, &capi_config);
-        let build_targets = BuildTargets::new(
+        let install_paths =
+            InstallPaths::new(name, rustc_target, args, &capi_config, library_types)?;
+        let mut build_targets = BuildTargets::new(
             name,
             rustc_target,
             root_output,
             library_types,
             &capi_config,
             args.get_flag("meson"),
+            rustc_target.is_msys2(args.get_one::<String>("msys2").unwrap()),
         )?;
+        build_targets.c_array = args
+            .contains_id("emit_c_array")
+            .then(|| root_output.join(format!("{name}_blob.c")));
+        if DebugInfoPolicy::from_args(args) == DebugInfoPolicy::Separate
+            && matches!(LibType::from_build_targets(&build_targets), LibType::So)
+        {
+            build_targets.debug_info = build_targets.shared_lib.as_ref().map(|lib| {
+                let mut debug = lib.clone().into_os_string();
+                debug.push(".debug");
+                PathBuf::from(debug)
+            });
+        }
 
         let finger_print = FingerPrint::new(&id, root_output, &build_targets, &install_paths);
 
+        let dependencies = pkg
+            .dependencies()
+            .iter()
+            .filter(|d| d.kind() == DepKind::Normal)
+            .map(|d| d.package_name())
+            .collect();
+
         Ok(CPackage {
             version,
+            name: pkg_name,
             root_path,
             capi_config,
             build_targets,
             install_paths,
+            library_types,
             finger_print,
+            dependencies,
         })
     }
+
+    /// Use the artifacts of the previous build, without compiling
+    pub(crate) fn reuse_previous_build(
+        &mut self,
+        out_dirs: &HashMap<PackageId, PathBuf>,
+    ) -> anyhow::Result<()> {
+        let root_output = self.finger_print.root_output.clone();
+        self.finger_print.static_libs = self.finger_print.load_previous()?.static_libs;
+
+        self.setup_extra_targets(&root_output, out_dirs)
+    }
+
+    /// Add the headers and the assets to install
+    ///
+    /// `out_dirs` are the build scripts outputs of the packages in the build, empty if the
+    /// package is not built.
+    pub fn setup_extra_targets(
+        &mut self,
+        root_output: &Path,
+        out_dirs: &HashMap<PackageId, PathBuf>,
+    ) -> anyhow::Result<()> {
+        let out_dir = out_dirs.get(&self.finger_print.id).map(|p| p.as_path());
+        let dependency_out_dirs = dependency_out_dirs(out_dirs, &self.dependencies);
+        self.build_targets.extra.setup(
+            &self.capi_config,
+            &self.root_path,
+            out_dir,
+            &dependency_out_dirs,
+        )?;
+
+        if self.capi_config.header.generation && !self.capi_config.header.generator.is_cbindgen() {
+            self.setup_generated_bindings(root_output)?;
+        } else if self.capi_config.header.generation {
+            let subdirectory = Path::new(&self.capi_config.header.subdirectory);
+            for header_name in self.capi_config.header.generated_names() {
+                let from = root_output.join(&header_name);
+                let to = subdirectory.join(&header_name);
+                self.build_targets.extra.include.push((from, to));
+            }
+        }
+
+        if let Some(umbrella) = &self.capi_config.header.umbrella {
+            let from = root_output.join("umbrella").join(umbrella);
+            let to = Path::new(&self.capi_config.header.subdirectory).join(umbrella);
+            if self
+                .build_targets
+                .extra
+                .include
+                .iter()
+                .any(|(_, t)| *t == to)
+            {
+                anyhow::bail!(tr("umbrella-overwrite", &[("path", &to.display())]));
+            }
+            self.build_targets.extra.include.push((from, to));
+        }
+
+        Ok(())
+    }
+
+    /// Add the files produced by an external header generator to the install targets
+    fn setup_generated_bindings(&mut self, root_output: &Path) -> anyhow::Result<()> {
+        let generator = &self.capi_config.header.generator;
+        let name = &self.capi_config.library.name;
+        let output_dir = HeaderGenerator::output_dir(root_output, name);
+        let extra = &mut self.build_targets.extra;
+
+        extra
+            .include
+            .retain(|(from, _)| !from.starts_with(&output_dir));
+        extra
+            .data
+            .retain(|(from, _)| !from.starts_with(&output_dir));
+
+        extra.include.extend(generator.headers(
+            root_output,
+            name,
+            &self.capi_config.header.subdirectory,
+        )?);
+        extra.data.extend(generator.bindings(
+            root_output,
+            name,
+            &self.capi_config.install.data_subdirectory,
+        )?);
+
+        Ok(())
+    }
 }
 
-fn deprecation_warnings(ws: &Workspace, args: &ArgMatches) -> anyhow::Result<()> {
-    if args.contains_id("dlltool") {
-        ws.gctx()
-        .shell()
-        .warn("The `dlltool` support is now builtin. The cli option is deprecated and will be removed in the future")?;
+/// What library types to build, none for the packages without a library target
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LibraryTypes {
+    pub staticlib: bool,
+    pub cdylib: bool,
+    /// Other workspace members depend on the library
+    pub rlib: bool,
+}
+
+impl LibraryTypes {
+    fn from_target(target: &target::Target) -> Self {
+        // for os == "none", cdylib does not make sense. By default cdylib is also not built on
+        // musl, but that can be overriden by the user. That is useful when musl is being used as
+        // main libc, e.g. in Alpine, Gentoo and OpenWRT
+        //
+        // See also
+        //
+        // - https://github.com/lu-zero/cargo-c?tab=readme-ov-file#shared-libraries-are-not-built-on-musl-systems
+        // - https://github.com/lu-zero/cargo-c/issues/180
+        Self {
+            staticlib: true,
+            cdylib: target.os != "none" && target.env != "musl",
+            rlib: false,
+        }
+    }
+
+    /// The `--library-type`, or else the `default_library_types` of the library
+    pub(crate) fn from_args(
+        target: &target::Target,
+        args: &ArgMatches,
+        library: &LibraryCApiConfig,
+    ) -> Self {
+        match (
+            args.get_many::<String>("library-type"),
+            &library.default_library_types,
+        ) {
+            (Some(library_types), _) => Self::from_library_types(target, library_types),
+            (None, Some(library_types)) => Self::from_library_types(target, library_types.iter()),
+            (None, None) => Self::from_target(target),
+        }
+    }
+
+    pub(crate) fn from_library_types<S: AsRef<str>>(
+        target: &target::Target,
+        library_types: impl Iterator<Item = S>,
+    ) -> Self {
+        let (mut staticlib, mut cdylib) = (false, false);
+
+        for library_type in library_types {
+            staticlib |= library_type.as_ref() == "staticlib";
+            cdylib |= library_type.as_ref() == "cdylib";
+        }
+
+        // when os is none, a cdylib cannot be produced
+        // forcing a cdylib for musl is allowed here (see [`LibraryTypes::from_target`])
+        cdylib &= target.os != "none";
+
+        Self {
+            staticlib,
+            cdylib,
+            rlib: false,
+        }
+    }
+
+    pub(crate) const fn none(self) -> bool {
+        !self.staticlib && !self.cdylib
+    }
+
+    const fn only_staticlib(self) -> bool {
+        self.staticlib && !self.cdylib
+    }
+
+    const fn only_cdylib(self) -> bool {
+        self.cdylib && !self.staticlib
+    }
+}
+
+/// The workspace members other members link, through a normal or build dependency
+fn workspace_dependencies(ws: &Workspace) -> HashSet<PackageId> {
+    let members: Vec<_> = ws.members().map(|m| m.package_id()).collect();
+    ws.members()
+        .flat_map(|m| {
+            let members = &members;
+            m.dependencies()
+                .iter()
+                .filter(|d| d.kind() != DepKind::Development && d.source_id().is_path())
+                .filter_map(move |d| {
+                    members
+                        .iter()
+                        .find(|&&id| id != m.package_id() && d.matches_id(id))
+                })
+                .copied()
+        })
+        .collect()
+}
+
+/// Merge the OUT_DIR of the packages just compiled with the ones recorded by the previous builds
+///
+/// The fresh packages are not passed to the executor, so their OUT_DIR is only known from
+/// the build that compiled them. Only the `build_scripts` packages, the ones in the build,
+/// are kept.
+fn record_out_dirs(
+    root_output: &Path,
+    build_scripts: &HashSet<PackageId>,
+    compiled: &HashMap<PackageId, PathBuf>,
+) -> anyhow::Result<HashMap<PackageId, PathBuf>> {
+    let path = root_output.join("cargo-c-out-dirs.toml");
+    let previous: BTreeMap<PackageId, PathBuf> = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| toml::from_str(&s).ok())
+        .unwrap_or_default();
+
+    let mut out_dirs = previous.clone();
+    out_dirs.extend(compiled.iter().map(|(id, out_dir)| (*id, out_dir.clone())));
+    out_dirs.retain(|id, _| build_scripts.contains(id));
+    if out_dirs != previous {
+        create_dir_all(root_output)?;
+        write(&path, toml::to_string(&out_dirs)?)?;
+    }
+
+    Ok(out_dirs.into_iter().collect())
+}
+
+/// The OUT_DIR of the build scripts of `dependencies`, by package name
+fn dependency_out_dirs(
+    out_dirs: &HashMap<PackageId, PathBuf>,
+    dependencies: &[InternedString],
+) -> HashMap<String, PathBuf> {
+    out_dirs
+        .iter()
+        .filter(|(id, _)| dependencies.contains(&id.name()))
+        .map(|(id, out_dir)| (id.name().to_string(), out_dir.clone()))
+        .collect()
+}
+
+/// Fail with `--forbid-unversioned-soname` if a shared library of a target using sonames,
+/// or install names on Apple, has no ABI version in it
+fn check_soname_policy(packages: &[CPackage], args: &ArgMatches) -> anyhow::Result<()> {
+    if !args.flag("forbid_unversioned_soname") {
+        return Ok(());
+    }
+
+    let errors: Vec<_> = packages
+        .iter()
+        .filter(|pkg| {
+            let targets = &pkg.build_targets;
+            targets.shared_lib.is_some()
+                && !targets.target.has_dlls()
+                && !matches!(targets.target.os.as_str(), "android" | "emscripten")
+        })
+        .filter_map(|pkg| {
+            let library = &pkg.capi_config.library;
+            let reason = library.unversioned_soname()?;
+            Some(format!("lib{}: {reason}", library.name))
+        })
+        .collect();
+
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "{}\n  {}",
+            tr("unversioned-soname", &[]),
+            errors.join("\n  ")
+        );
     }
 
     Ok(())
 }
 
-/// What library types to build
-#[derive(Debug, Clone, Copy)]
-pub struct LibraryTypes {
-    pub staticlib: bool,
-    pub cdylib: bool,
+/// Warn about the install targets declared in the metadata matching no file, e.g. a
+/// misspelled header glob, or fail with `--strict-install-targets`, and about the files
+/// matched but not installed
+pub(crate) fn check_install_targets(
+    ws: &Workspace,
+    packages: &[CPackage],
+    args: &ArgMatches,
+) -> anyhow::Result<()> {
+    let unmatched: Vec<_> = packages
+        .iter()
+        .flat_map(|pkg| {
+            let unmatched = &pkg.build_targets.extra.unmatched;
+            unmatched.iter().map(|u| format!("{}: {u}", pkg.name))
+        })
+        .collect();
+
+    if args.flag("strict_install_targets") && !unmatched.is_empty() {
+        anyhow::bail!(
+            "{}\n  {}",
+            tr("unmatched-install-targets", &[]),
+            unmatched.join("\n  ")
+        );
+    }
+    let skipped = packages.iter().flat_map(|pkg| {
+        let skipped = &pkg.build_targets.extra.skipped;
+        skipped.iter().map(|s| format!("{}: {s}", pkg.name))
+    });
+    for warning in unmatched.into_iter().chain(skipped) {
+        ws.gctx().shell().warn(warning)?;
+    }
+
+    Ok(())
 }
 
-impl LibraryTypes {
-    fn from_target(target: &target::Target) -> Self {
-        // for os == "none", cdylib does not make sense. By default cdylib is also not built on
-        // musl, but that can be overriden by the user. That is useful when musl is being used as
-        // main libc, e.g. in Alpine, Gentoo and OpenWRT
-        //
-        // See also
-        //
-        // - https://github.com/lu-zero/cargo-c?tab=readme-ov-file#shared-libraries-are-not-built-on-musl-systems
-        // - https://github.com/lu-zero/cargo-c/issues/180
-        Self {
-            staticlib: true,
-            cdylib: target.os != "none" && target.env != "musl",
+/// Fail if the `requires` of the packages exclude the version of a `.pc` built with them,
+/// the installed files would never resolve together
+fn check_sibling_requires(packages: &[CPackage]) -> anyhow::Result<()> {
+    let mut errors = Vec::new();
+    for pkg in packages {
+        let pc = &pkg.capi_config.pkg_config;
+        let requirements = pc
+            .requires
+            .iter()
+            .chain(&pc.requires_private)
+            .flat_map(|r| r.split(','))
+            .map(str::trim)
+            .filter(|r| !r.is_empty());
+        for requirement in requirements {
+            let module = requirement.split_whitespace().next().unwrap_or_default();
+            let Some(sibling) = packages
+                .iter()
+                .map(|p| &p.capi_config.pkg_config)
+                .find(|p| p.filename == module)
+            else {
+                continue;
+            };
+            if satisfies(requirement, &sibling.version) == Some(false) {
+                errors.push(format!(
+                    "{} requires `{requirement}`, but {module} {} is built",
+                    pkg.name, sibling.version
+                ));
+            }
         }
     }
 
-    fn from_args(target: &target::Target, args: &ArgMatches) -> Self {
-        match args.get_many::<String>("library-type") {
-            Some(library_types) => Self::from_library_types(target, library_types),
-            None => Self::from_target(target),
-        }
+    if !errors.is_empty() {
+        anyhow::bail!("{}\n  {}", tr("sibling-requires", &[]), errors.join("\n  "));
     }
 
-    pub(crate) fn from_library_types<S: AsRef<str>>(
-        target: &target::Target,
-        library_types: impl Iterator<Item = S>,
-    ) -> Self {
-        let (mut staticlib, mut cdylib) = (false, false);
+    Ok(())
+}
 
-        for library_type in library_types {
-            staticlib |= library_type.as_ref() == "staticlib";
-            cdylib |= library_type.as_ref() == "cdylib";
+/// Add the libraries of the pkg-config requirements of a C-API dependency to `Libs.private`
+///
+/// The Rust code of the dependency is in the static library already, its .pc may not be
+/// installed.
+fn merge_dependency_pc(
+    ws: &Workspace,
+    pc: &mut PkgConfig,
+    dep: &str,
+    dep_pc: &PkgConfigCApiConfig,
+) -> anyhow::Result<()> {
+    let requirements = dep_pc
+        .requires
+        .iter()
+        .chain(&dep_pc.requires_private)
+        .flat_map(|r| r.split(','))
+        .map(str::trim)
+        .filter(|r| !r.is_empty());
+    for requirement in requirements {
+        match probe_libs(requirement) {
+            Some(libs) if !libs.is_empty() => {
+                pc.add_lib_private(libs.join(" "));
+            }
+            Some(_) => {}
+            None => ws.gctx().shell().warn(tr(
+                "requirement-not-found",
+                &[("requirement", &requirement), ("dependency", &dep)],
+            ))?,
         }
-
-        // when os is none, a cdylib cannot be produced
-        // forcing a cdylib for musl is allowed here (see [`LibraryTypes::from_target`])
-        cdylib &= target.os != "none";
-
-        Self { staticlib, cdylib }
     }
-
-    const fn only_staticlib(self) -> bool {
-        self.staticlib && !self.cdylib
+    if !dep_pc.link.is_empty() {
+        pc.add_lib_private(dep_pc.link.join(" "));
     }
 
-    const fn only_cdylib(self) -> bool {
-        self.cdylib && !self.staticlib
-    }
+    Ok(())
 }
 
 fn static_libraries(link_line: &str, rustc_target: &target::Target) -> String {
-    link_line
-        .trim()
-        .split(' ')
+    link_items(std::iter::once(link_line))
+        .into_iter()
         .filter(|s| {
             if rustc_target.env == "msvc" && s.starts_with("/defaultlib") {
                 return false;
@@ -1081,40 +3708,242 @@ fn static_libraries(link_line: &str, rustc_target: &target::Target) -> String {
         .join(" ")
 }
 
+/// The C-API packages selected by the arguments, configured as `cbuild` would without building
+pub fn capi_packages(
+    ws: &mut Workspace,
+    config: &GlobalContext,
+    args: &ArgMatches,
+    default_profile: &str,
+) -> anyhow::Result<Vec<CPackage>> {
+    let (target, is_target_overridden) = match args.get_one::<String>("pretend_target") {
+        Some(target) => (target.to_string(), true),
+        None => match args.targets()?.as_slice() {
+            [] => (config.load_global_rustc(Some(ws))?.host.to_string(), false),
+            [target] => (target.to_string(), true),
+            [..] => anyhow::bail!(tr("multiple-targets", &[])),
+        },
+    };
+
+    let rustc_target = target::Target::new(Some(&target), is_target_overridden)?;
+    let profile = args.get_profile_name(default_profile, ProfileChecking::Custom)?;
+    let profiles = Profiles::new(ws, profile)?;
+    let compile_opts = compile_options(ws, config, args, profile, CompileMode::Build)?;
+
+    let root_output = ws
+        .target_dir()
+        .as_path_unlocked()
+        .join(target)
+        .join(profiles.get_dir_name());
+
+    let requested: Vec<_> = compile_opts
+        .spec
+        .get_packages(ws)?
+        .iter()
+        .map(|p| p.package_id())
+        .collect();
+
+    let dependencies = workspace_dependencies(ws);
+    let capi_feature = InternedString::new("capi");
+    ws.members_mut()
+        .filter(|p| {
+            p.summary().features().contains_key(&capi_feature)
+                && requested.contains(&p.package_id())
+        })
+        .map(|p| {
+            let rlib = dependencies.contains(&p.package_id());
+            CPackage::from_package(config, p, args, rlib, &rustc_target, &root_output, &profile)
+        })
+        .collect()
+}
+
+/// Generate the pkg-config files and the headers for `--pretend-target`, without compiling
+///
+/// The native libraries rustc would link are not known, the static
+/// consumers do not find them in `Libs.private`.
+pub fn pretend(
+    ws: &mut Workspace,
+    config: &GlobalContext,
+    args: &ArgMatches,
+    default_profile: &str,
+) -> anyhow::Result<Vec<CPackage>> {
+    let mut packages = capi_packages(ws, config, args, default_profile)?;
+    check_sibling_requires(&packages)?;
+    check_soname_policy(&packages, args)?;
+    if args.flag("warn_unused_metadata") {
+        let profile = args.get_profile_name(default_profile, ProfileChecking::Custom)?;
+        crate::unused::warn_unused_metadata(ws, &packages, &profile)?;
+    }
+
+    let siblings: Vec<_> = packages
+        .iter()
+        .map(|p| {
+            let pc = &p.capi_config.pkg_config;
+            (p.name, format!("{} >= {}", pc.filename, pc.version))
+        })
+        .collect();
+
+    for cpkg in packages.iter_mut() {
+        let root_output = cpkg.build_targets.pc.parent().unwrap().to_path_buf();
+        create_dir_all(&root_output)?;
+        cpkg.setup_extra_targets(&root_output, &HashMap::new())?;
+
+        let capi_config = &cpkg.capi_config;
+        let name = &capi_config.library.name;
+        let mut pc = PkgConfig::from_workspace(
+            name,
+            &cpkg.install_paths,
+            args,
+            capi_config,
+            &cpkg.build_targets,
+        );
+        for (sibling, requirement) in &siblings {
+            if *sibling != cpkg.name && cpkg.dependencies.contains(sibling) {
+                pc.add_requires(requirement);
+            }
+        }
+        build_pc_files(ws, &capi_config.pkg_config.filename, &root_output, &pc)?;
+        if let Some(script) = &cpkg.build_targets.config_script {
+            build_config_script(script, &pc)?;
+        }
+        if let Some(system_deps) = &cpkg.build_targets.system_deps {
+            build_system_deps(system_deps, capi_config)?;
+        }
+        if let Some(props) = &cpkg.build_targets.msbuild_props {
+            build_msbuild_props(
+                props,
+                capi_config,
+                &cpkg.install_paths,
+                &cpkg.build_targets,
+                "",
+            )?;
+        }
+
+        let header = &capi_config.header;
+        if !header.enabled {
+            continue;
+        }
+        if header.generation && !header.generator.is_cbindgen() {
+            ws.gctx()
+                .shell()
+                .warn(tr("header-from-library", &[("package", &cpkg.name)]))?;
+            continue;
+        }
+        if header.generation {
+            build_include_file(ws, header, &cpkg.version, &root_output, &cpkg.root_path)?;
+        }
+        if let Some(umbrella) = &header.umbrella {
+            build_umbrella_header(
+                ws,
+                name,
+                header,
+                &cpkg.build_targets,
+                &root_output,
+                umbrella,
+            )?;
+        }
+        if let Some(style) = header.include_guard_style {
+            check_include_guards(&cpkg.build_targets, style)?;
+        }
+        copy_prebuilt_include_file(ws, &cpkg.build_targets, &root_output)?;
+    }
+    check_install_targets(ws, &packages, args)?;
+
+    Ok(packages)
+}
+
+/// Build the binaries to install along with the libraries
+///
+/// `--bin` and `--bins` select them for every package, otherwise `install.bins` does.
+/// They are built by a regular cargo build, linking the crate as rlib.
+pub fn build_bins(
+    gctx: &GlobalContext,
+    args: &ArgMatches,
+    packages: &mut [CPackage],
+    default_profile: &str,
+) -> anyhow::Result<()> {
+    let requested: Vec<String> = args._values_of("bin");
+    let all = args.flag("bins");
+
+    // The workspace of cbuild has the library targets patched
+    let ws = args.workspace(gctx)?;
+    let profile = args.get_profile_name(default_profile, ProfileChecking::Custom)?;
+
+    for cpkg in packages.iter_mut() {
+        let bins = if all {
+            FilterRule::All
+        } else if !requested.is_empty() {
+            FilterRule::Just(requested.clone())
+        } else {
+            match &cpkg.capi_config.install.bins {
+                None => continue,
+                Some(bins) if bins.is_empty() => FilterRule::All,
+                Some(bins) => FilterRule::Just(bins.clone()),
+            }
+        };
+
+        let mut compile_opts =
+            args.compile_options(gctx, CompileMode::Build, Some(&ws), ProfileChecking::Custom)?;
+        compile_opts.build_config.requested_profile = profile;
+        compile_opts.spec = ops::Packages::Packages(vec![cpkg.name.to_string()]);
+        compile_opts.filter = CompileFilter::new(
+            LibRule::False,
+            bins,
+            FilterRule::none(),
+            FilterRule::none(),
+            FilterRule::none(),
+        );
+
+        let compilation = ops::compile(&ws, &compile_opts)?;
+        cpkg.build_targets.bins = compilation
+            .binaries
+            .iter()
+            .map(|output| output.path.clone())
+            .collect();
+    }
+
+    Ok(())
+}
+
 pub fn cbuild(
     ws: &mut Workspace,
     config: &GlobalContext,
     args: &ArgMatches,
     default_profile: &str,
 ) -> anyhow::Result<(Vec<CPackage>, CompileOptions)> {
-    deprecation_warnings(ws, args)?;
+    if args.contains_id("pretend_target") {
+        anyhow::bail!(tr("pretend-target-unsupported", &[]));
+    }
 
     let (target, is_target_overridden) = match args.targets()?.as_slice() {
         [] => (config.load_global_rustc(Some(ws))?.host.to_string(), false),
         [target] => (target.to_string(), true),
-        [..] => anyhow::bail!("Multiple targets not supported yet"),
+        [..] => anyhow::bail!(tr("multiple-targets", &[])),
     };
 
-    let rustc_target = target::Target::new(Some(&target), is_target_overridden)?;
-
-    let library_types = LibraryTypes::from_args(&rustc_target, args);
-
     let profile = args.get_profile_name(default_profile, ProfileChecking::Custom)?;
 
     let profiles = Profiles::new(ws, profile)?;
 
-    let mut compile_opts = compile_options(ws, config, args, profile, CompileMode::Build)?;
-
     // TODO: there must be a simpler way to get the right path.
     let root_output = ws
         .target_dir()
         .as_path_unlocked()
         .to_path_buf()
-        .join(PathBuf::from(target))
+        .join(PathBuf::from(&target))
         .join(profiles.get_dir_name());
 
+    let frozen = args.flag("frozen") || (args.flag("locked") && args.flag("offline"));
+    probe::load(recorded_probes(ws, &root_output), frozen);
+
+    let rustc_target = target::Target::new(Some(&target), is_target_overridden)?;
+
+    let timestamp = reproducible_timestamp(args)?;
+
+    let mut compile_opts = compile_options(ws, config, args, profile, CompileMode::Build)?;
+
     let mut members = Vec::new();
     let mut pristine = false;
+    let key = build_key(ws, &compile_opts, args)?;
 
     let requested: Vec<_> = compile_opts
         .spec
@@ -1125,47 +3954,48 @@ pub fn cbuild(
 
     let capi_feature = InternedString::new("capi");
     let is_relevant_package = |package: &Package| {
-        package.library().is_some()
-            && package.summary().features().contains_key(&capi_feature)
+        package.summary().features().contains_key(&capi_feature)
             && requested.contains(&package.package_id())
     };
 
+    let dependencies = workspace_dependencies(ws);
     for m in ws.members_mut().filter(|p| is_relevant_package(p)) {
-        let cpkg = CPackage::from_package(m, args, library_types, &rustc_target, &root_output)?;
+        let rlib = dependencies.contains(&m.package_id());
+        let mut cpkg =
+            CPackage::from_package(config, m, args, rlib, &rustc_target, &root_output, &profile)?;
 
         pristine |= cpkg.finger_print.load_previous().is_err();
+        cpkg.finger_print.build_key = format!("{key}-{:?}", cpkg.library_types);
 
         members.push(cpkg);
     }
 
+    check_sibling_requires(&members)?;
+    check_soname_policy(&members, args)?;
+    if args.flag("warn_unused_metadata") {
+        crate::unused::warn_unused_metadata(ws, &members, &profile)?;
+    }
+
     // If the cache is somehow missing force a full rebuild;
     compile_opts.build_config.force_rebuild |= pristine;
 
     let exec = Arc::new(Exec::default());
-    let out_dirs = compile_with_exec(
-        ws,
-        &compile_opts,
-        &(exec.clone() as Arc<dyn Executor>),
-        &rustc_target,
-        &root_output,
-        args,
-    )?;
+    // cargo rejects a build without library targets
+    let libraries = ws
+        .members()
+        .any(|p| requested.contains(&p.package_id()) && p.library().is_some());
+    let build_scripts = if libraries {
+        compile_with_exec(ws, &compile_opts, &exec, &rustc_target, &root_output, args)?
+    } else {
+        create_dir_all(&root_output)?;
+        HashSet::new()
+    };
+    let out_dirs = record_out_dirs(&root_output, &build_scripts, &exec.out_dirs.lock().unwrap())?;
 
     for cpkg in members.iter_mut() {
-        let out_dir = out_dirs.get(&cpkg.finger_print.id).map(|p| p.as_path());
-
-        cpkg.build_targets
-            .extra
-            .setup(&cpkg.capi_config, &cpkg.root_path, out_dir)?;
-
-        if cpkg.capi_config.header.generation {
-            let mut header_name = PathBuf::from(&cpkg.capi_config.header.name);
-            header_name.set_extension("h");
-            let from = root_output.join(&header_name);
-            let to = Path::new(&cpkg.capi_config.header.subdirectory).join(&header_name);
-            cpkg.build_targets.extra.include.push((from, to));
-        }
+        cpkg.setup_extra_targets(&root_output, &out_dirs)?;
     }
+    check_install_targets(ws, &members, args)?;
 
     if pristine {
         // restore the default to make sure the tests do not trigger a second rebuild.
@@ -1174,14 +4004,96 @@ pub fn cbuild(
 
     let new_build = exec.ran.load(Ordering::Relaxed);
 
+    // Split and signed before the fingerprint hashes them, the copies of the names with
+    // a `-` are split and signed as well
+    for cpkg in members.iter() {
+        let build_targets = &cpkg.build_targets;
+        let Some(shared_lib) = &build_targets.shared_lib else {
+            continue;
+        };
+        let changed = cpkg.finger_print.load_previous().map_or(true, |previous| {
+            previous.build_key != cpkg.finger_print.build_key
+        });
+        let split = build_targets
+            .stripped_lib()
+            .zip(build_targets.debug_info.as_ref());
+        let missing = split
+            .as_ref()
+            .is_some_and(|(s, d)| !s.exists() || !d.exists());
+        if !new_build && !changed && !missing {
+            continue;
+        }
+        let file_name = shared_lib.file_name().unwrap().to_string_lossy();
+        let built = shared_lib.with_file_name(file_name.replace('-', "_"));
+
+        if let Some((stripped, debug)) = &split {
+            split_debug_info(ws, &built, stripped, debug)?;
+        }
+        if let (Some(identity), true) = (
+            args.get_one::<String>("codesign"),
+            build_targets.target.is_apple(),
+        ) {
+            codesign(ws, identity, &built)?;
+        }
+    }
+
+    let link_lines = exec.link_line.lock().unwrap().clone();
+
+    // The C-API packages built together, the dependent ones require them in their .pc
+    let siblings: Vec<_> = members
+        .iter()
+        .map(|m| {
+            let pc = &m.capi_config.pkg_config;
+            (
+                m.finger_print.id,
+                format!("{} >= {}", pc.filename, pc.version),
+            )
+        })
+        .collect();
+
+    // The C-API workspace members not built together, with --include-dependencies-pc the
+    // requirements of the ones depended on are merged in the .pc
+    let unbuilt: Vec<_> = if args.flag("include_dependencies_pc") {
+        ws.members()
+            .filter(|p| {
+                p.summary().features().contains_key(&capi_feature)
+                    && !requested.contains(&p.package_id())
+            })
+            .map(|p| {
+                let capi_config = load_manifest_capi_config(p, &rustc_target, &profile)?;
+                Ok((p.name(), capi_config.pkg_config))
+            })
+            .collect::<anyhow::Result<_>>()?
+    } else {
+        Vec::new()
+    };
+
     for cpkg in members.iter_mut() {
+        if args.flag("explain_fingerprint") {
+            println!("{}", cpkg.finger_print.explain(new_build)?);
+        }
+
         // it is a new build, build the additional files and update update the cache
-        // if the hash value does not match.
-        if new_build && !cpkg.finger_print.is_valid() {
+        // if the hash value does not match. Nothing is compiled for the packages without
+        // libraries, their files are always written.
+        if (new_build && !cpkg.finger_print.is_valid()) || cpkg.library_types.none() {
+            let header = &cpkg.capi_config.header;
+            if header.enabled && header.generation && !header.generator.is_cbindgen() {
+                header.generator.generate(
+                    ws,
+                    &cpkg.capi_config.library.name,
+                    &cpkg.root_path,
+                    &root_output,
+                    cpkg.build_targets.shared_lib.as_deref(),
+                )?;
+                cpkg.setup_generated_bindings(&root_output)?;
+            }
+
             let name = &cpkg.capi_config.library.name;
+            let library_types = cpkg.library_types;
             let (pkg_config_static_libs, static_libs) = if library_types.only_cdylib() {
                 (String::new(), String::new())
-            } else if let Some(libs) = exec.link_line.lock().unwrap().get(&cpkg.finger_print.id) {
+            } else if let Some(libs) = link_lines.get(&cpkg.finger_print.id) {
                 (static_libraries(libs, &rustc_target), libs.to_string())
             } else {
                 (String::new(), String::new())
@@ -1189,32 +4101,173 @@ pub fn cbuild(
             let capi_config = &cpkg.capi_config;
             let build_targets = &cpkg.build_targets;
 
-            let mut pc = PkgConfig::from_workspace(name, &cpkg.install_paths, args, capi_config);
-            if library_types.only_staticlib() {
-                pc.add_lib(&pkg_config_static_libs);
+            let mut pc = PkgConfig::from_workspace(
+                name,
+                &cpkg.install_paths,
+                args,
+                capi_config,
+                &cpkg.build_targets,
+            );
+
+            let required_siblings: Vec<_> = siblings
+                .iter()
+                .filter(|(id, _)| {
+                    *id != cpkg.finger_print.id && cpkg.dependencies.contains(&id.name())
+                })
+                .collect();
+
+            // The libraries listed by the required siblings are pulled in by pkg-config
+            let mut provided = HashSet::new();
+            for (id, requirement) in required_siblings {
+                pc.add_requires(requirement);
+                if let Some(libs) = link_lines.get(id) {
+                    provided.extend(
+                        static_libraries(libs, &rustc_target)
+                            .split(' ')
+                            .map(str::to_string),
+                    );
+                }
+            }
+            let pkg_config_static_libs = pkg_config_static_libs
+                .split(' ')
+                .filter(|lib| !provided.contains(*lib))
+                .join(" ");
+
+            let include_dependencies = &capi_config.pkg_config.include_dependencies;
+            for (dep, dep_pc) in unbuilt.iter().filter(|(dep, _)| {
+                cpkg.dependencies.contains(dep)
+                    && include_dependencies.get(dep.as_str()) != Some(&false)
+            }) {
+                merge_dependency_pc(ws, &mut pc, dep, dep_pc)?;
+            }
+
+            if !pkg_config_static_libs.is_empty() {
+                if library_types.only_staticlib() {
+                    pc.add_lib(&pkg_config_static_libs);
+                }
+                pc.add_lib_private(&pkg_config_static_libs);
             }
-            pc.add_lib_private(&pkg_config_static_libs);
 
             build_pc_files(ws, &capi_config.pkg_config.filename, &root_output, &pc)?;
+            if let Some(script) = &build_targets.config_script {
+                build_config_script(script, &pc)?;
+            }
+            if let Some(system_deps) = &build_targets.system_deps {
+                build_system_deps(system_deps, capi_config)?;
+            }
+            if let Some(props) = &build_targets.msbuild_props {
+                build_msbuild_props(
+                    props,
+                    capi_config,
+                    &cpkg.install_paths,
+                    build_targets,
+                    &pkg_config_static_libs,
+                )?;
+            }
 
-            if !library_types.only_staticlib() && capi_config.library.import_library {
+            if library_types.cdylib && capi_config.library.import_library {
                 let lib_name = name;
-                build_def_file(ws, lib_name, &rustc_target, &root_output)?;
-                build_implib_file(ws, lib_name, &rustc_target, &root_output)?;
+                let dll_name = build_targets.shared_output_file_name().unwrap_or_default();
+                def::build_def_file(
+                    ws,
+                    lib_name,
+                    &capi_config.library.def,
+                    &rustc_target,
+                    &root_output,
+                )?;
+                build_implib_file(
+                    ws,
+                    lib_name,
+                    &dll_name.to_string_lossy(),
+                    &rustc_target,
+                    &root_output,
+                    args.get_one::<String>("implib_tool").unwrap(),
+                    args.get_one::<PathBuf>("dlltool").map(PathBuf::as_path),
+                )?;
+                build_msvc_implib_file(
+                    ws,
+                    build_targets,
+                    &dll_name.to_string_lossy(),
+                    &root_output.join(format!("{lib_name}.def")),
+                )?;
+                if capi_config.library.delay_load_import_library {
+                    build_delay_implib_file(
+                        ws,
+                        build_targets,
+                        &dll_name.to_string_lossy(),
+                        &root_output.join(format!("{lib_name}.def")),
+                        args.get_one::<PathBuf>("dlltool").map(PathBuf::as_path),
+                    )?;
+                }
             }
 
+            // cargo names the library after the crate, the copy below renames it
+            let crate_targets = BuildTargets::new(
+                &name.replace('-', "_"),
+                &rustc_target,
+                &root_output,
+                library_types,
+                capi_config,
+                args.get_flag("meson"),
+                rustc_target.is_msys2(args.get_one::<String>("msys2").unwrap()),
+            )?;
+            let cargo_dep_info = crate_targets
+                .shared_lib
+                .as_ref()
+                .or(crate_targets.static_lib.as_ref())
+                .map(|lib| lib.with_extension("d"));
+
             if capi_config.header.enabled {
-                let header_name = &capi_config.header.name;
-                if capi_config.header.generation {
-                    build_include_file(
+                if capi_config.header.generation && capi_config.header.generator.is_cbindgen() {
+                    let header_hash = cargo_dep_info.as_deref().and_then(|dep_info| {
+                        header_inputs_hash(
+                            &capi_config.header,
+                            &cpkg.version,
+                            &cpkg.root_path,
+                            dep_info,
+                        )
+                    });
+                    let previous = cpkg.finger_print.load_previous().ok();
+                    let unchanged = header_hash.is_some()
+                        && previous.is_some_and(|p| Some(&p.header_hash) == header_hash.as_ref())
+                        && header_files(&capi_config.header, &root_output)
+                            .iter()
+                            .all(|h| h.exists());
+                    if unchanged {
+                        ws.gctx().shell().verbose(|shell| {
+                            shell.status(
+                                "Fresh",
+                                format!(
+                                    "{}, the header inputs did not change",
+                                    capi_config.header.name
+                                ),
+                            )
+                        })?;
+                    } else {
+                        build_include_file(
+                            ws,
+                            &capi_config.header,
+                            &cpkg.version,
+                            &root_output,
+                            &cpkg.root_path,
+                        )?;
+                    }
+                    cpkg.finger_print.header_hash = header_hash.unwrap_or_default();
+                }
+
+                if let Some(umbrella) = &capi_config.header.umbrella {
+                    build_umbrella_header(
                         ws,
-                        header_name,
-                        &cpkg.version,
+                        name,
+                        &capi_config.header,
+                        build_targets,
                         &root_output,
-                        &cpkg.root_path,
+                        umbrella,
                     )?;
                 }
-
+                if let Some(style) = capi_config.header.include_guard_style {
+                    check_include_guards(build_targets, style)?;
+                }
                 copy_prebuilt_include_file(ws, build_targets, &root_output)?;
             }
 
@@ -1226,6 +4279,7 @@ pub fn cbuild(
                     library_types,
                     capi_config,
                     args.get_flag("meson"),
+                    rustc_target.is_msys2(args.get_one::<String>("msys2").unwrap()),
                 )?;
 
                 if let (Some(from_static_lib), Some(to_static_lib)) = (
@@ -1244,26 +4298,200 @@ pub fn cbuild(
                     from_build_targets.debug_info.as_ref(),
                     build_targets.debug_info.as_ref(),
                 ) {
-                    copy(from_debug_info, to_debug_info)?;
+                    // The dSYM bundles are written for the renamed dylib
+                    if from_debug_info.is_file() {
+                        copy(from_debug_info, to_debug_info)?;
+                    }
                 }
             }
 
+            if let Some(cargo_dep_info) = cargo_dep_info {
+                let filename = &capi_config.pkg_config.filename;
+                let pc_uninstalled = root_output.join(format!("{filename}-uninstalled.pc"));
+                let targets: Vec<&Path> = [
+                    Some(build_targets.pc.as_path()),
+                    Some(pc_uninstalled.as_path()),
+                    build_targets.include.as_deref(),
+                    build_targets.static_lib.as_deref(),
+                    build_targets.shared_lib.as_deref(),
+                    build_targets.def.as_deref(),
+                    build_targets.impl_lib.as_deref(),
+                ]
+                .into_iter()
+                .flatten()
+                .collect();
+                let mut extra_deps = vec![
+                    cpkg.root_path.join("Cargo.toml"),
+                    cpkg.root_path.join(
+                        capi_config
+                            .header
+                            .cbindgen_config
+                            .as_deref()
+                            .unwrap_or(Path::new("cbindgen.toml")),
+                    ),
+                ];
+                extra_deps.extend(
+                    capi_config
+                        .csources
+                        .files
+                        .iter()
+                        .chain(&capi_config.csources.headers)
+                        .map(|f| cpkg.root_path.join(f)),
+                );
+                build_dep_file(
+                    ws,
+                    name,
+                    &root_output,
+                    &cargo_dep_info,
+                    &targets,
+                    &extra_deps,
+                )?;
+            }
+
+            if capi_config.header.enabled {
+                let policy = args.get_one::<String>("header_check").unwrap();
+                check_header_symbols(ws, build_targets, policy)?;
+            }
+
+            let policy = args
+                .get_one::<String>("library_check")
+                .or(capi_config.library.symbols_check.as_ref())
+                .map_or("warn", String::as_str);
+            check_library_symbols(ws, build_targets, policy)?;
+
+            if let Some(timestamp) = timestamp {
+                let filename = &capi_config.pkg_config.filename;
+                let pc_uninstalled = root_output.join(format!("{filename}-uninstalled.pc"));
+                let generated = [
+                    Some(build_targets.pc.as_path()),
+                    Some(pc_uninstalled.as_path()),
+                    build_targets.include.as_deref(),
+                    build_targets.def.as_deref(),
+                    build_targets.impl_lib.as_deref(),
+                ];
+                normalize_timestamps(ws, generated.into_iter().flatten(), timestamp)?;
+            }
+
             // This can be supplied to Rust, so it must be in
             // linker-native syntax
             cpkg.finger_print.static_libs = static_libs;
+            match library_symbols(build_targets) {
+                Ok(symbols) => {
+                    if let Ok(previous) = cpkg.finger_print.load_previous() {
+                        check_removed_symbols(ws, build_targets, &previous.symbols, &symbols)?;
+                    }
+                    cpkg.finger_print.symbols = symbols;
+                }
+                Err(e) => {
+                    ws.gctx().shell().warn(tr(
+                        "removed-symbols-unreadable",
+                        &[("name", &cpkg.name), ("error", &format!("{e:#}"))],
+                    ))?;
+                }
+            }
             cpkg.finger_print.store()?;
         } else {
             // It is not a new build, recover the static_libs value from the cache
-            cpkg.finger_print.static_libs = cpkg.finger_print.load_previous()?.static_libs;
+            let previous = cpkg.finger_print.load_previous()?;
+            cpkg.finger_print.static_libs = previous.static_libs;
+            cpkg.finger_print.header_hash = previous.header_hash;
+            // The artifacts are current for these options, e.g. built before with them
+            if previous.build_key != cpkg.finger_print.build_key {
+                cpkg.finger_print.symbols = previous.symbols;
+                cpkg.finger_print.store()?;
+            }
+        }
+
+        if let (Some(shared_lib), Some(dsym)) = (
+            &cpkg.build_targets.shared_lib,
+            &cpkg.build_targets.debug_info,
+        ) {
+            if new_build
+                && cpkg.build_targets.target.is_apple()
+                && DebugInfoPolicy::from_args(args) != DebugInfoPolicy::None
+            {
+                build_dsym(ws, shared_lib, dsym)?;
+            }
+        }
+
+        if let Some(c_array) = &cpkg.build_targets.c_array {
+            if new_build || !c_array.exists() {
+                let library = match args.get_one::<String>("emit_c_array").unwrap().as_str() {
+                    "cdylib" => cpkg.build_targets.shared_lib.as_ref(),
+                    _ => cpkg.build_targets.static_lib.as_ref(),
+                };
+                let Some(library) = library else {
+                    anyhow::bail!(tr("emit-c-array-library", &[]));
+                };
+                build_c_array(ws, &cpkg.capi_config.library.name, library, c_array)?;
+            }
+        }
+
+        ws.gctx().shell().verbose(|s| {
+            let path = &format!("PKG_CONFIG_PATH=\"{}\"", root_output.display());
+            s.note(path)
+        })?;
+    }
+
+    if let Some(probes) = probe::changed() {
+        for cpkg in &members {
+            cpkg.finger_print.store_probes(&probes)?;
         }
+    }
+
+    Ok((members, compile_opts))
+}
+
+/// Set up the MSVC environment for the C tests when not run from a developer prompt
+///
+/// Without `INCLUDE`, `LIB` and the compiler in the `PATH` the inline C tests do not build.
+fn setup_msvc_env(ws: &Workspace, target: &target::Target) -> anyhow::Result<()> {
+    if target.env != "msvc" || std::env::var_os("VCINSTALLDIR").is_some() {
+        return Ok(());
+    }
+
+    let Some(cl) = cc::windows_registry::find_tool(&target.arch, "cl.exe") else {
+        ws.gctx().shell().warn(tr("msvc-not-found", &[]))?;
+        return Ok(());
+    };
+
+    for (key, value) in cl.get_envs() {
+        std::env::set_var(key, value);
+    }
+
+    Ok(())
+}
+
+/// The flags to build the C tests against the libraries and the headers in the build directory
+fn build_dir_flags(pkg: &CPackage, flags: &mut std::ffi::OsString) {
+    let builddir = pkg.build_targets.pc.parent().unwrap();
+
+    flags.push("-I");
+    flags.push(builddir);
+    flags.push(" ");
 
-        ws.gctx().shell().verbose(|s| {
-            let path = &format!("PKG_CONFIG_PATH=\"{}\"", root_output.display());
-            s.note(path)
-        })?;
+    // We push the full path here to work around macos ld not supporting the -l:{filename} syntax
+    if let Some(static_lib_path) = &pkg.build_targets.static_lib {
+        flags.push(static_lib_path);
     }
 
-    Ok((members, compile_opts))
+    // We push the static_libs as CFLAGS as well to avoid mangling the options on msvc
+    flags.push(" ");
+    flags.push(&pkg.finger_print.static_libs);
+
+    // The tests may include the headers of the dependencies, e.g. glib.h
+    let pc = &pkg.capi_config.pkg_config;
+    let requirements = pc.requires.iter().chain(&pc.requires_private);
+    for requirement in requirements
+        .flat_map(|r| r.split(','))
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+    {
+        for flag in probe_cflags(requirement).unwrap_or_default() {
+            flags.push(" ");
+            flags.push(flag);
+        }
+    }
 }
 
 pub fn ctest(
@@ -1286,6 +4514,12 @@ pub fn ctest(
 
     compile_opts.target_rustc_args = None;
 
+    let host = ws.gctx().load_global_rustc(Some(ws))?.host.to_string();
+    let triple = match compile_opts.build_config.single_requested_kind()? {
+        CompileKind::Target(t) => t.short_name().to_string(),
+        CompileKind::Host => host.clone(),
+    };
+
     let ops = ops::TestOptions {
         no_run: args.flag("no-run"),
         no_fail_fast: args.flag("no-fail-fast"),
@@ -1299,26 +4533,151 @@ pub fn ctest(
     use std::ffi::OsString;
 
     let mut cflags = OsString::new();
+    let mut inline_cflags = OsString::new();
+    // The inline C tests are built with the default compiler
+    let inline_msvc = crate::ctest::compiler(&triple, &host, false, None)?.is_like_msvc();
 
-    for pkg in packages {
-        let static_lib_path = pkg.build_targets.static_lib.as_ref().unwrap();
-        let builddir = static_lib_path.parent().unwrap();
+    let staged = if args.flag("installed") {
+        crate::ctest::stage(ws, packages)?
+    } else {
+        Vec::new()
+    };
 
-        cflags.push("-I");
-        cflags.push(builddir);
-        cflags.push(" ");
+    for (i, pkg) in packages.iter().enumerate() {
+        let mut flags = OsString::new();
+
+        if let Some(staged) = staged.get(i) {
+            flags.push(staged);
+        } else {
+            build_dir_flags(pkg, &mut flags);
+        }
+
+        cflags.push(&flags);
+        inline_cflags.push(&flags);
 
-        // We push the full path here to work around macos ld not supporting the -l:{filename} syntax
-        cflags.push(static_lib_path);
+        // The test programs get the C standard of their compiler
+        let test = pkg.capi_config.test.with_args(args);
+        for flag in test.flags(inline_msvc) {
+            inline_cflags.push(" ");
+            inline_cflags.push(flag);
+        }
+        for flag in &test.cflags {
+            cflags.push(" ");
+            cflags.push(flag);
+        }
 
-        // We push the static_libs as CFLAGS as well to avoid mangling the options on msvc
         cflags.push(" ");
-        cflags.push(&pkg.finger_print.static_libs);
+        inline_cflags.push(" ");
+    }
+
+    std::env::set_var("INLINE_C_RS_CFLAGS", &inline_cflags);
+
+    if let Some(pkg) = packages.first() {
+        setup_msvc_env(ws, &pkg.build_targets.target)?;
+    }
+
+    ops::run_tests(ws, &ops, &test_args)?;
+
+    crate::ctest::run_c_tests(ws, packages, args, &cflags, &triple, &host)?;
+
+    Ok(())
+}
+
+/// The first line, 1-based, where the two texts differ
+fn first_difference(expected: &str, actual: &str) -> Option<usize> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+
+    (1..).find_map(|line| match (expected_lines.next(), actual_lines.next()) {
+        (None, None) => Some(None),
+        (e, a) if e == a => None,
+        _ => Some(Some(line)),
+    })?
+}
+
+/// Regenerate the headers and compare them with the copies committed in `dir`
+///
+/// `dir` defaults to the package root, the headers are looked up with the
+/// same relative path they are generated with.
+pub fn verify_header(ws: &Workspace, packages: &[CPackage], args: &ArgMatches) -> CliResult {
+    let mut diverging = Vec::new();
+
+    for cpkg in packages {
+        let header = &cpkg.capi_config.header;
+        if !header.enabled || !header.generation {
+            continue;
+        }
+        if !header.generator.is_cbindgen() {
+            ws.gctx()
+                .shell()
+                .warn(tr("verify-header-generator", &[("package", &cpkg.name)]))?;
+            continue;
+        }
+
+        let out_dir = ws
+            .target_dir()
+            .as_path_unlocked()
+            .join("cargo-c-verify-header")
+            .join(cpkg.name.as_str());
+        if out_dir.exists() {
+            cargo_util::paths::remove_dir_all(&out_dir)?;
+        }
+        create_dir_all(&out_dir)?;
+
+        build_include_file(ws, header, &cpkg.version, &out_dir, &cpkg.root_path)?;
+
+        let generated = header.generated_names();
+
+        // The umbrella header includes the installed headers as well
+        if let Some(umbrella) = &header.umbrella {
+            let mut extra = ExtraTargets::default();
+            extra.setup(&cpkg.capi_config, &cpkg.root_path, None, &HashMap::new())?;
+            let subdirectory = Path::new(&header.subdirectory);
+            let mut includes = extra.include;
+            includes.extend(
+                generated
+                    .iter()
+                    .map(|name| (out_dir.join(name), subdirectory.join(name))),
+            );
+            let out = umbrella_header(
+                &cpkg.capi_config.library.name,
+                header,
+                &includes,
+                &subdirectory.join(umbrella),
+            );
+            write(out_dir.join(umbrella), out)?;
+        }
+
+        let committed_dir = args
+            .get_one::<PathBuf>("dir")
+            .map(|dir| ws.gctx().cwd().join(dir))
+            .unwrap_or_else(|| cpkg.root_path.clone());
+
+        let names = generated.into_iter().chain(header.umbrella.clone());
+
+        for name in names {
+            let generated = read(&out_dir.join(&name))?;
+            let committed_path = committed_dir.join(&name);
+            let problem = match read(&committed_path) {
+                Err(_) => Some(tr("verify-header-missing", &[])),
+                Ok(committed) => first_difference(&committed, &generated)
+                    .map(|line| tr("verify-header-differs", &[("line", &line)])),
+            };
+            if let Some(problem) = problem {
+                diverging.push(format!("{} {problem}", committed_path.display()));
+            }
+        }
     }
 
-    std::env::set_var("INLINE_C_RS_CFLAGS", cflags);
+    if !diverging.is_empty() {
+        Err(anyhow::anyhow!(
+            "{}\n  {}",
+            tr("verify-header-diverging", &[]),
+            diverging.join("\n  ")
+        ))?;
+    }
 
-    ops::run_tests(ws, &ops, &test_args)
+    Ok(())
 }
 
 #[cfg(test)]
@@ -1331,13 +4690,94 @@ mod tests {
             name: "example".to_string(),
             version: Version::parse(version).unwrap(),
             install_subdir: None,
+            origin_rpath: false,
+            rpath: Vec::new(),
             versioning: true,
             version_suffix_components: None,
             import_library: true,
+            delay_load_import_library: false,
+            msvc_import_library: false,
             rustflags: vec![],
+            current_version: None,
+            compatibility_version: None,
+            install_name: InstallName::Absolute,
+            def: DefCApiConfig::default(),
+            msbuild_props: false,
+            crt_static: false,
+            default_library_types: None,
+            lto: None,
+            symbols_check: None,
         }
     }
 
+    #[test]
+    fn include_guards() {
+        let guard =
+            "/* License\n * text #pragma once */\n// note\n#ifndef FOO_H\n#define FOO_H\n#endif\n";
+        assert_eq!(
+            detect_include_guard_style(guard),
+            Some(IncludeGuardStyle::Guard)
+        );
+        assert_eq!(
+            detect_include_guard_style("// c\n#pragma once\n"),
+            Some(IncludeGuardStyle::PragmaOnce)
+        );
+        assert_eq!(
+            detect_include_guard_style("#ifndef FOO_H\n#define BAR_H\n"),
+            None
+        );
+        assert_eq!(detect_include_guard_style("#include <stdint.h>\n"), None);
+    }
+
+    #[test]
+    fn umbrella() {
+        let headers = [
+            (
+                Path::new("foo/foo.h"),
+                "#include \"foo/types.h\"\n".to_string(),
+            ),
+            (
+                Path::new("foo/io/io.h"),
+                "#  include <foo/foo.h>\n".to_string(),
+            ),
+            (Path::new("foo/types.h"), String::new()),
+        ];
+        assert_eq!(umbrella_order(&headers), [2, 0, 1]);
+        assert_eq!(
+            relative_path(Path::new("foo/io"), Path::new("foo/types.h")),
+            Path::new("../types.h")
+        );
+    }
+
+    #[test]
+    fn def_file() {
+        let value = toml::Value::Table(toml::toml! {
+            exclude = ["internal_*"]
+            aliases = { foo = "foo_impl" }
+            ordinals = { foo = 1, bar = 2 }
+        });
+        let def = DefCApiConfig::from_value(&value).unwrap();
+        assert_eq!(def.ordinal("bar"), " @2");
+        assert_eq!(def.ordinal("foo_impl"), "");
+        assert_eq!(def.aliases["foo"], "foo_impl");
+
+        let value = toml::Value::Table(toml::toml! { ordinals = { foo = "one" } });
+        assert!(DefCApiConfig::from_value(&value).is_err());
+    }
+
+    #[test]
+    fn test_std_flag() {
+        let test = TestCApiConfig {
+            cflags: vec!["-Wall".into()],
+            std: Some("c11".into()),
+            cc: None,
+        };
+        // clang-cl on a gnu target, clang on a msvc one
+        assert_eq!(test.flags(true), ["-Wall", "/std:c11"]);
+        assert_eq!(test.flags(false), ["-Wall", "-std=c11"]);
+        assert_eq!(TestCApiConfig::default().std_flag(true), None);
+    }
+
     #[test]
     pub fn test_semver_zero_zero_zero() {
         let library = make_test_library_config("0.0.0");
@@ -1383,6 +4823,667 @@ mod tests {
         assert_eq!(sover, "1.0.0");
     }
 
+    #[test]
+    pub fn test_unversioned_soname() {
+        let mut library = make_test_library_config("0.3.1");
+        assert_eq!(library.unversioned_soname(), None);
+        library.version_suffix_components = Some(VersionSuffix::Major);
+        assert!(library
+            .unversioned_soname()
+            .unwrap()
+            .contains("version_suffix_components"));
+
+        let mut library = make_test_library_config("0.0.4");
+        library.version_suffix_components = Some(VersionSuffix::MajorMinor);
+        assert!(library.unversioned_soname().is_some());
+        let mut library = make_test_library_config("0.0.0");
+        library.version_suffix_components = Some(VersionSuffix::Major);
+        assert_eq!(library.unversioned_soname(), None);
+        library.versioning = false;
+        assert!(library.unversioned_soname().unwrap().contains("versioning"));
+    }
+
+    #[test]
+    pub fn test_apple_version() {
+        let valid = |v: &str| parse_apple_version("v", &toml::Value::from(v)).is_ok();
+
+        assert!(valid("1"));
+        assert!(valid("1.2"));
+        assert!(valid("65535.255.255"));
+        assert!(!valid("65536"));
+        assert!(!valid("1.256"));
+        assert!(!valid("1.2.3.4"));
+        assert!(!valid("1.a"));
+        assert!(!parse_apple_version("v", &toml::Value::from(1)).is_ok());
+    }
+
+    #[test]
+    pub fn test_install_name() {
+        let libdir = Path::new("/usr/local/lib");
+
+        assert_eq!(
+            InstallName::from_str("absolute").dir(libdir),
+            "/usr/local/lib"
+        );
+        assert_eq!(InstallName::from_str("rpath").dir(libdir), "@rpath");
+        assert_eq!(
+            InstallName::from_str("@loader_path/../lib/").dir(libdir),
+            "@loader_path/../lib"
+        );
+    }
+
+    #[test]
+    pub fn test_hash_file() {
+        use std::hash::Hasher;
+
+        let path = std::env::temp_dir().join(format!("cargo-c-hash-{}", std::process::id()));
+        let content: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        write(&path, &content).unwrap();
+
+        let mut whole = StableHasher::new();
+        whole.write(&content);
+        let mut streamed = StableHasher::new();
+        hash_file(&mut streamed, &path).unwrap();
+        assert_eq!(whole.finish(), streamed.finish());
+
+        // The hashes recorded by the previous builds stay valid
+        assert_eq!(file_hash(&path).unwrap(), "9431335453465031905");
+
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(file_hash(&path), None);
+    }
+
+    #[test]
+    pub fn test_inputs_hash() {
+        let inputs = |lib: Option<&str>| {
+            vec![
+                ("install-paths".to_string(), Some("1".to_string())),
+                ("file:libfoo.a".to_string(), lib.map(str::to_string)),
+            ]
+        };
+
+        let hash = inputs_hash(&inputs(Some("2"))).unwrap();
+        assert_eq!(hash, "8015035518767837584");
+        assert_ne!(inputs_hash(&inputs(Some("3"))).unwrap(), hash);
+        // An unreadable file is never a match
+        assert_eq!(inputs_hash(&inputs(None)), None);
+
+        let mut renamed = inputs(Some("2"));
+        renamed[1].0 = "file:libbar.a".to_string();
+        assert_ne!(inputs_hash(&renamed).unwrap(), hash);
+    }
+
+    #[test]
+    pub fn test_origin_rpath() {
+        let mut library = make_test_library_config("1.0.0");
+        library.install_subdir = Some("gstreamer-1.0".into());
+        assert_eq!(library.origin_rpath(false), None);
+
+        library.origin_rpath = true;
+        assert_eq!(library.origin_rpath(false).as_deref(), Some("$ORIGIN/.."));
+        library.install_subdir = Some("foo/plugins/".into());
+        assert_eq!(
+            library.origin_rpath(true).as_deref(),
+            Some("@loader_path/../..")
+        );
+        library.install_subdir = None;
+        assert_eq!(library.origin_rpath(false), None);
+
+        library.install_subdir = Some("plugins".into());
+        library.rpath = vec!["/opt/foo/lib".into()];
+        assert_eq!(library.rpaths(false), ["$ORIGIN/..", "/opt/foo/lib"]);
+    }
+
+    #[test]
+    pub fn test_cbindgen_config() {
+        let capi: toml::Value = toml::from_str(
+            r#"
+            header.cbindgen_config = "cbindgen-c.toml"
+            [target.'cfg(target_env = "msvc")'.header]
+            cbindgen_config = "cbindgen-msvc.toml"
+            [target.'cfg(target_os = "macos")'.header]
+            cbindgen_config = "cbindgen-apple.toml"
+            "#,
+        )
+        .unwrap();
+
+        let config = |triple| {
+            let target = target::Target::new(Some(triple), true).unwrap();
+            let capi = effective_capi_config(&capi, &target, "dev").unwrap();
+            cbindgen_config(capi.get("header")).unwrap().unwrap()
+        };
+
+        assert_eq!(
+            config("x86_64-unknown-linux-gnu"),
+            Path::new("cbindgen-c.toml")
+        );
+        assert_eq!(
+            config("x86_64-pc-windows-gnu"),
+            Path::new("cbindgen-c.toml")
+        );
+        assert_eq!(
+            config("x86_64-pc-windows-msvc"),
+            Path::new("cbindgen-msvc.toml")
+        );
+        assert_eq!(
+            config("aarch64-apple-darwin"),
+            Path::new("cbindgen-apple.toml")
+        );
+    }
+
+    #[test]
+    pub fn test_dependency_header() {
+        let value = |s: &str| toml::Value::String(s.into());
+        assert_eq!(
+            DependencyHeader::from_value(&value("header")).unwrap(),
+            DependencyHeader::Header
+        );
+        assert_eq!(
+            DependencyHeader::from_value(&value("inline")).unwrap(),
+            DependencyHeader::Inline
+        );
+        assert!(DependencyHeader::from_value(&value("deps")).is_err());
+        assert_eq!(
+            DependencyHeader::file_name("foo-types"),
+            Path::new("deps/foo-types.h")
+        );
+    }
+
+    #[test]
+    pub fn test_first_difference() {
+        assert_eq!(first_difference("a\nb\n", "a\nb\n"), None);
+        assert_eq!(first_difference("a\nb\n", "a\nc\n"), Some(2));
+        assert_eq!(first_difference("a\n", "a\nb\n"), Some(2));
+        assert_eq!(first_difference("a\nb\n", "a\n"), Some(2));
+    }
+
+    #[test]
+    pub fn test_default_library_types() {
+        let musl = target::Target::new(Some("x86_64-unknown-linux-musl"), false).unwrap();
+        let app = crate::cli::subcommand_build("build", "");
+        let types = |argv: &[&str], library: &LibraryCApiConfig| {
+            let args = app.clone().get_matches_from(argv);
+            let types = LibraryTypes::from_args(&musl, &args, library);
+            (types.staticlib, types.cdylib)
+        };
+
+        let mut library = make_test_library_config("0.1.0");
+        assert_eq!(types(&["build"], &library), (true, false));
+
+        library.default_library_types = Some(vec!["cdylib".into()]);
+        assert_eq!(types(&["build"], &library), (false, true));
+        assert_eq!(
+            types(&["build", "--library-type", "staticlib"], &library),
+            (true, false)
+        );
+    }
+
+    #[test]
+    pub fn test_install_category_targets() {
+        let install: toml::Value = toml::from_str(
+            r#"
+            [locale]
+            asset = [{ from = "i18n/*.mo" }]
+            "#,
+        )
+        .unwrap();
+        let froms = |install: &toml::Value| {
+            install_category_targets(Some(install), "locale", ["po/*.mo", "po/*.mo"])
+                .unwrap()
+                .into_iter()
+                .map(|t| match t {
+                    InstallTarget::Asset(p) => format!("asset {}", p.from),
+                    InstallTarget::Generated(p) => format!("generated {}", p.from),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(froms(&install), ["asset i18n/*.mo"]);
+        assert!(froms(&toml::Value::Table(Default::default())).is_empty());
+
+        let mut install = install;
+        install["locale"]
+            .as_table_mut()
+            .unwrap()
+            .insert("defaults".into(), true.into());
+        assert_eq!(
+            froms(&install),
+            ["asset i18n/*.mo", "asset po/*.mo", "generated po/*.mo"]
+        );
+    }
+
+    #[test]
+    pub fn test_pkg_config_link() {
+        let capi: toml::Value = toml::from_str(
+            r#"
+            pkg_config.link = ["-lfoo", "-framework CoreFoundation", "-F", "/opt/frameworks"]
+
+            [target.'cfg(windows)'.pkg_config]
+            link = ["-lws2_32"]
+            "#,
+        )
+        .unwrap();
+        let link = |triple| {
+            let target = target::Target::new(Some(triple), false).unwrap();
+            let capi = effective_capi_config(&capi, &target, "dev").unwrap();
+            pkg_config_link(&capi["pkg_config"], &target).unwrap()
+        };
+
+        assert_eq!(
+            link("aarch64-apple-darwin"),
+            ["-lfoo", "-framework CoreFoundation", "-F/opt/frameworks"]
+        );
+        assert_eq!(link("x86_64-unknown-linux-gnu"), ["-lfoo"]);
+        assert_eq!(link("x86_64-pc-windows-gnu"), ["-lws2_32"]);
+    }
+
+    #[test]
+    pub fn test_parse_dep_info() {
+        let content = concat!(
+            "/out/libfoo.so: /src/lib.rs /src/with\\ space.rs\n",
+            "\n",
+            "/src/lib.rs:\n",
+        );
+
+        assert_eq!(
+            parse_dep_info(content),
+            [
+                PathBuf::from("/src/lib.rs"),
+                PathBuf::from("/src/with space.rs")
+            ]
+        );
+        assert_eq!(
+            escape_dep_path(Path::new("/src/with space.rs")),
+            "/src/with\\ space.rs"
+        );
+
+        // The Windows separators are not escapes
+        let paths = [
+            PathBuf::from(r"C:\src\lib.rs"),
+            PathBuf::from(r"C:\Program Files\foo #1\$lib.rs"),
+        ];
+        let line = format!(
+            r"C:\out\foo.dll: {}",
+            paths.iter().map(|p| escape_dep_path(p)).join(" ")
+        );
+        assert_eq!(
+            line,
+            r"C:\out\foo.dll: C:\src\lib.rs C:\Program\ Files\foo\ \#1\$$lib.rs"
+        );
+        assert_eq!(parse_dep_info(&line), paths);
+    }
+
+    #[test]
+    pub fn test_out_dirs() {
+        use cargo::core::SourceId;
+
+        let source = SourceId::for_path(Path::new("/src")).unwrap();
+        let id = |name: &str, version: &str| PackageId::try_new(name, version, source).unwrap();
+        let [foo_1, foo_2, bar] = [
+            id("foo-sys", "0.1.0"),
+            id("foo-sys", "0.2.0"),
+            id("bar-sys", "0.1.0"),
+        ];
+        let out_dir = |id: PackageId| PathBuf::from(format!("/build/{}/out", id.version()));
+        let root_output = std::env::temp_dir().join(format!("cargo-c-out-{}", std::process::id()));
+
+        let compiled = HashMap::from([(foo_1, out_dir(foo_1)), (bar, out_dir(bar))]);
+        let out_dirs =
+            record_out_dirs(&root_output, &HashSet::from([foo_1, bar]), &compiled).unwrap();
+        assert_eq!(out_dirs, compiled);
+
+        // The fresh packages are not compiled
+        let out_dirs =
+            record_out_dirs(&root_output, &HashSet::from([foo_1, bar]), &HashMap::new()).unwrap();
+        assert_eq!(out_dirs, compiled);
+
+        // foo-sys is updated, bar-sys is not a dependency anymore
+        let compiled = HashMap::from([(foo_2, out_dir(foo_2))]);
+        let out_dirs = record_out_dirs(&root_output, &HashSet::from([foo_2]), &compiled).unwrap();
+        assert_eq!(out_dirs, compiled);
+        assert_eq!(
+            dependency_out_dirs(&out_dirs, &["foo-sys".into(), "baz".into()]),
+            HashMap::from([("foo-sys".to_string(), out_dir(foo_2))])
+        );
+        assert!(dependency_out_dirs(&out_dirs, &[]).is_empty());
+
+        std::fs::remove_dir_all(root_output).unwrap();
+    }
+
+    /// A workspace of path members in a temporary directory, `(name, manifest tail)`
+    fn temp_workspace(name: &str, members: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("cargo-c-{name}-{}", std::process::id()));
+        let names = members.iter().map(|(m, _)| format!("\"{m}\"")).join(", ");
+        create_dir_all(&root).unwrap();
+        write(
+            root.join("Cargo.toml"),
+            format!("[workspace]\nmembers = [{names}]\nresolver = \"2\"\n"),
+        )
+        .unwrap();
+        for (member, tail) in members {
+            create_dir_all(root.join(member).join("src")).unwrap();
+            write(
+                root.join(member).join("Cargo.toml"),
+                format!("[package]\nname = \"{member}\"\nversion = \"0.1.0\"\n{tail}"),
+            )
+            .unwrap();
+            write(root.join(member).join("src/lib.rs"), "").unwrap();
+        }
+
+        root
+    }
+
+    #[test]
+    pub fn test_debuginfo_rustc_args() {
+        let target = |os: &str| target::Target {
+            is_target_overridden: false,
+            cfg: Vec::new(),
+            arch: "x86_64".into(),
+            os: os.into(),
+            env: String::new(),
+        };
+
+        assert!(DebugInfoPolicy::Keep
+            .rustc_args(&target("linux"))
+            .is_empty());
+        assert!(DebugInfoPolicy::Separate
+            .rustc_args(&target("linux"))
+            .is_empty());
+        assert_eq!(
+            DebugInfoPolicy::None.rustc_args(&target("macos")),
+            [
+                "-C",
+                "debuginfo=0",
+                "-C",
+                "strip=debuginfo",
+                "-C",
+                "split-debuginfo=off"
+            ]
+        );
+        assert_eq!(
+            DebugInfoPolicy::None.rustc_args(&target("windows")),
+            ["-C", "debuginfo=0", "-C", "strip=debuginfo"]
+        );
+    }
+
+    #[test]
+    pub fn test_workspace_dependencies() {
+        let root = temp_workspace(
+            "dependencies",
+            &[
+                ("a", ""),
+                ("b", "[dependencies]\na = { path = \"../a\" }\n"),
+                ("c", "[build-dependencies]\nb = { path = \"../b\" }\n"),
+                ("d", "[dev-dependencies]\ne = { path = \"../e\" }\n"),
+                ("e", ""),
+                // A registry crate named like a member
+                ("f", "[dependencies]\nd = \"0.1\"\n"),
+            ],
+        );
+        let gctx = GlobalContext::default().unwrap();
+        let ws = Workspace::new(&root.join("Cargo.toml"), &gctx).unwrap();
+
+        let dependencies: BTreeSet<_> = workspace_dependencies(&ws)
+            .iter()
+            .map(|id| id.name().to_string())
+            .collect();
+        assert_eq!(dependencies, ["a", "b"].map(String::from).into());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    pub fn test_noarch_packages() {
+        let capi = "[features]\ncapi = []\n";
+        let data = format!("{capi}[package.metadata.capi.header]\ngeneration = false\n");
+        let root = temp_workspace("noarch", &[("lib", capi), ("data", &data)]);
+        std::fs::remove_file(root.join("data/src/lib.rs")).unwrap();
+        write(root.join("data/src/main.rs"), "fn main() {}").unwrap();
+
+        let gctx = GlobalContext::default().unwrap();
+        let mut ws = Workspace::new(&root.join("Cargo.toml"), &gctx).unwrap();
+        let target = target::Target::new(Some("x86_64-unknown-linux-gnu"), false).unwrap();
+        let args = crate::cli::subcommand_build("build", "").get_matches_from(["build"]);
+        let root_output = root.join("target");
+
+        let packages: HashMap<_, _> = ws
+            .members_mut()
+            .map(|p| {
+                let name = p.name().to_string();
+                let cpkg = CPackage::from_package(
+                    &gctx,
+                    p,
+                    &args,
+                    false,
+                    &target,
+                    &root_output,
+                    "release",
+                )
+                .unwrap();
+                (name, cpkg)
+            })
+            .collect();
+
+        let lib = &packages["lib"];
+        assert!(!lib.library_types.none());
+        assert_eq!(
+            lib.install_paths.pkgconfigdir,
+            lib.install_paths.libdir.join("pkgconfig")
+        );
+
+        let data = &packages["data"];
+        assert!(data.library_types.none());
+        assert!(data.build_targets.static_lib.is_none() && data.build_targets.shared_lib.is_none());
+        assert_eq!(
+            data.install_paths.pkgconfigdir,
+            Path::new("/usr/local/share/pkgconfig")
+        );
+        let pc = PkgConfig::from_workspace(
+            "data",
+            &data.install_paths,
+            &args,
+            &data.capi_config,
+            &data.build_targets,
+        );
+        assert!(pc.render().contains("\nLibs: \n"));
+        assert!(pc.uninstalled(&root_output).render().contains("\nLibs: \n"));
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    pub fn test_split_rlib_units() {
+        use cargo::core::compiler::{CompileMode, CrateType};
+
+        let root = temp_workspace(
+            "rlib-units",
+            &[
+                (
+                    "a",
+                    "[lib]\ncrate-type = [\"lib\", \"staticlib\", \"cdylib\"]\n",
+                ),
+                ("b", "[dependencies]\na = { path = \"../a\" }\n"),
+            ],
+        );
+        let gctx = GlobalContext::default().unwrap();
+        let ws = Workspace::new(&root.join("Cargo.toml"), &gctx).unwrap();
+        let interner = UnitInterner::new();
+        let crate_types = |unit: &Unit| unit.target.rustc_crate_types();
+
+        for (spec, linked) in [(vec!["a", "b"], true), (vec!["a"], false)] {
+            let mut options = CompileOptions::new(&gctx, CompileMode::Build).unwrap();
+            options.spec = ops::Packages::Packages(spec.into_iter().map(String::from).collect());
+            let mut bcx = create_bcx(&ws, &options, &interner).unwrap();
+            let rlibs = split_rlib_units(&mut bcx, &interner);
+
+            let lib = bcx.roots.iter().find(|u| u.pkg.name() == "a").unwrap();
+            assert_eq!(crate_types(lib), [CrateType::Staticlib, CrateType::Cdylib]);
+            assert_eq!(rlibs.contains_key(lib), linked);
+
+            // b links the rlib of a
+            let a_units: Vec<_> = bcx
+                .unit_graph
+                .keys()
+                .filter(|u| u.pkg.name() == "a")
+                .collect();
+            assert_eq!(a_units.len(), if linked { 2 } else { 1 });
+            if let Some(b) = bcx.roots.iter().find(|u| u.pkg.name() == "b") {
+                let dep = &bcx.unit_graph[b][0].unit;
+                assert_eq!(crate_types(dep), [CrateType::Lib]);
+                assert_eq!(Some(dep), rlibs.get(lib));
+            }
+        }
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    pub fn test_extra_headers() {
+        let root = std::env::temp_dir().join(format!("cargo-c-headers-{}", std::process::id()));
+        create_dir_all(root.join("src")).unwrap();
+        write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"foo\"\nversion = \"0.1.0\"\n\n[lib]\ncrate-type = [\"cdylib\"]\n",
+        )
+        .unwrap();
+        write(
+            root.join("src/lib.rs"),
+            r#"
+#[repr(C)]
+pub struct FooCtx {
+    pub flags: u32,
+}
+
+#[no_mangle]
+pub extern "C" fn foo_new() -> *mut FooCtx {
+    std::ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn foo_io_open(_ctx: *mut FooCtx) {}
+
+#[no_mangle]
+pub extern "C" fn foo_io_close(_ctx: *mut FooCtx) {}
+"#,
+        )
+        .unwrap();
+        write(root.join("cbindgen.toml"), "language = \"C\"\n").unwrap();
+
+        let header = HeaderCApiConfig {
+            name: "foo".into(),
+            subdirectory: "foo".into(),
+            generation: true,
+            enabled: true,
+            generator: Default::default(),
+            cbindgen_config: None,
+            filter: Default::default(),
+            headers: vec![ExtraHeader {
+                name: "foo/io".into(),
+                functions: vec![glob::Pattern::new("foo_io_*").unwrap()],
+                includes: Vec::new(),
+            }],
+            cpp: None,
+            umbrella: None,
+            include_guard_style: None,
+            dependencies: Default::default(),
+        };
+        let output = root.join("out");
+        create_dir_all(&output).unwrap();
+        write_headers(&header, &Version::new(0, 1, 0), &output, &root).unwrap();
+
+        let main = read(&output.join("foo.h")).unwrap();
+        let io = read(&output.join("foo/io.h")).unwrap();
+        assert!(main.contains("typedef struct FooCtx"));
+        assert!(main.contains("foo_new("));
+        assert!(!main.contains("foo_io_open("));
+
+        // The types come from the main header
+        assert!(io.contains("#include \"../foo.h\""));
+        assert!(!io.contains("FooCtx {"));
+        assert!(!io.contains("foo_new("));
+        assert!(io.contains("foo_io_open(") && io.contains("foo_io_close("));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    pub fn test_header_inputs_hash() {
+        let root = std::env::temp_dir().join(format!("cargo-c-header-hash-{}", std::process::id()));
+        create_dir_all(root.join("src")).unwrap();
+        write(root.join("Cargo.toml"), "[package]\nname = \"foo\"\n").unwrap();
+        write(root.join("src/lib.rs"), "").unwrap();
+        let dep_info = root.join("foo.d");
+        write(
+            &dep_info,
+            format!("{0}/foo.so: {0}/src/lib.rs\n", root.display()),
+        )
+        .unwrap();
+
+        let mut header = HeaderCApiConfig {
+            name: "foo".into(),
+            subdirectory: "foo".into(),
+            generation: true,
+            enabled: true,
+            generator: Default::default(),
+            cbindgen_config: None,
+            filter: Default::default(),
+            headers: Vec::new(),
+            cpp: None,
+            umbrella: None,
+            include_guard_style: None,
+            dependencies: Default::default(),
+        };
+        let version = Version::new(0, 1, 0);
+        let hash =
+            |header: &HeaderCApiConfig| header_inputs_hash(header, &version, &root, &dep_info);
+
+        let first = hash(&header).unwrap();
+        assert_eq!(hash(&header).unwrap(), first);
+        write(root.join("src/lib.rs"), "pub struct Foo;\n").unwrap();
+        assert_ne!(hash(&header).unwrap(), first);
+
+        // The sources of the dependencies are not tracked
+        write(root.join("cbindgen.toml"), "[parse]\nparse_deps = true\n").unwrap();
+        assert_eq!(hash(&header), None);
+        std::fs::remove_file(root.join("cbindgen.toml")).unwrap();
+        header
+            .dependencies
+            .insert("bar".into(), DependencyHeader::Inline);
+        assert_eq!(hash(&header), None);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    pub fn test_generated_names() {
+        let header = HeaderCApiConfig {
+            name: "foo".into(),
+            subdirectory: "foo".into(),
+            generation: true,
+            enabled: true,
+            generator: Default::default(),
+            cbindgen_config: None,
+            filter: Default::default(),
+            headers: vec![ExtraHeader {
+                name: "foo/io".into(),
+                functions: Vec::new(),
+                includes: Vec::new(),
+            }],
+            cpp: Some(Default::default()),
+            umbrella: Some("all.h".into()),
+            include_guard_style: None,
+            dependencies: [
+                ("bar".into(), DependencyHeader::Header),
+                ("baz".into(), DependencyHeader::Inline),
+            ]
+            .into(),
+        };
+        assert_eq!(
+            header.generated_names(),
+            ["foo.h", "foo/io.h", "foo.hpp", "deps/bar.h"].map(PathBuf::from)
+        );
+    }
+
     #[test]
     pub fn test_lib_listing() {
         let libs_osx = "-lSystem -lc -lm";
@@ -1398,6 +5499,13 @@ mod tests {
         let target_mingw = target::Target::new(Some("x86_64-pc-windows-gnu"), false).unwrap();
 
         assert_eq!(static_libraries(libs_osx, &target_osx), "-lSystem -lc -lm");
+        assert_eq!(
+            static_libraries(
+                "-framework Security -framework CoreFoundation -lSystem -framework Security",
+                &target_osx
+            ),
+            "-framework Security -framework CoreFoundation -lSystem"
+        );
         assert_eq!(
             static_libraries(libs_linux, &target_linux),
             "-lgcc_s -lutil -lrt -lpthread -lm -ldl -lc"
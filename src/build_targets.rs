@@ -1,7 +1,9 @@
+use std::collections::HashMap;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 
 use crate::build::{CApiConfig, InstallTarget, LibraryTypes};
+use crate::i18n::tr;
 use crate::install::LibType;
 use crate::target::Target;
 
@@ -9,38 +11,121 @@ use crate::target::Target;
 pub struct ExtraTargets {
     pub include: Vec<(PathBuf, PathBuf)>,
     pub data: Vec<(PathBuf, PathBuf)>,
+    /// The message catalogs, installed as `{lang}/LC_MESSAGES/{domain}.mo`
+    pub locale: Vec<(PathBuf, PathBuf)>,
+    /// The manual pages, installed as `man{section}/{page}`
+    pub man: Vec<(PathBuf, PathBuf)>,
+    /// The documentation, installed in the docdir
+    pub doc: Vec<(PathBuf, PathBuf)>,
+    /// The executables, installed in the bindir
+    pub bin: Vec<(PathBuf, PathBuf)>,
+    /// The helper executables, installed in the libexecdir
+    pub libexec: Vec<(PathBuf, PathBuf)>,
+    /// The install targets declared in the metadata matching no file
+    pub unmatched: Vec<String>,
+    /// The files matched but not installed, e.g. a README among the man pages
+    pub skipped: Vec<String>,
 }
 
 impl ExtraTargets {
+    /// `dependency_out_dirs` are the build scripts outputs of the dependencies, by name
     pub fn setup(
         &mut self,
         capi_config: &CApiConfig,
         root_dir: &Path,
         out_dir: Option<&Path>,
+        dependency_out_dirs: &HashMap<String, PathBuf>,
     ) -> anyhow::Result<()> {
-        self.include = extra_targets(&capi_config.install.include, root_dir, out_dir)?;
-        self.data = extra_targets(&capi_config.install.data, root_dir, out_dir)?;
+        let roots = Roots {
+            root_path: root_dir,
+            out_dir,
+            dependency_out_dirs,
+        };
+        let unmatched = &mut self.unmatched;
+        unmatched.clear();
+        self.include = extra_targets(&capi_config.install.include, &roots, unmatched)?;
+        self.data = extra_targets(&capi_config.install.data, &roots, unmatched)?;
+        let domain = format!("{}.mo", capi_config.install.locale_domain);
+        self.locale = extra_targets(&capi_config.install.locale, &roots, unmatched)?
+            .into_iter()
+            .filter_map(|(from, _)| {
+                let lang = from.file_stem()?;
+                let to = Path::new(lang).join("LC_MESSAGES").join(&domain);
+                Some((from, to))
+            })
+            .collect();
+        self.doc = extra_targets(&capi_config.install.doc, &roots, unmatched)?;
+        self.bin = extra_targets(&capi_config.install.bin, &roots, unmatched)?;
+        self.libexec = extra_targets(&capi_config.install.libexec, &roots, unmatched)?;
+        self.skipped.clear();
+        self.man = extra_targets(&capi_config.install.man, &roots, unmatched)?
+            .into_iter()
+            .filter_map(|(from, _)| match man_page_path(&from) {
+                Ok(to) => Some((from, to)),
+                Err(e) => {
+                    self.skipped.push(tr("man-page-skipped", &[("error", &e)]));
+                    None
+                }
+            })
+            .collect();
 
         Ok(())
     }
 }
 
+/// `man{section}/{page}`, the section is the leading digit of the extension, e.g. `3` for `foo.3ssl`
+fn man_page_path(page: &Path) -> anyhow::Result<PathBuf> {
+    let section = page
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(|e| e.chars().next())
+        .filter(|c| c.is_ascii_digit())
+        .ok_or_else(|| anyhow::anyhow!(tr("man-page-section", &[("path", &page.display())])))?;
+
+    Ok(Path::new(&format!("man{section}")).join(page.file_name().unwrap()))
+}
+
+/// The directories the install targets are relative to
+struct Roots<'a> {
+    root_path: &'a Path,
+    out_dir: Option<&'a Path>,
+    dependency_out_dirs: &'a HashMap<String, PathBuf>,
+}
+
+/// The files matched by the targets, the declared ones matching none are added to `unmatched`
+///
+/// The generated targets are not accounted for if the build script did not run.
 fn extra_targets(
     targets: &[InstallTarget],
-    root_path: &Path,
-    root_output: Option<&Path>,
+    roots: &Roots,
+    unmatched: &mut Vec<String>,
 ) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
-    use itertools::*;
-    targets
-        .iter()
-        .filter_map(|t| match t {
-            InstallTarget::Asset(paths) => Some(paths.install_paths(root_path)),
-            InstallTarget::Generated(paths) => {
-                root_output.map(|root_output| paths.install_paths(root_output))
-            }
-        })
-        .flatten_ok()
-        .collect()
+    let mut files = Vec::new();
+    for target in targets {
+        let (paths, root) = match target {
+            InstallTarget::Asset(paths) => (paths, Some(roots.root_path)),
+            InstallTarget::Generated(paths) => match &paths.dependency {
+                Some(dep) => (
+                    paths,
+                    roots.dependency_out_dirs.get(dep).map(|p| p.as_path()),
+                ),
+                None => (paths, roots.out_dir),
+            },
+        };
+        let Some(root) = root else {
+            continue;
+        };
+        let count = files.len();
+        files.extend(paths.install_paths(root)?);
+        if let Some(key) = paths.declared_in.as_ref().filter(|_| files.len() == count) {
+            unmatched.push(tr(
+                "install-target-unmatched",
+                &[("key", key), ("pattern", &paths.from)],
+            ));
+        }
+    }
+
+    Ok(files)
 }
 
 #[derive(Debug, Clone)]
@@ -50,12 +135,29 @@ pub struct BuildTargets {
     pub static_lib: Option<PathBuf>,
     pub shared_lib: Option<PathBuf>,
     pub impl_lib: Option<PathBuf>,
+    /// The import library delay-loading the dll, built by dlltool for windows-gnu
+    pub delay_impl_lib: Option<PathBuf>,
+    /// The MSVC import library of the dll built by the gnu toolchain
+    pub msvc_impl_lib: Option<PathBuf>,
     pub debug_info: Option<PathBuf>,
     pub def: Option<PathBuf>,
     pub pc: PathBuf,
+    pub config_script: Option<PathBuf>,
+    /// The `[package.metadata.system-deps]` snippet of the Rust consumers
+    pub system_deps: Option<PathBuf>,
+    /// The MSBuild property sheet, for the msvc targets
+    pub msbuild_props: Option<PathBuf>,
+    /// The binaries built by `build_bins`
+    pub bins: Vec<PathBuf>,
+    /// The library as a C array, `{name}_blob.c` and its header
+    pub c_array: Option<PathBuf>,
     pub target: Target,
     pub extra: ExtraTargets,
     pub use_meson_naming_convention: bool,
+    /// The MSYS2 names, the Meson ones with MinGW and `msys-{name}-{soversion}.dll` with Cygwin
+    pub msys2: bool,
+    /// The `-{soversion}` suffix of the versioned dll names, as Meson and Cygwin name them
+    pub meson_dll_suffix: String,
 }
 
 impl BuildTargets {
@@ -66,9 +168,23 @@ impl BuildTargets {
         library_types: LibraryTypes,
         capi_config: &CApiConfig,
         use_meson_naming_convention: bool,
+        msys2: bool,
     ) -> anyhow::Result<BuildTargets> {
-        let pc = targetdir.join(format!("{}.pc", &capi_config.pkg_config.filename));
-        let include = if capi_config.header.enabled && capi_config.header.generation {
+        let pc = targetdir.join(format!("{}.pc", capi_config.pkg_config.filename));
+        let config_script = capi_config
+            .pkg_config
+            .config_script
+            .then(|| targetdir.join(format!("{}-config", capi_config.pkg_config.filename)));
+        let system_deps = capi_config.pkg_config.system_deps.then(|| {
+            targetdir.join(format!(
+                "{}-system-deps.toml",
+                capi_config.pkg_config.filename
+            ))
+        });
+        let msbuild_props = (capi_config.library.msbuild_props && target.is_msvc())
+            .then(|| targetdir.join(format!("{}.props", capi_config.library.name)));
+        let header = &capi_config.header;
+        let include = if header.enabled && header.generation && header.generator.is_cbindgen() {
             Some(targetdir.join(&capi_config.header.name).with_extension("h"))
         } else {
             None
@@ -82,15 +198,37 @@ impl BuildTargets {
             ));
         };
 
+        // Meson does not version the modules, the libraries installed in a subdirectory are plugins
+        let library = &capi_config.library;
+        let meson_dll_suffix = if library.versioning && library.install_subdir.is_none() {
+            format!("-{}", library.sover())
+        } else {
+            String::new()
+        };
+
+        let delay_impl_lib = (library.delay_load_import_library && target.is_mingw())
+            .then(|| targetdir.join(format!("{name}.dll.delay.a")));
+        let msvc_impl_lib = (library.msvc_import_library && target.is_mingw())
+            .then(|| targetdir.join(format!("{name}.dll.lib")));
+
         Ok(BuildTargets {
             pc,
+            config_script,
+            system_deps,
+            msbuild_props,
+            bins: Vec::new(),
+            c_array: None,
+            meson_dll_suffix,
             include,
             static_lib: library_types.staticlib.then_some(file_names.static_lib),
             shared_lib: library_types.cdylib.then_some(file_names.shared_lib),
             impl_lib: file_names.impl_lib,
+            delay_impl_lib,
+            msvc_impl_lib,
             debug_info: file_names.debug_info,
             def: file_names.def,
             use_meson_naming_convention,
+            msys2,
             name: name.into(),
             target: target.clone(),
             extra: Default::default(),
@@ -101,15 +239,41 @@ impl BuildTargets {
         LibType::from_build_targets(self)
     }
 
+    fn use_meson_dll_names(&self) -> bool {
+        (self.use_meson_naming_convention || self.msys2) && self.target.os == "windows"
+    }
+
+    /// The dll name without extension as Meson names it
+    ///
+    /// `{name}-{soversion}` with msvc and `lib{name}-{soversion}` otherwise.
+    fn meson_dll_stem(&self) -> String {
+        let prefix = if self.target.env == "msvc" { "" } else { "lib" };
+        format!("{prefix}{}{}", self.name, self.meson_dll_suffix)
+    }
+
+    /// The copy of the ELF library stripped of the debug info split by `--debuginfo separate`,
+    /// the one installed
+    pub fn stripped_lib(&self) -> Option<PathBuf> {
+        self.debug_info.as_ref()?;
+        let shared_lib = self.shared_lib.as_ref()?;
+        matches!(self.lib_type(), LibType::So).then(|| {
+            shared_lib
+                .with_file_name("capi-stripped")
+                .join(shared_lib.file_name().unwrap())
+        })
+    }
+
     pub fn debug_info_file_name(&self, bindir: &Path, libdir: &Path) -> Option<PathBuf> {
+        let debug_info = self.debug_info.as_ref()?;
         match self.lib_type() {
             // FIXME: Requires setting split-debuginfo to packed and
             // specifying the corresponding file name convention
-            // in BuildTargets::new.
-            LibType::So | LibType::Dylib => {
-                Some(libdir.join(self.debug_info.as_ref()?.file_name()?))
+            // in BuildTargets::new, the dSYM bundle is written by dsymutil.
+            LibType::So | LibType::Dylib => Some(libdir.join(debug_info.file_name()?)),
+            LibType::Windows if self.use_meson_naming_convention => {
+                Some(bindir.join(format!("{}.pdb", self.meson_dll_stem())))
             }
-            LibType::Windows => Some(bindir.join(self.debug_info.as_ref()?.file_name()?)),
+            LibType::Windows => Some(bindir.join(debug_info.file_name()?)),
         }
     }
 
@@ -126,13 +290,54 @@ impl BuildTargets {
         }
     }
 
+    /// The library name, `cyg{name}-{soversion}.dll` for Cygwin, `msys-` prefixed for MSYS2
     pub fn shared_output_file_name(&self) -> Option<OsString> {
-        if self.shared_lib.is_some() && self.use_meson_naming_convention {
-            Some(format!("lib{}.dll", self.name).into())
+        if self.shared_lib.is_some() && self.use_meson_dll_names() {
+            Some(format!("{}.dll", self.meson_dll_stem()).into())
+        } else if self.shared_lib.is_some() && self.target.is_cygwin() {
+            let prefix = if self.msys2 { "msys-" } else { "cyg" };
+            Some(format!("{prefix}{}{}.dll", self.name, self.meson_dll_suffix).into())
         } else {
             Some(self.shared_lib.as_ref()?.file_name().unwrap().to_owned())
         }
     }
+
+    /// The import library name, `{name}.lib` with msvc and `lib{name}.dll.a` otherwise for Meson,
+    /// `lib{name}.dll.a` for Cygwin
+    pub fn impl_lib_output_file_name(&self) -> Option<OsString> {
+        let impl_lib = self.impl_lib.as_ref()?;
+        if self.target.is_cygwin() {
+            Some(format!("lib{}.dll.a", self.name).into())
+        } else if self.use_meson_dll_names() {
+            if self.target.env == "msvc" {
+                Some(format!("{}.lib", self.name).into())
+            } else {
+                Some(format!("lib{}.dll.a", self.name).into())
+            }
+        } else {
+            Some(impl_lib.file_name()?.to_owned())
+        }
+    }
+
+    /// The MSVC import library name, `{name}.lib` for Meson as with msvc
+    pub fn msvc_impl_lib_output_file_name(&self) -> Option<OsString> {
+        let msvc_impl_lib = self.msvc_impl_lib.as_ref()?;
+        if self.use_meson_dll_names() {
+            Some(format!("{}.lib", self.name).into())
+        } else {
+            Some(msvc_impl_lib.file_name()?.to_owned())
+        }
+    }
+
+    /// The delay-load import library name, `lib{name}.dll.delay.a` for Meson
+    pub fn delay_impl_lib_output_file_name(&self) -> Option<OsString> {
+        let delay_impl_lib = self.delay_impl_lib.as_ref()?;
+        if self.use_meson_dll_names() {
+            Some(format!("lib{}.dll.delay.a", self.name).into())
+        } else {
+            Some(delay_impl_lib.file_name()?.to_owned())
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -156,7 +361,8 @@ impl FileNames {
             "macos" | "ios" | "tvos" | "visionos" => {
                 let static_lib = targetdir.join(format!("lib{lib_name}.a"));
                 let shared_lib = targetdir.join(format!("lib{lib_name}.dylib"));
-                (shared_lib, static_lib, None, None, None)
+                let dsym = Some(targetdir.join(format!("lib{lib_name}.dylib.dSYM")));
+                (shared_lib, static_lib, None, dsym, None)
             }
             "windows" => {
                 let shared_lib = targetdir.join(format!("{lib_name}.dll"));
@@ -176,6 +382,14 @@ impl FileNames {
                     (shared_lib, static_lib, Some(impl_lib), pdb, Some(def))
                 }
             }
+            "cygwin" => {
+                let static_lib = targetdir.join(format!("lib{lib_name}.a"));
+                let shared_lib = targetdir.join(format!("{lib_name}.dll"));
+                let impl_lib = targetdir.join(format!("{lib_name}.dll.a"));
+                let def = targetdir.join(format!("{lib_name}.def"));
+
+                (shared_lib, static_lib, Some(impl_lib), None, Some(def))
+            }
             _ => return None,
         };
 
@@ -193,7 +407,201 @@ impl FileNames {
 mod test {
     use std::path::{Path, PathBuf};
 
-    use super::{FileNames, Target};
+    use std::collections::HashMap;
+
+    use super::{extra_targets, man_page_path, BuildTargets, FileNames, Roots, Target};
+    use crate::build::{InstallTarget, InstallTargetPaths};
+
+    fn windows_build_targets(env: &str, meson: bool, suffix: &str) -> BuildTargets {
+        let target = Target {
+            is_target_overridden: false,
+            cfg: Vec::new(),
+            arch: String::from("x86_64"),
+            os: String::from("windows"),
+            env: env.to_string(),
+        };
+        let file_names = FileNames::from_target(&target, "ferris", Path::new("/foo")).unwrap();
+
+        BuildTargets {
+            name: "ferris".into(),
+            include: None,
+            static_lib: Some(file_names.static_lib),
+            shared_lib: Some(file_names.shared_lib),
+            impl_lib: file_names.impl_lib,
+            delay_impl_lib: None,
+            msvc_impl_lib: None,
+            debug_info: file_names.debug_info,
+            def: file_names.def,
+            pc: PathBuf::from("/foo/ferris.pc"),
+            config_script: None,
+            system_deps: None,
+            msbuild_props: None,
+            bins: Vec::new(),
+            c_array: None,
+            target,
+            extra: Default::default(),
+            use_meson_naming_convention: meson,
+            msys2: false,
+            meson_dll_suffix: suffix.into(),
+        }
+    }
+
+    #[test]
+    fn man_pages() {
+        assert_eq!(
+            man_page_path(Path::new("assets/capi/man/foo_open.3ssl")).unwrap(),
+            Path::new("man3/foo_open.3ssl")
+        );
+        assert!(man_page_path(Path::new("assets/capi/man/README")).is_err());
+    }
+
+    #[test]
+    fn unmatched_targets() {
+        let paths = |from: &str, declared_in: Option<&str>| InstallTargetPaths {
+            from: from.to_string(),
+            to: String::new(),
+            dependency: None,
+            declared_in: declared_in.map(String::from),
+        };
+        let targets = [
+            InstallTarget::Asset(paths("src/lib.rs", Some("install.data.asset"))),
+            InstallTarget::Asset(paths("scr/*.rs", Some("install.data.asset"))),
+            InstallTarget::Asset(paths("assets/capi/share/**/*", None)),
+            InstallTarget::Generated(paths("foo/*", Some("install.data.generated"))),
+        ];
+        let roots = Roots {
+            root_path: Path::new(env!("CARGO_MANIFEST_DIR")),
+            out_dir: None,
+            dependency_out_dirs: &HashMap::new(),
+        };
+
+        let mut unmatched = Vec::new();
+        let files = extra_targets(&targets, &roots, &mut unmatched).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            unmatched,
+            ["`package.metadata.capi.install.data.asset` `scr/*.rs` matches no file"]
+        );
+    }
+
+    #[test]
+    fn meson_names() {
+        // env, versioned, dll, import library, static library, pdb
+        let expected = [
+            (
+                "msvc",
+                "-1",
+                "ferris-1.dll",
+                "ferris.lib",
+                "libferris.a",
+                Some("ferris-1.pdb"),
+            ),
+            (
+                "msvc",
+                "",
+                "ferris.dll",
+                "ferris.lib",
+                "libferris.a",
+                Some("ferris.pdb"),
+            ),
+            (
+                "gnu",
+                "-1",
+                "libferris-1.dll",
+                "libferris.dll.a",
+                "libferris.a",
+                None,
+            ),
+            (
+                "gnu",
+                "",
+                "libferris.dll",
+                "libferris.dll.a",
+                "libferris.a",
+                None,
+            ),
+        ];
+
+        for (env, suffix, dll, implib, static_lib, pdb) in expected {
+            let targets = windows_build_targets(env, true, suffix);
+            let bindir = Path::new("/usr/bin");
+
+            assert_eq!(targets.shared_output_file_name().unwrap(), dll);
+            assert_eq!(targets.impl_lib_output_file_name().unwrap(), implib);
+            assert_eq!(targets.static_output_file_name().unwrap(), static_lib);
+            assert_eq!(
+                targets.debug_info_file_name(bindir, Path::new("/usr/lib")),
+                pdb.map(|pdb| bindir.join(pdb))
+            );
+        }
+
+        let targets = windows_build_targets("msvc", false, "-1");
+        assert_eq!(targets.shared_output_file_name().unwrap(), "ferris.dll");
+        assert_eq!(
+            targets.impl_lib_output_file_name().unwrap(),
+            "ferris.dll.lib"
+        );
+        assert_eq!(targets.static_output_file_name().unwrap(), "ferris.lib");
+
+        let mut targets = windows_build_targets("gnu", false, "-1");
+        targets.delay_impl_lib = Some(PathBuf::from("/foo/ferris.dll.delay.a"));
+        assert_eq!(
+            targets.delay_impl_lib_output_file_name().unwrap(),
+            "ferris.dll.delay.a"
+        );
+        targets.msvc_impl_lib = Some(PathBuf::from("/foo/ferris.dll.lib"));
+        assert_eq!(
+            targets.msvc_impl_lib_output_file_name().unwrap(),
+            "ferris.dll.lib"
+        );
+        targets.use_meson_naming_convention = true;
+        assert_eq!(
+            targets.delay_impl_lib_output_file_name().unwrap(),
+            "libferris.dll.delay.a"
+        );
+        assert_eq!(
+            targets.msvc_impl_lib_output_file_name().unwrap(),
+            "ferris.lib"
+        );
+    }
+
+    #[test]
+    fn cygwin_names() {
+        for (meson, suffix, dll) in [
+            (false, "-1", "cygferris-1.dll"),
+            (true, "", "cygferris.dll"),
+        ] {
+            let mut targets = windows_build_targets("", meson, suffix);
+            targets.target.os = String::from("cygwin");
+
+            assert_eq!(targets.shared_output_file_name().unwrap(), dll);
+            assert_eq!(
+                targets.impl_lib_output_file_name().unwrap(),
+                "libferris.dll.a"
+            );
+            assert_eq!(targets.static_output_file_name().unwrap(), "libferris.a");
+        }
+
+        let mut targets = windows_build_targets("", false, "-1");
+        targets.target.os = String::from("cygwin");
+        targets.msys2 = true;
+        assert_eq!(
+            targets.shared_output_file_name().unwrap(),
+            "msys-ferris-1.dll"
+        );
+
+        // The MinGW packages of MSYS2 are named as Meson does
+        let mut targets = windows_build_targets("gnu", false, "-1");
+        targets.msys2 = true;
+        assert_eq!(
+            targets.shared_output_file_name().unwrap(),
+            "libferris-1.dll"
+        );
+        assert_eq!(
+            targets.impl_lib_output_file_name().unwrap(),
+            "libferris.dll.a"
+        );
+    }
 
     #[test]
     fn unix() {
@@ -211,6 +619,7 @@ mod test {
         ] {
             let target = Target {
                 is_target_overridden: false,
+                cfg: Vec::new(),
                 arch: String::from(""),
                 os: os.to_string(),
                 env: String::from(""),
@@ -229,11 +638,28 @@ mod test {
         }
     }
 
+    #[test]
+    fn stripped_lib() {
+        let mut targets = windows_build_targets("gnu", false, "");
+        assert_eq!(targets.stripped_lib(), None);
+
+        targets.target.os = "linux".into();
+        targets.shared_lib = Some(PathBuf::from("/foo/libferris.so"));
+        assert_eq!(targets.stripped_lib(), None);
+
+        targets.debug_info = Some(PathBuf::from("/foo/libferris.so.debug"));
+        assert_eq!(
+            targets.stripped_lib(),
+            Some(PathBuf::from("/foo/capi-stripped/libferris.so"))
+        );
+    }
+
     #[test]
     fn apple() {
         for os in ["macos", "ios", "tvos", "visionos"] {
             let target = Target {
                 is_target_overridden: false,
+                cfg: Vec::new(),
                 arch: String::from(""),
                 os: os.to_string(),
                 env: String::from(""),
@@ -244,7 +670,7 @@ mod test {
                 static_lib: PathBuf::from("/foo/bar/libferris.a"),
                 shared_lib: PathBuf::from("/foo/bar/libferris.dylib"),
                 impl_lib: None,
-                debug_info: None,
+                debug_info: Some(PathBuf::from("/foo/bar/libferris.dylib.dSYM")),
                 def: None,
             };
 
@@ -256,6 +682,7 @@ mod test {
     fn windows_msvc() {
         let target = Target {
             is_target_overridden: false,
+            cfg: Vec::new(),
             arch: String::from(""),
             os: String::from("windows"),
             env: String::from("msvc"),
@@ -277,6 +704,7 @@ mod test {
     fn windows_gnu() {
         let target = Target {
             is_target_overridden: false,
+            cfg: Vec::new(),
             arch: String::from(""),
             os: String::from("windows"),
             env: String::from("gnu"),
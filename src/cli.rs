@@ -24,9 +24,19 @@ struct Common {
     /// If they are absolute the prefix is ignored.
     #[clap(long = "prefix", default_value = "/usr/local")]
     prefix: PathBuf,
+    /// Use the prefix of the current user, `~/.local` or `%LOCALAPPDATA%` on Windows
+    #[clap(long = "user", conflicts_with = "prefix")]
+    user: bool,
     /// Path to directory for installing generated library files
-    #[clap(long = "libdir", default_value = "lib")]
+    ///
+    /// [default: see --libdir-policy]
+    #[clap(long = "libdir", default_value = "lib", hide_default_value = true)]
     libdir: PathBuf,
+    /// How the default libdir is chosen: `auto` uses `lib/{multiarch tuple}` on Debian
+    /// and `lib64` where the host uses it, `lib` when cross-compiling, the other values
+    /// select one regardless of the host, `multiarch` derives the tuple from the target
+    #[clap(long = "libdir-policy", default_value = "auto", value_parser = ["auto", "lib", "lib64", "multiarch"])]
+    libdir_policy: String,
     /// Path to directory for installing generated headers files
     #[clap(long = "includedir", default_value = "include")]
     includedir: PathBuf,
@@ -35,9 +45,14 @@ struct Common {
     bindir: Option<PathBuf>,
     /// Path to directory for installing generated pkg-config .pc files
     ///
-    /// [default: {libdir}/pkgconfig]
+    /// [default: see --pkgconfigdir-policy]
     #[clap(long = "pkgconfigdir")]
     pkgconfigdir: Option<PathBuf>,
+    /// Where the pkg-config files go if `--pkgconfigdir` is not passed: `{libdir}/pkgconfig`,
+    /// `{datarootdir}/pkgconfig` or, with `auto`, the latter for the architecture independent
+    /// packages only
+    #[clap(long = "pkgconfigdir-policy", default_value = "auto", value_parser = ["auto", "libdir", "datadir"])]
+    pkgconfigdir_policy: String,
     /// Path to directory for installing read-only data
     #[clap(long = "datarootdir", default_value = "share")]
     datarootdir: PathBuf,
@@ -46,15 +61,133 @@ struct Common {
     /// [default: {datarootdir}]
     #[clap(long = "datadir")]
     datadir: Option<PathBuf>,
+    /// Path to directory for installing documentation
+    ///
+    /// [default: {datarootdir}/doc/{library name}]
+    #[clap(long = "docdir")]
+    docdir: Option<PathBuf>,
+    /// Path to directory for installing the message catalogs
+    ///
+    /// [default: {datarootdir}/locale]
+    #[clap(long = "localedir")]
+    localedir: Option<PathBuf>,
+    /// Path to directory for installing the manual pages
+    ///
+    /// [default: {datarootdir}/man]
+    #[clap(long = "mandir")]
+    mandir: Option<PathBuf>,
+    /// Path to directory for installing the executables run by other programs
+    ///
+    /// [default: {prefix}/libexec]
+    #[clap(long = "libexecdir")]
+    libexecdir: Option<PathBuf>,
+    /// Path to directory for installing the debug info split by `--debuginfo separate`,
+    /// followed by the install path of the library as gdb looks it up
+    #[clap(long = "debugdir", default_value = "/usr/lib/debug")]
+    debugdir: PathBuf,
+    /// Rename the library and the pkg-config file, e.g. `foo=foo2` installs `libfoo2.so`
+    /// with the `libfoo2.so.{major}` soname and `foo2.pc`
+    ///
+    /// A value without `=` is a file listing a `FROM=TO` pair per line.
+    #[clap(long = "install-name-map", value_name = "FROM=TO|FILE")]
+    install_name_map: Vec<String>,
     #[clap(long = "dlltool")]
-    /// Use the provided dlltool when building for the windows-gnu targets. (deprecated and no-op)
+    /// Use the provided dlltool to build the import libraries with `--implib-tool`
+    /// and the delay-load import libraries for the windows-gnu targets
     dlltool: Option<PathBuf>,
+    /// How to build the import libraries, binutils dlltool supports only the windows-gnu targets
+    #[clap(long = "implib-tool", default_value = "builtin", value_parser = ["builtin", "dlltool", "llvm-dlltool"])]
+    implib_tool: String,
+    /// Add an rpath to the shared library, e.g. `$ORIGIN/../lib` or an absolute directory
+    #[clap(long = "rpath", value_name = "PATH")]
+    rpath: Vec<String>,
+    /// Do not add the rpaths of the metadata to the shared library, e.g. for the distribution
+    /// packages
+    #[clap(long = "no-rpath")]
+    no_rpath: bool,
+    /// The directory recorded in the Apple dylib install_name, overriding the metadata:
+    /// `absolute` for the libdir, `rpath` for `@rpath` or a directory such as `@loader_path`
+    #[clap(long = "install-name", value_name = "absolute|rpath|DIR")]
+    install_name: Option<String>,
+    /// Sign the Apple dylibs with `codesign` after the build and the install, ad-hoc unless
+    /// an identity is given, `CODESIGN` overrides the program
+    #[clap(
+        long = "codesign",
+        value_name = "IDENTITY",
+        num_args = 0..=1,
+        default_missing_value = "-"
+    )]
+    codesign: Option<String>,
+    /// What becomes of the debug info of the shared libraries: `keep` installs the PDB or
+    /// the dSYM bundle next to the library, `separate` splits the ELF ones as well and
+    /// installs them in the `--debugdir`, `none` builds the libraries without it
+    #[clap(long = "debuginfo", default_value = "keep", value_parser = ["none", "keep", "separate"])]
+    debuginfo: String,
     #[clap(long = "crt-static")]
     /// Build the library embedding the C runtime
     crt_static: bool,
+    /// Also write the library as a byte array in `{name}_blob.c` and `{name}_blob.h`,
+    /// installed as data
+    #[clap(
+        long = "emit-c-array",
+        value_name = "LIBRARY-TYPE",
+        num_args = 0..=1,
+        default_missing_value = "staticlib",
+        value_parser = ["staticlib", "cdylib"]
+    )]
+    emit_c_array: Option<String>,
+    /// Use the options of a named preset of `.cargo/capi.toml`, the command line takes precedence
+    #[clap(long = "preset", value_name = "NAME")]
+    preset: Option<String>,
     /// Use the Linux/Meson library naming convention on Windows
     #[clap(long = "meson-paths", default_value = "false")]
     meson: bool,
+    /// Use the MSYS2 library names for the MinGW and Cygwin targets, by default if MSYSTEM
+    /// is set, `always` installs in the environment prefix as well
+    #[clap(long = "msys2", default_value = "auto", value_parser = ["auto", "always", "never"])]
+    msys2: String,
+    /// Produce bit-identical artifacts across rebuilds
+    ///
+    /// Implied if SOURCE_DATE_EPOCH is set, its value is used as timestamp
+    /// for the generated files.
+    #[clap(long = "reproducible")]
+    reproducible: bool,
+    /// How to report the exported symbols not declared in the headers and
+    /// the declarations without a matching exported symbol
+    #[clap(long = "header-check", default_value = "warn", value_parser = ["warn", "deny", "off"])]
+    header_check: String,
+    /// How to report the C symbols available only in the static or only in
+    /// the shared library, it overrides `library.symbols_check` [default: warn]
+    #[clap(long = "library-check", value_parser = ["warn", "deny", "off"])]
+    library_check: Option<String>,
+    /// Fail if a dependency bundles a vendored copy of a system library
+    #[clap(long = "forbid-vendored")]
+    forbid_vendored: bool,
+    /// Fail if a shared library has no soname, or install name, version identifying its ABI
+    #[clap(long = "forbid-unversioned-soname")]
+    forbid_unversioned_soname: bool,
+    /// Fail, instead of warning, if an install target of the metadata matches no file
+    #[clap(long = "strict-install-targets")]
+    strict_install_targets: bool,
+    /// Warn about the metadata keys without effect for the target and the library types
+    #[clap(long = "warn-unused-metadata")]
+    warn_unused_metadata: bool,
+    /// Add the libraries of the pkg-config requirements of the C-API workspace
+    /// dependencies not built together to Libs.private
+    #[clap(long = "include-dependencies-pc")]
+    include_dependencies_pc: bool,
+    /// Print as JSON the inputs of the fingerprint deciding if the pkg-config
+    /// file and the header are generated again, compared with the previous build
+    #[clap(long = "explain-fingerprint")]
+    explain_fingerprint: bool,
+    /// Generate the pkg-config files and the headers and compute the install
+    /// layout for the target triple, without compiling
+    #[clap(
+        long = "pretend-target",
+        value_name = "TRIPLE",
+        conflicts_with = "target"
+    )]
+    pretend_target: Option<String>,
 }
 
 pub fn main_cli() -> Command {
@@ -74,40 +207,45 @@ pub fn main_cli() -> Command {
         .styles(styles)
 }
 
+/// The options configuring cargo itself
+fn global_args(app: Command) -> Command {
+    app.arg(
+        opt(
+            "verbose",
+            "Use verbose output (-vv very verbose/build.rs output)",
+        )
+        .short('v')
+        .action(ArgAction::Count)
+        .global(true),
+    )
+    .arg_silent_suggestion()
+    .arg(
+        opt("color", "Coloring: auto, always, never")
+            .value_name("WHEN")
+            .global(true),
+    )
+    .arg(flag("frozen", "Require Cargo.lock and cache are up to date").global(true))
+    .arg(flag("locked", "Require Cargo.lock is up to date").global(true))
+    .arg(flag("offline", "Run without accessing the network").global(true))
+    .arg(multi_opt("config", "KEY=VALUE", "Override a configuration value").global(true))
+    .arg(
+        Arg::new("unstable-features")
+            .help("Unstable (nightly-only) flags to Cargo, see 'cargo -Z help' for details")
+            .short('Z')
+            .value_name("FLAG")
+            .action(ArgAction::Append)
+            .global(true),
+    )
+}
+
 fn base_cli() -> Command {
     let default_target = Target::new::<&str>(None, false);
     let app = Common::command()
         .allow_external_subcommands(true)
         .arg(flag("version", "Print version info and exit").short('V'))
         .arg(flag("list", "List installed commands"))
-        .arg(opt("explain", "Run `rustc --explain CODE`").value_name("CODE"))
-        .arg(
-            opt(
-                "verbose",
-                "Use verbose output (-vv very verbose/build.rs output)",
-            )
-            .short('v')
-            .action(ArgAction::Count)
-            .global(true),
-        )
-        .arg_silent_suggestion()
-        .arg(
-            opt("color", "Coloring: auto, always, never")
-                .value_name("WHEN")
-                .global(true),
-        )
-        .arg(flag("frozen", "Require Cargo.lock and cache are up to date").global(true))
-        .arg(flag("locked", "Require Cargo.lock is up to date").global(true))
-        .arg(flag("offline", "Run without accessing the network").global(true))
-        .arg(multi_opt("config", "KEY=VALUE", "Override a configuration value").global(true))
-        .arg(
-            Arg::new("unstable-features")
-                .help("Unstable (nightly-only) flags to Cargo, see 'cargo -Z help' for details")
-                .short('Z')
-                .value_name("FLAG")
-                .action(ArgAction::Append)
-                .global(true),
-        )
+        .arg(opt("explain", "Run `rustc --explain CODE`").value_name("CODE"));
+    let app = global_args(app)
         .arg_parallel()
         .arg_targets_all(
             "Build only this package's library",
@@ -133,9 +271,6 @@ fn base_cli() -> Command {
         app.mut_arg("prefix", |a| {
             a.default_value(t.default_prefix().as_os_str().to_os_string())
         })
-        .mut_arg("libdir", |a| {
-            a.default_value(t.default_libdir().as_os_str().to_os_string())
-        })
         .mut_arg("datadir", |a| {
             a.default_value(t.default_datadir().as_os_str().to_os_string())
         })
@@ -155,12 +290,20 @@ pub fn subcommand_build(name: &'static str, about: &'static str) -> Command {
             multi_opt(
                 "library-type",
                 "LIBRARY-TYPE",
-                "Build only a type of library",
+                "Build only a type of library, overriding the default_library_types metadata",
             )
             .global(true)
             .ignore_case(true)
             .value_parser(["cdylib", "staticlib"]),
         )
+        .arg(
+            opt(
+                "artifact-dir",
+                "Copy the libraries, headers and pkg-config files to the directory, with their install names",
+            )
+            .value_name("DIR")
+            .value_parser(clap::value_parser!(PathBuf)),
+        )
         .arg_release("Build artifacts in release mode, with optimizations")
         .arg_package_spec_no_all(
             "Package to build (see `cargo help pkgid`)",
@@ -176,6 +319,15 @@ the --release flag will use the `release` profile instead.
         )
 }
 
+/// Parse an octal file mode, as `install(1)` does
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    let digits = mode.strip_prefix("0o").unwrap_or(mode);
+    match u32::from_str_radix(digits, 8) {
+        Ok(mode) if mode <= 0o7777 => Ok(mode),
+        _ => Err(format!("`{mode}` is not an octal file mode")),
+    }
+}
+
 pub fn subcommand_install(name: &'static str, about: &'static str) -> Command {
     base_cli()
         .name(name)
@@ -184,13 +336,56 @@ pub fn subcommand_install(name: &'static str, about: &'static str) -> Command {
             multi_opt(
                 "library-type",
                 "LIBRARY-TYPE",
-                "Build only a type of library",
+                "Build only a type of library, overriding the default_library_types metadata",
             )
             .global(true)
             .ignore_case(true)
             .value_parser(["cdylib", "staticlib"]),
         )
         .arg(flag("debug", "Build in debug mode instead of release mode"))
+        .arg(
+            opt(
+                "mode-exec",
+                "Set the mode of the installed shared libraries",
+            )
+            .value_name("MODE")
+            .default_value("0755")
+            .value_parser(parse_mode),
+        )
+        .arg(
+            opt("mode-data", "Set the mode of the other installed files")
+                .value_name("MODE")
+                .default_value("0644")
+                .value_parser(parse_mode),
+        )
+        .arg(opt("owner", "Set the owner of the installed files").value_name("OWNER"))
+        .arg(opt("group", "Set the group of the installed files").value_name("GROUP"))
+        .arg(flag(
+            "dry-run",
+            "Print what would be installed without writing to the filesystem",
+        ))
+        .arg(
+            flag(
+                "no-build",
+                "Install the artifacts of a previous build with the same options, without compiling",
+            )
+            .conflicts_with_all(["bin", "bins", "pretend_target"]),
+        )
+        .arg(
+            flag(
+                "reuse",
+                "Copy the artifacts of a previous build with the same options if their sources did not change, without running cargo",
+            )
+            .conflicts_with_all(["no-build", "bin", "bins", "pretend_target"]),
+        )
+        .arg(
+            opt(
+                "install-manifest",
+                "Write the list of the installed files as JSON to the given path",
+            )
+            .value_name("PATH")
+            .value_parser(clap::value_parser!(PathBuf)),
+        )
         .arg_release(
             "Build artifacts in release mode, with optimizations. This is the default behavior.",
         )
@@ -227,6 +422,178 @@ pub fn subcommand_test(name: &'static str) -> Command {
         )
         .arg(flag("no-run", "Compile, but don't run tests"))
         .arg(flag("no-fail-fast", "Run all tests regardless of failure"))
+        .arg(flag(
+            "installed",
+            "Stage an install and build the C tests against the installed files through pkg-config",
+        ))
+        .arg(
+            opt(
+                "runner",
+                "Run the tests with the given command, by default qemu-user or wine for a foreign target",
+            )
+            .value_name("RUNNER"),
+        )
+        .arg(
+            opt(
+                "wrapper",
+                "Run the C tests under the given command, e.g. `valgrind --error-exitcode=1`",
+            )
+            .value_name("COMMAND")
+            .allow_hyphen_values(true),
+        )
+        .arg(
+            opt(
+                "test-cc",
+                "The C compiler for the `tests/capi` programs, it overrides `test.cc`",
+            )
+            .value_name("CC"),
+        )
+        .arg(
+            opt(
+                "test-std",
+                "The C standard of the C tests, it overrides `test.std`",
+            )
+            .value_name("STD"),
+        )
+        .arg(
+            multi_opt(
+                "test-cflags",
+                "FLAGS",
+                "Additional flags for the C tests, after the `test.cflags` ones",
+            )
+            .allow_hyphen_values(true),
+        )
+}
+
+pub fn subcommand_abi_check(name: &'static str) -> Command {
+    subcommand_build(
+        name,
+        "Compare the ABI of the libraries with a baseline, failing on breaking changes",
+    )
+    .arg(
+        opt(
+            "baseline",
+            "The baseline file, written if it does not exist",
+        )
+        .value_name("PATH")
+        .value_parser(clap::value_parser!(PathBuf))
+        .required(true),
+    )
+    .arg(flag("update", "Write the current ABI to the baseline"))
+    .arg(flag(
+        "layouts",
+        "Record and compare the type definitions of the headers as well",
+    ))
+}
+
+pub fn subcommand_size_report(name: &'static str) -> Command {
+    subcommand_build(
+        name,
+        "Report the code size of the exported functions, compared with the previous report",
+    )
+}
+
+pub fn subcommand_msi(name: &'static str) -> Command {
+    subcommand_build(
+        name,
+        "Write a WiX fragment listing the installed files, for a Windows installer",
+    )
+    .arg(flag(
+        "nsis",
+        "Write an NSIS script instead of a WiX fragment",
+    ))
+}
+
+pub fn subcommand_shell(name: &'static str) -> Command {
+    subcommand_build(
+        name,
+        "Run a shell, or a command, with the uninstalled libraries in the search paths",
+    )
+    .arg(
+        Arg::new("command")
+            .value_name("COMMAND")
+            .help("The command to run instead of the shell")
+            .num_args(0..)
+            .last(true),
+    )
+    .arg(flag(
+        "print",
+        "Print the commands setting the environment instead of running a shell",
+    ))
+}
+
+pub fn subcommand_dirs(name: &'static str) -> Command {
+    base_cli()
+        .name(name)
+        .about("Print the install directories, with the defaults of the target")
+        .arg(flag("json", "Print them as a JSON object"))
+}
+
+pub fn subcommand_doc(name: &'static str) -> Command {
+    subcommand_build(name, "Generate the crate C-API documentation using Doxygen")
+        .arg(flag(
+            "install",
+            "Install the documentation in {docdir}/html",
+        ))
+        .arg(
+            opt(
+                "install-manifest",
+                "Write the list of the installed files as JSON to the given path",
+            )
+            .value_name("PATH")
+            .value_parser(clap::value_parser!(PathBuf))
+            .requires("install"),
+        )
+}
+
+pub fn subcommand_explain(name: &'static str) -> Command {
+    subcommand_build(
+        name,
+        "Explain which configuration produced the name and content of an artifact",
+    )
+    .arg(
+        Arg::new("artifact")
+            .value_name("ARTIFACT")
+            .help("The artifact, e.g. target/x86_64-unknown-linux-gnu/debug/libfoo.so or foo.pc")
+            .value_parser(clap::value_parser!(PathBuf))
+            .required(true),
+    )
+}
+
+pub fn subcommand_verify_header(name: &'static str) -> Command {
+    subcommand_build(
+        name,
+        "Check that the committed headers match the generated ones",
+    )
+    .arg(
+        Arg::new("dir")
+            .value_name("DIR")
+            .help("The directory holding the committed headers, by default the package root")
+            .value_parser(clap::value_parser!(PathBuf)),
+    )
+}
+
+pub fn subcommand_ide_setup(name: &'static str) -> Command {
+    global_args(Command::new(name))
+        .about("Configure rust-analyzer to analyze the crate C-API")
+        .arg(flag("version", "Print version info and exit").short('V'))
+        .arg(flag(
+            "stdout",
+            "Print the configuration instead of writing rust-analyzer.toml",
+        ))
+        .arg(flag("force", "Overwrite an existing rust-analyzer.toml"))
+        .arg_manifest_path()
+}
+
+pub fn subcommand_upgrade_metadata(name: &'static str) -> Command {
+    global_args(Command::new(name))
+        .about("Rewrite the deprecated package.metadata.capi keys to the current schema")
+        .arg(flag("version", "Print version info and exit").short('V'))
+        .arg(flag(
+            "dry-run",
+            "Print the changes without writing the manifests",
+        ))
+        .arg_manifest_path()
 }
 
 pub fn run_cargo_fallback(subcommand: &str, subcommand_args: &ArgMatches) -> CliResult {
@@ -251,3 +618,17 @@ pub fn run_cargo_fallback(subcommand: &str, subcommand_args: &ArgMatches) -> Cli
     }
     Err(CliError::new(err, 101))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::parse_mode;
+
+    #[test]
+    fn mode() {
+        assert_eq!(parse_mode("0755"), Ok(0o755));
+        assert_eq!(parse_mode("644"), Ok(0o644));
+        assert_eq!(parse_mode("0o4755"), Ok(0o4755));
+        assert!(parse_mode("0955").is_err());
+        assert!(parse_mode("17777").is_err());
+    }
+}
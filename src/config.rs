@@ -1,7 +1,210 @@
 use std::env;
+use std::ffi::OsString;
 
+use anyhow::Context as _;
 use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt};
 use cargo::{CliResult, GlobalContext};
+use clap::parser::ValueSource;
+use clap::Command;
+
+/// The defaults read from the `[capi]` or `[cargo-c]` table of the cargo configuration
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ConfigDefaults {
+    prefix: Option<String>,
+    libdir: Option<String>,
+    includedir: Option<String>,
+    bindir: Option<String>,
+    datarootdir: Option<String>,
+    pkgconfigdir: Option<String>,
+    library_type: Option<Vec<String>>,
+    meson_paths: Option<bool>,
+}
+
+impl ConfigDefaults {
+    fn load(gctx: &GlobalContext) -> anyhow::Result<Self> {
+        let mut defaults = gctx
+            .get::<Option<ConfigDefaults>>("cargo-c")?
+            .unwrap_or_default();
+        if let Some(capi) = gctx.get::<Option<ConfigDefaults>>("capi")? {
+            defaults.merge(capi);
+        }
+        Ok(defaults)
+    }
+
+    fn merge(&mut self, other: ConfigDefaults) {
+        self.prefix = other.prefix.or(self.prefix.take());
+        self.libdir = other.libdir.or(self.libdir.take());
+        self.includedir = other.includedir.or(self.includedir.take());
+        self.bindir = other.bindir.or(self.bindir.take());
+        self.datarootdir = other.datarootdir.or(self.datarootdir.take());
+        self.pkgconfigdir = other.pkgconfigdir.or(self.pkgconfigdir.take());
+        self.library_type = other.library_type.or(self.library_type.take());
+        self.meson_paths = other.meson_paths.or(self.meson_paths.take());
+    }
+
+    /// The command line options for the arguments not passed explicitly
+    fn args(&self, cmd: &Command, matches: &ArgMatches) -> Vec<OsString> {
+        let missing = |id: &str| {
+            cmd.get_arguments().any(|a| a.get_id() == id)
+                && matches.value_source(id) != Some(ValueSource::CommandLine)
+        };
+
+        let mut args = Vec::new();
+        let paths = [
+            ("prefix", &self.prefix),
+            ("libdir", &self.libdir),
+            ("includedir", &self.includedir),
+            ("bindir", &self.bindir),
+            ("datarootdir", &self.datarootdir),
+            ("pkgconfigdir", &self.pkgconfigdir),
+        ];
+        // `--user` replaces the configured prefix
+        let user = matches.try_get_one::<bool>("user").ok().flatten() == Some(&true);
+        for (id, value) in paths {
+            if id == "prefix" && user {
+                continue;
+            }
+            if let Some(value) = value.as_ref().filter(|_| missing(id)) {
+                args.push(format!("--{id}={value}").into());
+            }
+        }
+        if let Some(types) = self
+            .library_type
+            .as_ref()
+            .filter(|_| missing("library-type"))
+        {
+            args.extend(types.iter().map(|t| format!("--library-type={t}").into()));
+        }
+        if self.meson_paths == Some(true) && missing("meson") {
+            args.push("--meson-paths".into());
+        }
+
+        args
+    }
+}
+
+/// The options of a named preset from the closest `.cargo/capi.toml`, e.g.
+///
+/// ```toml
+/// [preset.distro]
+/// prefix = "/usr"
+/// library-type = ["cdylib"]
+/// features = ["simd"]
+/// ```
+fn preset_args(
+    gctx: &GlobalContext,
+    cmd: &Command,
+    matches: &ArgMatches,
+) -> anyhow::Result<Vec<OsString>> {
+    let Some(name) = matches.try_get_one::<String>("preset").ok().flatten() else {
+        return Ok(Vec::new());
+    };
+
+    let Some(path) = gctx
+        .cwd()
+        .ancestors()
+        .map(|dir| dir.join(".cargo/capi.toml"))
+        .find(|path| path.exists())
+    else {
+        anyhow::bail!("Cannot find the `.cargo/capi.toml` defining the preset `{name}`");
+    };
+    let presets: toml::Table = toml::from_str(&std::fs::read_to_string(&path)?)
+        .with_context(|| format!("Cannot parse {}", path.display()))?;
+    let Some(preset) = presets
+        .get("preset")
+        .and_then(|p| p.get(name))
+        .and_then(|p| p.as_table())
+    else {
+        anyhow::bail!("No preset `{name}` in {}", path.display());
+    };
+
+    let mut args = Vec::new();
+    for (key, value) in preset {
+        let Some(arg) = cmd.get_arguments().find(|a| a.get_long() == Some(key)) else {
+            anyhow::bail!("Unknown option `{key}` in the preset `{name}`");
+        };
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let values = match value {
+            toml::Value::Array(values) => values.clone(),
+            value => vec![value.clone()],
+        };
+        for value in values {
+            match value {
+                toml::Value::Boolean(true) => args.push(format!("--{key}").into()),
+                toml::Value::Boolean(false) => {}
+                toml::Value::String(s) => args.push(format!("--{key}={s}").into()),
+                value => args.push(format!("--{key}={value}").into()),
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// The subcommand the options belong to, its matches and its position in the command line
+fn leaf_subcommand<'a>(
+    app: &'a Command,
+    matches: &'a ArgMatches,
+    argv: &[OsString],
+) -> Option<(&'a Command, &'a ArgMatches, usize)> {
+    let (mut cmd, mut leaf) = (app, matches);
+    let mut position = 0;
+    while let Some((name, sub_matches)) = leaf.subcommand() {
+        let Some(sub_cmd) = cmd.find_subcommand(name) else {
+            break;
+        };
+        position += argv[position + 1..].iter().position(|a| a == name)? + 1;
+        cmd = sub_cmd;
+        leaf = sub_matches;
+    }
+
+    (position > 0).then_some((cmd, leaf, position))
+}
+
+/// Parse the command line, expanding `--preset` and taking the install directories,
+/// library types and `--meson-paths` not passed from the cargo configuration,
+/// e.g. `.cargo/config.toml`:
+///
+/// ```toml
+/// [capi]
+/// prefix = "/usr"
+/// libdir = "lib64"
+/// library-type = ["cdylib"]
+/// ```
+pub fn get_matches(app: &Command, gctx: &GlobalContext) -> anyhow::Result<ArgMatches> {
+    let mut argv: Vec<OsString> = env::args_os().collect();
+    let matches = app.clone().get_matches_from(&argv);
+
+    let matches = expand(app, &mut argv, matches, |cmd, leaf| {
+        preset_args(gctx, cmd, leaf)
+    })?;
+    let defaults = ConfigDefaults::load(gctx)?;
+    expand(app, &mut argv, matches, |cmd, leaf| {
+        Ok(defaults.args(cmd, leaf))
+    })
+}
+
+/// Insert the options returned by `f` after the subcommand and parse the command line again
+fn expand(
+    app: &Command,
+    argv: &mut Vec<OsString>,
+    matches: ArgMatches,
+    f: impl FnOnce(&Command, &ArgMatches) -> anyhow::Result<Vec<OsString>>,
+) -> anyhow::Result<ArgMatches> {
+    let Some((cmd, leaf, position)) = leaf_subcommand(app, &matches, argv) else {
+        return Ok(matches);
+    };
+    let args = f(cmd, leaf)?;
+    if args.is_empty() {
+        return Ok(matches);
+    }
+    argv.splice(position + 1..position + 1, args);
+
+    Ok(app.clone().get_matches_from(argv.iter()))
+}
 
 // Take the original cargo instance and save it as a separate env var if not already set.
 fn setup_env() {
@@ -12,13 +215,8 @@ fn setup_env() {
     }
 }
 
-pub fn global_context_configure(gctx: &mut GlobalContext, args: &ArgMatches) -> CliResult {
+fn configure(gctx: &mut GlobalContext, args: &ArgMatches, config_args: &[String]) -> CliResult {
     let arg_target_dir = &args.value_of_path("target-dir", gctx);
-    let config_args: Vec<_> = args
-        .get_many::<String>("config")
-        .unwrap_or_default()
-        .map(|s| s.to_owned())
-        .collect();
     gctx.configure(
         args.verbose(),
         args.flag("quiet"),
@@ -32,8 +230,27 @@ pub fn global_context_configure(gctx: &mut GlobalContext, args: &ArgMatches) ->
             .unwrap_or_default()
             .map(|s| s.to_owned())
             .collect::<Vec<String>>(),
-        &config_args,
+        config_args,
     )?;
+    Ok(())
+}
+
+pub fn global_context_configure(gctx: &mut GlobalContext, args: &ArgMatches) -> CliResult {
+    let mut config_args: Vec<_> = args
+        .get_many::<String>("config")
+        .unwrap_or_default()
+        .map(|s| s.to_owned())
+        .collect();
+
+    // Whether a runner is configured depends on the `--config` values, the one found is
+    // added to them, the configuration cannot be extended once merged
+    if args.try_get_one::<String>("runner").is_ok() {
+        let mut probe = GlobalContext::default()?;
+        configure(&mut probe, args, &config_args)?;
+        config_args.extend(crate::runner::runner_config(&probe, args)?);
+    }
+
+    configure(gctx, args, &config_args)?;
 
     // Make sure that the env-vars are correctly set at this point.
     setup_env();
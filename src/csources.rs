@@ -0,0 +1,267 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use cargo::core::profiles::Profile;
+use cargo::core::Workspace;
+use cargo::util::context::ConfigRelativePath;
+use cargo::GlobalContext;
+use cargo_util::paths::{create_dir_all, read, remove_dir_all};
+use cargo_util::ProcessBuilder;
+use itertools::Itertools;
+
+use crate::build::{escape_dep_path, parse_dep_info, CSourcesCApiConfig};
+use crate::i18n::tr;
+use crate::progress::{self, Phase};
+
+const LIB_NAME: &str = "capi_csources";
+
+/// The archiver or ranlib of `triple`, `key` is `ar` or `ranlib`
+///
+/// `target.{triple}.{key}` of the cargo configuration, then the variables `cc` looks up,
+/// e.g. `AR_{triple}`, `TARGET_AR` and `AR`, set in the environment or in the `[env]`
+/// table, which `cc` does not see as it runs outside of a build script.
+fn configured_tool(
+    gctx: &GlobalContext,
+    triple: &str,
+    host: &str,
+    key: &str,
+) -> anyhow::Result<Option<PathBuf>> {
+    if let Some(path) = gctx.get::<Option<ConfigRelativePath>>(&format!("target.{triple}.{key}"))? {
+        return Ok(Some(path.resolve_program(gctx)));
+    }
+
+    let var = key.to_uppercase();
+    let kind = if triple == host { "HOST" } else { "TARGET" };
+    let names = [
+        format!("{var}_{triple}"),
+        format!("{var}_{}", triple.replace('-', "_")),
+        format!("{kind}_{var}"),
+        var,
+    ];
+    let env_config = gctx.env_config()?;
+    for name in &names {
+        match (env_config.get(name), gctx.get_env_os(name)) {
+            (Some(value), None) => return Ok(Some(value.resolve(gctx).into_owned().into())),
+            (Some(value), Some(_)) if value.is_force() => {
+                return Ok(Some(value.resolve(gctx).into_owned().into()))
+            }
+            (_, Some(value)) => return Ok(Some(value.into())),
+            (None, None) => {}
+        }
+    }
+
+    Ok(None)
+}
+
+/// Compile the C sources listed in `[package.metadata.capi.csources]`
+///
+/// Return the rustc arguments linking them into the library, rustc exports their symbols
+/// from the cdylib along with the Rust ones, and the files they are built from, for cargo
+/// to relink the library when one changes.
+#[allow(clippy::too_many_arguments)]
+pub fn build(
+    ws: &Workspace,
+    csources: &CSourcesCApiConfig,
+    name: &str,
+    root_path: &Path,
+    root_output: &Path,
+    triple: &str,
+    host: &str,
+    profile: &Profile,
+) -> anyhow::Result<(Vec<String>, Vec<PathBuf>)> {
+    progress::status(
+        ws,
+        Phase::CSources,
+        "Building",
+        tr("build-csources", &[("name", &name)]),
+    )?;
+
+    // The dependency files of the removed sources must not linger
+    let out_dir = root_output.join("capi-csources").join(name);
+    if out_dir.exists() {
+        remove_dir_all(&out_dir)?;
+    }
+    create_dir_all(&out_dir)?;
+
+    let mut build = cc::Build::new();
+    build
+        .target(triple)
+        .host(host)
+        .opt_level_str(profile.opt_level.as_str())
+        .debug(profile.debuginfo.into_inner().to_string() != "0")
+        .out_dir(&out_dir)
+        .cargo_metadata(false)
+        .cargo_warnings(false)
+        .pic(true);
+    if let Some(ar) = configured_tool(ws.gctx(), triple, host, "ar")? {
+        build.archiver(ar);
+    }
+    if let Some(ranlib) = configured_tool(ws.gctx(), triple, host, "ranlib")? {
+        build.ranlib(ranlib);
+    }
+
+    for file in &csources.files {
+        build.file(root_path.join(file));
+    }
+    for dir in &csources.include_dirs {
+        build.include(root_path.join(dir));
+    }
+    for flag in &csources.flags {
+        build.flag(flag);
+    }
+
+    // Write the headers each object includes next to it
+    let compiler_deps = !build.try_get_compiler()?.is_like_msvc();
+    if compiler_deps {
+        build.flag("-MMD");
+    }
+
+    build.try_compile(LIB_NAME)?;
+
+    let mut inputs: Vec<PathBuf> = csources
+        .files
+        .iter()
+        .chain(&csources.headers)
+        .map(|f| root_path.join(f))
+        .collect();
+    if compiler_deps {
+        inputs.extend(object_deps(&out_dir)?);
+    }
+    let inputs = inputs.into_iter().unique().collect();
+
+    let rustc_args = vec![
+        "-L".to_string(),
+        format!("native={}", out_dir.display()),
+        "-l".to_string(),
+        format!("static:+whole-archive,+export-symbols={LIB_NAME}"),
+    ];
+
+    Ok((rustc_args, inputs))
+}
+
+/// The sources and headers listed by the dependency files the compiler wrote in `out_dir`
+fn object_deps(out_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut deps = Vec::new();
+    let pattern = out_dir.join("**").join("*.d");
+    for path in glob::glob(&pattern.to_string_lossy())? {
+        let content = read(&path?)?;
+        deps.extend(parse_object_deps(&content));
+    }
+
+    Ok(deps)
+}
+
+/// The dependencies of a Makefile rule written by `-MMD`, continued over several lines
+fn parse_object_deps(content: &str) -> Vec<PathBuf> {
+    let content = content.replace("\\\r\n", " ").replace("\\\n", " ");
+    parse_dep_info(&content)
+}
+
+/// The path of the dep-info file `cmd` makes rustc write, if it is asked to
+pub(crate) fn rustc_dep_info(cmd: &ProcessBuilder) -> Option<PathBuf> {
+    let mut out_dir = None;
+    let mut crate_name = None;
+    let mut extra_filename = "";
+    let mut dep_info = false;
+
+    let mut args = cmd.get_args().filter_map(|a| a.to_str());
+    while let Some(arg) = args.next() {
+        match arg {
+            "--out-dir" => out_dir = args.next(),
+            "--crate-name" => crate_name = args.next(),
+            "-C" => {
+                if let Some(extra) = args.next().and_then(|a| a.strip_prefix("extra-filename=")) {
+                    extra_filename = extra;
+                }
+            }
+            _ => {
+                if let Some(emit) = arg.strip_prefix("--emit=") {
+                    dep_info = emit.split(',').any(|e| e == "dep-info");
+                }
+            }
+        }
+    }
+
+    if !dep_info {
+        return None;
+    }
+
+    Some(Path::new(out_dir?).join(format!("{}{extra_filename}.d", crate_name?)))
+}
+
+/// Add `inputs` to the dependencies of the first rule of a rustc dep-info file
+///
+/// cargo reads only that one to know when the library is stale.
+pub(crate) fn add_dep_info_inputs(content: &str, inputs: &[PathBuf]) -> String {
+    let mut done = false;
+    let mut out = String::new();
+    for line in content.lines() {
+        out.push_str(line);
+        if !done && line.contains(": ") {
+            for input in inputs {
+                write!(out, " {}", escape_dep_path(input)).unwrap();
+            }
+            done = true;
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_deps() {
+        let content = "out/1234-varargs.o: src/capi/varargs.c \\\n  src/capi/varargs.h src/capi/my\\ types.h\n";
+        assert_eq!(
+            parse_object_deps(content),
+            [
+                "src/capi/varargs.c",
+                "src/capi/varargs.h",
+                "src/capi/my types.h"
+            ]
+            .map(PathBuf::from)
+        );
+    }
+
+    #[test]
+    fn dep_info() {
+        let mut cmd = ProcessBuilder::new("rustc");
+        cmd.args(&[
+            "--crate-name",
+            "example",
+            "--emit=dep-info,link",
+            "-C",
+            "extra-filename=-0123abcd",
+            "--out-dir",
+            "/target/debug/deps",
+        ]);
+        assert_eq!(
+            rustc_dep_info(&cmd),
+            Some(PathBuf::from("/target/debug/deps/example-0123abcd.d"))
+        );
+
+        let mut cmd = ProcessBuilder::new("rustc");
+        cmd.args(&[
+            "--crate-name",
+            "example",
+            "--emit=link",
+            "--out-dir",
+            "/deps",
+        ]);
+        assert_eq!(rustc_dep_info(&cmd), None);
+
+        let content =
+            "/deps/example.d: src/lib.rs\n\n/deps/libexample.so: src/lib.rs\n\nsrc/lib.rs:\n";
+        let inputs = ["src/capi/varargs.c", "src/capi/my types.h"].map(PathBuf::from);
+        let content = add_dep_info_inputs(content, &inputs);
+        assert_eq!(
+            content,
+            "/deps/example.d: src/lib.rs src/capi/varargs.c src/capi/my\\ types.h\n\n\
+             /deps/libexample.so: src/lib.rs\n\nsrc/lib.rs:\n"
+        );
+    }
+}
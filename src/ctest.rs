@@ -0,0 +1,392 @@
+//! The C and C++ test programs in `tests/capi`, built against the freshly built libraries
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+
+use anyhow::Context;
+use cargo::core::Workspace;
+use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt};
+use cargo_util::paths::{create_dir_all, read, remove_dir_all, write};
+
+use crate::build::CPackage;
+use crate::install::{append_to_destdir, install_packages, InstallManifest};
+
+/// The `*.c` and `*.cpp` test programs of a package, sorted by name
+fn c_tests(root: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut tests = Vec::new();
+    for extension in ["c", "cpp"] {
+        let pattern = root
+            .join("tests")
+            .join("capi")
+            .join(format!("*.{extension}"));
+        for test in glob::glob(&pattern.to_string_lossy())? {
+            tests.push(test?);
+        }
+    }
+    tests.sort();
+    Ok(tests)
+}
+
+fn is_cpp(source: &Path) -> bool {
+    source.extension().is_some_and(|e| e == "cpp")
+}
+
+/// The C or C++ compiler for `triple`, `cc` or else `CC` and `CXX` override the default one
+pub(crate) fn compiler(
+    triple: &str,
+    host: &str,
+    cpp: bool,
+    cc: Option<&str>,
+) -> anyhow::Result<cc::Tool> {
+    let mut build = cc::Build::new();
+    if let Some(cc) = cc {
+        build.compiler(cc);
+    }
+    Ok(build
+        .cpp(cpp)
+        .target(triple)
+        .host(host)
+        .opt_level(0)
+        .cargo_metadata(false)
+        .cargo_warnings(false)
+        .try_get_compiler()?)
+}
+
+/// Compile `source` to `exe`, the `cflags` name the libraries to link as well
+fn compile(
+    compiler: &cc::Tool,
+    msvc: bool,
+    source: &Path,
+    exe: &Path,
+    cflags: &OsStr,
+) -> anyhow::Result<Output> {
+    let cflags = cflags.to_string_lossy();
+    let cflags = cflags.split_whitespace();
+
+    let mut cmd;
+    if msvc {
+        let (cflags, link_args) = msvc_flags(cflags);
+        cmd = compiler.to_command();
+        cmd.args(cflags);
+        let mut fo = OsString::from("-Fo");
+        fo.push(exe.with_extension("obj"));
+        let mut fe = OsString::from("-Fe");
+        fe.push(exe);
+        cmd.arg(fo).arg(fe).arg(source);
+        if !link_args.is_empty() {
+            cmd.arg("/link").args(link_args);
+        }
+    } else {
+        // the input must come before the libraries
+        cmd = std::process::Command::new(compiler.path());
+        cmd.arg(source).args(compiler.args()).args(cflags);
+        cmd.arg("-o").arg(exe);
+    }
+
+    cmd.output()
+        .with_context(|| format!("Cannot run the C compiler {}", compiler.path().display()))
+}
+
+/// Translate the `-L` and `-l` flags pkg-config prints to the MSVC ones
+///
+/// The libraries are passed to `cl` as inputs, the search paths after `/link`.
+fn msvc_flags<'a>(flags: impl Iterator<Item = &'a str>) -> (Vec<String>, Vec<String>) {
+    let mut cflags = Vec::new();
+    let mut link_args = Vec::new();
+    for flag in flags {
+        if let Some(dir) = flag.strip_prefix("-L") {
+            link_args.push(format!("/LIBPATH:{dir}"));
+        } else if let Some(lib) = flag.strip_prefix("-l") {
+            cflags.push(format!("{lib}.lib"));
+        } else {
+            cflags.push(flag.to_string());
+        }
+    }
+
+    (cflags, link_args)
+}
+
+/// Move the install paths of a staged .pc file in `stage`
+///
+/// Only the staged files are relocated, the system packages they require keep their paths.
+fn relocate_pc(content: &str, stage: &Path) -> String {
+    let mut relocated = String::with_capacity(content.len());
+    for line in content.lines() {
+        match line.split_once('=') {
+            Some((var @ ("prefix" | "exec_prefix" | "libdir" | "includedir"), value))
+                if !value.starts_with("${") =>
+            {
+                let path = append_to_destdir(Some(stage), Path::new(value));
+                relocated.push_str(&format!("{var}={}", path.display()));
+            }
+            _ => relocated.push_str(line),
+        }
+        relocated.push('\n');
+    }
+
+    relocated
+}
+
+/// Install the packages in `capi-staged` and return the flags to build against each of them
+///
+/// The flags come from the installed .pc files through pkg-config, relocated in the stage,
+/// the staged library directories are added to the search path of the dynamic loader for
+/// the tests.
+pub fn stage(ws: &Workspace, packages: &[CPackage]) -> anyhow::Result<Vec<OsString>> {
+    let Some(first) = packages.first() else {
+        return Ok(Vec::new());
+    };
+    let stage = first.build_targets.pc.parent().unwrap().join("capi-staged");
+    if stage.exists() {
+        remove_dir_all(&stage)?;
+    }
+
+    ws.gctx()
+        .shell()
+        .status("Staging", format!("the install in {}", stage.display()))?;
+    install_packages(ws, packages, &mut InstallManifest::default(), Some(&stage))?;
+
+    let mut pc_path = Vec::new();
+    let mut library_path = Vec::new();
+    for pkg in packages {
+        let paths = &pkg.install_paths;
+        let pkgconfigdir = append_to_destdir(Some(&stage), &paths.pkgconfigdir);
+        let pc = pkgconfigdir.join(pkg.build_targets.pc.file_name().unwrap());
+        write(&pc, relocate_pc(&read(&pc)?, &stage))?;
+        pc_path.push(pkgconfigdir);
+        let mut libdir = paths.libdir.clone();
+        if let Some(subdir) = &pkg.capi_config.library.install_subdir {
+            libdir.push(subdir);
+        }
+        library_path.push(append_to_destdir(Some(&stage), &libdir));
+        // The dlls are installed in the bindir
+        library_path.push(append_to_destdir(Some(&stage), &paths.bindir));
+    }
+    pc_path.extend(
+        std::env::var_os("PKG_CONFIG_PATH")
+            .map_or_else(Vec::new, |p| std::env::split_paths(&p).collect()),
+    );
+
+    let pkg_config = std::env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".into());
+    let mut flags = Vec::new();
+    for pkg in packages {
+        let name = pkg.build_targets.pc.file_stem().unwrap();
+        let mut cmd = std::process::Command::new(&pkg_config);
+        cmd.env("PKG_CONFIG_PATH", std::env::join_paths(&pc_path)?)
+            .arg("--cflags")
+            .arg("--libs");
+        if pkg.build_targets.shared_lib.is_none() {
+            cmd.arg("--static");
+        }
+        cmd.arg(name);
+        let out = cmd
+            .output()
+            .with_context(|| format!("Cannot run {pkg_config}"))?;
+        if !out.status.success() {
+            anyhow::bail!(
+                "pkg-config cannot use the installed {}.pc\n{}",
+                name.to_string_lossy(),
+                output_text(&out)
+            );
+        }
+        flags.push(OsString::from(String::from_utf8_lossy(&out.stdout).trim()));
+    }
+
+    let os = &first.build_targets.target.os;
+    let var = if os == "windows" || os == "cygwin" {
+        "PATH"
+    } else if os == "macos" || os == "ios" {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+    library_path.extend(
+        std::env::var_os(var).map_or_else(Vec::new, |p| std::env::split_paths(&p).collect()),
+    );
+    std::env::set_var(var, std::env::join_paths(library_path)?);
+
+    Ok(flags)
+}
+
+fn output_text(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+/// Compile and run the C and C++ test programs of the packages, the way libtest reports the
+/// Rust tests
+///
+/// `cflags` are the ones of the libraries, the C tests get the C standard of their package in
+/// the option syntax of their compiler. `TESTNAME` selects the tests by name. The programs run with the runner of the Rust tests,
+/// under the `--wrapper` command if given.
+pub fn run_c_tests(
+    ws: &Workspace,
+    packages: &[CPackage],
+    args: &ArgMatches,
+    cflags: &OsStr,
+    triple: &str,
+    host: &str,
+) -> anyhow::Result<()> {
+    let mut shell = ws.gctx().shell();
+    let mut failed = 0;
+    // by language and `test.cc`, the packages may pin different compilers
+    let mut compilers = HashMap::new();
+
+    let filter = args.get_one::<String>("TESTNAME");
+    let no_run = args.flag("no-run");
+
+    // e.g. `valgrind --error-exitcode=1 qemu-aarch64 test`
+    let mut launcher: Vec<OsString> = args
+        .get_one::<String>("wrapper")
+        .into_iter()
+        .flat_map(|w| w.split_whitespace())
+        .map(OsString::from)
+        .collect();
+    if let Some(runner) = crate::runner::runner(ws.gctx(), triple)? {
+        launcher.push(runner.path.resolve_program(ws.gctx()).into());
+        launcher.extend(runner.args.into_iter().map(OsString::from));
+    }
+
+    for pkg in packages {
+        let tests: Vec<_> = c_tests(&pkg.root_path)?
+            .into_iter()
+            .filter(|t| filter.map_or(true, |f| t.to_string_lossy().contains(f.as_str())))
+            .collect();
+        if tests.is_empty() {
+            continue;
+        }
+
+        let out_dir = pkg
+            .build_targets
+            .pc
+            .parent()
+            .unwrap()
+            .join("capi-tests")
+            .join(pkg.name.as_str());
+        create_dir_all(&out_dir)?;
+
+        let test = pkg.capi_config.test.with_args(args);
+
+        shell.status("Running", format!("the C and C++ tests of {}", pkg.name))?;
+        let plural = if tests.len() == 1 { "" } else { "s" };
+        println!("\nrunning {} test{plural}", tests.len());
+
+        let mut failures = Vec::new();
+        for source in &tests {
+            let name = source
+                .strip_prefix(&pkg.root_path)
+                .unwrap_or(source)
+                .display()
+                .to_string();
+            // foo.c and foo.cpp do not clash
+            let exe = out_dir
+                .join(
+                    source
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('.', "_"),
+                )
+                .with_extension(std::env::consts::EXE_EXTENSION);
+
+            let cpp = is_cpp(source);
+            // `test.cc` is the C compiler, `CXX` picks the C++ one
+            let cc = if cpp { None } else { test.cc.as_deref() };
+            let compiler = match compilers.entry((cpp, cc.map(String::from))) {
+                Entry::Occupied(e) => e.into_mut(),
+                Entry::Vacant(e) => e.insert(self::compiler(triple, host, cpp, cc)?),
+            };
+            // clang-cl takes the MSVC options as well, gcc does not on a msvc target
+            let msvc = compiler.is_like_msvc();
+
+            let mut flags = OsString::from(cflags);
+            if let Some(std) = test.std_flag(msvc).filter(|_| !cpp) {
+                flags.push(" ");
+                flags.push(std);
+            }
+
+            let compiled = compile(compiler, msvc, source, &exe, &flags)?;
+            let outcome = if !compiled.status.success() {
+                Some(format!("compilation failed\n{}", output_text(&compiled)))
+            } else if no_run {
+                None
+            } else {
+                let mut cmd = match launcher.split_first() {
+                    Some((program, args)) => {
+                        let mut cmd = std::process::Command::new(program);
+                        cmd.args(args).arg(&exe);
+                        cmd
+                    }
+                    None => std::process::Command::new(&exe),
+                };
+                let run = cmd
+                    .output()
+                    .with_context(|| format!("Cannot run {}", exe.display()))?;
+                (!run.status.success()).then(|| format!("{}\n{}", run.status, output_text(&run)))
+            };
+
+            match outcome {
+                None if no_run => println!("test {name} ... compiled"),
+                None => println!("test {name} ... ok"),
+                Some(failure) => {
+                    println!("test {name} ... FAILED");
+                    failures.push((name, failure));
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            println!("\nfailures:\n");
+            for (name, failure) in &failures {
+                println!("---- {name} ----\n{}", failure.trim_end());
+            }
+        }
+        println!(
+            "\ntest result: {}. {} passed; {} failed\n",
+            if failures.is_empty() { "ok" } else { "FAILED" },
+            tests.len() - failures.len(),
+            failures.len()
+        );
+        failed += failures.len();
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{failed} C and C++ tests failed");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relocated_pc() {
+        let content = "prefix=/usr/local\nexec_prefix=${prefix}\nlibdir=/usr/lib64\n\
+                       includedir=${prefix}/include\n\nName: foo\nRequires: glib-2.0\n\
+                       Libs: -L${libdir} -lfoo\n";
+        assert_eq!(
+            relocate_pc(content, Path::new("/target/capi-staged")),
+            "prefix=/target/capi-staged/usr/local\nexec_prefix=${prefix}\n\
+             libdir=/target/capi-staged/usr/lib64\nincludedir=${prefix}/include\n\n\
+             Name: foo\nRequires: glib-2.0\nLibs: -L${libdir} -lfoo\n"
+        );
+    }
+
+    #[test]
+    fn msvc_libs() {
+        let flags = "-IC:/staged/include/foo -DFOO -LC:/staged/lib -lfoo -lws2_32";
+        let (cflags, link_args) = msvc_flags(flags.split_whitespace());
+        assert_eq!(
+            cflags,
+            ["-IC:/staged/include/foo", "-DFOO", "foo.lib", "ws2_32.lib"]
+        );
+        assert_eq!(link_args, ["/LIBPATH:C:/staged/lib"]);
+    }
+}
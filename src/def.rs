@@ -0,0 +1,314 @@
+//! The `.def` files listing the dll exports, customized by `library.def`
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use cargo::core::Workspace;
+use cargo::CargoResult;
+use cargo_util::paths::{create, read, write, write_if_changed};
+use cargo_util::ProcessBuilder;
+
+use crate::build::DefCApiConfig;
+use crate::i18n::tr;
+use crate::progress::{self, Phase};
+use crate::target::Target;
+
+/// How the library link applies the `.def` customization
+#[derive(Debug)]
+pub(crate) struct DefLink {
+    pub def: DefCApiConfig,
+    /// The exports the dll is linked with, written from the ones rustc gives it
+    pub path: PathBuf,
+}
+
+/// The `{name}-exports.def` file of the customization and the arguments linking the library
+/// with it
+///
+/// The gnu linkers merge it with the exports of rustc, the aliases and the ordinals apply
+/// but the excluded exports cannot be removed. link.exe takes a single `/DEF`, the library
+/// is linked a second time with the complete list, see [`relink`]. The file is rewritten
+/// only if changed, cargo relinks the library if it is newer.
+pub(crate) fn link_args(
+    def: &DefCApiConfig,
+    name: &str,
+    target: &Target,
+    root_output: &Path,
+) -> anyhow::Result<Option<(PathBuf, Vec<String>)>> {
+    if def.is_empty() || !target.has_dlls() {
+        return Ok(None);
+    }
+
+    let path = root_output.join(format!("{name}-exports.def"));
+    write_if_changed(&path, write_def_exports(def, Vec::new())?)?;
+
+    let args = if target.is_msvc() {
+        Vec::new()
+    } else {
+        vec!["-C".into(), format!("link-arg={}", path.display())]
+    };
+
+    Ok(Some((path, args)))
+}
+
+/// Link the msvc dll again, with the `.def` written from its exports as `/DEF`
+///
+/// The last `/DEF` replaces the one of rustc.
+pub(crate) fn relink(
+    cmd: &ProcessBuilder,
+    link: &DefLink,
+    on_stderr_line: &mut dyn FnMut(&str) -> CargoResult<()>,
+) -> CargoResult<()> {
+    let Some(dll) = rustc_output(cmd, "cdylib", "dll") else {
+        return Ok(());
+    };
+
+    let exports = dll_exports(&dll)?;
+    write_def_file(None, &exports, &link.def, create(&link.path)?)?;
+
+    let mut cmd = cmd.clone();
+    cmd.arg("-C")
+        .arg(format!("link-arg=/DEF:{}", link.path.display()));
+    // The diagnostics are the ones of the first link
+    cmd.exec_with_streaming(
+        &mut |_| Ok(()),
+        &mut |s| {
+            #[derive(serde::Deserialize)]
+            struct Message {
+                level: String,
+            }
+            match serde_json::from_str::<Message>(s) {
+                Ok(msg) if msg.level != "error" => Ok(()),
+                Err(_) if s.starts_with('{') => Ok(()),
+                _ => on_stderr_line(s),
+            }
+        },
+        false,
+    )?;
+
+    Ok(())
+}
+
+/// The `extension` file the rustc command writes for `crate_type`, if it builds one
+fn rustc_output(cmd: &ProcessBuilder, crate_type: &str, extension: &str) -> Option<PathBuf> {
+    let mut out_dir = None;
+    let mut crate_name = None;
+    let mut extra_filename = "";
+    let mut crate_types = Vec::new();
+
+    let mut args = cmd.get_args().filter_map(|a| a.to_str());
+    while let Some(arg) = args.next() {
+        match arg {
+            "--out-dir" => out_dir = args.next(),
+            "--crate-name" => crate_name = args.next(),
+            "--crate-type" => crate_types.extend(args.next()),
+            "-C" => {
+                if let Some(extra) = args.next().and_then(|a| a.strip_prefix("extra-filename=")) {
+                    extra_filename = extra;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if !crate_types.contains(&crate_type) {
+        return None;
+    }
+
+    Some(Path::new(out_dir?).join(format!("{}{extra_filename}.{extension}", crate_name?)))
+}
+
+fn dll_exports(path: &Path) -> anyhow::Result<Vec<String>> {
+    use object::read::Object;
+
+    let dll_content = std::fs::read(path)?;
+    let dll_file = object::File::parse(&*dll_content)?;
+
+    Ok(dll_file
+        .exports()?
+        .iter()
+        .map(|e| String::from_utf8_lossy(e.name()).into_owned())
+        .collect())
+}
+
+/// Build def file for windows-msvc
+///
+/// If customized, it is the one the dll is linked with. The gnu linkers write it, the
+/// excluded exports are removed from it.
+pub fn build_def_file(
+    ws: &Workspace,
+    name: &str,
+    def: &DefCApiConfig,
+    target: &Target,
+    targetdir: &Path,
+) -> anyhow::Result<()> {
+    if target.is_msvc() {
+        progress::status(ws, Phase::ImportLibrary, "Building", tr("build-def", &[]))?;
+
+        let def_path = targetdir.join(format!("{name}.def"));
+        let linked = targetdir.join(format!("{name}-link.def"));
+        if !def.is_empty() && linked.exists() {
+            write(
+                def_path,
+                format!("LIBRARY \"{name}.dll\"\n{}", read(&linked)?),
+            )?;
+            return Ok(());
+        }
+
+        // Parse the .dll as an object file
+        let dll_path = targetdir.join(format!("{}.dll", name.replace('-', "_")));
+        let exports = dll_exports(&dll_path)?;
+
+        // Create the .def output file
+        write_def_file(Some(name), &exports, def, create(def_path)?)?;
+    } else if target.has_dlls() && !def.exclude.is_empty() {
+        let def_path = targetdir.join(format!("{name}.def"));
+        write(&def_path, exclude_exports(&read(&def_path)?, def))?;
+    }
+
+    Ok(())
+}
+
+/// The `.def` without the excluded exports
+fn exclude_exports(def_file: &str, def: &DefCApiConfig) -> String {
+    let mut exports = false;
+    def_file
+        .lines()
+        .filter(|line| {
+            let line = line.trim();
+            if line == "EXPORTS" {
+                exports = true;
+                return true;
+            }
+            let name = line.split([' ', '=']).next().unwrap_or_default();
+            !exports || !def.exclude.iter().any(|p| p.matches(name))
+        })
+        .map(|line| format!("{line}\n"))
+        .collect()
+}
+
+/// The `.def` of the exports, `name` is the one of the dll if the `LIBRARY` line is written
+pub(crate) fn write_def_file<W: Write>(
+    name: Option<&str>,
+    exports: &[String],
+    def: &DefCApiConfig,
+    mut def_file: W,
+) -> anyhow::Result<W> {
+    let exported = |symbol: &str| exports.iter().any(|e| e == symbol);
+    for symbol in def.ordinals.keys() {
+        if !exported(symbol) && !def.aliases.contains_key(symbol) {
+            anyhow::bail!("`{symbol}` has an ordinal but it is not exported by the dll");
+        }
+    }
+    for (alias, symbol) in &def.aliases {
+        if !exported(symbol) {
+            anyhow::bail!("`{symbol}` aliased as `{alias}` is not exported by the dll");
+        }
+    }
+
+    if let Some(name) = name {
+        writeln!(def_file, "LIBRARY \"{name}.dll\"")?;
+    }
+    writeln!(def_file, "EXPORTS")?;
+
+    for export in exports {
+        if def.exclude.iter().any(|p| p.matches(export)) {
+            continue;
+        }
+        writeln!(def_file, "{export}{}", def.ordinal(export))?;
+    }
+
+    for (alias, symbol) in &def.aliases {
+        writeln!(def_file, "{alias}={symbol}{}", def.ordinal(alias))?;
+    }
+
+    Ok(def_file)
+}
+
+/// The customization alone, what the gnu linkers add to the exports of rustc
+fn write_def_exports<W: Write>(def: &DefCApiConfig, mut def_file: W) -> anyhow::Result<W> {
+    for pattern in &def.exclude {
+        writeln!(def_file, "; exclude {pattern}")?;
+    }
+    writeln!(def_file, "EXPORTS")?;
+
+    for (symbol, _) in def
+        .ordinals
+        .iter()
+        .filter(|(symbol, _)| !def.aliases.contains_key(*symbol))
+    {
+        writeln!(def_file, "{symbol}{}", def.ordinal(symbol))?;
+    }
+
+    for (alias, symbol) in &def.aliases {
+        writeln!(def_file, "{alias}={symbol}{}", def.ordinal(alias))?;
+    }
+
+    Ok(def_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn def() -> DefCApiConfig {
+        DefCApiConfig {
+            exclude: vec![glob::Pattern::new("internal_*").unwrap()],
+            ordinals: [("bar".into(), 2), ("foo".into(), 1)].into(),
+            aliases: [("foo".into(), "foo_impl".into())].into(),
+        }
+    }
+
+    #[test]
+    fn def_file() {
+        let exports = ["foo_impl", "bar", "internal_baz"].map(String::from);
+        let def_file = write_def_file(Some("example"), &exports, &def(), Vec::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(def_file).unwrap(),
+            "LIBRARY \"example.dll\"\nEXPORTS\nfoo_impl\nbar @2\nfoo=foo_impl @1\n"
+        );
+
+        let def = DefCApiConfig {
+            aliases: [("foo".into(), "missing".into())].into(),
+            ..Default::default()
+        };
+        assert!(write_def_file(None, &exports, &def, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn gnu_def_file() {
+        let def_file = "EXPORTS\n    bar @1\n    foo = foo_impl @2\n    internal_baz @3\n";
+        assert_eq!(
+            exclude_exports(def_file, &def()),
+            "EXPORTS\n    bar @1\n    foo = foo_impl @2\n"
+        );
+    }
+
+    #[test]
+    fn def_exports() {
+        let def_file = write_def_exports(&def(), Vec::new()).unwrap();
+        assert_eq!(
+            String::from_utf8(def_file).unwrap(),
+            "; exclude internal_*\nEXPORTS\nbar @2\nfoo=foo_impl @1\n"
+        );
+    }
+
+    #[test]
+    fn rustc_dll() {
+        let mut cmd = ProcessBuilder::new("rustc");
+        cmd.args(&[
+            "--crate-name",
+            "example",
+            "--crate-type",
+            "cdylib",
+            "-C",
+            "extra-filename=-0123abcd",
+            "--out-dir",
+            "target/release/deps",
+        ]);
+        assert_eq!(
+            rustc_output(&cmd, "cdylib", "dll").unwrap(),
+            Path::new("target/release/deps/example-0123abcd.dll")
+        );
+        assert_eq!(rustc_output(&cmd, "staticlib", "lib"), None);
+    }
+}
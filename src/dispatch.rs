@@ -0,0 +1,143 @@
+//! The subcommands shared by `cargo capi` and the `cbuild`, `cinstall`, `ctest` and `cdoc`
+//! binaries
+
+use std::path::PathBuf;
+
+use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt};
+use cargo::{CliResult, GlobalContext};
+
+use crate::abi::abi_check;
+use crate::build::{build_bins, capi_packages, cbuild, ctest, pretend, verify_header};
+use crate::config::global_context_configure;
+use crate::doc::cdoc;
+use crate::explain::explain;
+use crate::ide::ide_setup;
+use crate::install::{cinstall, copy_artifacts, print_dirs};
+use crate::installer::write_installers;
+use crate::reuse::{prebuilt, reusable};
+use crate::shell::capi_shell;
+use crate::size::size_report;
+use crate::upgrade::upgrade_metadata;
+
+/// The names of the standalone binaries, accepted by `cargo capi` as deprecated aliases
+pub const LEGACY_NAMES: &[(&str, &str)] = &[
+    ("cbuild", "build"),
+    ("cinstall", "install"),
+    ("ctest", "test"),
+    ("cdoc", "doc"),
+];
+
+/// The subcommand a legacy name stands for
+pub fn legacy_name(name: &str) -> Option<&'static str> {
+    LEGACY_NAMES
+        .iter()
+        .find_map(|(legacy, cmd)| (*legacy == name).then_some(*cmd))
+}
+
+/// The profile of the subcommand if none is selected
+///
+/// The installs and the size reports are meant for the release builds.
+pub fn default_profile(cmd: &str) -> &'static str {
+    match cmd {
+        "install" | "size-report" | "msi" => "release",
+        _ => "dev",
+    }
+}
+
+/// Run the `cargo capi` subcommand `cmd`, the standalone binaries run the same code
+///
+/// `cmd` may be a legacy name, the deprecation is reported once the shell is configured.
+pub fn run(cmd: &str, args: &ArgMatches, config: &mut GlobalContext) -> CliResult {
+    if args.flag("version") {
+        println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        return Ok(());
+    }
+
+    global_context_configure(config, args)?;
+
+    let cmd = match legacy_name(cmd) {
+        Some(new) => {
+            config.shell().warn(format!(
+                "`cargo capi {cmd}` is deprecated and will be removed, use `cargo capi {new}` or `cargo {cmd}`"
+            ))?;
+            new
+        }
+        None => cmd,
+    };
+
+    // No workspace is needed
+    if cmd == "dirs" {
+        return Ok(print_dirs(args)?);
+    }
+
+    let default_profile = default_profile(cmd);
+    let mut ws = args.workspace(config)?;
+
+    match cmd {
+        "ide-setup" => return Ok(ide_setup(&ws, args)?),
+        "upgrade-metadata" => return Ok(upgrade_metadata(&ws, args)?),
+        "explain" => {
+            let packages = capi_packages(&mut ws, config, args, default_profile)?;
+            return Ok(explain(&ws, &packages, args)?);
+        }
+        "verify-header" => {
+            let packages = capi_packages(&mut ws, config, args, default_profile)?;
+            return verify_header(&ws, &packages, args);
+        }
+        "install" if args.flag("no-build") => {
+            let packages = prebuilt(&mut ws, config, args, default_profile)?;
+            return Ok(cinstall(&ws, &packages, args)?);
+        }
+        "build" | "install" if args.contains_id("pretend_target") => {
+            let packages = pretend(&mut ws, config, args, default_profile)?;
+            if cmd == "install" {
+                cinstall(&ws, &packages, args)?;
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if cmd == "install" && args.flag("reuse") {
+        if let Some(mut packages) = reusable(&mut ws, config, args, default_profile)? {
+            build_bins(config, args, &mut packages, default_profile)?;
+            return Ok(cinstall(&ws, &packages, args)?);
+        }
+    }
+
+    let (mut packages, compile_opts) = cbuild(&mut ws, config, args, default_profile)?;
+
+    if let Ok(Some(dir)) = args.try_get_one::<PathBuf>("artifact-dir") {
+        copy_artifacts(&ws, &packages, dir)?;
+    }
+
+    match cmd {
+        "install" => {
+            build_bins(config, args, &mut packages, default_profile)?;
+            cinstall(&ws, &packages, args)?;
+        }
+        "test" => ctest(&ws, args, &packages, compile_opts)?,
+        "doc" => cdoc(&ws, &packages, args)?,
+        "abi-check" => abi_check(&ws, &packages, args)?,
+        "size-report" => size_report(&ws, &packages)?,
+        "msi" => write_installers(&ws, &packages, args)?,
+        "shell" => capi_shell(&ws, &packages, args)?,
+        _ => {}
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_names() {
+        assert_eq!(legacy_name("cbuild"), Some("build"));
+        assert_eq!(legacy_name("ctest"), Some("test"));
+        assert_eq!(legacy_name("build"), None);
+        assert_eq!(default_profile("install"), "release");
+        assert_eq!(default_profile("test"), "dev");
+    }
+}
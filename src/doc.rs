@@ -0,0 +1,149 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use cargo::core::Workspace;
+use cargo_util::paths::{create_dir_all, remove_dir_all, write};
+use clap::ArgMatches;
+
+use crate::build::CPackage;
+use crate::install::{append_to_destdir, FileCategory, InstallManifest};
+use crate::progress::{self, Phase};
+
+/// A Doxyfile string, the quotes are escaped
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('"', "\\\""))
+}
+
+/// Render a Doxyfile documenting `headers` in `output`
+fn doxyfile(cpkg: &CPackage, headers: &[&Path], root_output: &Path, output: &Path) -> String {
+    let pc = &cpkg.capi_config.pkg_config;
+    let quote_path = |path: &Path| quote(&path.display().to_string());
+    let mut buf = String::with_capacity(512);
+
+    // writing to a String only fails on OOM, which we disregard
+    writeln!(buf, "# Generated by cargo-c").unwrap();
+    writeln!(buf, "PROJECT_NAME = {}", quote(&pc.name)).unwrap();
+    writeln!(buf, "PROJECT_NUMBER = {}", quote(&pc.version)).unwrap();
+    writeln!(
+        buf,
+        "PROJECT_BRIEF = {}",
+        quote(&pc.description.replace('\n', " "))
+    )
+    .unwrap();
+    let input = headers.iter().map(|h| quote_path(h)).collect::<Vec<_>>();
+    writeln!(buf, "INPUT = {}", input.join(" ")).unwrap();
+    writeln!(buf, "STRIP_FROM_PATH = {}", quote_path(root_output)).unwrap();
+    writeln!(buf, "OUTPUT_DIRECTORY = {}", quote_path(output)).unwrap();
+    writeln!(buf, "OPTIMIZE_OUTPUT_FOR_C = YES").unwrap();
+    writeln!(buf, "EXTRACT_ALL = YES").unwrap();
+    writeln!(buf, "JAVADOC_AUTOBRIEF = YES").unwrap();
+    writeln!(buf, "GENERATE_HTML = YES").unwrap();
+    writeln!(buf, "GENERATE_LATEX = NO").unwrap();
+    writeln!(buf, "QUIET = YES").unwrap();
+
+    buf
+}
+
+/// Generate the documentation of the C-API from the headers using Doxygen
+///
+/// The Doxyfile is written in `{target}/capi-doc/{library name}`, `--install`
+/// copies the html documentation to `{docdir}/html`, listed in `--install-manifest`.
+pub fn cdoc(ws: &Workspace, packages: &[CPackage], args: &ArgMatches) -> anyhow::Result<()> {
+    let doxygen = std::env::var_os("DOXYGEN").unwrap_or_else(|| "doxygen".into());
+    let mut manifest = InstallManifest::default();
+
+    for cpkg in packages {
+        if !cpkg.capi_config.header.enabled {
+            continue;
+        }
+
+        let headers: Vec<&Path> = cpkg
+            .build_targets
+            .extra
+            .include
+            .iter()
+            .map(|(from, _)| from.as_path())
+            .filter(|from| {
+                from.extension()
+                    .is_some_and(|ext| ext == "h" || ext == "hpp")
+            })
+            .collect();
+        if headers.is_empty() {
+            continue;
+        }
+
+        let name = &cpkg.capi_config.library.name;
+        let root_output = cpkg.build_targets.pc.parent().unwrap();
+        let output: PathBuf = root_output.join("capi-doc").join(name);
+        if output.exists() {
+            remove_dir_all(&output)?;
+        }
+        create_dir_all(&output)?;
+
+        let path = output.join("Doxyfile");
+        write(&path, doxyfile(cpkg, &headers, root_output, &output))?;
+
+        progress::status(
+            ws,
+            Phase::Doc,
+            "Documenting",
+            format!("{name} C-API using {}", Path::new(&doxygen).display()),
+        )?;
+
+        let status = Command::new(&doxygen)
+            .arg(&path)
+            .current_dir(&output)
+            .status();
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => anyhow::bail!("{} failed: {status}", Path::new(&doxygen).display()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                ws.gctx().shell().warn(format!(
+                    "{} is not available, run it on {} to generate the documentation",
+                    Path::new(&doxygen).display(),
+                    path.display()
+                ))?;
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        }
+
+        if args.get_flag("install") {
+            let paths = &cpkg.install_paths;
+            let docdir = append_to_destdir(paths.destdir.as_deref(), &paths.docdir);
+            manifest.destdir.clone_from(&paths.destdir);
+
+            progress::status(
+                ws,
+                Phase::Install,
+                "Installing",
+                format!("documentation to {}", docdir.display()),
+            )?;
+            manifest.copy_dir(
+                ws,
+                FileCategory::Doc,
+                &output.join("html"),
+                docdir.join("html"),
+            )?;
+        }
+    }
+
+    if let Some(path) = args.get_one::<PathBuf>("install-manifest") {
+        progress::status(ws, Phase::Install, "Writing", path.display())?;
+        manifest.write(path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::quote;
+
+    #[test]
+    fn quoted() {
+        assert_eq!(quote("foo"), "\"foo\"");
+        assert_eq!(quote("the \"foo\" library"), r#""the \"foo\" library""#);
+    }
+}
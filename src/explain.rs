@@ -0,0 +1,341 @@
+use std::path::Path;
+
+use cargo::core::Workspace;
+use cargo_util::paths::read;
+use clap::ArgMatches;
+
+use crate::build::{CPackage, VersionSuffix};
+use crate::install::{LibType, UnixLibNames};
+
+/// Where the value of a `package.metadata.capi` key comes from
+pub(crate) struct Metadata {
+    pub(crate) capi: Option<toml::Value>,
+}
+
+impl Metadata {
+    pub(crate) fn load(root_path: &Path) -> anyhow::Result<Self> {
+        let manifest = read(&root_path.join("Cargo.toml"))?.parse::<toml::Value>()?;
+        let capi = manifest
+            .get("package")
+            .and_then(|v| v.get("metadata"))
+            .and_then(|v| v.get("capi"))
+            .cloned();
+
+        Ok(Self { capi })
+    }
+
+    pub(crate) fn is_set(&self, table: &str, key: &str) -> bool {
+        self.capi
+            .as_ref()
+            .and_then(|capi| capi.get(table))
+            .and_then(|table| table.get(key))
+            .is_some()
+    }
+
+    fn source(&self, table: &str, key: &str, default: &str) -> String {
+        if self.is_set(table, key) {
+            format!("`package.metadata.capi.{table}.{key}`")
+        } else {
+            format!("not set in `package.metadata.capi.{table}.{key}`, {default}")
+        }
+    }
+}
+
+fn explain_name(cpkg: &CPackage, metadata: &Metadata, lines: &mut Vec<String>) {
+    lines.push(format!(
+        "library name `{}`: {}",
+        cpkg.capi_config.library.name,
+        metadata.source("library", "name", "the crate name is used")
+    ));
+}
+
+fn explain_library_types(metadata: &Metadata, lines: &mut Vec<String>) {
+    lines.push(format!(
+        "built: {}, `--library-type` overrides it",
+        metadata.source(
+            "library",
+            "default_library_types",
+            "staticlib and cdylib are built, only staticlib on musl"
+        )
+    ));
+}
+
+fn explain_version(cpkg: &CPackage, metadata: &Metadata, lines: &mut Vec<String>) {
+    let library = &cpkg.capi_config.library;
+    lines.push(format!(
+        "version `{}`: {}",
+        library.version,
+        metadata.source("library", "version", "the package version is used")
+    ));
+
+    let rule = match library.version_suffix_components {
+        None => "the major version, `0.minor` for 0.x and `0.0.patch` for 0.0.x versions",
+        Some(VersionSuffix::Major) => "the major version",
+        Some(VersionSuffix::MajorMinor) => "the major and minor versions",
+        Some(VersionSuffix::MajorMinorPatch) => "the full version",
+    };
+    lines.push(format!(
+        "sover `{}`, {rule}: {}",
+        library.sover(),
+        metadata.source("library", "version_suffix_components", "the default rule")
+    ));
+}
+
+/// The lines explaining the name and content of `file_name`, `None` if the package does not produce it
+fn explain_artifact(cpkg: &CPackage, metadata: &Metadata, file_name: &str) -> Option<Vec<String>> {
+    let build_targets = &cpkg.build_targets;
+    let capi_config = &cpkg.capi_config;
+    let same_name = |path: Option<&Path>| {
+        path.and_then(|p| p.file_name())
+            .is_some_and(|name| name == file_name)
+    };
+
+    let mut lines = Vec::new();
+
+    if same_name(Some(&build_targets.pc))
+        || file_name == format!("{}-uninstalled.pc", capi_config.pkg_config.filename)
+    {
+        let pc = &capi_config.pkg_config;
+        lines.push(format!(
+            "pkg-config file `{}.pc`: {}",
+            pc.filename,
+            metadata.source("pkg_config", "filename", "the crate name is used")
+        ));
+        lines.push(format!(
+            "Name `{}`: {}",
+            pc.name,
+            metadata.source("pkg_config", "name", "the crate name is used")
+        ));
+        lines.push(format!(
+            "Description: {}",
+            metadata.source(
+                "pkg_config",
+                "description",
+                "the package description is used"
+            )
+        ));
+        lines.push(format!(
+            "Version `{}`: {}",
+            pc.version,
+            metadata.source("pkg_config", "version", "the package version is used")
+        ));
+        lines.push(format!(
+            "Requires: {}, the C-API packages of the workspace it depends on are added",
+            metadata.source("pkg_config", "requires", "empty")
+        ));
+        lines.push(format!(
+            "Requires.private: {}",
+            metadata.source("pkg_config", "requires_private", "empty")
+        ));
+        lines.push(format!(
+            "Libs `-l{}`: the library name, `Libs.private` lists the native libraries rustc links",
+            capi_config.library.name
+        ));
+        lines.push(format!(
+            "Libs exact file name: {}, only the ELF linkers resolve `-l:`",
+            metadata.source("pkg_config", "link_filename", "not used")
+        ));
+        lines.push(format!(
+            "Cflags include subdirectory `{}`: {}, {}",
+            capi_config.header.subdirectory,
+            metadata.source("header", "subdirectory", "the crate name is used"),
+            metadata.source(
+                "pkg_config",
+                "strip_include_path_components",
+                "no components are stripped"
+            )
+        ));
+        lines.push(format!(
+            "prefix `{}`, libdir and includedir: `--prefix`, `--libdir` and `--includedir`",
+            cpkg.install_paths.prefix.display()
+        ));
+        if file_name.ends_with("-uninstalled.pc") {
+            lines.push("the uninstalled variant points to the build directory".to_string());
+        }
+        return Some(lines);
+    }
+
+    let header = &capi_config.header;
+    let header_file = |ext: &str| format!("{}.{ext}", header.name);
+    if file_name == header_file("h") || file_name == header_file("hpp") {
+        lines.push(format!(
+            "header name `{}`: {}",
+            header.name,
+            metadata.source("header", "name", "the crate name is used")
+        ));
+        lines.push(format!(
+            "installed in `{{includedir}}/{}`: {}",
+            header.subdirectory,
+            metadata.source("header", "subdirectory", "the crate name is used")
+        ));
+        lines.push(format!(
+            "cbindgen configuration: {}",
+            metadata.source(
+                "header",
+                "cbindgen_config",
+                "`cbindgen.toml` is used if present"
+            )
+        ));
+        if file_name.ends_with(".hpp") {
+            lines.push(format!(
+                "C++ header: {}",
+                metadata.source("header", "cpp", "it is not generated")
+            ));
+        }
+        return Some(lines);
+    }
+    if let Some(extra) = header
+        .headers
+        .iter()
+        .find(|h| h.file_name().file_name().is_some_and(|n| n == file_name))
+    {
+        lines.push(format!(
+            "additional header `{}`: `package.metadata.capi.header.headers`",
+            extra.name
+        ));
+        return Some(lines);
+    }
+
+    if same_name(build_targets.static_lib.as_deref())
+        || build_targets
+            .static_output_file_name()
+            .is_some_and(|name| name == file_name)
+    {
+        lines.push("static library".to_string());
+        explain_name(cpkg, metadata, &mut lines);
+        explain_library_types(metadata, &mut lines);
+        if build_targets.use_meson_naming_convention {
+            lines.push("installed with the meson naming convention: `--meson-paths`".to_string());
+        }
+        return Some(lines);
+    }
+
+    if same_name(build_targets.impl_lib.as_deref())
+        || same_name(build_targets.def.as_deref())
+        || build_targets
+            .impl_lib_output_file_name()
+            .is_some_and(|name| name == file_name)
+    {
+        lines.push("import library of the dll".to_string());
+        explain_name(cpkg, metadata, &mut lines);
+        lines.push(format!(
+            "installed: {}",
+            metadata.source("library", "import_library", "it is installed")
+        ));
+        return Some(lines);
+    }
+
+    let shared_lib = build_targets.shared_lib.as_ref()?;
+    match LibType::from_build_targets(build_targets) {
+        LibType::So | LibType::Dylib => {
+            let names = UnixLibNames::new(
+                LibType::from_build_targets(build_targets),
+                &capi_config.library,
+            )?;
+            if file_name == names.with_full_ver {
+                lines.push("shared library, with the full version".to_string());
+                explain_name(cpkg, metadata, &mut lines);
+                explain_version(cpkg, metadata, &mut lines);
+            } else if file_name == names.with_main_ver {
+                lines.push("symlink named after the soname".to_string());
+                explain_name(cpkg, metadata, &mut lines);
+                explain_version(cpkg, metadata, &mut lines);
+            } else if file_name == names.canonical || same_name(Some(shared_lib)) {
+                lines.push("shared library, unversioned name used by the linker".to_string());
+                explain_name(cpkg, metadata, &mut lines);
+            } else {
+                return None;
+            }
+            if !capi_config.library.versioning {
+                lines.push(format!(
+                    "installed without the versioned names: {}",
+                    metadata.source("library", "versioning", "they are installed")
+                ));
+            }
+        }
+        LibType::Windows => {
+            let is_dll = same_name(Some(shared_lib))
+                || build_targets
+                    .shared_output_file_name()
+                    .is_some_and(|name| name == file_name);
+            if !is_dll {
+                return None;
+            }
+            lines.push("dll".to_string());
+            explain_name(cpkg, metadata, &mut lines);
+            if build_targets.use_meson_naming_convention {
+                lines.push(
+                    "installed with the meson naming convention: `--meson-paths`".to_string(),
+                );
+            }
+            lines.push(format!(
+                "installed in `{}`: {}",
+                if capi_config.library.install_subdir.is_some() {
+                    "{libdir}"
+                } else {
+                    "{bindir}"
+                },
+                metadata.source("library", "install_subdir", "the bindir is used")
+            ));
+        }
+    }
+    explain_library_types(metadata, &mut lines);
+
+    Some(lines)
+}
+
+/// Explain which configuration produced the name and the content of an artifact
+pub fn explain(ws: &Workspace, packages: &[CPackage], args: &ArgMatches) -> anyhow::Result<()> {
+    let artifact = args.get_one::<std::path::PathBuf>("artifact").unwrap();
+    let file_name = artifact
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut found = false;
+    for cpkg in packages {
+        let metadata = Metadata::load(&cpkg.root_path)?;
+        if let Some(lines) = explain_artifact(cpkg, &metadata, &file_name) {
+            found = true;
+            ws.gctx()
+                .shell()
+                .status("Explaining", format!("{file_name} of {}", cpkg.name))?;
+            for line in lines {
+                println!("  {line}");
+            }
+        }
+    }
+
+    if !found {
+        anyhow::bail!("`{file_name}` is not produced by the C-API packages of the workspace");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metadata;
+
+    #[test]
+    fn source() {
+        let metadata = Metadata {
+            capi: Some(
+                toml::toml! {
+                    [library]
+                    name = "foo"
+                }
+                .into(),
+            ),
+        };
+
+        assert_eq!(
+            metadata.source("library", "name", "the crate name is used"),
+            "`package.metadata.capi.library.name`"
+        );
+        assert_eq!(
+            metadata.source("library", "version", "the package version is used"),
+            "not set in `package.metadata.capi.library.version`, the package version is used"
+        );
+    }
+}
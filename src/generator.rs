@@ -0,0 +1,256 @@
+use std::path::{Path, PathBuf};
+
+use cargo::core::Workspace;
+use cargo_util::paths::{create_dir_all, remove_dir_all};
+use cargo_util::ProcessBuilder;
+
+use crate::progress::{self, Phase};
+
+/// The tool used to produce the C header
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum HeaderGenerator {
+    #[default]
+    Cbindgen,
+    Diplomat(ExternalGenerator),
+    Uniffi(ExternalGenerator),
+}
+
+/// Settings for a header generator that runs as a separate process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalGenerator {
+    /// The executable to run
+    pub tool: PathBuf,
+    /// Additional languages to produce bindings for, they are installed as data
+    pub bindings: Vec<String>,
+}
+
+impl HeaderGenerator {
+    /// Parse the `generator`, `generator_path` and `bindings` keys of the header section
+    pub fn from_value(header: Option<&toml::Value>) -> anyhow::Result<Self> {
+        let Some(header) = header else {
+            return Ok(HeaderGenerator::Cbindgen);
+        };
+
+        let kind = header
+            .get("generator")
+            .map(|v| v.clone().try_into::<String>())
+            .transpose()?;
+        let tool = header
+            .get("generator_path")
+            .map(|v| v.clone().try_into::<String>())
+            .transpose()?;
+        let bindings = header
+            .get("bindings")
+            .map(|v| v.clone().try_into::<Vec<String>>())
+            .transpose()?
+            .unwrap_or_default();
+
+        let external = |default_tool: &str| ExternalGenerator {
+            tool: PathBuf::from(tool.as_deref().unwrap_or(default_tool)),
+            bindings: bindings.clone(),
+        };
+
+        match kind.as_deref() {
+            None | Some("cbindgen") => {
+                if !bindings.is_empty() {
+                    anyhow::bail!("`bindings` requires the `diplomat` or `uniffi` generator");
+                }
+                Ok(HeaderGenerator::Cbindgen)
+            }
+            Some("diplomat") => Ok(HeaderGenerator::Diplomat(external("diplomat-tool"))),
+            Some("uniffi") => Ok(HeaderGenerator::Uniffi(external("uniffi-bindgen"))),
+            Some(other) => anyhow::bail!(
+                "Unknown header generator `{other}`, expected `cbindgen`, `diplomat` or `uniffi`"
+            ),
+        }
+    }
+
+    pub fn is_cbindgen(&self) -> bool {
+        matches!(self, HeaderGenerator::Cbindgen)
+    }
+
+    fn external(&self) -> Option<&ExternalGenerator> {
+        match self {
+            HeaderGenerator::Cbindgen => None,
+            HeaderGenerator::Diplomat(e) | HeaderGenerator::Uniffi(e) => Some(e),
+        }
+    }
+
+    /// Directory the external generator writes into
+    pub fn output_dir(root_output: &Path, name: &str) -> PathBuf {
+        root_output.join("capi-bindings").join(name)
+    }
+
+    /// Run the external generator, producing the C headers and the additional bindings
+    pub fn generate(
+        &self,
+        ws: &Workspace,
+        name: &str,
+        root_path: &Path,
+        root_output: &Path,
+        shared_lib: Option<&Path>,
+    ) -> anyhow::Result<()> {
+        let Some(external) = self.external() else {
+            return Ok(());
+        };
+
+        let output_dir = Self::output_dir(root_output, name);
+        if output_dir.exists() {
+            remove_dir_all(&output_dir)?;
+        }
+
+        let languages = std::iter::once("c").chain(external.bindings.iter().map(String::as_str));
+
+        for language in languages {
+            progress::status(
+                ws,
+                Phase::Bindings,
+                "Building",
+                format!("{language} bindings using {}", external.tool.display()),
+            )?;
+
+            let out = output_dir.join(language);
+            create_dir_all(&out)?;
+
+            let mut cmd = ProcessBuilder::new(&external.tool);
+            cmd.cwd(root_path);
+
+            match self {
+                HeaderGenerator::Diplomat(_) => {
+                    cmd.arg(language).arg(&out);
+                }
+                HeaderGenerator::Uniffi(_) => {
+                    let Some(shared_lib) = shared_lib else {
+                        anyhow::bail!("The uniffi generator requires building the cdylib");
+                    };
+                    // uniffi does not have a C backend, the header is part of the
+                    // swift scaffolding.
+                    let language = if language == "c" { "swift" } else { language };
+                    cmd.arg("generate")
+                        .arg("--library")
+                        .arg(shared_lib)
+                        .arg("--language")
+                        .arg(language)
+                        .arg("--out-dir")
+                        .arg(&out);
+                }
+                HeaderGenerator::Cbindgen => unreachable!(),
+            }
+
+            ws.gctx()
+                .shell()
+                .verbose(|shell| shell.status("Running", &cmd))?;
+            cmd.exec_with_output()?;
+        }
+
+        Ok(())
+    }
+
+    /// The headers produced by the last run of the external generator
+    ///
+    /// They are installed in `{includedir}/{subdirectory}`.
+    pub fn headers(
+        &self,
+        root_output: &Path,
+        name: &str,
+        subdirectory: &str,
+    ) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+        if self.external().is_none() {
+            return Ok(Vec::new());
+        }
+
+        let c_dir = Self::output_dir(root_output, name).join("c");
+        let pattern = c_dir.join("**").join("*.h");
+
+        glob::glob(pattern.to_str().unwrap())?
+            .map(|p| {
+                let from = p?;
+                let to = Path::new(subdirectory).join(from.strip_prefix(&c_dir)?);
+                Ok((from, to))
+            })
+            .collect()
+    }
+
+    /// The additional bindings produced by the last run of the external generator
+    ///
+    /// They are installed in `{datadir}/{subdirectory}/bindings/{language}`.
+    pub fn bindings(
+        &self,
+        root_output: &Path,
+        name: &str,
+        subdirectory: &str,
+    ) -> anyhow::Result<Vec<(PathBuf, PathBuf)>> {
+        let Some(external) = self.external() else {
+            return Ok(Vec::new());
+        };
+
+        let output_dir = Self::output_dir(root_output, name);
+        let mut files = Vec::new();
+
+        for language in &external.bindings {
+            let lang_dir = output_dir.join(language);
+            let pattern = lang_dir.join("**").join("*");
+            for p in glob::glob(pattern.to_str().unwrap())? {
+                let from = p?;
+                if from.is_file() {
+                    let to = Path::new(subdirectory)
+                        .join("bindings")
+                        .join(language)
+                        .join(from.strip_prefix(&lang_dir)?);
+                    files.push((from, to));
+                }
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_generator() {
+        assert_eq!(
+            HeaderGenerator::from_value(None).unwrap(),
+            HeaderGenerator::Cbindgen
+        );
+
+        let header: toml::Value = toml::from_str(
+            r#"
+            generator = "diplomat"
+            bindings = ["cpp", "js"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            HeaderGenerator::from_value(Some(&header)).unwrap(),
+            HeaderGenerator::Diplomat(ExternalGenerator {
+                tool: PathBuf::from("diplomat-tool"),
+                bindings: vec!["cpp".into(), "js".into()],
+            })
+        );
+
+        let header: toml::Value = toml::from_str(
+            r#"
+            generator = "uniffi"
+            generator_path = "/opt/uniffi/bin/uniffi-bindgen"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            HeaderGenerator::from_value(Some(&header)).unwrap(),
+            HeaderGenerator::Uniffi(ExternalGenerator {
+                tool: PathBuf::from("/opt/uniffi/bin/uniffi-bindgen"),
+                bindings: Vec::new(),
+            })
+        );
+
+        let header: toml::Value = toml::from_str(r#"generator = "swig""#).unwrap();
+        assert!(HeaderGenerator::from_value(Some(&header)).is_err());
+
+        let header: toml::Value = toml::from_str(r#"bindings = ["cpp"]"#).unwrap();
+        assert!(HeaderGenerator::from_value(Some(&header)).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const ENGLISH: &str = include_str!("../locales/en/cargo-c.ftl");
+
+/// The messages shown to the user, in the language selected by the locale
+///
+/// The catalogs use the `id = message` subset of the Fluent syntax, with
+/// `{ $name }` placeables. English is built in, the translations are looked
+/// up in `CARGO_C_LOCALE_DIR` or in `{prefix}/share/cargo-c/locales`.
+struct Catalog {
+    messages: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+static CATALOG: OnceLock<Catalog> = OnceLock::new();
+
+fn parse(src: &str) -> HashMap<String, String> {
+    src.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(id, message)| (id.trim().to_string(), message.trim().to_string()))
+        .collect()
+}
+
+/// The locales to try, from the most to the least specific, e.g. `pt_BR` and `pt`
+fn locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+
+    // e.g. pt_BR.UTF-8@euro
+    let locale = locale.split(['.', '@']).next().unwrap_or_default();
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+
+    let mut locales = vec![locale.to_string()];
+    if let Some((language, _)) = locale.split_once('_') {
+        locales.push(language.to_string());
+    }
+    locales
+}
+
+fn locale_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("CARGO_C_LOCALE_DIR") {
+        return Some(dir.into());
+    }
+
+    let exe = std::env::current_exe().ok()?;
+    Some(exe.parent()?.parent()?.join("share/cargo-c/locales"))
+}
+
+fn catalog() -> &'static Catalog {
+    CATALOG.get_or_init(|| {
+        let fallback = parse(ENGLISH);
+        let messages = locale_dir()
+            .and_then(|dir| {
+                locales().iter().find_map(|locale| {
+                    std::fs::read_to_string(dir.join(locale).join("cargo-c.ftl")).ok()
+                })
+            })
+            .map(|src| parse(&src))
+            .unwrap_or_default();
+
+        Catalog { messages, fallback }
+    })
+}
+
+fn format(message: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut out = message.to_string();
+    for (name, value) in args {
+        out = out.replace(&format!("{{ ${name} }}"), &value.to_string());
+    }
+    out
+}
+
+/// The translated message `id`, with its `{ $name }` placeables replaced by `args`
+pub fn tr(id: &str, args: &[(&str, &dyn Display)]) -> String {
+    let catalog = catalog();
+    let message = catalog
+        .messages
+        .get(id)
+        .or_else(|| catalog.fallback.get(id))
+        .map(String::as_str)
+        .unwrap_or(id);
+
+    format(message, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog() {
+        let messages = parse(concat!(
+            "# comment\n",
+            "\n",
+            "install-header = file di intestazione\n",
+            "vendored = { $package } include { $list }\n",
+        ));
+
+        assert_eq!(messages["install-header"], "file di intestazione");
+        assert_eq!(
+            format(
+                &messages["vendored"],
+                &[("package", &"foo"), ("list", &"openssl-src v1.0")]
+            ),
+            "foo include openssl-src v1.0"
+        );
+    }
+
+    #[test]
+    fn english() {
+        let english = parse(ENGLISH);
+
+        assert_eq!(english["install-header"], "header file");
+        assert_eq!(
+            format(&english["build-csources"], &[("name", &"foo")]),
+            "C sources for foo"
+        );
+    }
+
+    #[test]
+    fn ids() {
+        let english = parse(ENGLISH);
+        let src = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+
+        for entry in std::fs::read_dir(src).unwrap() {
+            let Ok(source) = std::fs::read_to_string(entry.unwrap().path()) else {
+                continue;
+            };
+            for (i, _) in source.match_indices("tr(\"") {
+                // e.g. not `push_str("`
+                if source[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    continue;
+                }
+                let id = source[i + 4..].split('"').next().unwrap();
+                assert!(english.contains_key(id), "`{id}` is not in the catalog");
+            }
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use std::fmt::Write as _;
+
+use cargo::core::Workspace;
+use cargo::util::interning::InternedString;
+use cargo_util::paths::{read, write};
+use clap::ArgMatches;
+
+use crate::i18n::tr;
+
+const RUST_ANALYZER_CONFIG: &str = "rust-analyzer.toml";
+
+/// Render a rust-analyzer configuration that enables the `capi` feature and the
+/// `cargo_c` cfg, so the C-API code is analyzed instead of being greyed out.
+fn rust_analyzer_config(packages: &[&str]) -> String {
+    let mut buf = String::with_capacity(256);
+
+    // writing to a String only fails on OOM, which we disregard
+    writeln!(buf, "# Generated by cargo-c, enables the C-API code paths").unwrap();
+    writeln!(buf, "[cargo]").unwrap();
+    let features = packages
+        .iter()
+        .map(|name| format!("\"{name}/capi\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(buf, "features = [{features}]").unwrap();
+    writeln!(buf, "cfgs = [\"debug_assertions\", \"miri\", \"cargo_c\"]").unwrap();
+
+    buf
+}
+
+pub fn ide_setup(ws: &Workspace, args: &ArgMatches) -> anyhow::Result<()> {
+    let capi_feature = InternedString::new("capi");
+    let packages: Vec<&str> = ws
+        .members()
+        .filter(|p| p.library().is_some() && p.summary().features().contains_key(&capi_feature))
+        .map(|p| p.name().as_str())
+        .collect();
+
+    if packages.is_empty() {
+        anyhow::bail!(tr("ide-no-capi", &[]));
+    }
+
+    let config = rust_analyzer_config(&packages);
+
+    if args.get_flag("stdout") {
+        print!("{config}");
+        return Ok(());
+    }
+
+    let path = ws.root().join(RUST_ANALYZER_CONFIG);
+    if path.exists() && !args.get_flag("force") {
+        if read(&path)? == config {
+            return Ok(());
+        }
+        anyhow::bail!(tr("ide-exists", &[("path", &path.display())]));
+    }
+
+    ws.gctx()
+        .shell()
+        .status("Writing", format!("{}", path.display()))?;
+
+    write(path, config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rust_analyzer_config;
+
+    #[test]
+    fn config() {
+        let expected = concat!(
+            "# Generated by cargo-c, enables the C-API code paths\n",
+            "[cargo]\n",
+            "features = [\"foo/capi\", \"bar/capi\"]\n",
+            "cfgs = [\"debug_assertions\", \"miri\", \"cargo_c\"]\n",
+        );
+
+        assert_eq!(rust_analyzer_config(&["foo", "bar"]), expected);
+    }
+}
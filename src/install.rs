@@ -2,11 +2,17 @@ use clap::ArgMatches;
 use std::path::{Component, Path, PathBuf};
 
 use cargo::core::Workspace;
+use cargo::util::command_prelude::ArgMatchesExt;
 use cargo_util::paths::{self, create_dir_all};
+use cargo_util::ProcessBuilder;
+
+use anyhow::Context as _;
 
 use crate::build::*;
 use crate::build_targets::BuildTargets;
-use crate::target::Target;
+use crate::i18n::tr;
+use crate::progress::{self, Phase};
+use crate::target::{LibdirPolicy, Target};
 
 pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(ws: &Workspace, from: P, to: Q) -> anyhow::Result<u64> {
     ws.gctx().shell().verbose(|shell| {
@@ -19,7 +25,283 @@ pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(ws: &Workspace, from: P, to: Q) -> a
     paths::copy(from, to)
 }
 
-fn append_to_destdir(destdir: Option<&Path>, path: &Path) -> PathBuf {
+/// The kind of an installed file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileCategory {
+    Library,
+    Header,
+    Pc,
+    Data,
+    DebugInfo,
+    ConfigScript,
+    Locale,
+    MsbuildProps,
+    SystemDeps,
+    Binary,
+    Man,
+    Doc,
+    Libexec,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct InstalledFile {
+    pub category: FileCategory,
+    /// The installed file, including the destdir
+    pub path: PathBuf,
+    /// The file it was copied from, or the target of the symbolic link
+    pub source: PathBuf,
+    pub symlink: bool,
+}
+
+/// The modes and the ownership of the installed files, as `install(1)` sets them
+#[derive(Debug, Clone, Default)]
+pub struct InstallPermissions {
+    /// The mode of the shared libraries
+    pub exec_mode: Option<u32>,
+    /// The mode of every other file
+    pub data_mode: Option<u32>,
+    pub owner: Option<String>,
+    pub group: Option<String>,
+}
+
+impl InstallPermissions {
+    pub fn from_args(args: &ArgMatches) -> Self {
+        InstallPermissions {
+            exec_mode: args.get_one::<u32>("mode-exec").copied(),
+            data_mode: args.get_one::<u32>("mode-data").copied(),
+            owner: args.get_one::<String>("owner").cloned(),
+            group: args.get_one::<String>("group").cloned(),
+        }
+    }
+
+    fn apply(&self, path: &Path, executable: bool) -> anyhow::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let mode = if executable {
+                self.exec_mode
+            } else {
+                self.data_mode
+            };
+            if let Some(mode) = mode {
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                    .with_context(|| tr("set-mode-failed", &[("path", &path.display())]))?;
+            }
+        }
+
+        let owner = match (&self.owner, &self.group) {
+            (Some(owner), Some(group)) => format!("{owner}:{group}"),
+            (Some(owner), None) => owner.clone(),
+            (None, Some(group)) => format!(":{group}"),
+            (None, None) => return Ok(()),
+        };
+
+        let mut chown = ProcessBuilder::new("chown");
+        chown.arg(&owner).arg(path);
+        chown.exec_with_output()?;
+
+        Ok(())
+    }
+}
+
+/// Every file written by `cinstall`, it can be stored as JSON for packaging tools
+#[derive(Debug, Default, serde::Serialize)]
+pub struct InstallManifest {
+    pub destdir: Option<PathBuf>,
+    pub files: Vec<InstalledFile>,
+    /// Only report what would be installed
+    #[serde(skip)]
+    pub dry_run: bool,
+    #[serde(skip)]
+    pub permissions: InstallPermissions,
+    /// The identity to sign the installed dylibs with
+    #[serde(skip)]
+    pub codesign: Option<String>,
+    #[serde(skip)]
+    pub debuginfo: DebugInfoPolicy,
+}
+
+impl InstallManifest {
+    fn copy(
+        &mut self,
+        ws: &Workspace,
+        category: FileCategory,
+        from: &Path,
+        to: PathBuf,
+    ) -> anyhow::Result<()> {
+        self.install_file(ws, category, from, to, false)
+    }
+
+    /// Copy the files of the directory `from`, e.g. a dSYM bundle
+    pub(crate) fn copy_dir(
+        &mut self,
+        ws: &Workspace,
+        category: FileCategory,
+        from: &Path,
+        to: PathBuf,
+    ) -> anyhow::Result<()> {
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            let to = to.join(entry.file_name());
+            if entry.file_type()?.is_dir() {
+                self.copy_dir(ws, category, &entry.path(), to)?;
+            } else {
+                self.copy(ws, category, &entry.path(), to)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Install the files of an install category, e.g. the man pages, in `dir`, `compress`
+    /// them with gzip
+    fn install_category(
+        &mut self,
+        ws: &Workspace,
+        category: FileCategory,
+        files: &[(PathBuf, PathBuf)],
+        dir: &Path,
+        compress: bool,
+    ) -> anyhow::Result<()> {
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let message = match category {
+            FileCategory::Binary => "install-bin",
+            FileCategory::Libexec => "install-libexec",
+            FileCategory::Man => "install-man",
+            FileCategory::Doc => "install-doc",
+            FileCategory::Locale => "install-locale",
+            _ => "install-data",
+        };
+        progress::status(ws, Phase::Install, "Installing", tr(message, &[]))?;
+
+        for (from, to) in files {
+            let to = dir.join(to);
+            if compress {
+                let mut to = to.into_os_string();
+                to.push(".gz");
+                self.gzip(ws, category, from, to.into())?;
+            } else {
+                let executable = matches!(category, FileCategory::Binary | FileCategory::Libexec);
+                self.install_file(ws, category, from, to, executable)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copy a shared library, it gets the executable mode
+    fn copy_executable(&mut self, ws: &Workspace, from: &Path, to: PathBuf) -> anyhow::Result<()> {
+        self.install_file(ws, FileCategory::Library, from, to, true)
+    }
+
+    fn install_file(
+        &mut self,
+        ws: &Workspace,
+        category: FileCategory,
+        from: &Path,
+        to: PathBuf,
+        executable: bool,
+    ) -> anyhow::Result<()> {
+        if self.dry_run {
+            ws.gctx().shell().status(
+                "Would copy",
+                format!("{} to {}", from.display(), to.display()),
+            )?;
+        } else {
+            create_dir_all(to.parent().unwrap())?;
+            copy(ws, from, &to)?;
+            self.permissions.apply(&to, executable)?;
+        }
+        self.files.push(InstalledFile {
+            category,
+            path: to,
+            source: from.to_path_buf(),
+            symlink: false,
+        });
+        Ok(())
+    }
+
+    /// Install `from` compressed with gzip
+    fn gzip(
+        &mut self,
+        ws: &Workspace,
+        category: FileCategory,
+        from: &Path,
+        to: PathBuf,
+    ) -> anyhow::Result<()> {
+        if self.dry_run {
+            ws.gctx().shell().status(
+                "Would compress",
+                format!("{} to {}", from.display(), to.display()),
+            )?;
+        } else {
+            use std::io::Write;
+
+            create_dir_all(to.parent().unwrap())?;
+            let content = std::fs::read(from)
+                .with_context(|| tr("read-failed", &[("path", &from.display())]))?;
+            // The mtime in the header is left to 0, the output is reproducible
+            let file = cargo_util::paths::create(&to)?;
+            let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::best());
+            encoder.write_all(&content)?;
+            encoder.finish()?;
+            self.permissions.apply(&to, false)?;
+        }
+        self.files.push(InstalledFile {
+            category,
+            path: to,
+            source: from.to_path_buf(),
+            symlink: false,
+        });
+        Ok(())
+    }
+
+    fn symlink(
+        &mut self,
+        ws: &Workspace,
+        category: FileCategory,
+        target: &str,
+        link: PathBuf,
+    ) -> anyhow::Result<()> {
+        if self.dry_run {
+            ws.gctx()
+                .shell()
+                .status("Would link", format!("{} to {}", link.display(), target))?;
+        } else {
+            let mut ln_sf = std::process::Command::new("ln");
+            ln_sf.arg("-sf");
+            ln_sf.arg(target).arg(&link);
+            let _ = ln_sf.status().unwrap();
+        }
+        self.files.push(InstalledFile {
+            category,
+            path: link,
+            source: PathBuf::from(target),
+            symlink: true,
+        });
+        Ok(())
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        paths::write(path, serde_json::to_string_pretty(self)? + "\n")
+    }
+}
+
+/// Where gdb looks up the debug info of the library installed with `installed`, its path
+/// under the debug-file-directory `debugdir`
+fn separate_debug_path(debugdir: &Path, installed: &Path) -> PathBuf {
+    let relative: PathBuf = installed
+        .components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect();
+    debugdir.join(relative)
+}
+
+pub(crate) fn append_to_destdir(destdir: Option<&Path>, path: &Path) -> PathBuf {
     if let Some(destdir) = destdir {
         let mut joined = destdir.to_path_buf();
         for component in path.components() {
@@ -38,6 +320,17 @@ fn append_to_destdir(destdir: Option<&Path>, path: &Path) -> PathBuf {
 mod tests {
     use std::path::{Path, PathBuf};
 
+    #[test]
+    fn separate_debug_path() {
+        assert_eq!(
+            super::separate_debug_path(
+                Path::new("/usr/lib/debug"),
+                Path::new("/opt/foo/lib/libfoo.so.debug")
+            ),
+            PathBuf::from("/usr/lib/debug/opt/foo/lib/libfoo.so.debug")
+        );
+    }
+
     #[test]
     fn append_to_destdir() {
         assert_eq!(
@@ -82,6 +375,7 @@ mod tests {
     }
 }
 
+#[derive(Clone, Copy)]
 pub(crate) enum LibType {
     So,
     Dylib,
@@ -90,7 +384,10 @@ pub(crate) enum LibType {
 
 impl LibType {
     pub(crate) fn from_build_targets(build_targets: &BuildTargets) -> Self {
-        let target = &build_targets.target;
+        Self::from_target(&build_targets.target)
+    }
+
+    pub(crate) fn from_target(target: &Target) -> Self {
         let os = &target.os;
         let env = &target.env;
 
@@ -106,16 +403,16 @@ impl LibType {
             | ("emscripten", _)
             | ("hurd", _) => LibType::So,
             ("macos", _) | ("ios", _) | ("tvos", _) | ("visionos", _) => LibType::Dylib,
-            ("windows", _) => LibType::Windows,
+            ("windows", _) | ("cygwin", _) => LibType::Windows,
             _ => unimplemented!("The target {}-{} is not supported yet", os, env),
         }
     }
 }
 
 pub(crate) struct UnixLibNames {
-    canonical: String,
-    with_main_ver: String,
-    with_full_ver: String,
+    pub(crate) canonical: String,
+    pub(crate) with_main_ver: String,
+    pub(crate) with_full_ver: String,
 }
 
 impl UnixLibNames {
@@ -157,148 +454,418 @@ impl UnixLibNames {
         }
     }
 
-    fn links(&self, install_path_lib: &Path) {
+    fn links(
+        &self,
+        ws: &Workspace,
+        manifest: &mut InstallManifest,
+        install_path_lib: &Path,
+    ) -> anyhow::Result<()> {
         if self.with_main_ver != self.with_full_ver {
-            let mut ln_sf = std::process::Command::new("ln");
-            ln_sf.arg("-sf");
-            ln_sf
-                .arg(&self.with_full_ver)
-                .arg(install_path_lib.join(&self.with_main_ver));
-            let _ = ln_sf.status().unwrap();
+            manifest.symlink(
+                ws,
+                FileCategory::Library,
+                &self.with_full_ver,
+                install_path_lib.join(&self.with_main_ver),
+            )?;
         }
 
-        let mut ln_sf = std::process::Command::new("ln");
-        ln_sf.arg("-sf");
-        ln_sf
-            .arg(&self.with_full_ver)
-            .arg(install_path_lib.join(&self.canonical));
-        let _ = ln_sf.status().unwrap();
+        manifest.symlink(
+            ws,
+            FileCategory::Library,
+            &self.with_full_ver,
+            install_path_lib.join(&self.canonical),
+        )
     }
 
     pub(crate) fn install(
         &self,
         ws: &Workspace,
+        manifest: &mut InstallManifest,
         capi_config: &CApiConfig,
         shared_lib: &Path,
         install_path_lib: &Path,
-    ) -> anyhow::Result<()> {
+    ) -> anyhow::Result<PathBuf> {
         if capi_config.library.versioning {
-            copy(ws, shared_lib, install_path_lib.join(&self.with_full_ver))?;
-            self.links(install_path_lib);
+            let to = install_path_lib.join(&self.with_full_ver);
+            manifest.copy_executable(ws, shared_lib, to.clone())?;
+            self.links(ws, manifest, install_path_lib)?;
+            Ok(to)
         } else {
-            copy(ws, shared_lib, install_path_lib.join(&self.canonical))?;
+            let to = install_path_lib.join(&self.canonical);
+            manifest.copy_executable(ws, shared_lib, to.clone())?;
+            Ok(to)
         }
-        Ok(())
     }
 }
 
-pub fn cinstall(ws: &Workspace, packages: &[CPackage]) -> anyhow::Result<()> {
+pub fn cinstall(ws: &Workspace, packages: &[CPackage], args: &ArgMatches) -> anyhow::Result<()> {
+    let mut manifest = InstallManifest {
+        // Nothing is built with --pretend-target, only the layout is computed
+        dry_run: args.get_flag("dry-run") || args.contains_id("pretend_target"),
+        permissions: InstallPermissions::from_args(args),
+        codesign: args.get_one::<String>("codesign").cloned(),
+        debuginfo: DebugInfoPolicy::from_args(args),
+        ..Default::default()
+    };
+
+    install_packages(ws, packages, &mut manifest, None)?;
+
+    if args.flag("user") {
+        user_install_hint(ws, packages)?;
+    }
+
+    if let Some(path) = args.get_one::<PathBuf>("install-manifest") {
+        progress::status(
+            ws,
+            Phase::Install,
+            "Writing",
+            tr("install-manifest", &[("path", &path.display())]),
+        )?;
+        manifest.write(path)?;
+    }
+
+    Ok(())
+}
+
+/// Point to the search paths missing the pkg-config files and the libraries installed
+/// for the current user
+fn user_install_hint(ws: &Workspace, packages: &[CPackage]) -> anyhow::Result<()> {
+    let search_path = |var: &str| -> Vec<PathBuf> {
+        std::env::var_os(var).map_or_else(Vec::new, |p| std::env::split_paths(&p).collect())
+    };
+    let pc_path = search_path("PKG_CONFIG_PATH");
+
+    let mut shell = ws.gctx().shell();
+    let mut hinted = Vec::new();
+    for pkg in packages {
+        let dir = &pkg.install_paths.pkgconfigdir;
+        if !pc_path.contains(dir) && !hinted.contains(&dir) {
+            shell.note(tr("pkg-config-path-hint", &[("path", &dir.display())]))?;
+            hinted.push(dir);
+        }
+
+        let target = &pkg.build_targets.target;
+        let var = if target.has_dlls() {
+            "PATH"
+        } else if target.is_apple() {
+            "DYLD_LIBRARY_PATH"
+        } else {
+            "LD_LIBRARY_PATH"
+        };
+        let dir = if target.has_dlls() {
+            &pkg.install_paths.bindir
+        } else {
+            &pkg.install_paths.libdir
+        };
+        if pkg.build_targets.shared_lib.is_some()
+            && !search_path(var).contains(dir)
+            && !hinted.contains(&dir)
+        {
+            shell.note(tr(
+                "library-path-hint",
+                &[("path", &dir.display()), ("var", &var)],
+            ))?;
+            hinted.push(dir);
+        }
+    }
+
+    Ok(())
+}
+
+/// Copy the libraries and the pkg-config files to `dir` with their install names, without
+/// the install layout
+///
+/// The headers keep their path relative to the includedir, in `{dir}/include`.
+pub fn copy_artifacts(ws: &Workspace, packages: &[CPackage], dir: &Path) -> anyhow::Result<()> {
+    let mut manifest = InstallManifest::default();
+    let include = dir.join("include");
+
+    for pkg in packages {
+        let capi_config = &pkg.capi_config;
+        let build_targets = &pkg.build_targets;
+
+        let pc = &build_targets.pc;
+        manifest.copy(ws, FileCategory::Pc, pc, dir.join(pc.file_name().unwrap()))?;
+
+        if capi_config.header.enabled {
+            for (from, to) in build_targets.extra.include.iter() {
+                manifest.copy(ws, FileCategory::Header, from, include.join(to))?;
+            }
+        }
+
+        if let Some(static_lib) = &build_targets.static_lib {
+            let to = dir.join(build_targets.static_output_file_name().unwrap());
+            manifest.copy(ws, FileCategory::Library, static_lib, to)?;
+        }
+
+        if let Some(shared_lib) = &build_targets.shared_lib {
+            let lib_type = LibType::from_build_targets(build_targets);
+            if let Some(lib) = UnixLibNames::new(lib_type, &capi_config.library) {
+                lib.install(ws, &mut manifest, capi_config, shared_lib, dir)?;
+            } else {
+                let to = dir.join(build_targets.shared_output_file_name().unwrap());
+                manifest.copy_executable(ws, shared_lib, to)?;
+                if capi_config.library.import_library {
+                    let impl_lib = build_targets.impl_lib.as_ref().unwrap();
+                    let to = dir.join(build_targets.impl_lib_output_file_name().unwrap());
+                    manifest.copy(ws, FileCategory::Library, impl_lib, to)?;
+                    let def = build_targets.def.as_ref().unwrap();
+                    let to = dir.join(def.file_name().unwrap());
+                    manifest.copy(ws, FileCategory::Library, def, to)?;
+                }
+            }
+        }
+    }
+
+    ws.gctx().shell().status(
+        "Copied",
+        format!("{} artifacts to {}", manifest.files.len(), dir.display()),
+    )?;
+
+    Ok(())
+}
+
+/// Install the packages in `destdir`, e.g. to test the installed files, instead of the one
+/// of the install paths
+pub(crate) fn install_packages(
+    ws: &Workspace,
+    packages: &[CPackage],
+    manifest: &mut InstallManifest,
+    destdir: Option<&Path>,
+) -> anyhow::Result<()> {
     for pkg in packages {
         let paths = &pkg.install_paths;
         let capi_config = &pkg.capi_config;
         let build_targets = &pkg.build_targets;
 
-        let destdir = &paths.destdir;
+        let destdir = &destdir
+            .map(Path::to_path_buf)
+            .or_else(|| paths.destdir.clone());
+        manifest.destdir.clone_from(destdir);
 
-        let mut install_path_lib = paths.libdir.clone();
+        let mut libdir = paths.libdir.clone();
         if let Some(subdir) = &capi_config.library.install_subdir {
-            install_path_lib.push(subdir);
+            libdir.push(subdir);
         }
 
         let install_path_bin = append_to_destdir(destdir.as_deref(), &paths.bindir);
-        let install_path_lib = append_to_destdir(destdir.as_deref(), &install_path_lib);
+        let install_path_libexec = append_to_destdir(destdir.as_deref(), &paths.libexecdir);
+        let install_path_lib = append_to_destdir(destdir.as_deref(), &libdir);
         let install_path_pc = append_to_destdir(destdir.as_deref(), &paths.pkgconfigdir);
         let install_path_include = append_to_destdir(destdir.as_deref(), &paths.includedir);
         let install_path_data = append_to_destdir(destdir.as_deref(), &paths.datadir);
+        let install_path_locale = append_to_destdir(destdir.as_deref(), &paths.localedir);
+        let install_path_man = append_to_destdir(destdir.as_deref(), &paths.mandir);
+        let install_path_doc = append_to_destdir(destdir.as_deref(), &paths.docdir);
 
-        create_dir_all(&install_path_lib)?;
-        create_dir_all(&install_path_pc)?;
-
-        ws.gctx().shell().status("Installing", "pkg-config file")?;
+        progress::status(
+            ws,
+            Phase::Install,
+            "Installing",
+            tr("install-pkg-config", &[]),
+        )?;
 
-        copy(
+        manifest.copy(
             ws,
+            FileCategory::Pc,
             &build_targets.pc,
             install_path_pc.join(build_targets.pc.file_name().unwrap()),
         )?;
 
+        for bin in &build_targets.bins {
+            let to = install_path_bin.join(bin.file_name().unwrap());
+            manifest.install_file(ws, FileCategory::Binary, bin, to, true)?;
+        }
+
+        manifest.install_category(
+            ws,
+            FileCategory::Binary,
+            &build_targets.extra.bin,
+            &install_path_bin,
+            false,
+        )?;
+        manifest.install_category(
+            ws,
+            FileCategory::Libexec,
+            &build_targets.extra.libexec,
+            &install_path_libexec,
+            false,
+        )?;
+
+        if let Some(script) = &build_targets.config_script {
+            let to = install_path_bin.join(script.file_name().unwrap());
+            manifest.install_file(ws, FileCategory::ConfigScript, script, to, true)?;
+        }
+
         if capi_config.header.enabled {
-            ws.gctx().shell().status("Installing", "header file")?;
+            progress::status(ws, Phase::Install, "Installing", tr("install-header", &[]))?;
             for (from, to) in build_targets.extra.include.iter() {
                 let to = install_path_include.join(to);
-                create_dir_all(to.parent().unwrap())?;
-                copy(ws, from, to)?;
+                manifest.copy(ws, FileCategory::Header, from, to)?;
             }
         }
 
         if !build_targets.extra.data.is_empty() {
-            ws.gctx().shell().status("Installing", "data file")?;
+            progress::status(ws, Phase::Install, "Installing", tr("install-data", &[]))?;
             for (from, to) in build_targets.extra.data.iter() {
                 let to = install_path_data.join(to);
-                create_dir_all(to.parent().unwrap())?;
-                copy(ws, from, to)?;
+                manifest.copy(ws, FileCategory::Data, from, to)?;
+            }
+        }
+
+        if let Some(c_array) = &build_targets.c_array {
+            let to = install_path_data.join(&capi_config.install.data_subdirectory);
+            for from in [c_array.clone(), c_array.with_extension("h")] {
+                let to = to.join(from.file_name().unwrap());
+                manifest.copy(ws, FileCategory::Data, &from, to)?;
             }
         }
 
+        if let Some(props) = &build_targets.msbuild_props {
+            let to = install_path_data
+                .join(&capi_config.install.data_subdirectory)
+                .join(props.file_name().unwrap());
+            manifest.copy(ws, FileCategory::MsbuildProps, props, to)?;
+        }
+
+        if let Some(system_deps) = &build_targets.system_deps {
+            let to = install_path_data
+                .join(&capi_config.install.data_subdirectory)
+                .join(system_deps.file_name().unwrap());
+            manifest.copy(ws, FileCategory::SystemDeps, system_deps, to)?;
+        }
+
+        manifest.install_category(
+            ws,
+            FileCategory::Man,
+            &build_targets.extra.man,
+            &install_path_man,
+            capi_config.install.man_gzip,
+        )?;
+
+        manifest.install_category(
+            ws,
+            FileCategory::Doc,
+            &build_targets.extra.doc,
+            &install_path_doc,
+            false,
+        )?;
+
+        manifest.install_category(
+            ws,
+            FileCategory::Locale,
+            &build_targets.extra.locale,
+            &install_path_locale,
+            false,
+        )?;
+
         if let Some(ref static_lib) = build_targets.static_lib {
-            ws.gctx().shell().status("Installing", "static library")?;
+            progress::status(
+                ws,
+                Phase::Install,
+                "Installing",
+                tr("install-static-lib", &[]),
+            )?;
             let file_name = build_targets.static_output_file_name().unwrap();
 
-            copy(ws, static_lib, install_path_lib.join(file_name))?;
+            let to = install_path_lib.join(file_name);
+            manifest.copy(ws, FileCategory::Library, static_lib, to)?;
         }
 
         if let Some(ref shared_lib) = build_targets.shared_lib {
-            ws.gctx().shell().status("Installing", "shared library")?;
+            progress::status(
+                ws,
+                Phase::Install,
+                "Installing",
+                tr("install-shared-lib", &[]),
+            )?;
 
             let lib_type = LibType::from_build_targets(build_targets);
             match lib_type {
                 LibType::So | LibType::Dylib => {
+                    let stripped = build_targets.stripped_lib();
+                    let shared_lib = stripped.as_ref().unwrap_or(shared_lib);
                     let lib = UnixLibNames::new(lib_type, &capi_config.library).unwrap();
-                    lib.install(ws, capi_config, shared_lib, &install_path_lib)?;
+                    let to =
+                        lib.install(ws, manifest, capi_config, shared_lib, &install_path_lib)?;
+                    if let (LibType::Dylib, Some(identity), false) =
+                        (lib_type, &manifest.codesign, manifest.dry_run)
+                    {
+                        codesign(ws, identity, &to)?;
+                    }
                 }
                 LibType::Windows => {
                     let lib_name = build_targets.shared_output_file_name().unwrap();
 
                     if capi_config.library.install_subdir.is_none() {
                         let install_path_bin = append_to_destdir(destdir.as_deref(), &paths.bindir);
-                        create_dir_all(&install_path_bin)?;
 
-                        copy(ws, shared_lib, install_path_bin.join(lib_name))?;
+                        let to = install_path_bin.join(lib_name);
+                        manifest.copy_executable(ws, shared_lib, to)?;
                     } else {
                         // We assume they are plugins, install them in the custom libdir path
-                        copy(ws, shared_lib, install_path_lib.join(lib_name))?;
+                        let to = install_path_lib.join(lib_name);
+                        manifest.copy_executable(ws, shared_lib, to)?;
                     }
                     if capi_config.library.import_library {
                         let impl_lib = build_targets.impl_lib.as_ref().unwrap();
-                        let impl_lib_name = if build_targets.use_meson_naming_convention {
-                            format!("{}.lib", build_targets.name).into()
-                        } else {
-                            impl_lib.file_name().unwrap().to_owned()
-                        };
-                        copy(ws, impl_lib, install_path_lib.join(impl_lib_name))?;
+                        let impl_lib_name = build_targets.impl_lib_output_file_name().unwrap();
+                        let to = install_path_lib.join(impl_lib_name);
+                        manifest.copy(ws, FileCategory::Library, impl_lib, to)?;
+                        if let Some(delay_impl_lib) = &build_targets.delay_impl_lib {
+                            let name = build_targets.delay_impl_lib_output_file_name().unwrap();
+                            let to = install_path_lib.join(name);
+                            manifest.copy(ws, FileCategory::Library, delay_impl_lib, to)?;
+                        }
+                        if let Some(msvc_impl_lib) = &build_targets.msvc_impl_lib {
+                            let name = build_targets.msvc_impl_lib_output_file_name().unwrap();
+                            let to = install_path_lib.join(name);
+                            manifest.copy(ws, FileCategory::Library, msvc_impl_lib, to)?;
+                        }
                         let def = build_targets.def.as_ref().unwrap();
                         let def_name = def.file_name().unwrap();
-                        copy(ws, def, install_path_lib.join(def_name))?;
+                        let to = install_path_lib.join(def_name);
+                        manifest.copy(ws, FileCategory::Library, def, to)?;
                     }
                 }
             }
         }
 
-        if let Some(ref debug_info) = build_targets.debug_info {
+        let debug_info = build_targets
+            .debug_info
+            .as_ref()
+            .filter(|_| manifest.debuginfo != DebugInfoPolicy::None);
+        if let Some(debug_info) = debug_info {
             if debug_info.exists() {
-                ws.gctx()
-                    .shell()
-                    .status("Installing", "debugging information")?;
-                let destination_path = build_targets
-                    .debug_info_file_name(&install_path_bin, &install_path_lib)
-                    .unwrap();
+                progress::status(
+                    ws,
+                    Phase::Install,
+                    "Installing",
+                    tr("install-debug-info", &[]),
+                )?;
+                let destination_path = if manifest.debuginfo == DebugInfoPolicy::Separate {
+                    let installed = build_targets
+                        .debug_info_file_name(&paths.bindir, &libdir)
+                        .unwrap();
+                    append_to_destdir(
+                        destdir.as_deref(),
+                        &separate_debug_path(&paths.debugdir, &installed),
+                    )
+                } else {
+                    build_targets
+                        .debug_info_file_name(&install_path_bin, &install_path_lib)
+                        .unwrap()
+                };
 
-                create_dir_all(destination_path.parent().unwrap())?;
-                copy(ws, debug_info, destination_path)?;
+                if debug_info.is_dir() {
+                    manifest.copy_dir(ws, FileCategory::DebugInfo, debug_info, destination_path)?;
+                } else {
+                    manifest.copy(ws, FileCategory::DebugInfo, debug_info, destination_path)?;
+                }
             } else {
                 ws.gctx()
                     .shell()
-                    .verbose(|shell| shell.status("Absent", "debugging information"))?;
+                    .verbose(|shell| shell.status("Absent", tr("install-debug-info", &[])))?;
             }
         }
     }
@@ -314,8 +881,51 @@ pub struct InstallPaths {
     pub libdir: PathBuf,
     pub includedir: PathBuf,
     pub datadir: PathBuf,
+    pub docdir: PathBuf,
+    pub localedir: PathBuf,
+    pub mandir: PathBuf,
     pub bindir: PathBuf,
+    pub libexecdir: PathBuf,
     pub pkgconfigdir: PathBuf,
+    /// The gdb debug-file-directory, see `DebugInfoPolicy::Separate`
+    pub debugdir: PathBuf,
+}
+
+/// Print the install directories, the defaults of the target unless set, for the packaging
+/// scripts
+pub fn print_dirs(args: &ArgMatches) -> anyhow::Result<()> {
+    let target = match args.targets()?.as_slice() {
+        [] => Target::new::<&str>(None, false)?,
+        [target] => Target::new(Some(target), true)?,
+        [..] => anyhow::bail!(tr("multiple-targets", &[])),
+    };
+    let paths = InstallPaths::from_args("", &target, args, false)?;
+    let dirs = [
+        ("prefix", &paths.prefix),
+        ("libdir", &paths.libdir),
+        ("includedir", &paths.includedir),
+        ("bindir", &paths.bindir),
+        ("pkgconfigdir", &paths.pkgconfigdir),
+        ("datadir", &paths.datadir),
+        ("libexecdir", &paths.libexecdir),
+        ("debugdir", &paths.debugdir),
+        ("localedir", &paths.localedir),
+        ("mandir", &paths.mandir),
+    ];
+
+    if args.flag("json") {
+        let dirs: serde_json::Map<_, _> = dirs
+            .iter()
+            .map(|(name, dir)| (name.to_string(), dir.display().to_string().into()))
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&dirs)?);
+    } else {
+        for (name, dir) in dirs {
+            println!("{name}={}", dir.display());
+        }
+    }
+
+    Ok(())
 }
 
 fn get_path_or(args: &ArgMatches, id: &str, f: impl FnOnce() -> PathBuf) -> PathBuf {
@@ -331,15 +941,46 @@ fn get_path_or(args: &ArgMatches, id: &str, f: impl FnOnce() -> PathBuf) -> Path
 
 impl InstallPaths {
     pub fn new(
-        _name: &str,
+        name: &str,
         rustc_target: &Target,
         args: &ArgMatches,
         capi_config: &CApiConfig,
-    ) -> Self {
+        library_types: LibraryTypes,
+    ) -> anyhow::Result<Self> {
+        // The packages without libraries have an architecture independent .pc
+        let noarch = capi_config.pkg_config.noarch || library_types.none();
+        let mut paths = Self::from_args(name, rustc_target, args, noarch)?;
+        paths.subdir_name = PathBuf::from(&capi_config.header.subdirectory);
+        Ok(paths)
+    }
+
+    /// The directories set on the command line, or the defaults of the target
+    pub fn from_args(
+        name: &str,
+        rustc_target: &Target,
+        args: &ArgMatches,
+        noarch: bool,
+    ) -> anyhow::Result<Self> {
         let destdir = args.get_one::<PathBuf>("destdir").map(PathBuf::from);
-        let prefix = get_path_or(args, "prefix", || rustc_target.default_prefix());
+        let prefix = if args.flag("user") {
+            rustc_target
+                .user_prefix()
+                .with_context(|| tr("user-prefix", &[]))?
+        } else {
+            get_path_or(args, "prefix", || {
+                // Only on request, the environment root is managed by pacman
+                let msys2 = args.get_one::<String>("msys2").unwrap() == "always"
+                    && rustc_target.is_msys2("always");
+                msys2
+                    .then(|| rustc_target.msys2_prefix())
+                    .flatten()
+                    .unwrap_or_else(|| rustc_target.default_prefix())
+            })
+        };
+        let libdir_policy =
+            LibdirPolicy::from_name(args.get_one::<String>("libdir_policy").unwrap());
         let libdir = prefix.join(get_path_or(args, "libdir", || {
-            rustc_target.default_libdir()
+            rustc_target.default_libdir(libdir_policy)
         }));
         let includedir = prefix.join(get_path_or(args, "includedir", || {
             rustc_target.default_includedir()
@@ -351,24 +992,51 @@ impl InstallPaths {
             .get_one::<PathBuf>("datadir")
             .map(|d| prefix.join(d))
             .unwrap_or_else(|| datarootdir.clone());
-
-        let subdir_name = PathBuf::from(&capi_config.header.subdirectory);
+        let docdir = args
+            .get_one::<PathBuf>("docdir")
+            .map(|d| prefix.join(d))
+            .unwrap_or_else(|| datarootdir.join("doc").join(name));
+        let mandir = args
+            .get_one::<PathBuf>("mandir")
+            .map(|d| prefix.join(d))
+            .unwrap_or_else(|| datarootdir.join("man"));
+        let localedir = args
+            .get_one::<PathBuf>("localedir")
+            .map(|d| prefix.join(d))
+            .unwrap_or_else(|| datarootdir.join("locale"));
 
         let bindir = prefix.join(args.get_one::<PathBuf>("bindir").unwrap());
+        let libexecdir = prefix.join(
+            args.get_one::<PathBuf>("libexecdir")
+                .map_or(Path::new("libexec"), |d| d.as_path()),
+        );
+        let debugdir = prefix.join(args.get_one::<PathBuf>("debugdir").unwrap());
         let pkgconfigdir = args
             .get_one::<PathBuf>("pkgconfigdir")
             .map(|d| prefix.join(d))
-            .unwrap_or_else(|| libdir.join("pkgconfig"));
+            .unwrap_or_else(|| {
+                let policy = args.get_one::<String>("pkgconfigdir_policy").unwrap();
+                if policy == "datadir" || (policy == "auto" && noarch) {
+                    datarootdir.join("pkgconfig")
+                } else {
+                    libdir.join("pkgconfig")
+                }
+            });
 
-        InstallPaths {
-            subdir_name,
+        Ok(InstallPaths {
+            subdir_name: PathBuf::new(),
             destdir,
             prefix,
             libdir,
             includedir,
             datadir,
+            docdir,
+            localedir,
+            mandir,
             bindir,
+            libexecdir,
             pkgconfigdir,
-        }
+            debugdir,
+        })
     }
 }
@@ -0,0 +1,228 @@
+//! The WiX fragment or the NSIS script installing the C-API, for the Windows SDK distributions
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use cargo::core::Workspace;
+use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt};
+use cargo_util::paths::{remove_dir_all, write};
+use cargo_util::Sha256;
+
+use crate::build::CPackage;
+use crate::install::{append_to_destdir, install_packages, InstallManifest};
+
+/// A file of the staged install
+struct InstallerFile {
+    /// The staged file
+    source: PathBuf,
+    /// The directory relative to the install folder, with `\` as separator
+    dir: String,
+    /// The path relative to the install folder
+    path: String,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn windows_path(path: &Path) -> String {
+    path.display().to_string().replace('/', "\\")
+}
+
+/// A GUID derived from `name`, stable across the builds as the MSI component rules require
+fn guid(name: &str) -> String {
+    let mut hash = Sha256::new().update(name.as_bytes()).finish();
+    // RFC 9562 version 8, custom
+    hash[6] = (hash[6] & 0x0f) | 0x80;
+    hash[8] = (hash[8] & 0x3f) | 0x80;
+    let hex: String = hash[..16].iter().map(|b| format!("{b:02X}")).collect();
+    format!(
+        "{{{}-{}-{}-{}-{}}}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// The WiX v4 fragment, a `ComponentGroup` named after the library to reference from
+/// the `Feature` installing it in `INSTALLFOLDER`
+fn render_wix(group: &str, files: &[InstallerFile]) -> String {
+    let mut wxs = String::new();
+    let _ = writeln!(wxs, r#"<?xml version="1.0" encoding="utf-8"?>"#);
+    let _ = writeln!(wxs, r#"<Wix xmlns="http://wixtoolset.org/schemas/v4/wxs">"#);
+    let _ = writeln!(wxs, "  <Fragment>");
+    let _ = writeln!(
+        wxs,
+        r#"    <ComponentGroup Id="{group}" Directory="INSTALLFOLDER">"#
+    );
+    for file in files {
+        let guid = guid(&format!("{group}/{}", file.path));
+        // The ids are limited to 72 characters
+        let id = guid[1..9].to_string() + &guid[10..14];
+        let _ = writeln!(
+            wxs,
+            r#"      <Component Id="c{id}" Subdirectory="{}" Guid="{guid}">"#,
+            escape(&file.dir)
+        );
+        let _ = writeln!(
+            wxs,
+            r#"        <File Id="f{id}" Source="{}" KeyPath="yes" />"#,
+            escape(&windows_path(&file.source))
+        );
+        let _ = writeln!(wxs, "      </Component>");
+    }
+    let _ = writeln!(wxs, "    </ComponentGroup>");
+    let _ = writeln!(wxs, "  </Fragment>");
+    let _ = writeln!(wxs, "</Wix>");
+    wxs
+}
+
+fn nsis_escape(s: &str) -> String {
+    s.replace('$', "$$").replace('"', "$\\\"")
+}
+
+/// The NSIS `{group}_Install` and `{group}_Uninstall` macros, installing in `$INSTDIR`
+fn render_nsis(group: &str, files: &[InstallerFile]) -> String {
+    let mut nsi = String::new();
+
+    let _ = writeln!(nsi, "!macro {group}_Install");
+    let mut current = None;
+    for file in files {
+        if current != Some(&file.dir) {
+            let _ = writeln!(nsi, "  SetOutPath \"$INSTDIR\\{}\"", nsis_escape(&file.dir));
+            current = Some(&file.dir);
+        }
+        let _ = writeln!(
+            nsi,
+            "  File \"{}\"",
+            nsis_escape(&windows_path(&file.source))
+        );
+    }
+    let _ = writeln!(nsi, "!macroend\n");
+
+    let _ = writeln!(nsi, "!macro {group}_Uninstall");
+    for file in files {
+        let _ = writeln!(nsi, "  Delete \"$INSTDIR\\{}\"", nsis_escape(&file.path));
+    }
+    // The deepest directories first, they are removed only if empty
+    let mut dirs: Vec<&str> = Vec::new();
+    for file in files {
+        let mut dir = file.dir.as_str();
+        while !dir.is_empty() && !dirs.contains(&dir) {
+            dirs.push(dir);
+            dir = dir.rsplit_once('\\').map_or("", |(parent, _)| parent);
+        }
+    }
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.matches('\\').count()));
+    for dir in dirs {
+        let _ = writeln!(nsi, "  RMDir \"$INSTDIR\\{}\"", nsis_escape(dir));
+    }
+    let _ = writeln!(nsi, "!macroend");
+    nsi
+}
+
+/// Stage the install of each package and write `{name}.wxs`, or `{name}.nsh` with `--nsis`,
+/// next to its pkg-config file
+///
+/// The paths are relative to the prefix, the symbolic links are not installed.
+pub fn write_installers(
+    ws: &Workspace,
+    packages: &[CPackage],
+    args: &ArgMatches,
+) -> anyhow::Result<()> {
+    let nsis = args.flag("nsis");
+
+    for pkg in packages {
+        let root_output = pkg.build_targets.pc.parent().unwrap();
+        let name = &pkg.capi_config.library.name;
+        // The WiX ids and the NSIS macro names are identifiers
+        let group = name.replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        let stage = root_output.join("capi-installer").join(name);
+        if stage.exists() {
+            remove_dir_all(&stage)?;
+        }
+
+        let mut manifest = InstallManifest::default();
+        install_packages(ws, std::slice::from_ref(pkg), &mut manifest, Some(&stage))?;
+
+        let prefix = append_to_destdir(Some(&stage), &pkg.install_paths.prefix);
+        let files: Vec<_> = manifest
+            .files
+            .into_iter()
+            .filter(|file| !file.symlink)
+            .map(|file| {
+                let relative = file
+                    .path
+                    .strip_prefix(&prefix)
+                    .or_else(|_| file.path.strip_prefix(&stage))
+                    .unwrap()
+                    .to_path_buf();
+                InstallerFile {
+                    dir: relative.parent().map(windows_path).unwrap_or_default(),
+                    path: windows_path(&relative),
+                    source: file.path,
+                }
+            })
+            .collect();
+
+        let (path, content) = if nsis {
+            (
+                root_output.join(format!("{name}.nsh")),
+                render_nsis(&group, &files),
+            )
+        } else {
+            (
+                root_output.join(format!("{name}.wxs")),
+                render_wix(&group, &files),
+            )
+        };
+        ws.gctx()
+            .shell()
+            .status("Writing", format!("{}", path.display()))?;
+        write(&path, content)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files() -> Vec<InstallerFile> {
+        ["bin\\foo.dll", "lib\\foo.dll.a", "include\\foo\\foo.h"]
+            .iter()
+            .map(|path| InstallerFile {
+                source: Path::new("/stage").join(path.replace('\\', "/")),
+                dir: path.rsplit_once('\\').unwrap().0.to_string(),
+                path: path.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn installers() {
+        let g = guid("foo/bin\\foo.dll");
+        assert_eq!(g, guid("foo/bin\\foo.dll"));
+        assert_ne!(g, guid("bar/bin\\foo.dll"));
+        assert_eq!(g.len(), 38);
+        assert_eq!(&g[15..16], "8");
+
+        let wxs = render_wix("foo", &files());
+        assert!(wxs.contains(r#"<ComponentGroup Id="foo" Directory="INSTALLFOLDER">"#));
+        assert!(wxs.contains(&format!(r#"Subdirectory="bin" Guid="{g}""#)));
+        assert!(wxs.contains(r#"Source="\stage\include\foo\foo.h""#));
+
+        let nsi = render_nsis("foo", &files());
+        assert!(nsi.contains(
+            "SetOutPath \"$INSTDIR\\include\\foo\"\n  File \"\\stage\\include\\foo\\foo.h\""
+        ));
+        assert!(nsi.contains("RMDir \"$INSTDIR\\include\\foo\"\n  RMDir \"$INSTDIR\\bin\""));
+        assert!(nsi.ends_with("RMDir \"$INSTDIR\\include\"\n!macroend\n"));
+    }
+}
@@ -1,7 +1,30 @@
+pub mod abi;
 pub mod build;
 pub mod build_targets;
 pub mod cli;
 pub mod config;
+pub mod csources;
+pub mod ctest;
+pub mod def;
+pub mod dispatch;
+pub mod doc;
+pub mod explain;
+pub mod generator;
+pub mod i18n;
+pub mod ide;
 pub mod install;
+pub mod installer;
+pub mod msbuild;
+pub mod overrides;
 pub mod pkg_config_gen;
+pub mod probe;
+pub mod progress;
+pub mod reuse;
+pub mod runner;
+pub mod shell;
+pub mod size;
+pub mod symbols;
 pub mod target;
+pub mod unused;
+pub mod upgrade;
+pub mod vendored;
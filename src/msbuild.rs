@@ -0,0 +1,99 @@
+//! MSBuild property sheet, for the Visual Studio projects consuming the installed library
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use crate::build::CApiConfig;
+use crate::build_targets::BuildTargets;
+use crate::install::InstallPaths;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn windows_path(path: &Path) -> String {
+    escape(&path.display().to_string().replace('/', "\\"))
+}
+
+/// Render `{name}.props`, importing it sets the include and library paths and links the library
+///
+/// `system_libs` are the `-l` items the static library requires, unused if it is linked
+/// through the import library.
+pub fn render_props(
+    capi_config: &CApiConfig,
+    install_paths: &InstallPaths,
+    build_targets: &BuildTargets,
+    system_libs: &str,
+) -> String {
+    let include_path = install_paths
+        .includedir
+        .join(&capi_config.header.subdirectory);
+    let include_path = include_path
+        .ancestors()
+        .nth(capi_config.pkg_config.strip_include_path_components)
+        .unwrap_or(&install_paths.includedir);
+    let mut library_path = install_paths.libdir.clone();
+    if let Some(subdir) = &capi_config.library.install_subdir {
+        library_path.push(subdir);
+    }
+
+    let mut libs = Vec::new();
+    if let Some(impl_lib) = build_targets.impl_lib_output_file_name() {
+        libs.push(impl_lib.to_string_lossy().into_owned());
+    } else if let Some(static_lib) = build_targets.static_output_file_name() {
+        libs.push(static_lib.to_string_lossy().into_owned());
+        libs.extend(
+            system_libs
+                .split_whitespace()
+                .filter_map(|lib| lib.strip_prefix("-l"))
+                .map(|lib| format!("{lib}.lib")),
+        );
+    }
+    libs.push("%(AdditionalDependencies)".into());
+
+    let mut w = String::with_capacity(1024);
+    // writing to a String only fails on OOM, which we disregard
+    writeln!(w, r#"<?xml version="1.0" encoding="utf-8"?>"#).unwrap();
+    writeln!(
+        w,
+        "<!-- Generated by cargo-c, import it to use {} -->",
+        escape(&capi_config.library.name)
+    )
+    .unwrap();
+    writeln!(
+        w,
+        r#"<Project ToolsVersion="4.0" xmlns="http://schemas.microsoft.com/developer/msbuild/2003">"#
+    )
+    .unwrap();
+    writeln!(w, "  <PropertyGroup>").unwrap();
+    if capi_config.header.enabled {
+        writeln!(
+            w,
+            "    <IncludePath>{};$(IncludePath)</IncludePath>",
+            windows_path(include_path)
+        )
+        .unwrap();
+    }
+    writeln!(
+        w,
+        "    <LibraryPath>{};$(LibraryPath)</LibraryPath>",
+        windows_path(&library_path)
+    )
+    .unwrap();
+    writeln!(w, "  </PropertyGroup>").unwrap();
+    writeln!(w, "  <ItemDefinitionGroup>").unwrap();
+    writeln!(w, "    <Link>").unwrap();
+    writeln!(
+        w,
+        "      <AdditionalDependencies>{}</AdditionalDependencies>",
+        escape(&libs.join(";"))
+    )
+    .unwrap();
+    writeln!(w, "    </Link>").unwrap();
+    writeln!(w, "  </ItemDefinitionGroup>").unwrap();
+    writeln!(w, "</Project>").unwrap();
+
+    w
+}
@@ -0,0 +1,135 @@
+//! The `target.'cfg(...)'` and `profile` tables overriding the `package.metadata.capi` keys
+
+use anyhow::Context as _;
+
+use crate::target;
+
+/// Merge `overrides` into `base`, the tables are merged recursively and the other values replaced
+fn merge_toml(base: &mut toml::Value, overrides: &toml::Value) {
+    match (base, overrides) {
+        (toml::Value::Table(base), toml::Value::Table(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(key) {
+                    Some(base_value) => merge_toml(base_value, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides.clone(),
+    }
+}
+
+/// The `capi` tables applying to the target and the profile, each with the path of its keys
+///
+/// The `capi` metadata comes first, then the `target.'cfg(...)'` tables matching the target
+/// and the `profile.{profile}` one, the later ones override the keys of the former.
+pub(crate) fn capi_layers<'a>(
+    capi: &'a toml::Value,
+    rustc_target: &target::Target,
+    profile: &str,
+) -> anyhow::Result<Vec<(String, &'a toml::Value)>> {
+    let mut layers = vec![(String::new(), capi)];
+    for (key, overrides) in capi
+        .get("target")
+        .and_then(|t| t.as_table())
+        .into_iter()
+        .flatten()
+    {
+        let Some(expr) = key.strip_prefix("cfg(").and_then(|k| k.strip_suffix(')')) else {
+            anyhow::bail!("Invalid `target.{key}` table, expected a `cfg(...)` expression");
+        };
+        let expr: cargo_platform::CfgExpr = expr
+            .parse()
+            .with_context(|| format!("Invalid `target.{key}` table"))?;
+        if expr.matches(&rustc_target.cfg) {
+            layers.push((format!("target.'{key}'."), overrides));
+        }
+    }
+    if let Some(overrides) = capi.get("profile").and_then(|p| p.get(profile)) {
+        layers.push((format!("profile.{profile}."), overrides));
+    }
+    Ok(layers)
+}
+
+/// The `capi` metadata with the `target.'cfg(...)'` tables matching the target and
+/// the `profile.{profile}` overrides applied, in this order
+pub(crate) fn effective_capi_config(
+    capi: &toml::Value,
+    rustc_target: &target::Target,
+    profile: &str,
+) -> anyhow::Result<toml::Value> {
+    let mut merged = capi.clone();
+    for (_, overrides) in capi_layers(capi, rustc_target, profile)?
+        .into_iter()
+        .skip(1)
+    {
+        merge_toml(&mut merged, overrides);
+    }
+    for (section, value) in merged.as_table().into_iter().flatten() {
+        if value.get("target").is_some_and(|t| t.is_table()) {
+            anyhow::bail!(
+                "The `{section}.target` tables are not supported, the `target.'cfg(...)'.{section}` tables override the `{section}` keys for a target"
+            );
+        }
+    }
+    if let Some(table) = merged.as_table_mut() {
+        table.remove("target");
+        table.remove("profile");
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let capi: toml::Value = toml::toml! {
+            [library]
+            name = "example"
+            versioning = true
+            rustflags = "-Cpanic=abort"
+
+            [profile.dev.library]
+            versioning = false
+        }
+        .into();
+
+        let target = target::Target::new(None::<&str>, false).unwrap();
+        let dev = effective_capi_config(&capi, &target, "dev").unwrap();
+        assert_eq!(dev["library"]["versioning"].as_bool(), Some(false));
+        assert_eq!(dev["library"]["name"].as_str(), Some("example"));
+        assert_eq!(dev["library"]["rustflags"].as_str(), Some("-Cpanic=abort"));
+        assert!(dev.get("profile").is_none());
+
+        let release = effective_capi_config(&capi, &target, "release").unwrap();
+        assert_eq!(release["library"]["versioning"].as_bool(), Some(true));
+
+        let capi: toml::Value = toml::toml! {
+            [library]
+            import_library = true
+
+            [target."cfg(not(target_os = \"none\"))".library]
+            import_library = false
+
+            [target."cfg(target_os = \"none\")".pkg_config]
+            requires = "none"
+        }
+        .into();
+        let config = effective_capi_config(&capi, &target, "dev").unwrap();
+        assert_eq!(config["library"]["import_library"].as_bool(), Some(false));
+        assert!(config.get("pkg_config").is_none());
+        assert!(config.get("target").is_none());
+
+        // The per-section target tables are replaced by the cfg ones
+        let capi: toml::Value = toml::toml! {
+            [library.target.windows-msvc]
+            crt_static = true
+        }
+        .into();
+        assert!(effective_capi_config(&capi, &target, "dev").is_err());
+    }
+}
@@ -1,7 +1,9 @@
 #![allow(dead_code)]
 
-use crate::build::CApiConfig;
-use crate::install::InstallPaths;
+use crate::build::{CApiConfig, LinkFilename};
+use crate::build_targets::BuildTargets;
+use crate::install::{InstallPaths, LibType, UnixLibNames};
+use crate::probe;
 use std::path::{Component, Path, PathBuf};
 
 fn canonicalize<P: AsRef<Path>>(path: P) -> String {
@@ -57,6 +59,187 @@ fn canonicalize<P: AsRef<Path>>(path: P) -> String {
     }
 }
 
+/// Ask the system pkg-config for the compiler flags of a requirement, e.g. `foo >= 1.2`
+///
+/// A requirement that cannot be resolved is not an error, the flags are
+/// only an help for the static linking consumers and the C tests.
+pub(crate) fn probe_cflags(requirement: &str) -> Option<Vec<String>> {
+    let name = requirement.split_whitespace().next()?;
+    let lib = pkg_config::Config::new()
+        .cargo_metadata(false)
+        .env_metadata(false)
+        .print_system_cflags(false)
+        .probe(name)
+        .ok()?;
+    if !satisfies(requirement, &lib.version)? {
+        return None;
+    }
+
+    let includes = lib
+        .include_paths
+        .iter()
+        .map(|path| format!("-I{}", path.display()));
+    let defines = lib.defines.iter().map(|(name, value)| match value {
+        Some(value) => format!("-D{name}={value}"),
+        None => format!("-D{name}"),
+    });
+
+    Some(includes.chain(defines).collect())
+}
+
+/// Ask the system pkg-config for the libraries to link statically for a requirement
+pub(crate) fn probe_libs(requirement: &str) -> Option<Vec<String>> {
+    probe_flags(&["--libs", "--static"], requirement)
+}
+
+fn probe_flags(options: &[&str], requirement: &str) -> Option<Vec<String>> {
+    let pkg_config = std::env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".into());
+    let stamp = pc_stamp(&pkg_config);
+    let mut cmd = std::process::Command::new(pkg_config);
+    cmd.args(options).arg(requirement);
+    let out = probe::output_unless_changed(&mut cmd, stamp)?;
+
+    if !out.success {
+        return None;
+    }
+
+    Some(out.stdout.split_whitespace().map(str::to_string).collect())
+}
+
+/// Compare two versions the way pkg-config does, by runs of digits, numerically, and of
+/// letters, the other characters only separate them
+pub fn version_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    use std::cmp::Ordering;
+
+    fn segments(v: &str) -> impl Iterator<Item = &str> {
+        v.split(|c: char| !c.is_ascii_alphanumeric())
+            .flat_map(|part| {
+                let mut rest = part;
+                std::iter::from_fn(move || {
+                    let first = rest.chars().next()?;
+                    let end = rest
+                        .find(|c: char| c.is_ascii_digit() != first.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    let (segment, tail) = rest.split_at(end);
+                    rest = tail;
+                    Some(segment)
+                })
+            })
+    }
+
+    let mut a = segments(a);
+    let mut b = segments(b);
+    loop {
+        match (a.next(), b.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(x), Some(y)) => {
+                let x_num = x.starts_with(|c: char| c.is_ascii_digit());
+                let y_num = y.starts_with(|c: char| c.is_ascii_digit());
+                let ord = match (x_num, y_num) {
+                    (true, true) => {
+                        let x = x.trim_start_matches('0');
+                        let y = y.trim_start_matches('0');
+                        x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+                    }
+                    // The numbers are newer than the letters
+                    (true, false) => Ordering::Greater,
+                    (false, true) => Ordering::Less,
+                    (false, false) => x.cmp(y),
+                };
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+        }
+    }
+}
+
+/// Whether `version` satisfies the constraint of `requirement`, e.g. `foo >= 1.2`, `None`
+/// if it is malformed
+pub fn satisfies(requirement: &str, version: &str) -> Option<bool> {
+    let mut parts = requirement.split_whitespace().skip(1);
+    let (op, wanted) = match (parts.next(), parts.next()) {
+        (None, _) => return Some(true),
+        (Some(op), Some(wanted)) => (op, wanted),
+        _ => return None,
+    };
+    let ord = version_cmp(version, wanted);
+    Some(match op {
+        "=" => ord.is_eq(),
+        "!=" => ord.is_ne(),
+        "<" => ord.is_lt(),
+        "<=" => ord.is_le(),
+        ">" => ord.is_gt(),
+        ">=" => ord.is_ge(),
+        _ => return None,
+    })
+}
+
+/// The pkg-config search path, `PKG_CONFIG_PATH` and `PKG_CONFIG_LIBDIR` or the default one
+fn pc_search_path(pkg_config: &str) -> Option<Vec<PathBuf>> {
+    let mut dirs: Vec<PathBuf> = std::env::var_os("PKG_CONFIG_PATH")
+        .map_or_else(Vec::new, |p| std::env::split_paths(&p).collect());
+    match std::env::var_os("PKG_CONFIG_LIBDIR") {
+        Some(libdir) => dirs.extend(std::env::split_paths(&libdir)),
+        None => {
+            let mut cmd = std::process::Command::new(pkg_config);
+            cmd.args(["--variable", "pc_path", "pkg-config"]);
+            let out = probe::output(&mut cmd).filter(|out| out.success)?;
+            dirs.extend(std::env::split_paths(out.stdout.trim()));
+        }
+    }
+
+    Some(dirs)
+}
+
+/// The state of every `.pc` file in `dirs`, their names, sizes and mtimes
+///
+/// The requirements of a requirement are `.pc` files of the search path as well, the probes
+/// are reused only while none of them changes.
+fn pc_files_stamp(dirs: &[PathBuf]) -> String {
+    let mut files = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension() != Some("pc".as_ref()) {
+                continue;
+            }
+            let (len, mtime) = entry.metadata().map_or((0, 0), |m| {
+                let mtime = m
+                    .modified()
+                    .ok()
+                    .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_nanos());
+                (m.len(), mtime)
+            });
+            files.push(format!("{} {len} {mtime}", path.display()));
+        }
+    }
+    files.sort();
+
+    let mut hasher = cargo_util::Sha256::new();
+    for file in &files {
+        hasher.update(file.as_bytes()).update(b"\n");
+    }
+    hasher.finish_hex()
+}
+
+/// The stamp of the pkg-config probes, computed once per run
+///
+/// The requirement and the pkg-config variables are part of the probe key already.
+fn pc_stamp(pkg_config: &str) -> Option<String> {
+    static STAMP: std::sync::OnceLock<Option<String>> = std::sync::OnceLock::new();
+
+    STAMP
+        .get_or_init(|| pc_search_path(pkg_config).map(|dirs| pc_files_stamp(&dirs)))
+        .clone()
+}
+
 #[derive(Debug, Clone)]
 pub struct PkgConfig {
     prefix: PathBuf,
@@ -75,8 +258,12 @@ pub struct PkgConfig {
     libs_private: Vec<String>,
 
     cflags: Vec<String>,
+    cflags_private: Vec<String>,
 
     conflicts: Vec<String>,
+
+    /// The `-l:` library item of the uninstalled variant, the build directory has no soname
+    uninstalled_lib: Option<String>,
 }
 
 impl PkgConfig {
@@ -110,10 +297,11 @@ impl PkgConfig {
             libdir.push(subdir);
         }
 
-        let libs = vec![
+        let mut libs = vec![
             format!("-L{}", libdir.display()),
             format!("-l{}", capi_config.library.name),
         ];
+        libs.extend(capi_config.pkg_config.link.iter().cloned());
 
         let cflags = if capi_config.header.enabled {
             let includedir = Path::new("${includedir}").join(&capi_config.header.subdirectory);
@@ -144,8 +332,11 @@ impl PkgConfig {
             requires_private,
 
             cflags: vec![cflags],
+            cflags_private: capi_config.pkg_config.cflags_private.clone(),
 
             conflicts: Vec::new(),
+
+            uninstalled_lib: None,
         }
     }
 
@@ -154,6 +345,7 @@ impl PkgConfig {
         install_paths: &InstallPaths,
         args: &clap::ArgMatches,
         capi_config: &CApiConfig,
+        build_targets: &BuildTargets,
     ) -> Self {
         let mut pc = PkgConfig::new(name, capi_config);
 
@@ -173,6 +365,39 @@ impl PkgConfig {
                 pc.libdir.clone_from(&install_paths.libdir);
             }
         }
+
+        let file_name = |path: &Option<PathBuf>| {
+            path.as_deref()
+                .and_then(Path::file_name)
+                .map(|f| f.to_string_lossy().into_owned())
+        };
+        let exact = match capi_config.pkg_config.link_filename {
+            // Only the ELF linkers resolve `-l:`
+            Some(_) if !matches!(LibType::from_build_targets(build_targets), LibType::So) => None,
+            Some(LinkFilename::Shared) => file_name(&build_targets.shared_lib).map(|uninstalled| {
+                let library = &capi_config.library;
+                let names = UnixLibNames::new(LibType::So, library).unwrap();
+                let installed = if library.versioning {
+                    names.with_main_ver
+                } else {
+                    names.canonical
+                };
+                (installed, uninstalled)
+            }),
+            Some(LinkFilename::Static) => file_name(&build_targets.static_lib)
+                .map(|static_lib| (static_lib.clone(), static_lib)),
+            None => None,
+        };
+        if let Some((installed, uninstalled)) = exact {
+            pc.libs[1] = format!("-l:{installed}");
+            pc.uninstalled_lib = Some(format!("-l:{uninstalled}"));
+        }
+
+        // The header-only and data-only packages have nothing to link
+        if build_targets.static_lib.is_none() && build_targets.shared_lib.is_none() {
+            pc.libs.drain(..2);
+        }
+
         pc
     }
 
@@ -181,8 +406,17 @@ impl PkgConfig {
         uninstalled.prefix = output.to_path_buf();
         uninstalled.includedir = "${prefix}/include".into();
         uninstalled.libdir = "${prefix}".into();
-        // First libs item is the search path
-        uninstalled.libs[0] = "-L${prefix}".into();
+        // First libs item is the search path, if a library is linked
+        if let Some(search_path) = uninstalled
+            .libs
+            .first_mut()
+            .filter(|l| l.starts_with("-L${libdir}"))
+        {
+            *search_path = "-L${prefix}".into();
+        }
+        if let Some(lib) = &self.uninstalled_lib {
+            uninstalled.libs[1].clone_from(lib);
+        }
 
         uninstalled
     }
@@ -218,6 +452,16 @@ impl PkgConfig {
         self
     }
 
+    /// Add a requirement, unless the same package is already required
+    pub fn add_requires<S: AsRef<str>>(&mut self, req: S) -> &mut Self {
+        let req = req.as_ref();
+        let module = |r: &str| r.split_whitespace().next().unwrap_or_default().to_owned();
+        if !self.requires.iter().any(|r| module(r) == module(req)) {
+            self.requires.push(req.to_owned());
+        }
+        self
+    }
+
     pub fn set_cflags<S: AsRef<str>>(&mut self, flag: S) -> &mut Self {
         let flag = flag.as_ref().to_owned();
         self.cflags.clear();
@@ -231,11 +475,107 @@ impl PkgConfig {
         self
     }
 
+    pub fn add_cflag_private<S: AsRef<str>>(&mut self, flag: S) -> &mut Self {
+        let flag = flag.as_ref();
+        self.cflags_private.push(flag.to_owned());
+        self
+    }
+
     pub fn render(&self) -> String {
         // writing to a String only fails on OOM, which we disregard
         self.render_help(String::with_capacity(1024)).unwrap()
     }
 
+    /// Render a `{name}-config` shell script answering `--cflags`, `--libs` and `--version`
+    ///
+    /// Meant for the consumers without pkg-config, the `Requires` are not resolved.
+    pub fn render_config_script(&self, script_name: &str) -> String {
+        use std::fmt::Write as _;
+
+        // The pc variables use the shell syntax, escape everything else
+        let quote = |s: &str| {
+            let escaped = s
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('`', "\\`");
+            format!("\"{escaped}\"")
+        };
+        let mut libs_private = self.libs_private.clone();
+        libs_private.retain(|lib| !lib.is_empty());
+        let cflags_private = self.cflags_private.join(" ");
+
+        let mut w = String::with_capacity(1024);
+        // writing to a String only fails on OOM, which we disregard
+        writeln!(w, "#!/bin/sh").unwrap();
+        writeln!(
+            w,
+            "# Generated by cargo-c from the data of {}.pc",
+            self.name
+        )
+        .unwrap();
+        writeln!(w).unwrap();
+        writeln!(w, "prefix={}", quote(&canonicalize(&self.prefix))).unwrap();
+        writeln!(w, "exec_prefix={}", quote(&canonicalize(&self.exec_prefix))).unwrap();
+        writeln!(w, "libdir={}", quote(&canonicalize(&self.libdir))).unwrap();
+        writeln!(w, "includedir={}", quote(&canonicalize(&self.includedir))).unwrap();
+        writeln!(w).unwrap();
+        writeln!(w, "usage() {{").unwrap();
+        writeln!(
+            w,
+            "    echo \"Usage: {script_name} [--prefix] [--exec-prefix] [--version] [--cflags] [--libs] [--static]\""
+        )
+        .unwrap();
+        writeln!(w, "}}").unwrap();
+        writeln!(w).unwrap();
+        writeln!(w, "if test $# -eq 0; then").unwrap();
+        writeln!(w, "    usage 1>&2").unwrap();
+        writeln!(w, "    exit 1").unwrap();
+        writeln!(w, "fi").unwrap();
+        writeln!(w).unwrap();
+        writeln!(w, "static=no").unwrap();
+        writeln!(w, "for arg in \"$@\"; do").unwrap();
+        writeln!(w, "    test \"$arg\" = --static && static=yes").unwrap();
+        writeln!(w, "done").unwrap();
+        writeln!(w).unwrap();
+        writeln!(w, "out=").unwrap();
+        writeln!(w, "for arg in \"$@\"; do").unwrap();
+        writeln!(w, "    case \"$arg\" in").unwrap();
+        writeln!(w, "    --prefix) out=\"$out $prefix\" ;;").unwrap();
+        writeln!(w, "    --exec-prefix) out=\"$out $exec_prefix\" ;;").unwrap();
+        writeln!(w, "    --version) out=\"$out {}\" ;;", self.version).unwrap();
+        writeln!(w, "    --cflags)").unwrap();
+        writeln!(w, "        out=\"$out \"{}", quote(&self.cflags.join(" "))).unwrap();
+        if !cflags_private.is_empty() {
+            writeln!(
+                w,
+                "        test $static = yes && out=\"$out \"{}",
+                quote(&cflags_private)
+            )
+            .unwrap();
+        }
+        writeln!(w, "        ;;").unwrap();
+        writeln!(w, "    --libs)").unwrap();
+        writeln!(w, "        out=\"$out \"{}", quote(&self.libs.join(" "))).unwrap();
+        if !libs_private.is_empty() {
+            writeln!(
+                w,
+                "        test $static = yes && out=\"$out \"{}",
+                quote(&libs_private.join(" "))
+            )
+            .unwrap();
+        }
+        writeln!(w, "        ;;").unwrap();
+        writeln!(w, "    --static) ;;").unwrap();
+        writeln!(w, "    --help) usage; exit 0 ;;").unwrap();
+        writeln!(w, "    *) usage 1>&2; exit 1 ;;").unwrap();
+        writeln!(w, "    esac").unwrap();
+        writeln!(w, "done").unwrap();
+        writeln!(w).unwrap();
+        writeln!(w, "echo $out").unwrap();
+
+        w
+    }
+
     fn render_help<W: core::fmt::Write>(&self, mut w: W) -> Result<W, core::fmt::Error> {
         writeln!(w, "prefix={}", canonicalize(&self.prefix))?;
         writeln!(w, "exec_prefix={}", canonicalize(&self.exec_prefix))?;
@@ -250,6 +590,10 @@ impl PkgConfig {
         writeln!(w, "Libs: {}", self.libs.join(" "))?;
         writeln!(w, "Cflags: {}", self.cflags.join(" "))?;
 
+        if !self.cflags_private.is_empty() {
+            writeln!(w, "Cflags.private: {}", self.cflags_private.join(" "))?;
+        }
+
         if !self.libs_private.is_empty() {
             writeln!(w, "Libs.private: {}", self.libs_private.join(" "))?;
         }
@@ -267,43 +611,95 @@ impl PkgConfig {
     }
 }
 
+/// The `[package.metadata.system-deps]` table probing the installed library, for the Rust
+/// crates linking it, keyed by the pkg-config file name as the `-sys` crates do
+pub(crate) fn render_system_deps(capi_config: &CApiConfig) -> String {
+    let pc = &capi_config.pkg_config;
+    let key = pc
+        .filename
+        .replace(|c: char| !c.is_ascii_alphanumeric() && c != '_', "_");
+    let name = if key == pc.filename {
+        String::new()
+    } else {
+        format!("name = \"{}\", ", pc.filename)
+    };
+
+    format!(
+        "# Add to the Cargo.toml of the crates probing {} with system-deps\n\
+         [package.metadata.system-deps]\n\
+         {key} = {{ {name}version = \"{}\" }}\n",
+        pc.filename, pc.version
+    )
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use semver::Version;
 
+    fn test_capi_config() -> CApiConfig {
+        CApiConfig {
+            header: crate::build::HeaderCApiConfig {
+                name: "foo".into(),
+                subdirectory: "".into(),
+                generation: true,
+                enabled: true,
+                generator: Default::default(),
+                cbindgen_config: None,
+                filter: Default::default(),
+                headers: Vec::new(),
+                cpp: None,
+                umbrella: None,
+                include_guard_style: None,
+                dependencies: Default::default(),
+            },
+            pkg_config: crate::build::PkgConfigCApiConfig {
+                name: "foo".into(),
+                filename: "foo".into(),
+                description: "".into(),
+                version: "0.1".into(),
+                requires: Some("somelib, someotherlib".into()),
+                requires_private: Some("someprivatelib >= 1.0".into()),
+                cflags_private: Vec::new(),
+                strip_include_path_components: 0,
+                link: Vec::new(),
+                config_script: false,
+                system_deps: false,
+                noarch: false,
+                include_dependencies: Default::default(),
+                link_filename: None,
+            },
+            library: crate::build::LibraryCApiConfig {
+                name: "foo".into(),
+                version: Version::parse("0.1.0").unwrap(),
+                install_subdir: None,
+                origin_rpath: false,
+                rpath: Vec::new(),
+                versioning: true,
+                version_suffix_components: None,
+                import_library: true,
+                delay_load_import_library: false,
+                msvc_import_library: false,
+                rustflags: Vec::default(),
+                current_version: None,
+                compatibility_version: None,
+                install_name: crate::build::InstallName::Absolute,
+                def: Default::default(),
+                msbuild_props: false,
+                crt_static: false,
+                default_library_types: None,
+                lto: None,
+                symbols_check: None,
+            },
+            install: Default::default(),
+            csources: Default::default(),
+            test: Default::default(),
+        }
+    }
+
     #[test]
     fn simple() {
-        let mut pkg = PkgConfig::new(
-            "foo",
-            &CApiConfig {
-                header: crate::build::HeaderCApiConfig {
-                    name: "foo".into(),
-                    subdirectory: "".into(),
-                    generation: true,
-                    enabled: true,
-                },
-                pkg_config: crate::build::PkgConfigCApiConfig {
-                    name: "foo".into(),
-                    filename: "foo".into(),
-                    description: "".into(),
-                    version: "0.1".into(),
-                    requires: Some("somelib, someotherlib".into()),
-                    requires_private: Some("someprivatelib >= 1.0".into()),
-                    strip_include_path_components: 0,
-                },
-                library: crate::build::LibraryCApiConfig {
-                    name: "foo".into(),
-                    version: Version::parse("0.1.0").unwrap(),
-                    install_subdir: None,
-                    versioning: true,
-                    version_suffix_components: None,
-                    import_library: true,
-                    rustflags: Vec::default(),
-                },
-                install: Default::default(),
-            },
-        );
+        let mut pkg = PkgConfig::new("foo", &test_capi_config());
         pkg.add_lib("-lbar").add_cflag("-DFOO");
 
         let expected = concat!(
@@ -324,6 +720,125 @@ mod test {
         assert_eq!(expected, pkg.render());
     }
 
+    #[test]
+    fn cflags_private() {
+        let mut capi_config = test_capi_config();
+        capi_config.pkg_config.requires = None;
+        capi_config.pkg_config.requires_private = Some("bar".into());
+        capi_config.pkg_config.cflags_private = vec!["-DFOO_STATIC".into(), "-DFOO_NO_TLS".into()];
+
+        // The flags of `bar` are not probed, pkg-config adds them from Requires.private
+        let pkg = PkgConfig::new("foo", &capi_config);
+
+        let expected = concat!(
+            "prefix=/usr/local\n",
+            "exec_prefix=${prefix}\n",
+            "libdir=${exec_prefix}/lib\n",
+            "includedir=${prefix}/include\n",
+            "\n",
+            "Name: foo\n",
+            "Description: \n",
+            "Version: 0.1\n",
+            "Libs: -L${libdir} -lfoo\n",
+            "Cflags: -I${includedir}\n",
+            "Cflags.private: -DFOO_STATIC -DFOO_NO_TLS\n",
+            "Requires.private: bar\n",
+        );
+
+        assert_eq!(expected, pkg.render());
+    }
+
+    #[test]
+    fn add_requires() {
+        let mut pkg = PkgConfig::new("foo", &test_capi_config());
+        pkg.add_requires("somelib >= 2.0")
+            .add_requires("bar = 0.1.0");
+
+        assert_eq!(pkg.requires, ["somelib", "someotherlib", "bar = 0.1.0"]);
+    }
+
+    #[test]
+    fn versions() {
+        use std::cmp::Ordering::*;
+
+        assert_eq!(version_cmp("1.10.0", "1.9.0"), Greater);
+        assert_eq!(version_cmp("1.0", "1.0.0"), Less);
+        assert_eq!(version_cmp("1.02", "1.2"), Equal);
+        assert_eq!(version_cmp("1.0.0-alpha.1", "1.0.0"), Greater);
+        assert_eq!(version_cmp("1a", "1b"), Less);
+
+        assert_eq!(satisfies("foo", "0.1.0"), Some(true));
+        assert_eq!(satisfies("foo >= 0.2", "0.1.0"), Some(false));
+        assert_eq!(satisfies("foo < 1", "0.1.0"), Some(true));
+        assert_eq!(satisfies("foo != 0.1.0", "0.1.0"), Some(false));
+        assert_eq!(satisfies("foo ~ 1", "0.1.0"), None);
+        assert_eq!(satisfies("foo >=", "0.1.0"), None);
+    }
+
+    #[test]
+    fn pc_files() {
+        let dir = std::env::temp_dir().join(format!("cargo-c-pc-stamp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("foo.pc"), "Requires: bar\n").unwrap();
+        let dirs = [dir.clone()];
+        let stamp = pc_files_stamp(&dirs);
+
+        std::fs::write(dir.join("README"), "").unwrap();
+        assert_eq!(pc_files_stamp(&dirs), stamp);
+
+        // The requirements of the requirements count as well
+        std::fs::write(dir.join("bar.pc"), "Version: 1\n").unwrap();
+        let with_bar = pc_files_stamp(&dirs);
+        assert_ne!(with_bar, stamp);
+        std::fs::write(dir.join("bar.pc"), "Version: 1.1\n").unwrap();
+        assert_ne!(pc_files_stamp(&dirs), with_bar);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn system_deps() {
+        let mut capi_config = test_capi_config();
+        assert!(render_system_deps(&capi_config)
+            .ends_with("[package.metadata.system-deps]\nfoo = { version = \"0.1\" }\n"));
+
+        capi_config.pkg_config.filename = "gstreamer-1.0".into();
+        let toml: toml::Value = render_system_deps(&capi_config).parse().unwrap();
+        let dep = &toml["package"]["metadata"]["system-deps"]["gstreamer_1_0"];
+        assert_eq!(dep["name"].as_str(), Some("gstreamer-1.0"));
+        assert_eq!(dep["version"].as_str(), Some("0.1"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn config_script() {
+        let mut capi_config = test_capi_config();
+        capi_config.pkg_config.cflags_private = vec!["-DFOO_STATIC".into()];
+        let pkg = PkgConfig::new("foo", &capi_config);
+
+        let path = std::env::temp_dir().join(format!("foo-config-{}", std::process::id()));
+        std::fs::write(&path, pkg.render_config_script("foo-config")).unwrap();
+        let run = |args: &[&str]| {
+            let out = std::process::Command::new("sh")
+                .arg(&path)
+                .args(args)
+                .output()
+                .unwrap();
+            String::from_utf8(out.stdout).unwrap()
+        };
+
+        assert_eq!(run(&["--version"]), "0.1\n");
+        assert_eq!(
+            run(&["--cflags", "--libs"]),
+            "-I/usr/local/include -L/usr/local/lib -lfoo\n"
+        );
+        assert_eq!(
+            run(&["--static", "--cflags"]),
+            "-I/usr/local/include -DFOO_STATIC\n"
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
     mod test_canonicalize {
         use super::canonicalize;
 
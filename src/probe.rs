@@ -0,0 +1,167 @@
+use std::collections::BTreeMap;
+use std::process::Command;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ProbeOutput {
+    pub success: bool,
+    pub stdout: String,
+    /// The state of the inputs of the tool when it ran, see `output_unless_changed`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stamp: Option<String>,
+}
+
+/// The recorded probe results, by command line and environment
+pub type ProbeResults = BTreeMap<String, ProbeOutput>;
+
+#[derive(Debug, Default)]
+struct ProbeCache {
+    frozen: bool,
+    dirty: bool,
+    results: ProbeResults,
+}
+
+impl ProbeCache {
+    fn output_unless_changed(
+        &mut self,
+        cmd: &mut Command,
+        stamp: Option<String>,
+    ) -> Option<ProbeOutput> {
+        let key = key(cmd);
+
+        if let Some(out) = self.results.get(&key) {
+            if self.frozen || (stamp.is_some() && out.stamp == stamp) {
+                return Some(out.clone());
+            }
+        }
+
+        let out = cmd.output().ok()?;
+        let out = ProbeOutput {
+            success: out.status.success(),
+            stdout: String::from_utf8_lossy(&out.stdout).into_owned(),
+            stamp,
+        };
+
+        if self.results.get(&key) != Some(&out) {
+            self.results.insert(key, out.clone());
+            self.dirty = true;
+        }
+
+        Some(out)
+    }
+}
+
+/// The outputs of the tools run to probe the build environment
+///
+/// `rustc --print cfg`, `pkg-config` and `dpkg-architecture` are recorded in the
+/// fingerprints of the packages. With `--frozen`, or `--locked --offline`, the recorded
+/// results are used instead of running the tools again, so rebuilds from a prepared
+/// environment do not diverge.
+static CACHE: Mutex<ProbeCache> = Mutex::new(ProbeCache {
+    frozen: false,
+    dirty: false,
+    results: BTreeMap::new(),
+});
+
+/// The command line and the environment affecting the result
+fn key(cmd: &Command) -> String {
+    let mut key = cmd.get_program().to_string_lossy().into_owned();
+    for arg in cmd.get_args() {
+        key.push(' ');
+        key.push_str(&arg.to_string_lossy());
+    }
+    for (var, value) in std::env::vars() {
+        if var.starts_with("PKG_CONFIG") {
+            key.push_str(&format!(" {var}={value}"));
+        }
+    }
+    key
+}
+
+/// Use the recorded probes, `frozen` makes them take precedence over running the tools
+pub fn load(results: ProbeResults, frozen: bool) {
+    *CACHE.lock().unwrap() = ProbeCache {
+        frozen,
+        dirty: false,
+        results,
+    };
+}
+
+/// The probes to record, `None` if none was run since `load`
+pub fn changed() -> Option<ProbeResults> {
+    let cache = CACHE.lock().unwrap();
+    cache.dirty.then(|| cache.results.clone())
+}
+
+/// The probes run or reused since `load`
+pub fn results() -> ProbeResults {
+    CACHE.lock().unwrap().results.clone()
+}
+
+/// Run the command, or reuse its recorded output if the cache is frozen
+///
+/// `None` if the command cannot be run.
+pub fn output(cmd: &mut Command) -> Option<ProbeOutput> {
+    output_unless_changed(cmd, None)
+}
+
+/// Run the command, or reuse its recorded output if the cache is frozen or if it was
+/// recorded with the same `stamp`
+///
+/// The stamp describes the inputs of the tool, e.g. the mtimes of the files it reads.
+pub fn output_unless_changed(cmd: &mut Command, stamp: Option<String>) -> Option<ProbeOutput> {
+    CACHE.lock().unwrap().output_unless_changed(cmd, stamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frozen() {
+        let mut cmd = Command::new("rustc");
+        cmd.arg("--version");
+
+        // A cache of its own, the global one is used by the other tests
+        let mut cache = ProbeCache::default();
+        let out = cache.output_unless_changed(&mut cmd, None).unwrap();
+        assert!(out.success);
+        assert!(cache.dirty);
+
+        // The recorded output is returned as is
+        let mut results = cache.results.clone();
+        results.get_mut(&key(&cmd)).unwrap().stdout = "recorded".into();
+        let mut cache = ProbeCache {
+            frozen: true,
+            dirty: false,
+            results: results.clone(),
+        };
+        assert_eq!(
+            cache.output_unless_changed(&mut cmd, None).unwrap().stdout,
+            "recorded"
+        );
+        assert!(!cache.dirty);
+
+        cache.frozen = false;
+        assert_eq!(cache.output_unless_changed(&mut cmd, None).unwrap(), out);
+
+        // Until the stamp changes
+        let stamp = || Some("inputs".to_string());
+        cache.output_unless_changed(&mut cmd, stamp()).unwrap();
+        cache.results.get_mut(&key(&cmd)).unwrap().stdout = "recorded".into();
+        assert_eq!(
+            cache
+                .output_unless_changed(&mut cmd, stamp())
+                .unwrap()
+                .stdout,
+            "recorded"
+        );
+        assert_eq!(
+            cache
+                .output_unless_changed(&mut cmd, Some("changed".into()))
+                .unwrap()
+                .stdout,
+            out.stdout
+        );
+    }
+}
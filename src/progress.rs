@@ -0,0 +1,93 @@
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+
+use cargo::core::Workspace;
+
+/// The cargo-c phases reporting their progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Header,
+    PkgConfig,
+    ImportLibrary,
+    CSources,
+    Bindings,
+    CArray,
+    Doc,
+    Install,
+}
+
+/// A progress event, `status` is the verb cargo shows, e.g. `Building`
+#[derive(Debug)]
+pub struct Event<'a> {
+    pub phase: Phase,
+    pub status: &'a str,
+    pub message: &'a str,
+}
+
+type Callback = Arc<dyn Fn(&Event) + Send + Sync>;
+
+static CALLBACK: Mutex<Option<Callback>> = Mutex::new(None);
+
+/// Report the progress to `callback` instead of the cargo shell
+///
+/// Meant for the frontends embedding cargo-c as a library.
+pub fn set_callback(callback: impl Fn(&Event) + Send + Sync + 'static) {
+    *CALLBACK.lock().unwrap() = Some(Arc::new(callback));
+}
+
+/// Report the progress to the cargo shell again
+pub fn clear_callback() {
+    *CALLBACK.lock().unwrap() = None;
+}
+
+/// Report a progress event to the callback if set, or print it as a status
+pub fn status(
+    ws: &Workspace,
+    phase: Phase,
+    status: &str,
+    message: impl Display,
+) -> anyhow::Result<()> {
+    let message = message.to_string();
+    if notify(&Event {
+        phase,
+        status,
+        message: &message,
+    }) {
+        return Ok(());
+    }
+
+    ws.gctx().shell().status(status, message)
+}
+
+/// Pass `event` to the callback if set, the lock is released before calling it so the
+/// callback may set or clear it
+fn notify(event: &Event) -> bool {
+    let callback = CALLBACK.lock().unwrap().clone();
+    if let Some(callback) = callback {
+        callback(event);
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reentrant_callback() {
+        let event = Event {
+            phase: Phase::Install,
+            status: "Installing",
+            message: "foo",
+        };
+        assert!(!notify(&event));
+
+        set_callback(|event| {
+            assert_eq!(event.message, "foo");
+            clear_callback();
+        });
+        assert!(notify(&event));
+        assert!(!notify(&event));
+    }
+}
@@ -0,0 +1,302 @@
+//! The previous builds installed without running cargo, `cinstall --no-build` and `--reuse`
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use cargo::core::Workspace;
+use cargo::ops::CompileOptions;
+use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt, CompileMode, ProfileChecking};
+use cargo::util::StableHasher;
+use cargo::GlobalContext;
+
+use crate::build::{
+    capi_packages, check_install_targets, compile_options, parse_dep_info, previous_out_dirs,
+    CPackage,
+};
+use crate::i18n::tr;
+
+/// The packages of a previous build, for `cinstall --no-build`
+///
+/// The fingerprint of the artifacts must match, the ones built with different options or
+/// install paths are not installed.
+pub fn prebuilt(
+    ws: &mut Workspace,
+    config: &GlobalContext,
+    args: &ArgMatches,
+    default_profile: &str,
+) -> anyhow::Result<Vec<CPackage>> {
+    let mut packages = capi_packages(ws, config, args, default_profile)?;
+    let profile = args.get_profile_name(default_profile, ProfileChecking::Custom)?;
+    let compile_opts = compile_options(ws, config, args, profile, CompileMode::Build)?;
+    let out_dirs = previous_out_dirs(ws, &compile_opts, &packages)?;
+
+    for cpkg in packages.iter_mut() {
+        let root_output = cpkg.build_targets.pc.parent().unwrap().to_path_buf();
+        if !cpkg.finger_print.is_valid() {
+            anyhow::bail!(tr(
+                "stale-artifacts",
+                &[("package", &cpkg.name), ("path", &root_output.display())]
+            ));
+        }
+        cpkg.reuse_previous_build(&out_dirs)?;
+    }
+    check_install_targets(ws, &packages, args)?;
+
+    Ok(packages)
+}
+
+/// The packages of a previous build with the same options, if nothing changed since, so
+/// that `cinstall --reuse` copies them without running cargo
+///
+/// The sources listed in the dep-info file and the lock file must be older than the
+/// artifacts. The packages with a build script are always built, what makes it run again
+/// is not known.
+pub fn reusable(
+    ws: &mut Workspace,
+    config: &GlobalContext,
+    args: &ArgMatches,
+    default_profile: &str,
+) -> anyhow::Result<Option<Vec<CPackage>>> {
+    let mut packages = capi_packages(ws, config, args, default_profile)?;
+    if packages.is_empty() {
+        return Ok(None);
+    }
+
+    let has_build_script = ws.members().any(|m| {
+        packages.iter().any(|p| p.name == m.name())
+            && m.targets().iter().any(|t| t.is_custom_build())
+    });
+    if has_build_script {
+        return Ok(None);
+    }
+
+    let profile = args.get_profile_name(default_profile, ProfileChecking::Custom)?;
+    let compile_opts = compile_options(ws, config, args, profile, CompileMode::Build)?;
+    let lock_file = ws.lock_root().as_path_unlocked().join("Cargo.lock");
+    let key = build_key(ws, &compile_opts, args)?;
+
+    for cpkg in packages.iter_mut() {
+        let Ok(previous) = cpkg.finger_print.load_previous() else {
+            return Ok(None);
+        };
+        let dep_file = cpkg
+            .finger_print
+            .root_output
+            .join(format!("cargo-c-{}.d", cpkg.capi_config.library.name));
+        let targets = &cpkg.build_targets;
+        let libraries: Vec<_> = [&targets.static_lib, &targets.shared_lib]
+            .into_iter()
+            .flatten()
+            .map(PathBuf::as_path)
+            .collect();
+        if previous.build_key != format!("{key}-{:?}", cpkg.library_types)
+            || !cpkg.finger_print.is_valid()
+            || !sources_unchanged(&dep_file, &lock_file, &libraries)
+        {
+            return Ok(None);
+        }
+    }
+
+    let out_dirs = previous_out_dirs(ws, &compile_opts, &packages)?;
+    for cpkg in packages.iter_mut() {
+        ws.gctx().shell().verbose(|shell| {
+            shell.status(
+                "Fresh",
+                format!("{}, reusing the previous build", cpkg.name),
+            )
+        })?;
+        cpkg.reuse_previous_build(&out_dirs)?;
+    }
+    check_install_targets(ws, &packages, args)?;
+
+    Ok(Some(packages))
+}
+
+/// Whether the dependencies listed by a cargo-c dep-info file, and `lock_file` if present,
+/// are older than the libraries
+///
+/// The other files generated are not written again if the libraries do not change.
+fn sources_unchanged(dep_file: &Path, lock_file: &Path, libraries: &[&Path]) -> bool {
+    let Ok(content) = std::fs::read_to_string(dep_file) else {
+        return false;
+    };
+    let mtime = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    let Some(built) = libraries
+        .iter()
+        .map(|lib| mtime(lib))
+        .collect::<Option<Vec<_>>>()
+        .and_then(|mtimes| mtimes.into_iter().min())
+    else {
+        return false;
+    };
+
+    parse_dep_info(&content)
+        .iter()
+        .map(PathBuf::as_path)
+        .chain(lock_file.exists().then_some(lock_file))
+        .all(|dep| mtime(dep).is_some_and(|m| m <= built))
+}
+
+/// The options deciding how the libraries are compiled, besides the install paths and
+/// the sources
+///
+/// The profile is the one resolved from the manifest, the configuration and the environment,
+/// the rustflags the ones of the target. The library types of each package are appended.
+pub(crate) fn build_key(
+    ws: &Workspace,
+    compile_opts: &CompileOptions,
+    args: &ArgMatches,
+) -> anyhow::Result<String> {
+    use cargo::core::compiler::RustcTargetData;
+    use cargo::core::profiles::Profiles;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = StableHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    let requested_profile = compile_opts.build_config.requested_profile;
+    requested_profile.hash(&mut hasher);
+    format!("{:?}", Profiles::new(ws, requested_profile)?.base_profile()).hash(&mut hasher);
+
+    let requested_kinds = &compile_opts.build_config.requested_kinds;
+    let target_data = RustcTargetData::new(ws, requested_kinds)?;
+    target_data.rustc.verbose_version.hash(&mut hasher);
+    for kind in requested_kinds {
+        format!("{kind:?}").hash(&mut hasher);
+        target_data.info(*kind).rustflags.hash(&mut hasher);
+    }
+
+    format!("{:?}", compile_opts.cli_features).hash(&mut hasher);
+    format!("{:?}", compile_opts.spec).hash(&mut hasher);
+    for id in ["crt_static", "reproducible", "forbid_vendored", "no_rpath"] {
+        args.flag(id).hash(&mut hasher);
+    }
+    args.get_many::<String>("rpath")
+        .into_iter()
+        .flatten()
+        .for_each(|rpath| rpath.hash(&mut hasher));
+    args.get_one::<String>("install_name").hash(&mut hasher);
+    args.get_one::<String>("codesign").hash(&mut hasher);
+    args.get_one::<String>("debuginfo").hash(&mut hasher);
+    toolchain_env(std::env::vars_os()).hash(&mut hasher);
+
+    Ok(hasher.finish().to_string())
+}
+
+/// The environment variables the build scripts of the dependencies and the linking depend
+/// on, the C toolchain ones of the cc crate, besides the ones in the build key already
+fn toolchain_env(
+    vars: impl Iterator<Item = (std::ffi::OsString, std::ffi::OsString)>,
+) -> BTreeMap<String, std::ffi::OsString> {
+    const NAMES: [&str; 9] = [
+        "CC",
+        "CXX",
+        "AR",
+        "RANLIB",
+        "CFLAGS",
+        "CXXFLAGS",
+        "ARFLAGS",
+        "CRATE_CC_NO_DEFAULTS",
+        "SOURCE_DATE_EPOCH",
+    ];
+
+    vars.filter_map(|(name, value)| Some((name.into_string().ok()?, value)))
+        .filter(|(name, _)| {
+            let unprefixed = name
+                .strip_prefix("HOST_")
+                .or_else(|| name.strip_prefix("TARGET_"))
+                .unwrap_or(name);
+            NAMES.iter().any(|known| {
+                unprefixed == *known
+                    || unprefixed
+                        .strip_prefix(known)
+                        .is_some_and(|suffix| suffix.starts_with('_'))
+            }) || name.starts_with("CARGO_TARGET_")
+                || name.starts_with("PKG_CONFIG")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::escape_dep_path;
+    use cargo_util::paths::{create_dir_all, write};
+
+    #[test]
+    pub fn test_sources_unchanged() {
+        use std::time::{Duration, SystemTime};
+
+        let dir = std::env::temp_dir().join(format!("cargo-c-reuse-{}", std::process::id()));
+        create_dir_all(&dir).unwrap();
+        let [source, lock_file, lib, dep_file] =
+            ["lib.rs", "Cargo.lock", "libfoo.a", "cargo-c-foo.d"].map(|name| dir.join(name));
+        let touch = |path: &Path, secs: u64| {
+            write(path, "").unwrap();
+            let time = SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+            std::fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        let unchanged = || sources_unchanged(&dep_file, &lock_file, &[&lib]);
+
+        // Not built yet
+        assert!(!unchanged());
+
+        write(
+            &dep_file,
+            format!("{}: {}\n", escape_dep_path(&lib), escape_dep_path(&source)),
+        )
+        .unwrap();
+        touch(&source, 1000);
+        touch(&lock_file, 1000);
+        touch(&lib, 2000);
+        assert!(unchanged());
+
+        touch(&source, 3000);
+        assert!(!unchanged());
+        touch(&source, 1000);
+
+        // A dependency updated
+        touch(&lock_file, 3000);
+        assert!(!unchanged());
+        touch(&lock_file, 1000);
+
+        std::fs::remove_file(&source).unwrap();
+        assert!(!unchanged());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    pub fn test_toolchain_env() {
+        let vars = [
+            "CC",
+            "CC_x86_64_unknown_linux_gnu",
+            "TARGET_CFLAGS",
+            "HOST_CXX",
+            "CARGO_TARGET_X86_64_UNKNOWN_LINUX_GNU_LINKER",
+            "PKG_CONFIG_PATH",
+            "CCACHE_DIR",
+            "PATH",
+            "HOME",
+        ]
+        .map(|name| (name.into(), "value".into()));
+
+        let env = toolchain_env(vars.into_iter());
+        assert_eq!(
+            env.keys().collect::<Vec<_>>(),
+            [
+                "CARGO_TARGET_X86_64_UNKNOWN_LINUX_GNU_LINKER",
+                "CC",
+                "CC_x86_64_unknown_linux_gnu",
+                "HOST_CXX",
+                "PKG_CONFIG_PATH",
+                "TARGET_CFLAGS",
+            ]
+        );
+    }
+}
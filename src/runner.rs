@@ -0,0 +1,233 @@
+//! The runner of the tests built for a foreign target, qemu-user or wine
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt};
+use cargo::util::context::PathAndArgs;
+use cargo::GlobalContext;
+use cargo_platform::{Cfg, CfgExpr};
+
+use crate::target::Target;
+
+fn has_cfg(target: &Target, key: &str, value: &str) -> bool {
+    target
+        .cfg
+        .iter()
+        .any(|cfg| matches!(cfg, Cfg::KeyPair(k, v) if k.as_str() == key && v == value))
+}
+
+/// The qemu-user emulator of the architecture
+fn qemu_arch(target: &Target) -> Option<&'static str> {
+    let big_endian = has_cfg(target, "target_endian", "big");
+    let arch = match (target.arch.as_str(), big_endian) {
+        ("x86", _) => "i386",
+        ("x86_64", _) => "x86_64",
+        ("arm", false) => "arm",
+        ("arm", true) => "armeb",
+        ("aarch64", false) => "aarch64",
+        ("aarch64", true) => "aarch64_be",
+        ("riscv32", _) => "riscv32",
+        ("riscv64", _) => "riscv64",
+        ("powerpc", _) => "ppc",
+        ("powerpc64", false) => "ppc64le",
+        ("powerpc64", true) => "ppc64",
+        ("mips", false) => "mipsel",
+        ("mips", true) => "mips",
+        ("mips64", false) => "mips64el",
+        ("mips64", true) => "mips64",
+        ("s390x", _) => "s390x",
+        ("sparc64", _) => "sparc64",
+        ("loongarch64", _) => "loongarch64",
+        _ => return None,
+    };
+    Some(arch)
+}
+
+/// The sysroot of the cross toolchain, e.g. `/usr/aarch64-linux-gnu`
+///
+/// `QEMU_LD_PREFIX` is used by qemu as is.
+fn sysroot(triple: &str) -> Option<PathBuf> {
+    let mut parts: Vec<_> = triple.split('-').collect();
+    if parts.len() == 4 {
+        // the toolchains do not have the vendor
+        parts.remove(1);
+    }
+    let arch = parts.first_mut()?;
+    if arch.starts_with("arm") || arch.starts_with("thumb") {
+        *arch = "arm";
+    } else if arch.starts_with("riscv64") {
+        *arch = "riscv64";
+    }
+
+    Some(Path::new("/usr").join(parts.join("-"))).filter(|p| p.is_dir())
+}
+
+/// The program in `PATH`
+fn find_program(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Whether `host` runs the binaries of `target`, not every aarch64 system runs the arm ones
+fn is_native(target: &Target, host: &Target) -> bool {
+    target.arch == host.arch || (target.arch == "x86" && host.arch == "x86_64")
+}
+
+/// Whether a `target.'cfg(...)'` table matching `target` sets a runner
+fn has_cfg_runner(tables: &HashMap<String, toml::Value>, target: &Target) -> bool {
+    tables.iter().any(|(name, value)| {
+        value.get("runner").is_some()
+            && name
+                .strip_prefix("cfg(")
+                .and_then(|n| n.strip_suffix(')'))
+                .and_then(|expr| expr.parse::<CfgExpr>().ok())
+                .is_some_and(|expr| expr.matches(&target.cfg))
+    })
+}
+
+/// The runner able to execute the binaries of `target` on `host`, if present in the system
+fn foreign_runner(target: &Target, triple: &str, host: &Target) -> Option<String> {
+    if target.os == "windows" {
+        if host.os == "windows" {
+            return None;
+        }
+        let candidates: &[&str] = if target.arch == "x86_64" {
+            &["wine64", "wine"]
+        } else {
+            &["wine"]
+        };
+        let wine = candidates.iter().find_map(|name| find_program(name))?;
+        return Some(wine.display().to_string());
+    }
+
+    if target.os != "linux" || host.os != "linux" || is_native(target, host) {
+        return None;
+    }
+
+    let qemu = find_program(&format!("qemu-{}", qemu_arch(target)?))?;
+    let mut runner = qemu.display().to_string();
+    if std::env::var_os("QEMU_LD_PREFIX").is_none() {
+        if let Some(sysroot) = sysroot(triple) {
+            runner.push_str(&format!(" -L {}", sysroot.display()));
+        }
+    }
+
+    Some(runner)
+}
+
+/// The `--config` value setting `target.{triple}.runner` for the tests, if needed
+///
+/// `--runner` overrides the configured runner, otherwise for a foreign target qemu-user or
+/// wine is used if found and no runner is configured for it. `gctx` is configured with the
+/// other `--config` values.
+pub fn runner_config(gctx: &GlobalContext, args: &ArgMatches) -> anyhow::Result<Option<String>> {
+    // Only the test subcommand has the option
+    let Ok(runner) = args.try_get_one::<String>("runner") else {
+        return Ok(None);
+    };
+
+    let host = gctx.load_global_rustc(None)?.host.to_string();
+    let triple = match args.targets()?.as_slice() {
+        [triple] => triple.clone(),
+        _ => host.clone(),
+    };
+
+    let runner = match runner {
+        Some(runner) => runner.clone(),
+        None => {
+            if triple == host {
+                return Ok(None);
+            }
+            let target = Target::new(Some(&triple), true)?;
+            let key = format!("target.{triple}.runner");
+            let configured = gctx.get::<Option<PathAndArgs>>(&key)?.is_some()
+                || has_cfg_runner(
+                    &gctx
+                        .get::<Option<HashMap<String, toml::Value>>>("target")?
+                        .unwrap_or_default(),
+                    &target,
+                );
+            if configured {
+                return Ok(None);
+            }
+
+            let host = Target::new(Some(&host), false)?;
+            let Some(runner) = foreign_runner(&target, &triple, &host) else {
+                return Ok(None);
+            };
+            gctx.shell()
+                .status("Using", format!("{runner} to run the {triple} tests"))?;
+            runner
+        }
+    };
+
+    let quote = |s: String| toml::Value::String(s).to_string();
+    Ok(Some(format!(
+        "target.{}.runner={}",
+        quote(triple),
+        quote(runner)
+    )))
+}
+
+/// The runner of the tests, as cargo configures it for `triple`
+pub fn runner(gctx: &GlobalContext, triple: &str) -> anyhow::Result<Option<PathAndArgs>> {
+    gctx.get::<Option<PathAndArgs>>(&format!("target.{triple}.runner"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn qemu() {
+        let target = |arch: &str, endian: &str| Target {
+            is_target_overridden: true,
+            arch: arch.into(),
+            os: "linux".into(),
+            env: "gnu".into(),
+            cfg: vec![format!("target_endian=\"{endian}\"").parse().unwrap()],
+        };
+        assert_eq!(qemu_arch(&target("aarch64", "little")), Some("aarch64"));
+        assert_eq!(qemu_arch(&target("powerpc64", "little")), Some("ppc64le"));
+        assert_eq!(qemu_arch(&target("powerpc64", "big")), Some("ppc64"));
+        assert_eq!(qemu_arch(&target("x86", "little")), Some("i386"));
+        assert_eq!(qemu_arch(&target("wasm32", "little")), None);
+    }
+
+    fn linux(arch: &str, cfg: &[&str]) -> Target {
+        Target {
+            is_target_overridden: true,
+            arch: arch.into(),
+            os: "linux".into(),
+            env: "gnu".into(),
+            cfg: cfg.iter().map(|c| c.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn native() {
+        let host = linux("aarch64", &[]);
+        assert!(is_native(&linux("aarch64", &[]), &host));
+        assert!(!is_native(&linux("arm", &[]), &host));
+        assert!(is_native(&linux("x86", &[]), &linux("x86_64", &[])));
+        assert!(!is_native(&linux("x86_64", &[]), &linux("x86", &[])));
+    }
+
+    #[test]
+    fn cfg_runner() {
+        let tables: HashMap<String, toml::Value> = toml::from_str(
+            r#"
+            'cfg(target_arch = "riscv64")' = { runner = "qemu-riscv64" }
+            'cfg(unix)' = { rustflags = ["-Cforce-frame-pointers"] }
+            "#,
+        )
+        .unwrap();
+        let aarch64 = linux("aarch64", &["unix", "target_arch=\"aarch64\""]);
+        let riscv64 = linux("riscv64", &["unix", "target_arch=\"riscv64\""]);
+
+        assert!(!has_cfg_runner(&tables, &aarch64));
+        assert!(has_cfg_runner(&tables, &riscv64));
+    }
+}
@@ -0,0 +1,154 @@
+//! A shell, or a command, with the environment to build and run C programs against the
+//! uninstalled libraries
+
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::Context;
+use cargo::core::Workspace;
+use cargo::util::command_prelude::{ArgMatches, ArgMatchesExt};
+
+use crate::build::CPackage;
+
+/// The search path variables pointing to the build directories of the packages, the
+/// current values follow
+fn uninstalled_env(packages: &[CPackage]) -> anyhow::Result<Vec<(&'static str, OsString)>> {
+    let mut pc_path = Vec::new();
+    let mut library_path = Vec::new();
+    let mut include_path = Vec::new();
+    let mut library_var = "LD_LIBRARY_PATH";
+
+    let push = |paths: &mut Vec<PathBuf>, dir: &Path| {
+        if !paths.iter().any(|p| p == dir) {
+            paths.push(dir.to_path_buf());
+        }
+    };
+
+    for pkg in packages {
+        let build_targets = &pkg.build_targets;
+        let root_output = build_targets.pc.parent().unwrap();
+        // pkg-config prefers the `-uninstalled.pc` next to the installed one
+        push(&mut pc_path, root_output);
+
+        if let Some(shared_lib) = &build_targets.shared_lib {
+            push(&mut library_path, shared_lib.parent().unwrap());
+        }
+
+        let header = &pkg.capi_config.header;
+        if header.enabled {
+            let includedir = root_output.join("include").join(&header.subdirectory);
+            let includedir = includedir
+                .ancestors()
+                .nth(pkg.capi_config.pkg_config.strip_include_path_components)
+                .unwrap_or(root_output);
+            push(&mut include_path, includedir);
+        }
+
+        library_var = if build_targets.target.has_dlls() {
+            "PATH"
+        } else if build_targets.target.is_apple() {
+            "DYLD_LIBRARY_PATH"
+        } else {
+            "LD_LIBRARY_PATH"
+        };
+    }
+
+    let mut env = Vec::new();
+    for (var, mut paths) in [
+        ("PKG_CONFIG_PATH", pc_path),
+        (library_var, library_path),
+        ("CPATH", include_path),
+    ] {
+        if paths.is_empty() {
+            continue;
+        }
+        if let Some(current) = std::env::var_os(var) {
+            paths.extend(std::env::split_paths(&current));
+        }
+        env.push((var, std::env::join_paths(paths)?));
+    }
+
+    Ok(env)
+}
+
+/// The commands setting `env`, for `cmd.exe` with `windows` or else a POSIX shell
+fn render_exports(env: &[(&str, OsString)], windows: bool) -> String {
+    env.iter()
+        .map(|(var, value)| {
+            let value = value.to_string_lossy();
+            if windows {
+                format!("set \"{var}={value}\"\n")
+            } else {
+                format!("export {var}='{}'\n", value.replace('\'', r"'\''"))
+            }
+        })
+        .collect()
+}
+
+/// Run `COMMAND`, or the user shell, with the uninstalled libraries, headers and pkg-config
+/// files in the search paths, or print the commands setting them with `--print`
+pub fn capi_shell(ws: &Workspace, packages: &[CPackage], args: &ArgMatches) -> anyhow::Result<()> {
+    let env = uninstalled_env(packages)?;
+
+    if args.flag("print") {
+        print!("{}", render_exports(&env, cfg!(windows)));
+        return Ok(());
+    }
+
+    let mut command: Vec<OsString> = args
+        .get_many::<String>("command")
+        .into_iter()
+        .flatten()
+        .map(OsString::from)
+        .collect();
+    let interactive = command.is_empty();
+    if interactive {
+        let shell = if cfg!(windows) {
+            std::env::var_os("COMSPEC").unwrap_or_else(|| "cmd.exe".into())
+        } else {
+            std::env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into())
+        };
+        ws.gctx().shell().status(
+            "Spawning",
+            format!(
+                "{} with the uninstalled environment, exit to return",
+                shell.to_string_lossy()
+            ),
+        )?;
+        command.push(shell);
+    }
+
+    let program = &command[0];
+    let status = Command::new(program)
+        .args(&command[1..])
+        .envs(env)
+        .status()
+        .with_context(|| format!("Cannot run {}", program.to_string_lossy()))?;
+    if !interactive && !status.success() {
+        anyhow::bail!("{} failed: {status}", program.to_string_lossy());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports() {
+        let env = [
+            ("PKG_CONFIG_PATH", OsString::from("/a b/debug")),
+            ("CPATH", OsString::from("/it's/include")),
+        ];
+        assert_eq!(
+            render_exports(&env, false),
+            "export PKG_CONFIG_PATH='/a b/debug'\nexport CPATH='/it'\\''s/include'\n"
+        );
+        assert_eq!(
+            render_exports(&env[..1], true),
+            "set \"PKG_CONFIG_PATH=/a b/debug\"\n"
+        );
+    }
+}
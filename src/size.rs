@@ -0,0 +1,230 @@
+//! `cargo capi size-report`, the code size of the exported functions compared with the
+//! previous report
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use cargo::core::Workspace;
+use cargo_util::paths::{read, read_bytes, write};
+use object::read::{Object, ObjectSection, ObjectSymbol};
+use object::{ObjectKind, SectionKind, SymbolKind};
+
+use crate::build::CPackage;
+use crate::symbols::{is_c_symbol, symbol_name};
+use crate::target::Target;
+
+/// The code size of a library and of the functions it exports
+#[derive(Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+struct SizeReport {
+    /// The size of the code sections
+    code: u64,
+    functions: BTreeMap<String, u64>,
+}
+
+/// Add the code and the functions of an object file to the report
+///
+/// The formats not recording the symbol sizes, e.g. Mach-O, get the distance to the
+/// next symbol or the size of the section holding only the function.
+fn add_object(
+    report: &mut SizeReport,
+    file: &object::File,
+    target: &Target,
+    wanted: impl Fn(&str) -> bool,
+) {
+    let relocatable = file.kind() == ObjectKind::Relocatable;
+    let text_sections: Vec<_> = file
+        .sections()
+        .filter(|s| s.kind() == SectionKind::Text)
+        .collect();
+    report.code += text_sections.iter().map(|s| s.size()).sum::<u64>();
+
+    let mut addresses: Vec<u64> = file
+        .symbols()
+        .chain(file.dynamic_symbols())
+        .filter(|s| s.kind() == SymbolKind::Text && s.is_definition())
+        .map(|s| s.address())
+        .chain(text_sections.iter().map(|s| s.address() + s.size()))
+        .collect();
+    addresses.sort_unstable();
+    addresses.dedup();
+
+    let mut per_section: BTreeMap<usize, usize> = BTreeMap::new();
+    if relocatable {
+        for symbol in file.symbols() {
+            if let (SymbolKind::Text, Some(index)) = (symbol.kind(), symbol.section_index()) {
+                *per_section.entry(index.0).or_default() += 1;
+            }
+        }
+    }
+
+    for symbol in file.symbols().chain(file.dynamic_symbols()) {
+        if symbol.kind() != SymbolKind::Text || !symbol.is_global() || !symbol.is_definition() {
+            continue;
+        }
+        let Ok(name) = symbol.name_bytes() else {
+            continue;
+        };
+        let name = symbol_name(name, target);
+        if !wanted(&name) {
+            continue;
+        }
+
+        let size = if symbol.size() > 0 {
+            symbol.size()
+        } else if relocatable {
+            symbol
+                .section_index()
+                .filter(|index| per_section.get(&index.0) == Some(&1))
+                .and_then(|index| file.section_by_index(index).ok())
+                .map_or(0, |section| section.size())
+        } else {
+            addresses
+                .iter()
+                .find(|&&a| a > symbol.address())
+                .map_or(0, |next| next - symbol.address())
+        };
+
+        let entry = report.functions.entry(name).or_default();
+        *entry = (*entry).max(size);
+    }
+}
+
+fn library_sizes(cpkg: &CPackage) -> anyhow::Result<Option<(PathBuf, SizeReport)>> {
+    let build_targets = &cpkg.build_targets;
+    let target = &build_targets.target;
+    let mut report = SizeReport::default();
+
+    if let Some(shared_lib) = &build_targets.shared_lib {
+        let data = read_bytes(shared_lib)?;
+        let file = object::File::parse(&*data)?;
+        let exports: Vec<_> = file
+            .exports()?
+            .iter()
+            .map(|e| symbol_name(e.name(), target))
+            .collect();
+        add_object(&mut report, &file, target, |name| {
+            exports.iter().any(|e| e == name)
+        });
+        Ok(Some((shared_lib.clone(), report)))
+    } else if let Some(static_lib) = &build_targets.static_lib {
+        let data = read_bytes(static_lib)?;
+        let archive = object::read::archive::ArchiveFile::parse(&*data)?;
+        for member in archive.members() {
+            let member = member?;
+            // The metadata and the import members are not objects
+            let Ok(file) = object::File::parse(member.data(&*data)?) else {
+                continue;
+            };
+            add_object(&mut report, &file, target, |name| {
+                !String::from_utf8_lossy(member.name()).contains("compiler_builtins")
+                    && is_c_symbol(name)
+            });
+        }
+        Ok(Some((static_lib.clone(), report)))
+    } else {
+        Ok(None)
+    }
+}
+
+fn delta(current: u64, previous: Option<u64>) -> String {
+    match previous {
+        Some(previous) if previous != current => {
+            format!("{:+}", current as i64 - previous as i64)
+        }
+        _ => String::new(),
+    }
+}
+
+/// The report lines, the deltas are against `previous` if available
+fn render(report: &SizeReport, previous: Option<&SizeReport>) -> Vec<String> {
+    let mut lines = vec![format!("{:>10} {:>8}  function", "size", "delta")];
+
+    for (name, &size) in &report.functions {
+        let old = previous.map(|p| p.functions.get(name).copied().unwrap_or(0));
+        lines.push(format!("{size:>10} {:>8}  {name}", delta(size, old)));
+    }
+    if let Some(previous) = previous {
+        for (name, &size) in &previous.functions {
+            if !report.functions.contains_key(name) {
+                lines.push(format!(
+                    "{:>10} {:>8}  {name} (removed)",
+                    0,
+                    delta(0, Some(size))
+                ));
+            }
+        }
+    }
+
+    let total: u64 = report.functions.values().sum();
+    let percent = if report.code > 0 {
+        total as f64 * 100.0 / report.code as f64
+    } else {
+        0.0
+    };
+    let total_delta = delta(total, previous.map(|p| p.functions.values().sum()));
+    let code_delta = delta(report.code, previous.map(|p| p.code));
+    lines.push(format!(
+        "{total:>10} {total_delta:>8}  total of {} exported functions, {percent:.1}% of {} bytes of code {code_delta}",
+        report.functions.len(),
+        report.code,
+    ));
+
+    lines
+}
+
+/// Report the code size of the exported functions, compared with the previous report
+///
+/// The report is stored next to the library as `cargo-c-{library file name}-sizes.json`.
+pub fn size_report(ws: &Workspace, packages: &[CPackage]) -> anyhow::Result<()> {
+    for cpkg in packages {
+        let Some((library, report)) = library_sizes(cpkg)? else {
+            continue;
+        };
+        let file_name = library.file_name().unwrap().to_string_lossy();
+        let path = library
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(format!("cargo-c-{file_name}-sizes.json"));
+        let previous: Option<SizeReport> =
+            read(&path).ok().and_then(|s| serde_json::from_str(&s).ok());
+
+        ws.gctx()
+            .shell()
+            .status("Reporting", format!("the code size of {file_name}"))?;
+        for line in render(&report, previous.as_ref()) {
+            println!("{}", line.trim_end());
+        }
+
+        write(&path, serde_json::to_string_pretty(&report)? + "\n")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deltas() {
+        let previous = SizeReport {
+            code: 1000,
+            functions: [("ex_new".into(), 100), ("ex_old".into(), 50)].into(),
+        };
+        let current = SizeReport {
+            code: 1100,
+            functions: [("ex_new".into(), 120), ("ex_get".into(), 30)].into(),
+        };
+
+        assert_eq!(
+            render(&current, Some(&previous)),
+            [
+                "      size    delta  function",
+                "        30      +30  ex_get",
+                "       120      +20  ex_new",
+                "         0      -50  ex_old (removed)",
+                "       150           total of 2 exported functions, 13.6% of 1100 bytes of code +100",
+            ]
+        );
+    }
+}
@@ -0,0 +1,648 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use cargo::core::Workspace;
+use cargo_util::paths::{read, read_bytes};
+use itertools::Itertools;
+use object::read::{Object, ObjectSymbol};
+
+use crate::build_targets::BuildTargets;
+use crate::i18n::tr;
+use crate::target::Target;
+
+/// The symbols exported by a shared library
+///
+/// The leading underscore Apple platforms add to the C symbols is removed.
+pub fn exported_symbols(path: &Path, target: &Target) -> anyhow::Result<BTreeSet<String>> {
+    let data = read_bytes(path)?;
+    let file = object::File::parse(&*data)?;
+
+    let symbols = file
+        .exports()?
+        .iter()
+        .map(|e| symbol_name(e.name(), target))
+        .collect();
+
+    Ok(symbols)
+}
+
+pub(crate) fn symbol_name(name: &[u8], target: &Target) -> String {
+    let name = String::from_utf8_lossy(name);
+    let name = if target.is_apple() {
+        name.strip_prefix('_').unwrap_or(&name)
+    } else {
+        &name
+    };
+    name.to_string()
+}
+
+/// Whether the symbol is part of the C API and not a Rust or runtime one
+pub(crate) fn is_c_symbol(name: &str) -> bool {
+    // The identifiers reserved to the implementation and the mangled Rust symbols
+    let reserved = name.starts_with("__")
+        || name.starts_with('_') && name[1..].starts_with(|c: char| c.is_ascii_uppercase());
+
+    !name.is_empty() && !reserved && !name.starts_with("rust_") && !name.contains('.')
+}
+
+/// The C symbols defined by a static library
+///
+/// The Rust symbols and the compiler builtins are not part of the C API, they are ignored.
+pub fn static_library_symbols(path: &Path, target: &Target) -> anyhow::Result<BTreeSet<String>> {
+    archive_symbols(path, target, |_| true)
+}
+
+/// The symbols of a static library defined by the objects not built by rustc
+///
+/// They come from the C sources of the crate or the `-sys` dependencies, e.g. a vendored
+/// library the shared library does not export.
+pub fn c_object_symbols(path: &Path, target: &Target) -> anyhow::Result<BTreeSet<String>> {
+    archive_symbols(path, target, |name| !is_rust_object(name))
+}
+
+/// Whether the archive member is a codegen unit emitted by rustc
+fn is_rust_object(name: &[u8]) -> bool {
+    name.ends_with(b".rcgu.o")
+}
+
+fn archive_symbols(
+    path: &Path,
+    target: &Target,
+    member_filter: impl Fn(&[u8]) -> bool,
+) -> anyhow::Result<BTreeSet<String>> {
+    let data = read_bytes(path)?;
+    let archive = object::read::archive::ArchiveFile::parse(&*data)?;
+    let mut symbols = BTreeSet::new();
+
+    for member in archive.members() {
+        let member = member?;
+        if String::from_utf8_lossy(member.name()).contains("compiler_builtins")
+            || !member_filter(member.name())
+        {
+            continue;
+        }
+        // The metadata and the import members are not objects
+        let Ok(file) = object::File::parse(member.data(&*data)?) else {
+            continue;
+        };
+        symbols.extend(
+            file.symbols()
+                .filter(|s| s.is_global() && s.is_definition())
+                .filter_map(|s| s.name_bytes().ok())
+                .map(|name| symbol_name(name, target))
+                .filter(|name| is_c_symbol(name)),
+        );
+    }
+
+    Ok(symbols)
+}
+
+fn strip_comments(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut chars = src.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+                out.push(' ');
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn strip_preprocessor(src: &str) -> String {
+    let mut out = String::with_capacity(src.len());
+    let mut continuation = false;
+
+    for line in src.lines() {
+        let directive = continuation || line.trim_start().starts_with('#');
+        continuation = directive && line.trim_end().ends_with('\\');
+        if !directive {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+fn declared_name(statement: &str) -> Option<String> {
+    let statement = statement.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if statement.starts_with("typedef") {
+        return None;
+    }
+
+    if statement.contains('(') {
+        let re = regex::Regex::new(r"([A-Za-z_][A-Za-z0-9_]*)\s*\(").unwrap();
+        // Skip the compiler attributes that might precede the declaration
+        let name = re
+            .captures_iter(&statement)
+            .map(|c| c.get(1).unwrap().as_str())
+            .find(|name| !name.starts_with("__"))
+            .map(str::to_string);
+        name
+    } else if statement.starts_with("extern ") {
+        let re = regex::Regex::new(r"([A-Za-z_][A-Za-z0-9_]*)\s*(\[[^\]]*\]\s*)*$").unwrap();
+        re.captures(&statement)
+            .map(|c| c.get(1).unwrap().as_str().to_string())
+    } else {
+        None
+    }
+}
+
+/// The name of a type definition, e.g. `struct Foo { ... }` or `typedef ... Foo`
+///
+/// `statement` is the definition without the text within the braces.
+fn defined_type(statement: &str, definition: &str) -> Option<String> {
+    if statement.starts_with("typedef") {
+        // e.g. typedef void (*ex_cb)(int32_t value);
+        let pointer = regex::Regex::new(r"\(\s*\*\s*([A-Za-z_][A-Za-z0-9_]*)\s*\)").unwrap();
+        if let Some(c) = pointer.captures(statement) {
+            return Some(c[1].to_string());
+        }
+        let last = regex::Regex::new(r"([A-Za-z_][A-Za-z0-9_]*)\s*(\[[^\]]*\]\s*)*$").unwrap();
+        return last.captures(statement).map(|c| c[1].to_string());
+    }
+
+    let tagged =
+        regex::Regex::new(r"^(struct|union|enum)\s+([A-Za-z_][A-Za-z0-9_]*)\s*\{").unwrap();
+    tagged
+        .captures(definition)
+        .map(|c| format!("{} {}", &c[1], &c[2]))
+}
+
+/// The braces of a header
+enum Scope {
+    /// `extern "C" { ... }`, holding declarations
+    Linkage,
+    /// An inline function body
+    Body,
+    /// A struct, union or enum definition
+    Definition,
+}
+
+/// The declarations and the type definitions of a C header
+///
+/// This is not a C parser, it understands the subset of C cbindgen produces.
+/// The values are the normalized text of the declarations and definitions.
+#[derive(Debug, Default)]
+pub struct HeaderApi {
+    pub declarations: BTreeMap<String, String>,
+    pub types: BTreeMap<String, String>,
+}
+
+impl HeaderApi {
+    pub fn parse(header: &str) -> Self {
+        let code = strip_preprocessor(&strip_comments(header));
+        let normalize = |s: &str| s.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        let mut api = HeaderApi::default();
+        // The text outside of the braces and the whole statement
+        let mut statement = String::new();
+        let mut full = String::new();
+        let mut scopes: Vec<Scope> = Vec::new();
+
+        for c in code.chars() {
+            let depth = scopes
+                .iter()
+                .filter(|s| !matches!(s, Scope::Linkage))
+                .count();
+            let in_body = scopes.iter().any(|s| matches!(s, Scope::Body));
+            match c {
+                '{' => {
+                    let head = statement.trim_end();
+                    let is_extern_c = head.ends_with("extern \"C\"");
+                    let scope = if depth == 0 && is_extern_c {
+                        Scope::Linkage
+                    } else if in_body || (depth == 0 && head.ends_with(')')) {
+                        Scope::Body
+                    } else {
+                        Scope::Definition
+                    };
+                    if depth == 0 && !matches!(scope, Scope::Definition) {
+                        // Either a linkage block or an inline function definition
+                        statement.clear();
+                        full.clear();
+                    }
+                    if matches!(scope, Scope::Definition) {
+                        full.push(c);
+                    }
+                    scopes.push(scope);
+                }
+                '}' => {
+                    if let Some(Scope::Definition) = scopes.pop() {
+                        full.push(c);
+                    }
+                }
+                ';' if depth == 0 => {
+                    let normalized = normalize(&statement);
+                    if let Some(name) = declared_name(&normalized) {
+                        api.declarations.insert(name, normalized);
+                    } else {
+                        let definition = normalize(&full);
+                        if let Some(name) = defined_type(&normalized, &definition) {
+                            api.types.insert(name, definition);
+                        }
+                    }
+                    statement.clear();
+                    full.clear();
+                }
+                _ if depth == 0 => {
+                    statement.push(c);
+                    full.push(c);
+                }
+                _ if !in_body => full.push(c),
+                _ => {}
+            }
+        }
+
+        api
+    }
+}
+
+/// The functions and the variables declared in a C header
+pub fn header_declarations(header: &str) -> BTreeSet<String> {
+    HeaderApi::parse(header).declarations.into_keys().collect()
+}
+
+/// Compare the symbols exported by the shared library with the declarations found in the headers
+///
+/// `policy` is one of `warn`, `deny` or `off`.
+pub fn check_header_symbols(
+    ws: &Workspace,
+    build_targets: &BuildTargets,
+    policy: &str,
+) -> anyhow::Result<()> {
+    if policy == "off" {
+        return Ok(());
+    }
+
+    let Some(shared_lib) = build_targets.shared_lib.as_ref() else {
+        return Ok(());
+    };
+
+    let headers: Vec<&Path> = build_targets
+        .extra
+        .include
+        .iter()
+        .map(|(from, _)| from.as_path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "h"))
+        .collect();
+
+    if headers.is_empty() {
+        return Ok(());
+    }
+
+    ws.gctx()
+        .shell()
+        .verbose(|shell| shell.status("Checking", tr("check-header-symbols", &[])))?;
+
+    let exported = exported_symbols(shared_lib, &build_targets.target);
+    let Some(exported) = readable(ws, policy, build_targets, exported)? else {
+        return Ok(());
+    };
+    let mut declared = BTreeSet::new();
+    for header in headers {
+        declared.extend(header_declarations(&read(header)?));
+    }
+
+    let mut problems = Vec::new();
+    let missing = exported.difference(&declared).join(", ");
+    if !missing.is_empty() {
+        problems.push(tr(
+            "symbols-not-declared",
+            &[("name", &build_targets.name), ("symbols", &missing)],
+        ));
+    }
+    let unknown = declared.difference(&exported).join(", ");
+    if !unknown.is_empty() {
+        problems.push(tr(
+            "symbols-not-exported",
+            &[("name", &build_targets.name), ("symbols", &unknown)],
+        ));
+    }
+
+    report(ws, policy, problems)
+}
+
+/// Compare the symbols defined by the static library with the ones exported by the shared library
+///
+/// Consumers switching from one to the other would otherwise find out about the
+/// missing symbols at link time. The symbols of the C objects are not compared.
+/// `policy` is one of `warn`, `deny` or `off`.
+pub fn check_library_symbols(
+    ws: &Workspace,
+    build_targets: &BuildTargets,
+    policy: &str,
+) -> anyhow::Result<()> {
+    if policy == "off" {
+        return Ok(());
+    }
+
+    let (Some(static_lib), Some(shared_lib)) = (
+        build_targets.static_lib.as_ref(),
+        build_targets.shared_lib.as_ref(),
+    ) else {
+        return Ok(());
+    };
+
+    ws.gctx()
+        .shell()
+        .verbose(|shell| shell.status("Checking", tr("check-library-symbols", &[])))?;
+
+    let target = &build_targets.target;
+    let symbols = (|| {
+        // Whether the shared library exports the C objects depends on their visibility
+        let c_symbols = c_object_symbols(static_lib, target)?;
+        anyhow::Ok((
+            &static_library_symbols(static_lib, target)? - &c_symbols,
+            &exported_symbols(shared_lib, target)? - &c_symbols,
+        ))
+    })();
+    let Some((static_symbols, shared_symbols)) = readable(ws, policy, build_targets, symbols)?
+    else {
+        return Ok(());
+    };
+
+    let mut problems = Vec::new();
+    let static_only = static_symbols.difference(&shared_symbols).join(", ");
+    if !static_only.is_empty() {
+        problems.push(tr(
+            "symbols-static-only",
+            &[("name", &build_targets.name), ("symbols", &static_only)],
+        ));
+    }
+    let shared_only = shared_symbols.difference(&static_symbols).join(", ");
+    if !shared_only.is_empty() {
+        problems.push(tr(
+            "symbols-shared-only",
+            &[("name", &build_targets.name), ("symbols", &shared_only)],
+        ));
+    }
+
+    report(ws, policy, problems)
+}
+
+/// The symbols the library exports, from the shared library if built
+pub fn library_symbols(build_targets: &BuildTargets) -> anyhow::Result<BTreeSet<String>> {
+    let target = &build_targets.target;
+    if let Some(shared_lib) = &build_targets.shared_lib {
+        exported_symbols(shared_lib, target)
+    } else if let Some(static_lib) = &build_targets.static_lib {
+        static_library_symbols(static_lib, target)
+    } else {
+        Ok(BTreeSet::new())
+    }
+}
+
+/// Warn about the symbols exported by the previous build and not anymore
+pub fn check_removed_symbols(
+    ws: &Workspace,
+    build_targets: &BuildTargets,
+    previous: &BTreeSet<String>,
+    current: &BTreeSet<String>,
+) -> anyhow::Result<()> {
+    let removed = previous.difference(current).join(", ");
+    if removed.is_empty() {
+        return Ok(());
+    }
+
+    ws.gctx().shell().warn(format!(
+        "{}\n{}",
+        tr(
+            "symbols-removed",
+            &[("name", &build_targets.name), ("symbols", &removed)]
+        ),
+        tr("symbols-removed-hint", &[])
+    ))
+}
+
+/// The symbols if read, `None` once reported as `policy` says otherwise
+///
+/// `object` does not parse every format, e.g. the wasm modules built for emscripten.
+fn readable<T>(
+    ws: &Workspace,
+    policy: &str,
+    build_targets: &BuildTargets,
+    symbols: anyhow::Result<T>,
+) -> anyhow::Result<Option<T>> {
+    match symbols {
+        Ok(symbols) => Ok(Some(symbols)),
+        Err(e) if policy == "deny" => {
+            Err(e.context(tr("symbols-unreadable", &[("name", &build_targets.name)])))
+        }
+        Err(e) => {
+            ws.gctx().shell().warn(tr(
+                "symbols-unchecked",
+                &[("name", &build_targets.name), ("error", &format!("{e:#}"))],
+            ))?;
+            Ok(None)
+        }
+    }
+}
+
+fn report(ws: &Workspace, policy: &str, problems: Vec<String>) -> anyhow::Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+
+    if policy == "deny" {
+        anyhow::bail!("{}", problems.join("\n"));
+    }
+
+    for problem in problems {
+        ws.gctx().shell().warn(problem)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_library_symbols, header_declarations, is_c_symbol, is_rust_object, HeaderApi,
+    };
+    use crate::build_targets::BuildTargets;
+    use crate::target::Target;
+    use cargo::core::Workspace;
+    use cargo::GlobalContext;
+
+    #[test]
+    fn unreadable_library() {
+        let root = std::env::temp_dir().join(format!("cargo-c-unreadable-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(
+            root.join("Cargo.toml"),
+            "[package]\nname = \"example\"\nversion = \"0.1.0\"\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+        // Neither an archive nor an ELF, Mach-O or PE file, e.g. a wasm module
+        let lib = root.join("example.wasm");
+        std::fs::write(&lib, b"\0asm\x01\0\0\0").unwrap();
+
+        let gctx = GlobalContext::default().unwrap();
+        let ws = Workspace::new(&root.join("Cargo.toml"), &gctx).unwrap();
+        let build_targets = BuildTargets {
+            name: "example".into(),
+            include: None,
+            static_lib: Some(lib.clone()),
+            shared_lib: Some(lib),
+            impl_lib: None,
+            delay_impl_lib: None,
+            msvc_impl_lib: None,
+            debug_info: None,
+            def: None,
+            pc: root.join("example.pc"),
+            config_script: None,
+            system_deps: None,
+            msbuild_props: None,
+            bins: Vec::new(),
+            c_array: None,
+            target: Target::new(Some("wasm32-unknown-emscripten"), true).unwrap(),
+            extra: Default::default(),
+            use_meson_naming_convention: false,
+            msys2: false,
+            meson_dll_suffix: String::new(),
+        };
+
+        assert!(check_library_symbols(&ws, &build_targets, "warn").is_ok());
+        let err = check_library_symbols(&ws, &build_targets, "deny").unwrap_err();
+        assert_eq!(err.to_string(), "cannot read the symbols of example");
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn rust_objects() {
+        assert!(is_rust_object(
+            b"example-0123abcd.example.1a2b3c4d-cgu.0.rcgu.o"
+        ));
+        assert!(is_rust_object(b"example-0123abcd.2zx8w1m3n4b5v6c7.rcgu.o"));
+        for name in [
+            &b"zlib-0123abcd-adler32.o"[..],
+            b"foo.o",
+            b"foo.obj",
+            b"lib.rmeta",
+        ] {
+            assert!(!is_rust_object(name), "{}", String::from_utf8_lossy(name));
+        }
+    }
+
+    #[test]
+    fn c_symbols() {
+        for name in ["example_new", "EX_VERSION", "deflate", "_private_c"] {
+            assert!(is_c_symbol(name), "{name}");
+        }
+        for name in [
+            "_ZN4core3fmt5write17h0123456789abcdefE",
+            "_RNvCs1234_7example3foo",
+            "__rust_alloc",
+            "__rdl_alloc",
+            "__rg_oom",
+            "__addvdi3",
+            "rust_eh_personality",
+            "DW.ref.rust_eh_personality",
+            ".Lanon.0",
+        ] {
+            assert!(!is_c_symbol(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn cbindgen_header() {
+        let header = r#"
+#ifndef EXAMPLE_H
+#define EXAMPLE_H
+
+/* Generated with cbindgen:0.27.0 */
+
+#define EXAMPLE_MAJOR 0
+#define EXAMPLE_MINOR 1
+
+#include <stdarg.h>
+#include <stdint.h>
+
+typedef struct ExOpaque ExOpaque;
+
+typedef struct {
+  int32_t a;
+  void (*callback)(int32_t value);
+} ExConfig;
+
+enum ExKind {
+  EX_KIND_A,
+  EX_KIND_B,
+};
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+extern const int32_t EX_VERSION;
+
+extern uint8_t EX_TABLE[16];
+
+/**
+ * Create a new opaque value
+ */
+ExOpaque *ex_new(const ExConfig *config);
+
+void ex_free(ExOpaque *ex,
+             int32_t flags) __attribute__((nonnull));
+
+static inline int32_t ex_inline(void) {
+  return 1;
+}
+
+int32_t ex_last(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif /* EXAMPLE_H */
+"#;
+
+        let names: Vec<_> = header_declarations(header).into_iter().collect();
+
+        assert_eq!(
+            names,
+            ["EX_TABLE", "EX_VERSION", "ex_free", "ex_last", "ex_new"]
+        );
+
+        let api = HeaderApi::parse(header);
+        assert_eq!(
+            api.declarations["ex_free"],
+            "void ex_free(ExOpaque *ex, int32_t flags) __attribute__((nonnull))"
+        );
+        assert_eq!(
+            api.types.keys().collect::<Vec<_>>(),
+            ["ExConfig", "ExOpaque", "enum ExKind"]
+        );
+        assert_eq!(
+            api.types["ExConfig"],
+            "typedef struct { int32_t a; void (*callback)(int32_t value); } ExConfig"
+        );
+    }
+}
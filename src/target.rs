@@ -1,9 +1,70 @@
 use std::env::consts;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
 use anyhow::*;
 
 use crate::build::CApiConfig;
+use crate::probe;
+
+/// How the default libdir is chosen, `--libdir-policy`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LibdirPolicy {
+    /// `lib/{multiarch}` on Debian and `lib64` where the host uses it, `lib` when cross-compiling
+    #[default]
+    Auto,
+    Lib,
+    Lib64,
+    /// `lib/{multiarch}`, derived from the target when cross-compiling
+    Multiarch,
+}
+
+impl LibdirPolicy {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "lib" => LibdirPolicy::Lib,
+            "lib64" => LibdirPolicy::Lib64,
+            "multiarch" => LibdirPolicy::Multiarch,
+            _ => LibdirPolicy::Auto,
+        }
+    }
+}
+
+/// The multiarch tuple of the Debian host, `dpkg-architecture` runs once
+fn host_multiarch() -> Option<&'static String> {
+    static MULTIARCH: OnceLock<Option<String>> = OnceLock::new();
+
+    MULTIARCH
+        .get_or_init(|| {
+            if !Path::new("/etc/debian_version").exists() {
+                return None;
+            }
+            let mut cmd = std::process::Command::new("dpkg-architecture");
+            cmd.arg("-qDEB_HOST_MULTIARCH");
+            probe::output(&mut cmd)
+                .filter(|out| out.success)
+                .map(|out| out.stdout.trim().to_string())
+        })
+        .as_ref()
+}
+
+/// `MINGW_PREFIX` as a Windows path, `cygpath` runs once
+fn mingw_prefix() -> Option<&'static PathBuf> {
+    static PREFIX: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+    PREFIX
+        .get_or_init(|| {
+            let prefix = std::env::var_os("MINGW_PREFIX")?;
+            // The native programs do not resolve the MSYS2 paths
+            let mut cmd = std::process::Command::new("cygpath");
+            cmd.arg("-m").arg(&prefix);
+            let windows = probe::output(&mut cmd)
+                .filter(|out| out.success)
+                .map(|out| PathBuf::from(out.stdout.trim()));
+            Some(windows.unwrap_or_else(|| prefix.into()))
+        })
+        .as_ref()
+}
 
 /// Split a target string to its components
 ///
@@ -16,6 +77,8 @@ pub struct Target {
     // pub vendor: String,
     pub os: String,
     pub env: String,
+    /// The `cfg` values of the target, to match the `cfg(...)` metadata tables
+    pub cfg: Vec<cargo_platform::Cfg>,
 }
 
 impl Target {
@@ -31,8 +94,8 @@ impl Target {
             cmd.arg("--target").arg(target);
         }
 
-        let out = cmd.output()?;
-        if out.status.success() {
+        let out = probe::output(&mut cmd).with_context(|| format!("Cannot run {cmd:?}"))?;
+        if out.success {
             fn match_re(re: regex::Regex, s: &str) -> String {
                 re.captures(s)
                     .map_or("", |cap| cap.get(1).unwrap().as_str())
@@ -44,13 +107,14 @@ impl Target {
             let os_re = regex::Regex::new(r#"target_os="(.+)""#).unwrap();
             let env_re = regex::Regex::new(r#"target_env="(.+)""#).unwrap();
 
-            let s = std::str::from_utf8(&out.stdout).unwrap();
+            let s = &out.stdout;
 
             Ok(Target {
                 arch: match_re(arch_re, s),
                 // vendor: match_re(vendor_re, s),
                 os: match_re(os_re, s),
                 env: match_re(env_re, s),
+                cfg: s.lines().filter_map(|l| l.parse().ok()).collect(),
                 is_target_overridden,
             })
         } else {
@@ -81,35 +145,37 @@ impl Target {
 
         if os == "android" {
             lines.push(format!("-Wl,-soname,lib{lib_name}.so"));
-        } else if os == "linux"
-            || os == "freebsd"
-            || os == "dragonfly"
-            || os == "netbsd"
-            || os == "haiku"
-            || os == "illumos"
-            || os == "openbsd"
-            || os == "hurd"
-        {
+        } else if self.is_unix_elf() {
             lines.push(if capi_config.library.versioning {
                 format!("-Wl,-soname,lib{lib_name}.so.{sover}")
             } else {
                 format!("-Wl,-soname,lib{lib_name}.so")
             });
-        } else if os == "macos" || os == "ios" || os == "tvos" || os == "visionos" {
+        } else if self.is_apple() {
+            let install_name_dir = capi_config.library.install_name.dir(libdir);
             let line = if capi_config.library.versioning {
-                format!("-Wl,-install_name,{1}/lib{0}.{5}.dylib,-current_version,{2}.{3}.{4},-compatibility_version,{5}",
-                        lib_name, libdir.display(), major, minor, patch, sover)
+                let current_version = capi_config
+                    .library
+                    .current_version
+                    .clone()
+                    .unwrap_or_else(|| format!("{major}.{minor}.{patch}"));
+                let compatibility_version = capi_config
+                    .library
+                    .compatibility_version
+                    .as_ref()
+                    .unwrap_or(&sover);
+                format!("-Wl,-install_name,{1}/lib{0}.{2}.dylib,-current_version,{3},-compatibility_version,{4}",
+                        lib_name, install_name_dir, sover, current_version, compatibility_version)
             } else {
                 format!(
                     "-Wl,-install_name,{1}/lib{0}.dylib",
-                    lib_name,
-                    libdir.display()
+                    lib_name, install_name_dir
                 )
             };
             lines.push(line);
             // Enable larger LC_RPATH and install_name entries
             lines.push("-Wl,-headerpad_max_install_names".to_string());
-        } else if os == "windows" && env == "gnu" {
+        } else if (os == "windows" && env == "gnu") || os == "cygwin" {
             // This is only set up to work on GNU toolchain versions of Rust
             lines.push(format!(
                 "-Wl,--output-def,{}",
@@ -117,6 +183,12 @@ impl Target {
             ));
         }
 
+        if self.has_rpath() {
+            for rpath in capi_config.library.rpaths(self.is_apple()) {
+                lines.push(format!("-Wl,-rpath,{rpath}"));
+            }
+        }
+
         // Emscripten doesn't support soname or other dynamic linking flags (yet).
         // See: https://github.com/emscripten-core/emscripten/blob/3.1.39/emcc.py#L92-L94
         // else if os == "emscripten"
@@ -132,25 +204,120 @@ impl Target {
         self.os.eq_ignore_ascii_case("haiku")
     }
 
-    fn is_windows(&self) -> bool {
+    /// The ELF systems the shared libraries get a soname and the rpaths on
+    fn is_unix_elf(&self) -> bool {
+        matches!(
+            self.os.as_str(),
+            "linux" | "freebsd" | "dragonfly" | "netbsd" | "haiku" | "illumos" | "openbsd" | "hurd"
+        )
+    }
+
+    /// The shared libraries record the `rpath` and `origin_rpath` directories
+    pub(crate) fn has_rpath(&self) -> bool {
+        self.is_unix_elf() || self.is_apple()
+    }
+
+    pub(crate) fn is_msvc(&self) -> bool {
+        self.is_windows() && self.env == "msvc"
+    }
+
+    /// The windows-gnu and windows-gnullvm targets
+    pub(crate) fn is_mingw(&self) -> bool {
+        self.is_windows() && self.env != "msvc"
+    }
+
+    pub(crate) fn is_windows(&self) -> bool {
         self.os.eq_ignore_ascii_case("windows")
     }
 
-    pub fn default_libdir(&self) -> PathBuf {
+    pub(crate) fn is_cygwin(&self) -> bool {
+        self.os.eq_ignore_ascii_case("cygwin")
+    }
+
+    /// The shared libraries are dlls, installed in the bindir and found through `PATH`
+    pub(crate) fn has_dlls(&self) -> bool {
+        self.is_windows() || self.is_cygwin()
+    }
+
+    /// Whether the MSYS2 conventions apply, `--msys2`, by default if `MSYSTEM` is set
+    pub(crate) fn is_msys2(&self, policy: &str) -> bool {
+        if !(self.is_cygwin() || (self.is_windows() && self.env == "gnu")) {
+            return false;
+        }
+        match policy {
+            "always" => true,
+            "never" => false,
+            _ => std::env::var_os("MSYSTEM").is_some(),
+        }
+    }
+
+    /// The root of the MSYS2 environment, `MINGW_PREFIX` as a Windows path,
+    /// e.g. `C:/msys64/ucrt64`, or `/usr` for the MSYS runtime
+    pub(crate) fn msys2_prefix(&self) -> Option<PathBuf> {
+        if self.is_cygwin() {
+            return Some("/usr".into());
+        }
+        mingw_prefix().cloned()
+    }
+
+    pub(crate) fn is_apple(&self) -> bool {
+        matches!(self.os.as_str(), "macos" | "ios" | "tvos" | "visionos")
+    }
+
+    fn cfg_value(&self, key: &str) -> Option<&str> {
+        self.cfg.iter().find_map(|cfg| match cfg {
+            cargo_platform::Cfg::KeyPair(k, v) if k.as_str() == key => Some(v.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The Debian multiarch tuple, e.g. `aarch64-linux-gnu`
+    ///
+    /// The one of `dpkg-architecture` for the host, derived from the target otherwise.
+    pub fn multiarch(&self) -> Option<String> {
+        if !self.is_target_overridden {
+            if let Some(tuple) = host_multiarch() {
+                return Some(tuple.clone());
+            }
+        }
+        if self.os != "linux" {
+            return None;
+        }
+
+        let little_endian = self.cfg_value("target_endian") == Some("little");
+        let arch = match self.arch.as_str() {
+            "x86" => "i386",
+            "powerpc64" if little_endian => "powerpc64le",
+            "mips" if little_endian => "mipsel",
+            "mips64" if little_endian => "mips64el",
+            arch @ ("x86_64" | "aarch64" | "arm" | "riscv64" | "powerpc" | "powerpc64" | "mips"
+            | "mips64" | "s390x" | "sparc64" | "loongarch64") => arch,
+            _ => return None,
+        };
+        // e.g. gnueabihf or gnux32
+        let abi = self.cfg_value("target_abi").unwrap_or_default();
+
+        Some(format!("{arch}-linux-{}{abi}", self.env))
+    }
+
+    pub fn default_libdir(&self, policy: LibdirPolicy) -> PathBuf {
+        match policy {
+            LibdirPolicy::Lib => return "lib".into(),
+            LibdirPolicy::Lib64 => return "lib64".into(),
+            LibdirPolicy::Multiarch => {
+                return self
+                    .multiarch()
+                    .map_or_else(|| "lib".into(), |tuple| format!("lib/{tuple}").into())
+            }
+            LibdirPolicy::Auto => {}
+        }
+
         if self.is_target_overridden || self.is_freebsd() {
             return "lib".into();
         }
 
-        if PathBuf::from("/etc/debian_version").exists() {
-            let pc = std::process::Command::new("dpkg-architecture")
-                .arg("-qDEB_HOST_MULTIARCH")
-                .output();
-            if let std::result::Result::Ok(v) = pc {
-                if v.status.success() {
-                    let archpath = String::from_utf8_lossy(&v.stdout);
-                    return format!("lib/{}", archpath.trim()).into();
-                }
-            }
+        if let Some(tuple) = host_multiarch() {
+            return format!("lib/{tuple}").into();
         }
 
         if consts::ARCH.eq_ignore_ascii_case(&self.arch)
@@ -175,6 +342,16 @@ impl Target {
         }
     }
 
+    /// The prefix of the installs for the current user, `~/.local` as the XDG directories
+    /// or `%LOCALAPPDATA%` on Windows
+    pub fn user_prefix(&self) -> Option<PathBuf> {
+        if self.is_windows() {
+            std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("HOME").map(|home| Path::new(&home).join(".local"))
+        }
+    }
+
     pub fn default_datadir(&self) -> PathBuf {
         if self.is_haiku() {
             return "data".into();
@@ -189,3 +366,47 @@ impl Target {
         "include".into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn multiarch() {
+        let target = |arch: &str, env: &str, cfg: &[&str]| Target {
+            is_target_overridden: true,
+            arch: arch.into(),
+            os: "linux".into(),
+            env: env.into(),
+            cfg: cfg.iter().map(|c| c.parse().unwrap()).collect(),
+        };
+        let little = r#"target_endian="little""#;
+        assert_eq!(
+            target("aarch64", "gnu", &[little]).multiarch().as_deref(),
+            Some("aarch64-linux-gnu")
+        );
+        assert_eq!(
+            target("arm", "gnu", &[little, r#"target_abi="eabihf""#])
+                .multiarch()
+                .as_deref(),
+            Some("arm-linux-gnueabihf")
+        );
+        assert_eq!(
+            target("powerpc64", "gnu", &[little]).multiarch().as_deref(),
+            Some("powerpc64le-linux-gnu")
+        );
+        assert_eq!(
+            target("x86", "musl", &[little]).multiarch().as_deref(),
+            Some("i386-linux-musl")
+        );
+        assert_eq!(target("wasm32", "", &[little]).multiarch(), None);
+
+        let t = target("aarch64", "gnu", &[little]);
+        assert_eq!(t.default_libdir(LibdirPolicy::Auto), Path::new("lib"));
+        assert_eq!(t.default_libdir(LibdirPolicy::Lib64), Path::new("lib64"));
+        assert_eq!(
+            t.default_libdir(LibdirPolicy::Multiarch),
+            Path::new("lib/aarch64-linux-gnu")
+        );
+    }
+}
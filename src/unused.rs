@@ -0,0 +1,308 @@
+//! The `package.metadata.capi` keys without effect on the build, `--warn-unused-metadata`
+
+use std::collections::BTreeMap;
+
+use cargo::core::Workspace;
+
+use crate::build::CPackage;
+use crate::explain::Metadata;
+use crate::i18n::tr;
+use crate::install::LibType;
+use crate::overrides::capi_layers;
+use crate::target::Target;
+
+/// The keys set that the target, the library types or the overrides make irrelevant, with
+/// the reason
+///
+/// `capi` is the `package.metadata.capi` table, its `target.'cfg(...)'` tables matching the
+/// target and the `profile` one are resolved as the build does.
+fn unused_keys(
+    capi: &toml::Value,
+    target: &Target,
+    profile: &str,
+    header: bool,
+    shared: bool,
+) -> anyhow::Result<Vec<String>> {
+    // The predicates gating the use of the keys in the build, with the catalog id of the reason
+    let rules: [(bool, &str, &[&str], &str); 9] = [
+        (
+            !header,
+            "header",
+            &["name", "subdirectory", "generation"],
+            "unused-header-disabled",
+        ),
+        (
+            !shared,
+            "library",
+            &[
+                "versioning",
+                "version_suffix_components",
+                "rpath",
+                "origin_rpath",
+                "install_name",
+                "current_version",
+                "compatibility_version",
+                "import_library",
+                "def",
+                "delay_load_import_library",
+                "msvc_import_library",
+            ],
+            "unused-no-shared-library",
+        ),
+        (
+            !target.has_rpath(),
+            "library",
+            &["rpath", "origin_rpath"],
+            "unused-no-rpath",
+        ),
+        (
+            !target.is_apple(),
+            "library",
+            &["install_name", "current_version", "compatibility_version"],
+            "unused-not-apple",
+        ),
+        (
+            !target.has_dlls(),
+            "library",
+            &["import_library"],
+            "unused-no-import-library",
+        ),
+        (!target.has_dlls(), "library", &["def"], "unused-no-dll"),
+        (
+            !target.is_mingw(),
+            "library",
+            &["delay_load_import_library", "msvc_import_library"],
+            "unused-not-mingw",
+        ),
+        (
+            !target.is_msvc(),
+            "library",
+            &["msbuild_props"],
+            "unused-not-msvc",
+        ),
+        (
+            !matches!(LibType::from_target(target), LibType::So),
+            "pkg_config",
+            &["link_filename"],
+            "unused-not-elf",
+        ),
+    ];
+
+    // Where each key is set, in the order the tables are applied
+    let mut sources: BTreeMap<(&str, &str), Vec<(String, bool)>> = BTreeMap::new();
+    for (prefix, layer) in capi_layers(capi, target, profile)? {
+        for (section, table) in layer.as_table().into_iter().flatten() {
+            if prefix.is_empty() && matches!(section.as_str(), "target" | "profile") {
+                continue;
+            }
+            for (key, value) in table.as_table().into_iter().flatten() {
+                sources
+                    .entry((section, key))
+                    .or_default()
+                    .push((format!("{prefix}{section}.{key}"), value.is_table()));
+            }
+        }
+    }
+
+    let mut unused = Vec::new();
+    for ((section, key), paths) in sources {
+        let reason = rules
+            .iter()
+            .find(|(applies, table, keys, _)| *applies && *table == section && keys.contains(&key))
+            .map(|(.., reason)| reason);
+        if let Some(reason) = reason {
+            for (path, _) in &paths {
+                unused.push(tr(
+                    "unused-key",
+                    &[("key", path), ("reason", &tr(reason, &[]))],
+                ));
+            }
+        } else if let [overridden @ .., (last, false)] = paths.as_slice() {
+            // The tables are merged, the values replaced
+            for (path, _) in overridden.iter().filter(|(_, table)| !table) {
+                unused.push(tr(
+                    "unused-key-overridden",
+                    &[("key", path), ("last", last)],
+                ));
+            }
+        }
+    }
+    Ok(unused)
+}
+
+/// Warn about the metadata set for nothing, e.g. the Windows options of a Linux build
+pub fn warn_unused_metadata(
+    ws: &Workspace,
+    packages: &[CPackage],
+    profile: &str,
+) -> anyhow::Result<()> {
+    for cpkg in packages {
+        let Some(capi) = Metadata::load(&cpkg.root_path)?.capi else {
+            continue;
+        };
+        let unused = unused_keys(
+            &capi,
+            &cpkg.build_targets.target,
+            profile,
+            cpkg.capi_config.header.enabled,
+            cpkg.build_targets.shared_lib.is_some(),
+        )?;
+        for warning in unused {
+            ws.gctx()
+                .shell()
+                .warn(format!("{}: {warning}", cpkg.name))?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unused_keys;
+    use crate::target::Target;
+
+    const CAPI: &str = r#"
+        [library]
+        rpath = ["$ORIGIN/../lib"]
+        install_name = "rpath"
+        def = { exclude = ["internal_*"] }
+        msbuild_props = true
+        delay_load_import_library = true
+
+        [pkg_config]
+        link_filename = "shared"
+    "#;
+
+    fn unused(triple: &str, capi: &str, shared: bool) -> Vec<String> {
+        let target = Target::new(Some(triple), true).unwrap();
+        let capi: toml::Value = toml::from_str(capi).unwrap();
+        unused_keys(&capi, &target, "release", true, shared)
+            .unwrap()
+            .into_iter()
+            .map(|warning| {
+                let (key, reason) = warning.split_once(" has no effect, ").unwrap();
+                format!(
+                    "{} {reason}",
+                    key.trim_matches('`')
+                        .trim_start_matches("package.metadata.capi.")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn linux() {
+        assert_eq!(
+            unused("x86_64-unknown-linux-gnu", CAPI, true),
+            [
+                "library.def only the dlls use it",
+                "library.delay_load_import_library only the MinGW dlls use it",
+                "library.install_name only the Apple dylibs use it",
+                "library.msbuild_props only the msvc targets use it",
+            ]
+        );
+    }
+
+    #[test]
+    fn linux_staticlib() {
+        assert_eq!(
+            unused("x86_64-unknown-linux-gnu", CAPI, false),
+            [
+                "library.def no shared library is built",
+                "library.delay_load_import_library no shared library is built",
+                "library.install_name no shared library is built",
+                "library.msbuild_props only the msvc targets use it",
+                "library.rpath no shared library is built",
+            ]
+        );
+    }
+
+    #[test]
+    fn android() {
+        assert_eq!(
+            unused("aarch64-linux-android", CAPI, true),
+            [
+                "library.def only the dlls use it",
+                "library.delay_load_import_library only the MinGW dlls use it",
+                "library.install_name only the Apple dylibs use it",
+                "library.msbuild_props only the msvc targets use it",
+                "library.rpath the shared libraries of the target have no rpath",
+            ]
+        );
+    }
+
+    #[test]
+    fn apple() {
+        assert_eq!(
+            unused("aarch64-apple-darwin", CAPI, true),
+            [
+                "library.def only the dlls use it",
+                "library.delay_load_import_library only the MinGW dlls use it",
+                "library.msbuild_props only the msvc targets use it",
+                "pkg_config.link_filename only the ELF linkers resolve `-l:`",
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_msvc() {
+        assert_eq!(
+            unused("x86_64-pc-windows-msvc", CAPI, true),
+            [
+                "library.delay_load_import_library only the MinGW dlls use it",
+                "library.install_name only the Apple dylibs use it",
+                "library.rpath the shared libraries of the target have no rpath",
+                "pkg_config.link_filename only the ELF linkers resolve `-l:`",
+            ]
+        );
+    }
+
+    #[test]
+    fn windows_gnu() {
+        assert_eq!(
+            unused("x86_64-pc-windows-gnu", CAPI, true),
+            [
+                "library.install_name only the Apple dylibs use it",
+                "library.msbuild_props only the msvc targets use it",
+                "library.rpath the shared libraries of the target have no rpath",
+                "pkg_config.link_filename only the ELF linkers resolve `-l:`",
+            ]
+        );
+    }
+
+    #[test]
+    fn overrides() {
+        let capi = r#"
+            [library]
+            install_subdir = "lib"
+            versioning = true
+
+            [target.'cfg(windows)'.library]
+            install_subdir = "plugins"
+            rpath = ["$ORIGIN"]
+
+            [target.'cfg(unix)'.library]
+            import_library = false
+
+            [profile.release.library]
+            versioning = false
+        "#;
+
+        assert_eq!(
+            unused("x86_64-pc-windows-msvc", capi, true),
+            [
+                "library.install_subdir `target.'cfg(windows)'.library.install_subdir` overrides it",
+                "target.'cfg(windows)'.library.rpath the shared libraries of the target have no rpath",
+                "library.versioning `profile.release.library.versioning` overrides it",
+            ]
+        );
+        assert_eq!(
+            unused("x86_64-unknown-linux-gnu", capi, true),
+            [
+                "target.'cfg(unix)'.library.import_library only the dlls have import libraries",
+                "library.versioning `profile.release.library.versioning` overrides it",
+            ]
+        );
+    }
+}
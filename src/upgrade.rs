@@ -0,0 +1,184 @@
+//! Migrate the `package.metadata.capi` of the workspace members to the current schema
+
+use std::fmt::Write as _;
+
+use cargo::core::Workspace;
+use cargo_util::paths::{read, write};
+use clap::ArgMatches;
+use toml_edit::{DocumentMut, Item, Table};
+
+/// Rewrite the deprecated keys of `capi`, returns a note per change
+fn upgrade_capi(capi: &mut Table) -> Vec<String> {
+    let mut notes = Vec::new();
+
+    if let Some(header_name) = capi.remove("header_name") {
+        let header = capi
+            .entry("header")
+            .or_insert_with(|| {
+                let mut table = Table::new();
+                table.set_implicit(true);
+                Item::Table(table)
+            })
+            .as_table_like_mut();
+        match header {
+            Some(header) if !header.contains_key("name") => {
+                header.insert("name", header_name);
+                notes.push("`header_name` is now `header.name`".to_string());
+            }
+            _ => notes.push("`header_name` is ignored, `header.name` is set".to_string()),
+        }
+    }
+
+    // Do not leave an empty `[package.metadata.capi]` behind
+    if capi.iter().all(|(_, item)| item.is_table()) {
+        capi.set_implicit(true);
+    }
+
+    notes
+}
+
+/// The lines removed and added going from `old` to `new`, prefixed by `-` and `+`
+fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+
+    // The longest common subsequence of the lines, from the end
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    // writing to a String only fails on OOM, which we disregard
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            writeln!(diff, "+{}", new[j]).unwrap();
+            j += 1;
+        } else {
+            writeln!(diff, "-{}", old[i]).unwrap();
+            i += 1;
+        }
+    }
+
+    diff
+}
+
+/// Rewrite the deprecated `package.metadata.capi` keys of the workspace members
+///
+/// The changes are printed as a diff, `--dry-run` does not write them.
+pub fn upgrade_metadata(ws: &Workspace, args: &ArgMatches) -> anyhow::Result<()> {
+    let mut shell = ws.gctx().shell();
+    let dry_run = args.get_flag("dry-run");
+
+    // The members without a library target are not C-API packages
+    for pkg in ws.members() {
+        let Some(lib) = pkg.library() else {
+            continue;
+        };
+        let path = pkg.manifest_path();
+        let manifest = read(path)?;
+        let mut doc: DocumentMut = manifest.parse()?;
+
+        let Some(capi) = doc
+            .get_mut("package")
+            .and_then(|p| p.get_mut("metadata"))
+            .and_then(|m| m.get_mut("capi"))
+            .and_then(Item::as_table_mut)
+        else {
+            continue;
+        };
+
+        // The header in the package root is installed only for backward compatibility
+        let header_name = capi
+            .get("header")
+            .and_then(|h| h.get("name"))
+            .or_else(|| capi.get("header_name"))
+            .and_then(|n| n.as_str())
+            .map_or_else(
+                || lib.crate_name().to_string(),
+                |n| n.trim_end_matches(".h").to_string(),
+            );
+        if pkg.root().join(format!("{header_name}.h")).exists() {
+            shell.warn(format!(
+                "{}: `{header_name}.h` is installed from the package root by default, \
+                 list it in `install.include` since the default is going to be removed",
+                pkg.name()
+            ))?;
+        }
+
+        let notes = upgrade_capi(capi);
+        if notes.is_empty() {
+            continue;
+        }
+        for note in &notes {
+            shell.note(format!("{}: {note}", pkg.name()))?;
+        }
+
+        let upgraded = doc.to_string();
+        print!("--- {}\n+++ {}\n", path.display(), path.display());
+        print!("{}", line_diff(&manifest, &upgraded));
+
+        if !dry_run {
+            shell.status("Upgrading", path.display())?;
+            write(path, upgraded)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upgrade(manifest: &str) -> (String, Vec<String>) {
+        let mut doc: DocumentMut = manifest.parse().unwrap();
+        let capi = doc["package"]["metadata"]["capi"].as_table_mut().unwrap();
+        let notes = upgrade_capi(capi);
+        (doc.to_string(), notes)
+    }
+
+    #[test]
+    fn header_name() {
+        let (upgraded, notes) = upgrade(concat!(
+            "[package.metadata.capi]\n",
+            "min_version = \"0.9\"\n",
+            "header_name = \"foo\"\n",
+        ));
+        assert_eq!(
+            upgraded,
+            concat!(
+                "[package.metadata.capi]\n",
+                "min_version = \"0.9\"\n",
+                "\n",
+                "[package.metadata.capi.header]\n",
+                "name = \"foo\"\n",
+            )
+        );
+        assert_eq!(notes, ["`header_name` is now `header.name`"]);
+
+        let (upgraded, notes) = upgrade(concat!(
+            "[package.metadata.capi]\n",
+            "header_name = \"foo\"\n",
+            "[package.metadata.capi.header]\n",
+            "name = \"bar\"\n",
+        ));
+        assert_eq!(upgraded, "[package.metadata.capi.header]\nname = \"bar\"\n");
+        assert_eq!(notes, ["`header_name` is ignored, `header.name` is set"]);
+    }
+
+    #[test]
+    fn diff() {
+        assert_eq!(line_diff("a\nb\nc\n", "a\nc\nd\n"), "-b\n+d\n");
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::{BTreeSet, HashSet};
+
+use cargo::core::compiler::unit_graph::UnitGraph;
+use cargo::core::compiler::Unit;
+use cargo::core::Workspace;
+
+use crate::i18n::tr;
+
+/// The `-sys` crates and the features that build a bundled copy of the system library
+const VENDORING_FEATURES: &[(&str, &str)] = &[
+    ("bzip2-sys", "static"),
+    ("curl-sys", "static-curl"),
+    ("libgit2-sys", "vendored"),
+    ("libsqlite3-sys", "bundled"),
+    ("libssh2-sys", "vendored-openssl"),
+    ("libz-sys", "static"),
+    ("lzma-sys", "static"),
+    ("openssl-sys", "vendored"),
+];
+
+/// Whether the package builds a vendored copy of a system library
+fn vendored_library(name: &str, features: &[&str]) -> bool {
+    // e.g. openssl-src, lua-src
+    name.ends_with("-src")
+        || VENDORING_FEATURES
+            .iter()
+            .any(|(pkg, feature)| *pkg == name && features.contains(feature))
+}
+
+/// The packages building vendored system libraries that end up in the root library
+fn vendored_dependencies(root: &Unit, unit_graph: &UnitGraph) -> BTreeSet<String> {
+    let mut found = BTreeSet::new();
+    let mut visited = HashSet::new();
+    let mut queue = vec![root];
+
+    while let Some(unit) = queue.pop() {
+        if !visited.insert(unit) {
+            continue;
+        }
+        let features: Vec<&str> = unit.features.iter().map(|f| f.as_str()).collect();
+        if vendored_library(unit.pkg.name().as_str(), &features) {
+            found.insert(format!("{} v{}", unit.pkg.name(), unit.pkg.version()));
+        }
+        if let Some(deps) = unit_graph.get(unit) {
+            queue.extend(deps.iter().map(|dep| &dep.unit));
+        }
+    }
+
+    found
+}
+
+/// Report the dependencies bundling a copy of a system library, or fail if `forbid` is set
+///
+/// Distributions usually require linking against the system copy instead.
+pub fn check_vendored(
+    ws: &Workspace,
+    roots: &[Unit],
+    unit_graph: &UnitGraph,
+    forbid: bool,
+) -> anyhow::Result<()> {
+    for root in roots {
+        let vendored = vendored_dependencies(root, unit_graph);
+        if vendored.is_empty() {
+            continue;
+        }
+
+        let list = vendored.into_iter().collect::<Vec<_>>().join(", ");
+        let msg = tr(
+            "vendored",
+            &[("package", &root.pkg.name()), ("list", &list)],
+        );
+        if forbid {
+            anyhow::bail!("{msg}");
+        }
+        ws.gctx().shell().note(msg)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::vendored_library;
+
+    #[test]
+    fn vendored() {
+        assert!(vendored_library("openssl-src", &[]));
+        assert!(vendored_library("openssl-sys", &["vendored"]));
+        assert!(vendored_library("libz-sys", &["libc", "static"]));
+        assert!(!vendored_library("libz-sys", &["libc"]));
+        assert!(!vendored_library("openssl", &["vendored"]));
+    }
+}
@@ -0,0 +1,177 @@
+//! Snapshot the artifacts names, the pkg-config files and the install layout
+//!
+//! The targets are simulated with `--pretend-target`, replaying the
+//! `rustc --print cfg` output recorded in `tests/targets`. Set `CARGO_C_UPDATE_SNAPSHOTS=1`
+//! to update the snapshots in `tests/snapshots` after an intended change.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use cargo::util::command_prelude::ArgMatchesExt;
+use cargo::GlobalContext;
+use cargo_c::build::pretend;
+use cargo_c::cli::subcommand_install;
+use cargo_c::config::global_context_configure;
+use cargo_c::install::cinstall;
+use cargo_c::probe;
+
+const TARGETS: &[&str] = &[
+    "x86_64-unknown-linux-gnu",
+    "aarch64-apple-darwin",
+    "x86_64-pc-windows-msvc",
+    "x86_64-pc-windows-gnu",
+    "aarch64-linux-android",
+    "x86_64-unknown-freebsd",
+    "wasm32-unknown-emscripten",
+];
+
+const PROJECTS: &[&str] = &["example-project", "example-workspace"];
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Run `capi install --pretend-target` for the project and render what it would produce
+fn snapshot(project: &str, target: &str, extra_args: &[&str]) -> String {
+    let tmp_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let target_dir = tmp_dir.join("snapshots");
+    let manifest_path = root().join(project).join("Cargo.toml");
+    let install_manifest = tmp_dir.join(format!("{project}-{target}.json"));
+
+    let probes =
+        std::fs::read_to_string(root().join("tests/targets").join(format!("{target}.toml")))
+            .unwrap();
+    probe::load(toml::from_str(&probes).unwrap(), true);
+
+    let mut cmd_line = vec![
+        "install".into(),
+        "--pretend-target".into(),
+        target.into(),
+        "--manifest-path".into(),
+        manifest_path.into_os_string(),
+        "--target-dir".into(),
+        target_dir.clone().into_os_string(),
+        "--install-manifest".into(),
+        install_manifest.clone().into_os_string(),
+    ];
+    cmd_line.extend(extra_args.iter().map(Into::into));
+    let args = subcommand_install("install", "").get_matches_from(cmd_line);
+
+    let mut gctx = GlobalContext::default().unwrap();
+    global_context_configure(&mut gctx, &args).unwrap();
+    gctx.shell().set_verbosity(cargo::core::Verbosity::Quiet);
+    let mut ws = args.workspace(&gctx).unwrap();
+
+    let mut packages = pretend(&mut ws, &gctx, &args, "release").unwrap();
+    packages.sort_by_key(|p| p.name);
+    cinstall(&ws, &packages, &args).unwrap();
+
+    let relative = |path: &Path| {
+        let path = if let Ok(p) = path.strip_prefix(&target_dir) {
+            Path::new("$TARGET_DIR").join(p)
+        } else if let Ok(p) = path.strip_prefix(root()) {
+            Path::new("$SOURCE_DIR").join(p)
+        } else {
+            path.to_path_buf()
+        };
+        path.display().to_string().replace('\\', "/")
+    };
+
+    let mut out = String::new();
+    for cpkg in &packages {
+        let bt = &cpkg.build_targets;
+        writeln!(out, "[{}]", cpkg.name).unwrap();
+        for (kind, path) in [
+            ("pc", Some(&bt.pc)),
+            ("include", bt.include.as_ref()),
+            ("static_lib", bt.static_lib.as_ref()),
+            ("shared_lib", bt.shared_lib.as_ref()),
+            ("impl_lib", bt.impl_lib.as_ref()),
+            ("def", bt.def.as_ref()),
+            ("debug_info", bt.debug_info.as_ref()),
+        ] {
+            if let Some(path) = path {
+                writeln!(out, "{kind}: {}", relative(path)).unwrap();
+            }
+        }
+
+        writeln!(out, "\n{}", std::fs::read_to_string(&bt.pc).unwrap()).unwrap();
+        if let Some(props) = &bt.msbuild_props {
+            let props = std::fs::read_to_string(props).unwrap();
+            writeln!(out, "{props}").unwrap();
+        }
+    }
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(install_manifest).unwrap()).unwrap();
+    writeln!(out, "[install]").unwrap();
+    for file in manifest["files"].as_array().unwrap() {
+        let path = relative(Path::new(file["path"].as_str().unwrap()));
+        let source = relative(Path::new(file["source"].as_str().unwrap()));
+        let arrow = if file["symlink"].as_bool().unwrap() {
+            "->"
+        } else {
+            "<-"
+        };
+        writeln!(
+            out,
+            "{} {path} {arrow} {source}",
+            file["category"].as_str().unwrap()
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+fn check(name: &str, actual: &str) -> Option<String> {
+    let path = root().join("tests/snapshots").join(format!("{name}.snap"));
+
+    if std::env::var_os("CARGO_C_UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return None;
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap_or_default();
+    if expected == actual {
+        return None;
+    }
+
+    let line = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+    Some(format!(
+        "{} differs at line {}:\n  expected: {:?}\n  actual:   {:?}",
+        path.display(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or_default(),
+        actual.lines().nth(line).unwrap_or_default(),
+    ))
+}
+
+// The probes and the shell are global, the snapshots are taken in sequence
+#[test]
+fn artifacts() {
+    let mut failures = Vec::new();
+
+    for project in PROJECTS {
+        for target in TARGETS {
+            let actual = snapshot(project, target, &[]);
+            failures.extend(check(&format!("{project}-{target}"), &actual));
+        }
+    }
+
+    for target in ["x86_64-pc-windows-msvc", "x86_64-pc-windows-gnu"] {
+        let actual = snapshot("example-project", target, &["--meson-paths"]);
+        failures.extend(check(&format!("example-project-{target}-meson"), &actual));
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\nrun with CARGO_C_UPDATE_SNAPSHOTS=1 if the changes are intended",
+        failures.join("\n")
+    );
+}